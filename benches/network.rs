@@ -0,0 +1,38 @@
+//! Sweeps the number of threads and the section size of `MtNetwork` to find the fastest configuration on this machine.
+//! Run with `cargo bench --bench network`; throughput is reported in patches per second. With `PIN_CORES=1` every worker is
+//! pinned to a core of its own, which reduces the variance between samples on NUMA and hybrid-core machines.
+use std::sync::Arc;
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+use rust_ml::data::mnist::MnistData;
+use rust_ml::model::network::MtNetwork;
+use rust_ml::threading::thread_pool::ThreadPool;
+use rust_ml::utils::constants::PATCH_WIDTH;
+
+const NEURONS: usize = 240;
+const EPOCHS: usize = 5000;
+const THREADS: [usize; 5] = [1, 2, 4, 8, 16];
+const SECTION_SIZES: [usize; 4] = [1, 10, 40, 120];
+
+fn thread_and_section_sweep(c: &mut Criterion) {
+    let mut group = c.benchmark_group("train_complete_iterations");
+    group.sample_size(10);
+    group.throughput(Throughput::Elements((NEURONS * EPOCHS) as u64));
+    let pin_cores = std::env::var("PIN_CORES").is_ok_and(|value| value == "1");
+
+    for threads in THREADS {
+        for section_size in SECTION_SIZES {
+            let dataset = MnistData::new(section_size, PATCH_WIDTH).unwrap();
+            let pool = ThreadPool::builder(threads).label("oja").queue_capacity(threads).pin_cores(pin_cores).build().unwrap();
+            let mut network = MtNetwork::with_pool(section_size, Arc::new(pool), NEURONS, 0.01, dataset, Some(0)).unwrap();
+            // Hand out exactly one section at a time, so the section size is what is being measured.
+            network.set_chunk_size(Some(section_size)).unwrap();
+
+            let id = BenchmarkId::new(format!("{} threads", threads), format!("section size {}", section_size));
+            group.bench_function(id, |b| b.iter(|| network.train_complete_iterations(EPOCHS).unwrap()));
+        }
+    }
+    group.finish();
+}
+
+criterion_group!(benches, thread_and_section_sweep);
+criterion_main!(benches);
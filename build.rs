@@ -0,0 +1,16 @@
+// Regenerates the C header of the `ffi` module when building with `--features ffi`, so it can not drift from the bindings.
+fn main() {
+    #[cfg(feature = "ffi")]
+    {
+        let dir = std::env::var("CARGO_MANIFEST_DIR").expect("cargo sets the manifest directory");
+        println!("cargo:rerun-if-changed=src/ffi.rs");
+        println!("cargo:rerun-if-changed=cbindgen.toml");
+        let config = cbindgen::Config::from_file(format!("{}/cbindgen.toml", dir)).expect("unable to read cbindgen.toml");
+        cbindgen::Builder::new()
+            .with_config(config)
+            .with_src(format!("{}/src/ffi.rs", dir))
+            .generate()
+            .expect("unable to generate the C header")
+            .write_to_file(format!("{}/include/rust_ml.h", dir));
+    }
+}
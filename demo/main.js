@@ -0,0 +1,50 @@
+// Built with `wasm-pack build --target web --out-dir demo/pkg -- --features wasm` from the repository root, then served
+// from this directory with any static file server, e.g. `python3 -m http.server`.
+import init, { Demo } from "./pkg/rust_ml.js";
+
+const PATCH_WIDTH = 8;
+const PATCHES_PER_FRAME = 200;
+const SCALE = 4;
+
+// The pixels of an image as grayscale values, centered to zero mean so the first component is not just the brightness.
+async function grayscale(file) {
+    const bitmap = await createImageBitmap(file);
+    const canvas = new OffscreenCanvas(bitmap.width, bitmap.height);
+    const context = canvas.getContext("2d");
+    context.drawImage(bitmap, 0, 0);
+    const { data } = context.getImageData(0, 0, bitmap.width, bitmap.height);
+    const pixels = new Float32Array(bitmap.width * bitmap.height);
+    for (let i = 0; i < pixels.length; i++) {
+        pixels[i] = (0.299 * data[4 * i] + 0.587 * data[4 * i + 1] + 0.114 * data[4 * i + 2]) / 255;
+    }
+    const mean = pixels.reduce((sum, value) => sum + value, 0) / pixels.length;
+    return { pixels: pixels.map((value) => value - mean), rows: bitmap.height, columns: bitmap.width };
+}
+
+await init();
+const canvas = document.getElementById("fields");
+const status = document.getElementById("status");
+let frame = null;
+let demo = null;
+
+document.getElementById("image").addEventListener("change", async (event) => {
+    const { pixels, rows, columns } = await grayscale(event.target.files[0]);
+    const neurons = Number(document.getElementById("neurons").value);
+    const lr = Number(document.getElementById("lr").value);
+    cancelAnimationFrame(frame);
+    demo?.free();
+    demo = new Demo(pixels, rows, columns, neurons, PATCH_WIDTH, lr);
+
+    const train = () => {
+        demo.step(PATCHES_PER_FRAME);
+        const fields = demo.render(SCALE);
+        canvas.width = fields.width;
+        canvas.height = fields.height;
+        canvas.getContext("2d").putImageData(new ImageData(new Uint8ClampedArray(fields.pixels()), fields.width), 0, 0);
+        // Objects returned by the module live in its memory until they are freed.
+        fields.free();
+        status.textContent = `${demo.trained} patches per neuron`;
+        frame = requestAnimationFrame(train);
+    };
+    frame = requestAnimationFrame(train);
+});
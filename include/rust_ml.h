@@ -0,0 +1,151 @@
+#ifndef RUST_ML_H
+#define RUST_ML_H
+
+/* Generated by cbindgen from src/ffi.rs with `cargo build --features ffi`, do not edit. */
+
+#include <stdarg.h>
+#include <stdbool.h>
+#include <stddef.h>
+#include <stdint.h>
+#include <stdlib.h>
+
+// How `rust_ml_network_train` trains the network, see `TrainingMethod`, passed as `uint32_t` like `RustMlRule`. `Rayon`
+// requires the library to be built with the `rayon` feature.
+typedef enum RustMlMethod {
+  RUST_ML_METHOD_POOL = 0,
+  RUST_ML_METHOD_RAYON = 1,
+  RUST_ML_METHOD_SCOPED = 2,
+  RUST_ML_METHOD_MATRIX = 3,
+  // Trains with Sanger's rule, so the neurons learn the principal components in order.
+  RUST_ML_METHOD_HIERARCHICAL = 4,
+} RustMlMethod;
+
+// The learning rules, with the defaults of the experiment files for their parameters. Passed as `uint32_t`, since any other
+// value a C caller stores in a Rust enum is undefined behaviour, and checked by the library instead.
+typedef enum RustMlRule {
+  RUST_ML_RULE_OJA = 0,
+  RUST_ML_RULE_OJA_MOMENTUM = 1,
+  RUST_ML_RULE_BCM = 2,
+  RUST_ML_RULE_HEBBIAN = 3,
+  RUST_ML_RULE_ICA = 4,
+} RustMlRule;
+
+// Outcome of a call. The message of every status other than `Ok` can be read with `rust_ml_last_error`.
+typedef enum RustMlStatus {
+  RUST_ML_STATUS_OK = 0,
+  // A pointer argument was null.
+  RUST_ML_STATUS_NULL_POINTER,
+  // The configuration is invalid, or a buffer does not match the network.
+  RUST_ML_STATUS_INVALID_ARGUMENT,
+  // The weights diverged, usually because the learning rate is too high. The network keeps the weights it had before.
+  RUST_ML_STATUS_DIVERGED,
+  // A worker thread panicked, so only some of the neurons were trained.
+  RUST_ML_STATUS_WORKER_PANICKED,
+  // The call panicked. The network should not be used anymore, other than to free it.
+  RUST_ML_STATUS_PANICKED,
+  // Any other failure, e.g. the worker threads could not be started.
+  RUST_ML_STATUS_FAILED,
+} RustMlStatus;
+
+// A network and the samples it trains on, only handled through pointers.
+typedef struct RustMlNetwork RustMlNetwork;
+
+// The parameters of a network, starting from `rust_ml_config_default`.
+typedef struct RustMlConfig {
+  size_t neurons;
+  // Number of worker threads, or zero for one per core.
+  size_t threads;
+  float lr;
+  // One of the `RustMlRule` values.
+  uint32_t rule;
+  size_t batch_size;
+  // Seeds the initial weights and the sampling of patches if `seeded` is set, so runs can be reproduced.
+  uint64_t seed;
+  bool seeded;
+} RustMlConfig;
+
+#ifdef __cplusplus
+extern "C" {
+#endif // __cplusplus
+
+// The defaults of the Python bindings: 256 neurons trained with Oja's rule at a learning rate of 0.01, one sample at a time,
+// with one worker thread per core and an unseeded random number generator.
+RustMlConfig rust_ml_config_default(void);
+
+// The message of the last failed call on this thread, or null if none has failed. The string is owned by the library and
+// stays valid until the next call on this thread fails.
+const char *rust_ml_last_error(void);
+
+// Creates a network training on `count` samples of `dim` floats, stored one after the other in `samples`, and stores it in
+// `network`. The network must be released with `rust_ml_network_free`.
+//
+// # Safety
+//
+// `config` must point to a config, `samples` to `count * dim` floats and `network` to memory a pointer can be written to.
+RustMlStatus rust_ml_network_new(const RustMlConfig *config,
+                                 const float *samples,
+                                 size_t count,
+                                 size_t dim,
+                                 RustMlNetwork **network);
+
+// Replaces the samples the network trains on with `count` new samples of the network's `dim` floats, keeping its weights,
+// e.g. to train on the next batch of a stream.
+//
+// # Safety
+//
+// `network` must have been created by `rust_ml_network_new` and `samples` must point to `count * dim` floats.
+RustMlStatus rust_ml_network_feed(RustMlNetwork *network, const float *samples, size_t count);
+
+// Trains every neuron on `epochs` more samples with one of the `RustMlMethod` values, continuing from its current weights.
+//
+// # Safety
+//
+// `network` must have been created by `rust_ml_network_new`.
+RustMlStatus rust_ml_network_train(RustMlNetwork *network, uint32_t method, size_t epochs);
+
+// Number of neurons of the network, or zero for a null network.
+//
+// # Safety
+//
+// `network` must be null or have been created by `rust_ml_network_new`.
+size_t rust_ml_network_neurons(const RustMlNetwork *network);
+
+// Number of floats in a sample, and in the weights of a neuron, or zero for a null network.
+//
+// # Safety
+//
+// `network` must be null or have been created by `rust_ml_network_new`.
+size_t rust_ml_network_dim(const RustMlNetwork *network);
+
+// Copies the weights into `out`, which holds `len` floats and needs `neurons * dim`, one neuron after the other.
+//
+// # Safety
+//
+// `network` must have been created by `rust_ml_network_new` and `out` must point to `len` writable floats.
+RustMlStatus rust_ml_network_weights(const RustMlNetwork *network, float *out, size_t len);
+
+// Writes the outputs of every neuron for `count` samples of `dim` floats into `out`, which holds `len` floats and needs
+// `count * neurons`, sample after sample.
+//
+// # Safety
+//
+// `network` must have been created by `rust_ml_network_new`, `samples` must point to `count * dim` floats and `out` to
+// `len` writable floats.
+RustMlStatus rust_ml_network_encode(const RustMlNetwork *network,
+                                    const float *samples,
+                                    size_t count,
+                                    float *out,
+                                    size_t len);
+
+// Releases a network. Null is ignored.
+//
+// # Safety
+//
+// `network` must be null or have been created by `rust_ml_network_new`, and must not be used afterwards.
+void rust_ml_network_free(RustMlNetwork *network);
+
+#ifdef __cplusplus
+}  // extern "C"
+#endif  // __cplusplus
+
+#endif  /* RUST_ML_H */
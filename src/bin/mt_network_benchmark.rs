@@ -1,12 +1,14 @@
 use rust_ml::model::network::MtNetwork;
+use rust_ml::utils::constants::PATCH_WIDTH;
 
 fn main() {
+    tracing_subscriber::fmt::init();
     for rounds in 0..3 {
         println!("starting round: {}", rounds);
         for threads in 1..40 {
             println!("starting batch with: {} threads", threads);
-            let network = MtNetwork::new(100, threads + 1, 10000, 0.01);
-            network.train_complete_iterations(50000);
+            let mut network = MtNetwork::new(100, threads, 10000, 0.01, PATCH_WIDTH, None).expect("Unable to create network");
+            network.train_complete_iterations(50000).expect("Training failed");
         }
     }
 }
\ No newline at end of file
@@ -1,12 +1,15 @@
+use std::thread;
 use piston_window::{EventLoop, PistonWindow, WindowSettings};
 use plotters::prelude::*;
 use plotters_piston::{draw_piston_window};
 use rust_ml::model::network::MtNetwork;
+use rust_ml::utils::constants::PATCH_WIDTH;
 use rust_ml::plotting::cpu_plotting::CpuPlotter;
 
 const FPS: u32 = 10;
 
 fn main() {
+    tracing_subscriber::fmt::init();
     let mut cpu_plotter = CpuPlotter::new();
 
     let mut window: PistonWindow = WindowSettings::new("Oja monitoring", [1024, 768])
@@ -16,8 +19,9 @@ fn main() {
 
     window.set_max_fps(FPS as u64);
 
-    let network = MtNetwork::new(10, 30, 10000, 0.01);
-    network.train_complete_iterations(50000);
+    let mut network = MtNetwork::new(10, 30, 10000, 0.01, PATCH_WIDTH, None).expect("Unable to create network");
+    // Training blocks until all neurons are done, so it runs in the background while the CPU usage is plotted.
+    thread::spawn(move || network.train_complete_iterations(50000));
 
     while let Some(_) = draw_piston_window(&mut window, |b| {
 
@@ -0,0 +1,14 @@
+use rust_ml::model::network::MtNetwork;
+use rust_ml::utils::constants::PATCH_WIDTH;
+
+fn main(){
+    tracing_subscriber::fmt::init();
+    let mut network = MtNetwork::new(1, 1, 8, 0.001, PATCH_WIDTH, None).expect("Unable to create network");
+    let weights = network.train_hierarchical(50000).expect("Training failed");
+
+    for (i, weight) in weights.iter().enumerate() {
+        println!("Component {}: {:?}", i, weight);
+    }
+
+    network.save_receptive_fields("sanger_components.png", 10).unwrap();
+}
@@ -0,0 +1,30 @@
+use std::time::Instant;
+use rust_ml::model::oja::{oja_learning_rule, oja_learning_rule_scalar};
+use rust_ml::model::simd::simd_enabled;
+use rust_ml::utils::constants::PATCH_SIZE;
+
+// Compares the vectorized Oja update against the scalar one for a range of patch sizes.
+fn main() {
+    println!("AVX2/FMA kernels enabled: {}", simd_enabled());
+    for size in [PATCH_SIZE, 64, 256, 784] {
+        let patch: Vec<f32> = (0..size).map(|i| (i as f32 * 0.3).sin()).collect();
+        let updates = 50_000_000 / size;
+
+        let mut weights: Vec<f32> = (0..size).map(|i| (i as f32 * 0.7).cos() * 0.1).collect();
+        let now = Instant::now();
+        for _ in 0..updates {
+            oja_learning_rule_scalar(&patch, &mut weights, 0.001);
+        }
+        let scalar = now.elapsed();
+
+        let mut weights: Vec<f32> = (0..size).map(|i| (i as f32 * 0.7).cos() * 0.1).collect();
+        let now = Instant::now();
+        for _ in 0..updates {
+            oja_learning_rule(&patch, &mut weights, 0.001);
+        }
+        let vectorized = now.elapsed();
+
+        println!("patch size {:>4}: scalar {:>6} ms, vectorized {:>6} ms, speedup {:.2}x",
+                 size, scalar.as_millis(), vectorized.as_millis(), scalar.as_secs_f64() / vectorized.as_secs_f64());
+    }
+}
@@ -0,0 +1,19 @@
+use rust_ml::data::dataset::Dataset;
+use rust_ml::model::network::MtNetwork;
+use rust_ml::model::som::Som;
+use rust_ml::utils::constants::PATCH_WIDTH;
+
+fn main(){
+    tracing_subscriber::fmt::init();
+    let mut som = Som::new(10, 10, 8, 0.5, PATCH_WIDTH, None).expect("Unable to create map");
+    som.train(50000).expect("Training failed");
+    som.save_map("som.png", 10).unwrap();
+
+    // The same patches are used to compare the map against the components learned with Oja's rule.
+    let mut network = MtNetwork::new(10, 8, 100, 0.01, PATCH_WIDTH, None).expect("Unable to create network");
+    network.train_complete_iterations(50000).expect("Training failed");
+    network.save_receptive_fields("oja_components.png", 10).unwrap();
+
+    let patches = som.dataset().sample_patches(0, 1000, &mut rand::thread_rng());
+    println!("Quantization error of the map: {}", som.quantization_error(&patches));
+}
@@ -6,6 +6,6 @@ fn main() {
     for i in 0..100 {
         pool.execute(move || {
              let _o = i + i;
-        })
+        }).unwrap()
     }
 }
\ No newline at end of file
@@ -0,0 +1,183 @@
+//! Random transformations of the sampled patches, so the learned components do not depend on the exact position, orientation,
+//! contrast or noise level of the structures in the images. Applied on top of any dataset, like the normalization.
+use rand::{Rng, RngCore};
+use crate::data::dataset::Dataset;
+use crate::data::patch::{Patch, patch_width};
+use crate::error::{Error, Result};
+use crate::model::init::standard_normal;
+
+/// The transforms applied to every patch, in the order they are listed. All of them are disabled by default.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct Augmentation {
+    /// Maximum number of pixels the patch is shifted by in each direction. Pixels shifted in from outside the patch are zero.
+    pub max_shift: usize,
+    /// Probability of mirroring the patch horizontally.
+    pub flip_probability: f32,
+    /// Maximum relative change in contrast, the deviations from the mean of the patch are scaled by a factor in `[1 - c, 1 + c]`.
+    pub contrast: f32,
+    /// Standard deviation of the Gaussian noise added to every value.
+    pub noise_std: f32,
+}
+
+impl Augmentation {
+    pub fn new() -> Augmentation {
+        Augmentation::default()
+    }
+
+    pub fn shift(mut self, max_shift: usize) -> Augmentation {
+        self.max_shift = max_shift;
+        self
+    }
+
+    pub fn flip(mut self, probability: f32) -> Augmentation {
+        self.flip_probability = probability;
+        self
+    }
+
+    pub fn contrast(mut self, contrast: f32) -> Augmentation {
+        self.contrast = contrast;
+        self
+    }
+
+    pub fn noise(mut self, std: f32) -> Augmentation {
+        self.noise_std = std;
+        self
+    }
+
+    // Whether any of the transforms move pixels, which needs square patches.
+    fn is_spatial(&self) -> bool {
+        self.max_shift > 0 || self.flip_probability > 0.0
+    }
+
+    /// Applies the enabled transforms to a square patch of the given width.
+    pub fn apply(&self, patch: &mut Patch, width: usize, rng: &mut dyn RngCore) {
+        if self.max_shift > 0 {
+            let max = self.max_shift;
+            let rows = rng.gen_range(0..=2 * max) as isize - max as isize;
+            let columns = rng.gen_range(0..=2 * max) as isize - max as isize;
+            *patch = shifted(patch, width, rows, columns);
+        }
+        if self.flip_probability > 0.0 && rng.gen::<f32>() < self.flip_probability {
+            patch.chunks_mut(width).for_each(|row| row.reverse());
+        }
+        if self.contrast > 0.0 && !patch.is_empty() {
+            let factor = 1.0 + self.contrast * (2.0 * rng.gen::<f32>() - 1.0);
+            let mean = patch.iter().sum::<f32>() / patch.len() as f32;
+            patch.iter_mut().for_each(|value| *value = mean + factor * (*value - mean));
+        }
+        if self.noise_std > 0.0 {
+            patch.iter_mut().for_each(|value| *value += self.noise_std * standard_normal(rng));
+        }
+    }
+}
+
+/// The patch moved down by `rows` and right by `columns` pixels, negative values move it up and left.
+fn shifted(patch: &[f32], width: usize, rows: isize, columns: isize) -> Patch {
+    let mut result = vec![0.0; patch.len()];
+    let height = (patch.len() / width.max(1)) as isize;
+    for row in 0..height {
+        for column in 0..width as isize {
+            let (source_row, source_column) = (row - rows, column - columns);
+            if (0..height).contains(&source_row) && (0..width as isize).contains(&source_column) {
+                result[(row * width as isize + column) as usize] = patch[(source_row * width as isize + source_column) as usize];
+            }
+        }
+    }
+    result
+}
+
+/// A dataset whose patches are randomly transformed every time they are sampled.
+pub struct Augmented<D: Dataset> {
+    dataset: D,
+    augmentation: Augmentation,
+    width: usize,
+}
+
+impl<D: Dataset> Augmented<D> {
+    // Fails when shifts or flips are enabled but the patches of the dataset are not square.
+    pub fn new(dataset: D, augmentation: Augmentation) -> Result<Augmented<D>> {
+        let width = match patch_width(dataset.patch_dim()) {
+            Some(width) => width,
+            None if !augmentation.is_spatial() => dataset.patch_dim(),
+            None => return Err(Error::Incompatible("shifting and flipping need square patches".to_string())),
+        };
+        Ok(Augmented { dataset, augmentation, width })
+    }
+
+    pub fn augmentation(&self) -> Augmentation {
+        self.augmentation
+    }
+
+    pub fn inner(&self) -> &D {
+        &self.dataset
+    }
+
+    pub fn into_inner(self) -> D {
+        self.dataset
+    }
+}
+
+impl<D: Dataset> Dataset for Augmented<D> {
+    fn len(&self) -> usize {
+        self.dataset.len()
+    }
+
+    fn patch_dim(&self) -> usize {
+        self.dataset.patch_dim()
+    }
+
+    fn sample_patch(&self, index: usize, rng: &mut dyn RngCore) -> Patch {
+        let mut patch = self.dataset.sample_patch(index, rng);
+        self.augmentation.apply(&mut patch, self.width, rng);
+        patch
+    }
+}
+
+#[cfg(test)]
+mod test{
+    use super::*;
+    use rand::SeedableRng;
+    use rand::rngs::StdRng;
+
+    #[test]
+    fn spatial_transforms_move_pixels(){
+        let patch: Patch = (1..=9).map(|x| x as f32).collect();
+        assert_eq!(shifted(&patch, 3, 1, -1), vec![0.0, 0.0, 0.0, 2.0, 3.0, 0.0, 5.0, 6.0, 0.0]);
+
+        let mut flipped = patch.clone();
+        Augmentation::new().flip(1.0).apply(&mut flipped, 3, &mut StdRng::seed_from_u64(0));
+        assert_eq!(flipped, vec![3.0, 2.0, 1.0, 6.0, 5.0, 4.0, 9.0, 8.0, 7.0]);
+
+        let mut unchanged = patch.clone();
+        Augmentation::new().apply(&mut unchanged, 3, &mut StdRng::seed_from_u64(0));
+        assert_eq!(unchanged, patch);
+    }
+
+    #[test]
+    fn contrast_keeps_the_mean_and_noise_adds_variance(){
+        let mut rng = StdRng::seed_from_u64(1);
+        let patch: Patch = vec![0.0, 1.0, 2.0, 3.0];
+        let mut contrasted = patch.clone();
+        Augmentation::new().contrast(0.5).apply(&mut contrasted, 2, &mut rng);
+        assert!((contrasted.iter().sum::<f32>() - 6.0).abs() < 1e-5);
+        let spread = contrasted[3] - contrasted[0];
+        assert!((1.5..=4.5).contains(&spread));
+
+        let mut noisy = vec![0.0; 10000];
+        Augmentation::new().noise(0.5).apply(&mut noisy, 100, &mut rng);
+        let variance = noisy.iter().map(|x| x * x).sum::<f32>() / noisy.len() as f32;
+        assert!((variance - 0.25).abs() < 0.02);
+    }
+
+    #[test]
+    fn spatial_transforms_need_square_patches(){
+        struct Rows;
+        impl Dataset for Rows {
+            fn len(&self) -> usize { 1 }
+            fn patch_dim(&self) -> usize { 3 }
+            fn sample_patch(&self, _index: usize, _rng: &mut dyn RngCore) -> Patch { vec![1.0, 2.0, 3.0] }
+        }
+        assert!(Augmented::new(Rows, Augmentation::new().shift(1)).is_err());
+        assert!(Augmented::new(Rows, Augmentation::new().noise(0.1)).is_ok());
+    }
+}
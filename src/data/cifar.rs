@@ -0,0 +1,183 @@
+//! Reader for the binary version of CIFAR-10, 32x32 color photographs of ten classes. Every record of a batch file is a label
+//! byte followed by the red, green and blue planes of the image, each stored row by row.
+use std::fs::File;
+use std::io::{self, BufReader, Read};
+use std::path::Path;
+use rand::RngCore;
+use crate::data::dataset::Dataset;
+use crate::data::patch::Patch;
+use crate::data::sampler::{ImageSampler, PatchSampler};
+use crate::profile_scope;
+
+pub const CIFAR_WIDTH: usize = 32;
+const PLANE: usize = CIFAR_WIDTH * CIFAR_WIDTH;
+const RECORD: usize = 1 + 3 * PLANE;
+
+/// Names of the classes, indexed by label.
+pub const CLASSES: [&str; 10] = ["airplane", "automobile", "bird", "cat", "deer", "dog", "frog", "horse", "ship", "truck"];
+
+/// Images read from CIFAR-10 batch files, stored plane by plane and image after image.
+#[derive(Debug, Clone, PartialEq)]
+pub struct CifarImages {
+    pub labels: Vec<u8>,
+    pub pixels: Vec<u8>,
+}
+
+/// How the color channels end up in the patches.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Color {
+    /// Converts the images to their luma, so the patches have the same size as for the grayscale datasets.
+    Grayscale,
+    /// Keeps the red, green and blue values as separate dimensions, one channel after another.
+    Rgb,
+}
+
+impl Color {
+    pub fn channels(&self) -> usize {
+        match self {
+            Color::Grayscale => 1,
+            Color::Rgb => 3,
+        }
+    }
+}
+
+pub fn read_cifar_batch<P: AsRef<Path>>(path: P) -> io::Result<CifarImages> {
+    profile_scope!("read_cifar_batch");
+    read_cifar_batch_from(&mut BufReader::new(File::open(path)?))
+}
+
+pub fn read_cifar_batch_from<R: Read>(reader: &mut R) -> io::Result<CifarImages> {
+    let mut bytes = Vec::new();
+    reader.read_to_end(&mut bytes)?;
+    if bytes.is_empty() || bytes.len() % RECORD != 0 {
+        return Err(io::Error::new(io::ErrorKind::InvalidData, format!("{} bytes is not a whole number of CIFAR-10 records", bytes.len())));
+    }
+    let count = bytes.len() / RECORD;
+    let mut images = CifarImages { labels: Vec::with_capacity(count), pixels: Vec::with_capacity(count * 3 * PLANE) };
+    for record in bytes.chunks(RECORD) {
+        if record[0] as usize >= CLASSES.len() {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, format!("label {} is not a CIFAR-10 class", record[0])));
+        }
+        images.labels.push(record[0]);
+        images.pixels.extend_from_slice(&record[1..]);
+    }
+    Ok(images)
+}
+
+/// Converts planar RGB images to grayscale with the ITU-R BT.601 luma weights.
+fn grayscale(pixels: &[u8]) -> Vec<u8> {
+    pixels.chunks(3 * PLANE).flat_map(|image| {
+        (0..PLANE).map(move |pixel| {
+            let luma = 0.299 * image[pixel] as f32 + 0.587 * image[PLANE + pixel] as f32 + 0.114 * image[2 * PLANE + pixel] as f32;
+            luma.round() as u8
+        })
+    }).collect()
+}
+
+/// Labelled CIFAR-10 images, sampled as square patches of either the grayscale image or all three color channels.
+pub struct CifarDataset {
+    pixels: Vec<u8>,
+    labels: Vec<u8>,
+    color: Color,
+    sampler: ImageSampler,
+}
+
+impl CifarDataset {
+    /// Loads and concatenates the given batch files.
+    pub fn from_files<P: AsRef<Path>>(paths: &[P], color: Color, patch_width: usize) -> io::Result<CifarDataset> {
+        let mut images = CifarImages { labels: Vec::new(), pixels: Vec::new() };
+        for path in paths {
+            let batch = read_cifar_batch(path)?;
+            images.labels.extend(batch.labels);
+            images.pixels.extend(batch.pixels);
+        }
+        CifarDataset::new(images, color, patch_width)
+    }
+
+    pub fn new(images: CifarImages, color: Color, patch_width: usize) -> io::Result<CifarDataset> {
+        if images.pixels.len() != images.labels.len() * 3 * PLANE {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, format!("{} bytes of pixels for {} images", images.pixels.len(), images.labels.len())));
+        }
+        let sampler = ImageSampler::new(patch_width, CIFAR_WIDTH, CIFAR_WIDTH)?;
+        let pixels = match color {
+            Color::Grayscale => grayscale(&images.pixels),
+            Color::Rgb => images.pixels,
+        };
+        Ok(CifarDataset { pixels, labels: images.labels, color, sampler })
+    }
+
+    /// Loads the five training batches from a directory holding the original file names, such as `cifar-10-batches-bin`.
+    pub fn training<P: AsRef<Path>>(dir: P, color: Color, patch_width: usize) -> io::Result<CifarDataset> {
+        let dir = dir.as_ref();
+        let paths: Vec<_> = (1..=5).map(|batch| dir.join(format!("data_batch_{}.bin", batch))).collect();
+        CifarDataset::from_files(&paths, color, patch_width)
+    }
+
+    /// Loads the test batch, which training never samples from.
+    pub fn test<P: AsRef<Path>>(dir: P, color: Color, patch_width: usize) -> io::Result<CifarDataset> {
+        CifarDataset::from_files(&[dir.as_ref().join("test_batch.bin")], color, patch_width)
+    }
+
+    pub fn labels(&self) -> &[u8] {
+        &self.labels
+    }
+
+    pub fn color(&self) -> Color {
+        self.color
+    }
+
+    /// Changes how patches are placed within the images, see `ImageSampler::set`.
+    pub fn set_sampler(&mut self, sampler: PatchSampler) {
+        self.sampler.set(sampler);
+    }
+
+    pub fn patch_width(&self) -> usize {
+        self.sampler.patch_width()
+    }
+}
+
+impl Dataset for CifarDataset {
+    fn len(&self) -> usize {
+        self.labels.len()
+    }
+
+    fn patch_dim(&self) -> usize {
+        self.sampler.patch_size() * self.color.channels()
+    }
+
+    fn sample_patch(&self, index: usize, rng: &mut dyn RngCore) -> Patch {
+        let size = self.color.channels() * PLANE;
+        let image = &self.pixels[(index % self.len()) * size..][..size];
+        self.sampler.sample_channels(image, self.color.channels(), 1.0 / 256.0, rng)
+    }
+}
+
+#[cfg(test)]
+mod test{
+    use super::*;
+
+    fn record(label: u8, rgb: [u8; 3]) -> Vec<u8> {
+        let mut record = vec![label];
+        for value in rgb.iter() {
+            record.extend_from_slice(&[*value; PLANE]);
+        }
+        record
+    }
+
+    #[test]
+    fn read_batches_and_convert_to_grayscale(){
+        let bytes = [record(3, [255, 0, 0]), record(9, [0, 0, 255])].concat();
+        let images = read_cifar_batch_from(&mut bytes.as_slice()).unwrap();
+        assert_eq!(images.labels, vec![3, 9]);
+        assert!(read_cifar_batch_from(&mut &bytes[1..]).is_err());
+
+        let grayscale = CifarDataset::new(images.clone(), Color::Grayscale, 5).unwrap();
+        assert_eq!(grayscale.patch_dim(), 25);
+        assert_eq!(grayscale.sample_patch(1, &mut rand::thread_rng()), vec![29.0 / 256.0; 25]);
+
+        let rgb = CifarDataset::new(images, Color::Rgb, 2).unwrap();
+        assert_eq!(rgb.patch_dim(), 12);
+        assert_eq!(rgb.sample_patch(0, &mut rand::thread_rng()), [vec![255.0 / 256.0; 4], vec![0.0; 8]].concat());
+        assert!(CifarDataset::new(CifarImages { labels: vec![0], pixels: vec![0; 3 * PLANE] }, Color::Rgb, 33).is_err());
+    }
+}
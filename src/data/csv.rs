@@ -0,0 +1,100 @@
+//! Loader for tabular data stored as comma separated floats, one training vector per row.
+//! This lets the learning rules be used for dimensionality reduction on data which are not images.
+use std::fs::File;
+use std::io::{self, BufRead, BufReader};
+use std::path::Path;
+use rand::RngCore;
+use crate::data::dataset::Dataset;
+use crate::data::patch::Patch;
+
+pub struct CsvData {
+    rows: Vec<Patch>,
+    columns: usize,
+}
+
+impl CsvData {
+    /// Reads a CSV file where every row holds the same number of floats. The first line is skipped if `has_header` is set.
+    pub fn from_path<P: AsRef<Path>>(path: P, has_header: bool) -> io::Result<CsvData> {
+        CsvData::from_reader(BufReader::new(File::open(path)?), has_header)
+    }
+
+    pub fn from_reader<R: BufRead>(reader: R, has_header: bool) -> io::Result<CsvData> {
+        let mut rows: Vec<Patch> = Vec::new();
+
+        for (number, line) in reader.lines().enumerate().skip(has_header as usize) {
+            let line = line?;
+            if line.trim().is_empty() {
+                continue;
+            }
+
+            let row = line.split(',')
+                .map(|value| value.trim().parse::<f32>())
+                .collect::<Result<Patch, _>>()
+                .map_err(|error| invalid_data(format!("line {}: {}", number + 1, error)))?;
+
+            if let Some(first) = rows.first() {
+                if row.len() != first.len() {
+                    return Err(invalid_data(format!("line {}: expected {} columns, found {}", number + 1, first.len(), row.len())));
+                }
+            }
+            rows.push(row);
+        }
+
+        CsvData::from_rows(rows)
+    }
+
+    /// Takes rows which are already in memory, e.g. handed over from another language. Every row must have the same length.
+    pub fn from_rows(rows: Vec<Patch>) -> io::Result<CsvData> {
+        let columns = match rows.first() {
+            Some(first) if !first.is_empty() => first.len(),
+            _ => return Err(invalid_data("no rows of data found".to_string())),
+        };
+        if let Some(row) = rows.iter().position(|row| row.len() != columns) {
+            return Err(invalid_data(format!("row {}: expected {} columns, found {}", row + 1, columns, rows[row].len())));
+        }
+        Ok(CsvData { rows, columns })
+    }
+
+    pub fn rows(&self) -> &[Patch] {
+        &self.rows
+    }
+}
+
+impl Dataset for CsvData {
+    fn len(&self) -> usize {
+        self.rows.len()
+    }
+
+    fn patch_dim(&self) -> usize {
+        self.columns
+    }
+
+    /// Every row is used as a whole, so no randomness is involved.
+    fn sample_patch(&self, index: usize, _rng: &mut dyn RngCore) -> Patch {
+        self.rows[index].clone()
+    }
+}
+
+fn invalid_data(message: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+#[cfg(test)]
+mod test{
+    use super::*;
+
+    #[test]
+    fn parse_rows_with_header(){
+        let data = CsvData::from_reader("a,b,c\n1,2,3\n\n-0.5, 1e2 ,0\n".as_bytes(), true).unwrap();
+        assert_eq!(data.len(), 2);
+        assert_eq!(data.patch_dim(), 3);
+        assert_eq!(data.rows()[1], vec![-0.5, 100.0, 0.0]);
+    }
+
+    #[test]
+    fn reject_ragged_and_invalid_rows(){
+        assert!(CsvData::from_reader("1,2\n3\n".as_bytes(), false).is_err());
+        assert!(CsvData::from_reader("1,x\n".as_bytes(), false).is_err());
+        assert!(CsvData::from_reader("a,b\n".as_bytes(), true).is_err());
+    }
+}
@@ -0,0 +1,44 @@
+//! Common interface for the image corpora patches can be sampled from.
+use std::sync::Arc;
+use rand::RngCore;
+use crate::data::patch::Patch;
+
+/// A collection of samples which training patches can be drawn from.
+pub trait Dataset: Send + Sync {
+    /// Number of samples in the dataset.
+    fn len(&self) -> usize;
+
+    fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Number of values in every patch sampled from the dataset.
+    fn patch_dim(&self) -> usize;
+
+    /// Samples a patch from the sample at `index`, using `rng` for any random choices such as the patch position.
+    fn sample_patch(&self, index: usize, rng: &mut dyn RngCore) -> Patch;
+
+    /// Samples `length` patches from consecutive samples starting at `start`, wrapping around at the end of the dataset.
+    fn sample_patches(&self, start: usize, length: usize, rng: &mut dyn RngCore) -> Vec<Patch> {
+        (start..start + length).map(|index| self.sample_patch(index % self.len(), rng)).collect()
+    }
+}
+
+// A shared dataset, e.g. one chosen at run time as `Arc<dyn Dataset>` or used by several networks of a sweep.
+impl<D: Dataset + ?Sized> Dataset for Arc<D> {
+    fn len(&self) -> usize {
+        (**self).len()
+    }
+
+    fn patch_dim(&self) -> usize {
+        (**self).patch_dim()
+    }
+
+    fn sample_patch(&self, index: usize, rng: &mut dyn RngCore) -> Patch {
+        (**self).sample_patch(index, rng)
+    }
+
+    fn sample_patches(&self, start: usize, length: usize, rng: &mut dyn RngCore) -> Vec<Patch> {
+        (**self).sample_patches(start, length, rng)
+    }
+}
@@ -0,0 +1,130 @@
+//! Rejection of patches which carry (almost) no learning signal, applied on top of any dataset. Flat patches, such as the
+//! empty background around the digits, barely move the weights under Oja's rule but still cost a training step, so the
+//! filter replaces them with patches of the following samples and counts how many it rejected.
+use std::sync::Arc;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use rand::RngCore;
+use crate::data::dataset::Dataset;
+use crate::data::patch::Patch;
+use crate::data::sampler::variance;
+
+/// How many samples are tried for a single patch before the filter gives up and keeps the one with the highest variance.
+const MAX_ATTEMPTS: usize = 64;
+
+/// Number of patches a `VarianceFilter` handed out and rejected, shared with every clone of the handle.
+#[derive(Debug, Default)]
+pub struct FilterCounts {
+    accepted: AtomicUsize,
+    rejected: AtomicUsize,
+}
+
+impl FilterCounts {
+    /// Number of patches handed to training.
+    pub fn accepted(&self) -> usize {
+        self.accepted.load(Ordering::Relaxed)
+    }
+
+    /// Number of patches dropped because their variance was below the threshold.
+    pub fn rejected(&self) -> usize {
+        self.rejected.load(Ordering::Relaxed)
+    }
+
+    /// Fraction of the sampled patches which were rejected, zero before anything was sampled.
+    pub fn rejection_rate(&self) -> f64 {
+        let (accepted, rejected) = (self.accepted(), self.rejected());
+        if accepted + rejected == 0 {
+            return 0.0;
+        }
+        rejected as f64 / (accepted + rejected) as f64
+    }
+}
+
+/// A dataset which drops patches whose variance is below a threshold.
+pub struct VarianceFilter<D: Dataset> {
+    dataset: D,
+    threshold: f32,
+    counts: Arc<FilterCounts>,
+}
+
+impl<D: Dataset> VarianceFilter<D> {
+    pub fn new(dataset: D, threshold: f32) -> VarianceFilter<D> {
+        assert!(threshold >= 0.0, "the variance threshold can not be negative");
+        VarianceFilter { dataset, threshold, counts: Arc::new(FilterCounts::default()) }
+    }
+
+    pub fn threshold(&self) -> f32 {
+        self.threshold
+    }
+
+    /// The counters of the filter, which keep counting while the dataset is trained on, e.g. after it was moved into a network.
+    pub fn counts(&self) -> Arc<FilterCounts> {
+        Arc::clone(&self.counts)
+    }
+
+    pub fn inner(&self) -> &D {
+        &self.dataset
+    }
+
+    pub fn into_inner(self) -> D {
+        self.dataset
+    }
+}
+
+impl<D: Dataset> Dataset for VarianceFilter<D> {
+    fn len(&self) -> usize {
+        self.dataset.len()
+    }
+
+    fn patch_dim(&self) -> usize {
+        self.dataset.patch_dim()
+    }
+
+    // Moves on to the following samples until a patch passes, so a dataset of flat samples still ends up being trained on.
+    fn sample_patch(&self, index: usize, rng: &mut dyn RngCore) -> Patch {
+        let mut best: Option<(f32, Patch)> = None;
+        for attempt in 0..MAX_ATTEMPTS {
+            let patch = self.dataset.sample_patch((index + attempt) % self.len(), rng);
+            let variance = variance(&patch);
+            if variance >= self.threshold {
+                self.counts.accepted.fetch_add(1, Ordering::Relaxed);
+                return patch;
+            }
+            self.counts.rejected.fetch_add(1, Ordering::Relaxed);
+            if !matches!(&best, Some((best, _)) if *best >= variance) {
+                best = Some((variance, patch));
+            }
+        }
+        self.counts.accepted.fetch_add(1, Ordering::Relaxed);
+        best.expect("at least one sample is tried").1
+    }
+}
+
+#[cfg(test)]
+mod test{
+    use super::*;
+
+    struct Alternating;
+    impl Dataset for Alternating {
+        fn len(&self) -> usize { 4 }
+        fn patch_dim(&self) -> usize { 2 }
+        fn sample_patch(&self, index: usize, _rng: &mut dyn RngCore) -> Patch { vec![0.0, index as f32 % 2.0] }
+    }
+
+    #[test]
+    fn flat_patches_are_rejected_and_counted(){
+        let filter = VarianceFilter::new(Alternating, 0.1);
+        let counts = filter.counts();
+        let mut rng = rand::thread_rng();
+        let patches = filter.sample_patches(0, 4, &mut rng);
+        assert!(patches.iter().all(|patch| patch == &vec![0.0, 1.0]));
+        assert_eq!((counts.accepted(), counts.rejected()), (4, 2));
+        assert!((counts.rejection_rate() - 1.0 / 3.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn the_best_patch_is_kept_when_every_sample_is_flat(){
+        let filter = VarianceFilter::new(Alternating, 10.0);
+        assert_eq!(filter.sample_patch(0, &mut rand::thread_rng()), vec![0.0, 1.0]);
+        assert_eq!(filter.counts().rejected(), MAX_ATTEMPTS);
+    }
+}
@@ -0,0 +1,303 @@
+//! Reader for the IDX file format used by MNIST, Fashion-MNIST and EMNIST, and datasets built on top of it.
+//! Files can be read as distributed, gzipped, without decompressing them first.
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, Read};
+use std::path::Path;
+use std::str::FromStr;
+use flate2::read::GzDecoder;
+use rand::RngCore;
+use crate::data::dataset::Dataset;
+use crate::data::patch::Patch;
+use crate::data::sampler::{ImageSampler, PatchSampler};
+use crate::profile_scope;
+
+pub(crate) const IMAGES_MAGIC: u32 = 0x0000_0803;
+const LABELS_MAGIC: u32 = 0x0000_0801;
+const GZIP_MAGIC: [u8; 2] = [0x1f, 0x8b];
+
+/// Images read from an IDX file, stored row by row and image after image.
+#[derive(Debug, Clone, PartialEq)]
+pub struct IdxImages {
+    pub count: usize,
+    pub rows: usize,
+    pub columns: usize,
+    pub pixels: Vec<u8>,
+}
+
+/// Opens an IDX file, decompressing it on the fly if it is gzipped.
+/// When the file does not exist, the file of the same name with a `.gz` extension is opened instead.
+pub fn open_idx<P: AsRef<Path>>(path: P) -> io::Result<Box<dyn Read>> {
+    let path = path.as_ref();
+    let file = match File::open(path) {
+        Err(error) if error.kind() == io::ErrorKind::NotFound => {
+            let mut compressed = path.as_os_str().to_owned();
+            compressed.push(".gz");
+            File::open(compressed).map_err(|_| error)?
+        }
+        file => file?,
+    };
+    decompressed(BufReader::new(file))
+}
+
+/// Wraps the reader in a gzip decoder if its content starts with the gzip magic number.
+pub fn decompressed<R: BufRead + 'static>(mut reader: R) -> io::Result<Box<dyn Read>> {
+    if reader.fill_buf()?.starts_with(&GZIP_MAGIC) {
+        Ok(Box::new(GzDecoder::new(reader)))
+    } else {
+        Ok(Box::new(reader))
+    }
+}
+
+pub fn read_idx_images<P: AsRef<Path>>(path: P) -> io::Result<IdxImages> {
+    profile_scope!("read_idx_images");
+    read_idx_images_from(&mut open_idx(path)?)
+}
+
+pub fn read_idx_images_from<R: Read>(reader: &mut R) -> io::Result<IdxImages> {
+    if read_u32(reader)? != IMAGES_MAGIC {
+        return Err(io::Error::new(io::ErrorKind::InvalidData, "not an IDX image file"));
+    }
+    let count = read_u32(reader)? as usize;
+    let rows = read_u32(reader)? as usize;
+    let columns = read_u32(reader)? as usize;
+
+    let len = count.checked_mul(rows).and_then(|pixels| pixels.checked_mul(columns))
+        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, format!("{} images of {}x{} pixels overflow the address space", count, rows, columns)))?;
+    let pixels = read_bytes(reader, len)?;
+    Ok(IdxImages { count, rows, columns, pixels })
+}
+
+pub fn read_idx_labels<P: AsRef<Path>>(path: P) -> io::Result<Vec<u8>> {
+    read_idx_labels_from(&mut open_idx(path)?)
+}
+
+pub fn read_idx_labels_from<R: Read>(reader: &mut R) -> io::Result<Vec<u8>> {
+    if read_u32(reader)? != LABELS_MAGIC {
+        return Err(io::Error::new(io::ErrorKind::InvalidData, "not an IDX label file"));
+    }
+    let count = read_u32(reader)? as usize;
+
+    read_bytes(reader, count)
+}
+
+// Reads the `len` bytes the header announces. The buffer only grows with the bytes actually read, so a corrupt header fails
+// as a truncated file instead of allocating gigabytes first.
+fn read_bytes<R: Read>(reader: &mut R, len: usize) -> io::Result<Vec<u8>> {
+    let mut bytes = Vec::new();
+    reader.take(len as u64).read_to_end(&mut bytes)?;
+    if bytes.len() != len {
+        return Err(io::Error::new(io::ErrorKind::UnexpectedEof, format!("file is truncated, expected {} bytes but found {}", len, bytes.len())));
+    }
+    Ok(bytes)
+}
+
+/// IDX files store their header as big endian integers.
+fn read_u32<R: Read>(reader: &mut R) -> io::Result<u32> {
+    let mut buffer = [0u8; 4];
+    reader.read_exact(&mut buffer)?;
+    Ok(u32::from_be_bytes(buffer))
+}
+
+/// The splits EMNIST is distributed in, each with its own label set.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EmnistSplit {
+    ByClass,
+    ByMerge,
+    Balanced,
+    Letters,
+    Digits,
+    Mnist,
+}
+
+impl EmnistSplit {
+    fn name(&self) -> &'static str {
+        match self {
+            EmnistSplit::ByClass => "byclass",
+            EmnistSplit::ByMerge => "bymerge",
+            EmnistSplit::Balanced => "balanced",
+            EmnistSplit::Letters => "letters",
+            EmnistSplit::Digits => "digits",
+            EmnistSplit::Mnist => "mnist",
+        }
+    }
+
+    /// Number of distinct labels in the split.
+    pub fn classes(&self) -> usize {
+        match self {
+            EmnistSplit::ByClass => 62,
+            EmnistSplit::ByMerge | EmnistSplit::Balanced => 47,
+            EmnistSplit::Letters => 26,
+            EmnistSplit::Digits | EmnistSplit::Mnist => 10,
+        }
+    }
+}
+
+// Parses the name of a split as used in the EMNIST file names, e.g. from an experiment file.
+impl FromStr for EmnistSplit {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<EmnistSplit, String> {
+        [EmnistSplit::ByClass, EmnistSplit::ByMerge, EmnistSplit::Balanced, EmnistSplit::Letters, EmnistSplit::Digits, EmnistSplit::Mnist]
+            .iter()
+            .copied()
+            .find(|split| split.name() == value)
+            .ok_or_else(|| format!("`{}` is not an EMNIST split, expected byclass, bymerge, balanced, letters, digits or mnist", value))
+    }
+}
+
+/// Labelled grayscale images in the IDX format, such as Fashion-MNIST or EMNIST, sampled as square patches.
+pub struct IdxDataset {
+    images: IdxImages,
+    labels: Vec<u8>,
+    classes: usize,
+    sampler: ImageSampler,
+}
+
+impl IdxDataset {
+    /// Loads an image and label file pair. The images must be at least `patch_width` pixels in both directions.
+    pub fn from_files<P: AsRef<Path>>(images: P, labels: P, classes: usize, patch_width: usize) -> io::Result<IdxDataset> {
+        IdxDataset::new(read_idx_images(images)?, read_idx_labels(labels)?, classes, patch_width)
+    }
+
+    pub fn new(images: IdxImages, labels: Vec<u8>, classes: usize, patch_width: usize) -> io::Result<IdxDataset> {
+        if images.count != labels.len() {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, format!("{} images but {} labels", images.count, labels.len())));
+        }
+        let sampler = ImageSampler::new(patch_width, images.rows, images.columns)?;
+        Ok(IdxDataset { images, labels, classes, sampler })
+    }
+
+    /// Loads the Fashion-MNIST training set from a directory holding the original file names.
+    pub fn fashion_mnist<P: AsRef<Path>>(dir: P, patch_width: usize) -> io::Result<IdxDataset> {
+        let dir = dir.as_ref();
+        IdxDataset::from_files(dir.join("train-images-idx3-ubyte"), dir.join("train-labels-idx1-ubyte"), 10, patch_width)
+    }
+
+    /// Loads the Fashion-MNIST test set, which training never samples from.
+    pub fn fashion_mnist_test<P: AsRef<Path>>(dir: P, patch_width: usize) -> io::Result<IdxDataset> {
+        let dir = dir.as_ref();
+        IdxDataset::from_files(dir.join("t10k-images-idx3-ubyte"), dir.join("t10k-labels-idx1-ubyte"), 10, patch_width)
+    }
+
+    /// Loads the training set of an EMNIST split from a directory holding the original file names.
+    /// EMNIST images are stored transposed, so they are flipped back to the same orientation as MNIST.
+    pub fn emnist<P: AsRef<Path>>(dir: P, split: EmnistSplit, patch_width: usize) -> io::Result<IdxDataset> {
+        IdxDataset::emnist_part(dir.as_ref(), split, "train", patch_width)
+    }
+
+    /// Loads the test set of an EMNIST split, which training never samples from.
+    pub fn emnist_test<P: AsRef<Path>>(dir: P, split: EmnistSplit, patch_width: usize) -> io::Result<IdxDataset> {
+        IdxDataset::emnist_part(dir.as_ref(), split, "test", patch_width)
+    }
+
+    fn emnist_part(dir: &Path, split: EmnistSplit, part: &str, patch_width: usize) -> io::Result<IdxDataset> {
+        let mut images = read_idx_images(dir.join(format!("emnist-{}-{}-images-idx3-ubyte", split.name(), part)))?;
+        let labels = read_idx_labels(dir.join(format!("emnist-{}-{}-labels-idx1-ubyte", split.name(), part)))?;
+        transpose_images(&mut images);
+        IdxDataset::new(images, labels, split.classes(), patch_width)
+    }
+
+    pub fn labels(&self) -> &[u8] {
+        &self.labels
+    }
+
+    pub fn classes(&self) -> usize {
+        self.classes
+    }
+
+    /// Changes how patches are placed within the images, see `ImageSampler::set`.
+    pub fn set_sampler(&mut self, sampler: PatchSampler) {
+        self.sampler.set(sampler);
+    }
+
+    pub fn patch_width(&self) -> usize {
+        self.sampler.patch_width()
+    }
+}
+
+impl Dataset for IdxDataset {
+    fn len(&self) -> usize {
+        self.images.count
+    }
+
+    fn patch_dim(&self) -> usize {
+        self.sampler.patch_size()
+    }
+
+    fn sample_patch(&self, index: usize, rng: &mut dyn RngCore) -> Patch {
+        let size = self.images.rows * self.images.columns;
+        let image = &self.images.pixels[(index % self.images.count) * size..][..size];
+        self.sampler.sample(image, 1.0 / 256.0, rng)
+    }
+}
+
+fn transpose_images(images: &mut IdxImages) {
+    let (rows, columns) = (images.rows, images.columns);
+    for image in images.pixels.chunks_mut(rows * columns) {
+        let original = image.to_vec();
+        for row in 0..rows {
+            for column in 0..columns {
+                image[column * rows + row] = original[row * columns + column];
+            }
+        }
+    }
+    images.rows = columns;
+    images.columns = rows;
+}
+
+#[cfg(test)]
+mod test{
+    use super::*;
+
+    fn idx_bytes(header: &[u32], data: &[u8]) -> Vec<u8> {
+        let mut bytes: Vec<u8> = header.iter().flat_map(|v| v.to_be_bytes().to_vec()).collect();
+        bytes.extend_from_slice(data);
+        bytes
+    }
+
+    #[test]
+    fn read_images_and_labels(){
+        let pixels: Vec<u8> = (0..18).collect();
+        let images = read_idx_images_from(&mut idx_bytes(&[IMAGES_MAGIC, 2, 3, 3], &pixels).as_slice()).unwrap();
+        assert_eq!(images, IdxImages { count: 2, rows: 3, columns: 3, pixels });
+
+        let labels = read_idx_labels_from(&mut idx_bytes(&[LABELS_MAGIC, 2], &[7, 1]).as_slice()).unwrap();
+        assert_eq!(labels, vec![7, 1]);
+
+        assert!(read_idx_labels_from(&mut idx_bytes(&[IMAGES_MAGIC, 0], &[]).as_slice()).is_err());
+        let overflowing = read_idx_images_from(&mut idx_bytes(&[IMAGES_MAGIC, u32::MAX, u32::MAX, u32::MAX], &[0; 4]).as_slice());
+        assert_eq!(overflowing.unwrap_err().kind(), io::ErrorKind::InvalidData);
+        let truncated = read_idx_labels_from(&mut idx_bytes(&[LABELS_MAGIC, u32::MAX], &[1, 2]).as_slice());
+        assert_eq!(truncated.unwrap_err().kind(), io::ErrorKind::UnexpectedEof);
+    }
+
+    #[test]
+    fn gzipped_files_are_decompressed(){
+        use std::io::{Cursor, Write};
+        use flate2::Compression;
+        use flate2::write::GzEncoder;
+
+        let bytes = idx_bytes(&[LABELS_MAGIC, 3], &[4, 0, 9]);
+        let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+        encoder.write_all(&bytes).unwrap();
+        let compressed = encoder.finish().unwrap();
+
+        let labels = read_idx_labels_from(&mut decompressed(Cursor::new(compressed)).unwrap()).unwrap();
+        assert_eq!(labels, vec![4, 0, 9]);
+        let labels = read_idx_labels_from(&mut decompressed(Cursor::new(bytes)).unwrap()).unwrap();
+        assert_eq!(labels, vec![4, 0, 9]);
+    }
+
+    #[test]
+    fn transpose_and_sample_patches(){
+        let mut images = IdxImages { count: 1, rows: 2, columns: 3, pixels: vec![0, 1, 2, 3, 4, 5] };
+        transpose_images(&mut images);
+        assert_eq!((images.rows, images.columns), (3, 2));
+        assert_eq!(images.pixels, vec![0, 3, 1, 4, 2, 5]);
+
+        let dataset = IdxDataset::new(images, vec![0], 10, 2).unwrap();
+        let patch = dataset.sample_patch(0, &mut rand::thread_rng());
+        assert_eq!(patch.len(), dataset.patch_dim());
+        assert!(IdxDataset::new(IdxImages { count: 1, rows: 2, columns: 2, pixels: vec![0; 4] }, vec![0], 10, 3).is_err());
+    }
+}
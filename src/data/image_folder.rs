@@ -0,0 +1,134 @@
+//! Loader for a directory of PNG and JPEG images, such as a photo collection, so the network can be trained on natural images
+//! instead of digits. Every image is converted to grayscale and keeps its own size. Requires the `images` feature.
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use rand::RngCore;
+use tracing::{info, warn};
+use crate::data::dataset::Dataset;
+use crate::data::patch::Patch;
+use crate::data::sampler::{ImageSampler, PatchSampler};
+
+/// File extensions which are loaded, compared case insensitively.
+pub const EXTENSIONS: [&str; 3] = ["png", "jpg", "jpeg"];
+
+/// A grayscale image, stored row by row.
+#[derive(Debug, Clone, PartialEq)]
+pub struct GrayscaleImage {
+    pub rows: usize,
+    pub columns: usize,
+    pub pixels: Vec<u8>,
+}
+
+/// The images of a directory, sampled as square patches.
+pub struct ImageFolder {
+    images: Vec<GrayscaleImage>,
+    sampler: ImageSampler,
+}
+
+impl ImageFolder {
+    /// Loads every image with one of the `EXTENSIONS` directly inside `dir`, in the order of their file names.
+    pub fn open<P: AsRef<Path>>(dir: P, patch_width: usize) -> io::Result<ImageFolder> {
+        let mut images = Vec::new();
+        for path in image_paths(dir)? {
+            let image = image::open(&path)
+                .map_err(|error| io::Error::new(io::ErrorKind::InvalidData, format!("unable to decode {}: {}", path.display(), error)))?
+                .to_luma8();
+            let (columns, rows) = image.dimensions();
+            images.push(GrayscaleImage { rows: rows as usize, columns: columns as usize, pixels: image.into_raw() });
+        }
+        info!(images = images.len(), "loaded image folder");
+        ImageFolder::new(images, patch_width)
+    }
+
+    /// Images smaller than a patch are left out. At least one image has to remain.
+    pub fn new(images: Vec<GrayscaleImage>, patch_width: usize) -> io::Result<ImageFolder> {
+        if patch_width == 0 {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, "the patch width has to be at least one pixel"));
+        }
+        let count = images.len();
+        let images: Vec<GrayscaleImage> = images.into_iter().filter(|image| patch_width <= image.rows.min(image.columns)).collect();
+        if images.len() < count {
+            warn!(skipped = count - images.len(), patch_width, "skipped images smaller than a patch");
+        }
+        if images.is_empty() {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, format!("no image fits a patch of width {}", patch_width)));
+        }
+        // Every image is at least as large as the smallest row and column count, so a sampler fitting those fits every image.
+        let rows = images.iter().map(|image| image.rows).min().unwrap_or(0);
+        let columns = images.iter().map(|image| image.columns).min().unwrap_or(0);
+        let sampler = ImageSampler::new(patch_width, rows, columns)?;
+        Ok(ImageFolder { images, sampler })
+    }
+
+    pub fn images(&self) -> &[GrayscaleImage] {
+        &self.images
+    }
+
+    /// Changes how patches are placed within the images, see `ImageSampler::set`.
+    pub fn set_sampler(&mut self, sampler: PatchSampler) {
+        self.sampler.set(sampler);
+    }
+
+    pub fn patch_width(&self) -> usize {
+        self.sampler.patch_width()
+    }
+}
+
+impl Dataset for ImageFolder {
+    fn len(&self) -> usize {
+        self.images.len()
+    }
+
+    fn patch_dim(&self) -> usize {
+        self.sampler.patch_size()
+    }
+
+    fn sample_patch(&self, index: usize, rng: &mut dyn RngCore) -> Patch {
+        let image = &self.images[index % self.images.len()];
+        self.sampler.sampler().sample(&image.pixels, image.rows, image.columns, 1.0 / 256.0, rng)
+    }
+}
+
+/// Paths of the images directly inside `dir`, sorted by name.
+pub fn image_paths<P: AsRef<Path>>(dir: P) -> io::Result<Vec<PathBuf>> {
+    let mut paths = Vec::new();
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+        let extension = path.extension().and_then(|extension| extension.to_str()).map(|extension| extension.to_ascii_lowercase());
+        if path.is_file() && extension.is_some_and(|extension| EXTENSIONS.contains(&extension.as_str())) {
+            paths.push(path);
+        }
+    }
+    paths.sort();
+    Ok(paths)
+}
+
+#[cfg(test)]
+mod test{
+    use super::*;
+
+    #[test]
+    fn only_images_are_listed(){
+        let dir = std::env::temp_dir().join(format!("rust_ml_image_folder_test_{}", std::process::id()));
+        fs::create_dir_all(dir.join("nested.png")).unwrap();
+        for name in ["b.JPG", "a.png", "notes.txt", "c.jpeg"] {
+            fs::write(dir.join(name), b"").unwrap();
+        }
+        let names: Vec<String> = image_paths(&dir).unwrap().iter().map(|path| path.file_name().unwrap().to_string_lossy().into_owned()).collect();
+        fs::remove_dir_all(&dir).unwrap();
+        assert_eq!(names, vec!["a.png", "b.JPG", "c.jpeg"]);
+    }
+
+    #[test]
+    fn small_images_are_skipped_and_patches_sampled(){
+        let images = vec![
+            GrayscaleImage { rows: 2, columns: 2, pixels: vec![0; 4] },
+            GrayscaleImage { rows: 3, columns: 4, pixels: vec![128; 12] },
+        ];
+        let folder = ImageFolder::new(images.clone(), 3).unwrap();
+        assert_eq!(folder.len(), 1);
+        assert_eq!(folder.sample_patch(0, &mut rand::thread_rng()), vec![0.5; 9]);
+        assert!(ImageFolder::new(images, 5).is_err());
+    }
+}
@@ -0,0 +1,110 @@
+//! Datasets backed by memory mapped IDX files. Patches are extracted straight from the mapped file on demand,
+//! so only the pages of the images that are actually sampled are loaded, and datasets larger than the available memory can be used.
+//! Mapped files have to be uncompressed.
+use std::fs::File;
+use std::io;
+use std::path::Path;
+use memmap2::Mmap;
+use rand::RngCore;
+use crate::data::dataset::Dataset;
+use crate::data::idx::IMAGES_MAGIC;
+use crate::data::patch::Patch;
+use crate::data::sampler::{ImageSampler, PatchSampler};
+
+/// Size of the header of an IDX image file: the magic number followed by the number of images, rows and columns.
+const HEADER_SIZE: usize = 16;
+
+/// Images of an IDX file which is mapped into memory instead of read, sampled as square patches.
+pub struct MappedIdxDataset {
+    map: Mmap,
+    count: usize,
+    rows: usize,
+    columns: usize,
+    sampler: ImageSampler,
+}
+
+impl MappedIdxDataset {
+    /// Maps an uncompressed IDX image file. The file must not be modified while the dataset is in use.
+    pub fn open<P: AsRef<Path>>(path: P, patch_width: usize) -> io::Result<MappedIdxDataset> {
+        let file = File::open(path)?;
+        // Safe as long as no other process truncates or writes to the file, the dataset itself only ever reads from the map.
+        let map = unsafe { Mmap::map(&file)? };
+        if map.len() < HEADER_SIZE || header_value(&map, 0) != IMAGES_MAGIC {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, "not an uncompressed IDX image file"));
+        }
+
+        let count = header_value(&map, 1) as usize;
+        let rows = header_value(&map, 2) as usize;
+        let columns = header_value(&map, 3) as usize;
+        if map.len() < HEADER_SIZE + count * rows * columns {
+            return Err(io::Error::new(io::ErrorKind::UnexpectedEof, format!("file is too short for {} images of {}x{} pixels", count, rows, columns)));
+        }
+        let sampler = ImageSampler::new(patch_width, rows, columns)?;
+        Ok(MappedIdxDataset { map, count, rows, columns, sampler })
+    }
+
+    /// Maps the MNIST training images from a directory holding the original, extracted, file names.
+    pub fn mnist<P: AsRef<Path>>(dir: P, patch_width: usize) -> io::Result<MappedIdxDataset> {
+        MappedIdxDataset::open(dir.as_ref().join("train-images-idx3-ubyte"), patch_width)
+    }
+
+    /// Changes how patches are placed within the images, see `ImageSampler::set`.
+    pub fn set_sampler(&mut self, sampler: PatchSampler) {
+        self.sampler.set(sampler);
+    }
+
+    pub fn patch_width(&self) -> usize {
+        self.sampler.patch_width()
+    }
+}
+
+impl Dataset for MappedIdxDataset {
+    fn len(&self) -> usize {
+        self.count
+    }
+
+    fn patch_dim(&self) -> usize {
+        self.sampler.patch_size()
+    }
+
+    fn sample_patch(&self, index: usize, rng: &mut dyn RngCore) -> Patch {
+        let image_size = self.rows * self.columns;
+        let image = &self.map[HEADER_SIZE + (index % self.count) * image_size..][..image_size];
+        self.sampler.sample(image, 1.0 / 256.0, rng)
+    }
+}
+
+/// The header values of IDX files are stored as big endian integers.
+fn header_value(map: &[u8], position: usize) -> u32 {
+    let bytes = &map[position * 4..][..4];
+    u32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]])
+}
+
+#[cfg(test)]
+mod test{
+    use super::*;
+    use rand::SeedableRng;
+    use rand::rngs::StdRng;
+    use crate::data::idx::{IdxDataset, IdxImages};
+
+    #[test]
+    fn mapped_patches_match_the_loaded_dataset(){
+        let pixels: Vec<u8> = (0..2 * 4 * 4).map(|i| (i * 7 % 256) as u8).collect();
+        let mut bytes: Vec<u8> = [IMAGES_MAGIC, 2, 4, 4].iter().flat_map(|v| v.to_be_bytes().to_vec()).collect();
+        bytes.extend_from_slice(&pixels);
+        let path = std::env::temp_dir().join(format!("rust_ml_mmap_test_{}", std::process::id()));
+        std::fs::write(&path, &bytes).unwrap();
+
+        let mapped = MappedIdxDataset::open(&path, 2).unwrap();
+        let loaded = IdxDataset::new(IdxImages { count: 2, rows: 4, columns: 4, pixels }, vec![0, 1], 2, 2).unwrap();
+        assert_eq!(mapped.len(), 2);
+        for index in 0..4 {
+            let patch = mapped.sample_patch(index, &mut StdRng::seed_from_u64(index as u64));
+            assert_eq!(patch, loaded.sample_patch(index, &mut StdRng::seed_from_u64(index as u64)));
+        }
+
+        std::fs::write(&path, &bytes[..HEADER_SIZE + 8]).unwrap();
+        assert!(MappedIdxDataset::open(&path, 2).is_err());
+        std::fs::remove_file(&path).unwrap();
+    }
+}
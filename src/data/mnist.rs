@@ -1,67 +1,312 @@
-use mnist::{Mnist, MnistBuilder};
-use ndarray::{Array3, s};
-use rand::Rng;
-use crate::utils::constants::PATCH_SIZE;
+use ndarray::Array3;
+use std::sync::Arc;
+use rand::{Rng, RngCore, SeedableRng};
+use rand::rngs::StdRng;
+use rand::seq::SliceRandom;
+use crate::data::dataset::Dataset;
+use crate::data::patch::Patch;
+use crate::data::sampler::{ImageSampler, PatchSampler};
+use crate::data::whitening::{DEFAULT_EPSILON, Whitening};
+use crate::data::idx::read_idx_images;
+use crate::error::{Error, Result};
+use crate::utils::constants::IMAGE_WIDTH;
+use std::io;
+use std::path::{Path, PathBuf};
+#[cfg(feature = "download")]
+use std::fs::{self, File};
+#[cfg(feature = "download")]
+use std::io::Read;
+#[cfg(feature = "download")]
+use flate2::read::GzDecoder;
+
+/// Number of training images used, the remaining 10,000 are conventionally held out for validation.
+const TRAINING_SET_LENGTH: usize = 50_000;
 
 pub struct MnistData {
     section_size: usize,
-    training_data: Array3<f32>,
+    sampler: ImageSampler,
+    preprocessing: Option<Whitening>,
+    training_data: Arc<Array3<f32>>,
+    // Images this dataset samples from when it is part of a split, all images otherwise.
+    indices: Option<Arc<Vec<usize>>>,
+}
+
+/// Fractions of the images assigned to the training, validation and test sets. They are normalized, so they need not sum to one.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SplitRatios {
+    pub train: f32,
+    pub validation: f32,
+    pub test: f32,
+}
+
+impl SplitRatios {
+    pub fn new(train: f32, validation: f32, test: f32) -> SplitRatios {
+        SplitRatios { train, validation, test }
+    }
+}
+
+impl Default for SplitRatios {
+    fn default() -> SplitRatios {
+        SplitRatios::new(0.8, 0.1, 0.1)
+    }
+}
+
+/// Disjoint training, validation and test sets of the same images.
+pub struct Splits<D> {
+    pub train: D,
+    pub validation: D,
+    pub test: D,
+}
+
+/// Mirror hosting the standard MNIST files, since the original site no longer serves them reliably.
+#[cfg(feature = "download")]
+pub const MIRROR: &str = "https://ossci-datasets.s3.amazonaws.com/mnist/";
+
+/// The MNIST files with the MD5 checksum of their compressed download.
+#[cfg(feature = "download")]
+const FILES: [(&str, &str); 4] = [
+    ("train-images-idx3-ubyte", "f68b3c2dcbeaaa9fbdd348bbdeb94873"),
+    ("train-labels-idx1-ubyte", "d53e105ee54ea40749a09fcbcd1e9432"),
+    ("t10k-images-idx3-ubyte", "9fb629c4189551a2d022fa330f9573f3"),
+    ("t10k-labels-idx1-ubyte", "ec29112dd5afa0611ce80d1b7f02629c"),
+];
+
+/// Downloads the MNIST files into the cache directory, unless they are already there, and extracts them where `from_path` expects them.
+/// Every download is checked against its known checksum before it is extracted, so a corrupted or tampered file is never used.
+#[cfg(feature = "download")]
+pub fn download<P: AsRef<Path>>(cache_dir: P) -> io::Result<()> {
+    let cache_dir = cache_dir.as_ref();
+    fs::create_dir_all(cache_dir)?;
+
+    for (name, checksum) in FILES {
+        let path = cache_dir.join(name);
+        if path.exists() {
+            continue;
+        }
+
+        let url = format!("{}{}.gz", MIRROR, name);
+        let mut compressed = Vec::new();
+        ureq::get(&url).call()
+            .map_err(|error| io::Error::other(format!("unable to download {}: {}", url, error)))?
+            .into_reader()
+            .read_to_end(&mut compressed)?;
+
+        let digest = format!("{:x}", md5::compute(&compressed));
+        if digest != checksum {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, format!("checksum mismatch for {}: expected {}, got {}", url, checksum, digest)));
+        }
+
+        // Extracted into a temporary file first, so an interrupted download is not mistaken for a cached file on the next run.
+        let partial = cache_dir.join(format!("{}.part", name));
+        io::copy(&mut GzDecoder::new(compressed.as_slice()), &mut File::create(&partial)?)?;
+        fs::rename(partial, path)?;
+    }
+    Ok(())
 }
 
 impl MnistData {
-    pub fn new(section_size: usize) -> MnistData {
-        let Mnist {
-            trn_img,
-            ..
-        } = MnistBuilder::new()
-            .label_format_digit()
-            .training_set_length(50_000)
-            .finalize();
-
-        let train_data = Array3::from_shape_vec((50_000, 28, 28), trn_img)
+    // Loads MNIST from `data/`. With the `download` feature the files are downloaded there first if they are missing.
+    pub fn new(section_size: usize, patch_width: usize) -> Result<MnistData> {
+        #[cfg(feature = "download")]
+        download("data/")?;
+        MnistData::from_path(section_size, patch_width, "data/")
+    }
+
+    // Loads the MNIST IDX files from the given directory instead of the default `data/`.
+    // The images are read as distributed, gzipped, if the directory only holds `train-images-idx3-ubyte.gz`.
+    pub fn from_path(section_size: usize, patch_width: usize, base_path: &str) -> Result<MnistData> {
+        MnistData::from_file(section_size, patch_width, Path::new(base_path).join("train-images-idx3-ubyte"), TRAINING_SET_LENGTH)
+    }
+
+    // Loads MNIST from the given directory with every sample a whole flattened 28x28 digit instead of a patch, so the network
+    // learns "eigen-digits" whose receptive fields look like digit prototypes.
+    pub fn whole_images(section_size: usize, base_path: &str) -> Result<MnistData> {
+        MnistData::from_path(section_size, IMAGE_WIDTH, base_path)
+    }
+
+    // Loads the 10000 images of the MNIST test set from the given directory, which training never samples from.
+    pub fn test_set(patch_width: usize, base_path: &str) -> Result<MnistData> {
+        MnistData::from_file(1, patch_width, Path::new(base_path).join("t10k-images-idx3-ubyte"), usize::MAX)
+    }
+
+    // Fails with `Error::Io` if the images can not be read, and with `Error::InvalidConfig` for a patch width which does not fit them.
+    fn from_file(section_size: usize, patch_width: usize, path: PathBuf, limit: usize) -> Result<MnistData> {
+        let sampler = ImageSampler::new(patch_width, IMAGE_WIDTH, IMAGE_WIDTH).map_err(|error| Error::InvalidConfig(error.to_string()))?;
+
+        let images = read_idx_images(&path)
+            .map_err(|error| io::Error::new(error.kind(), format!("unable to read the MNIST images from {}: {}", path.display(), error)))?;
+        if images.rows != IMAGE_WIDTH || images.columns != IMAGE_WIDTH {
+            return Err(Error::Incompatible(format!("MNIST images must be {}x{} pixels, {} holds {}x{}", IMAGE_WIDTH, IMAGE_WIDTH, path.display(), images.rows, images.columns)));
+        }
+        let count = images.count.min(limit);
+        let mut pixels = images.pixels;
+        pixels.truncate(count * IMAGE_WIDTH * IMAGE_WIDTH);
+
+        let train_data = Array3::from_shape_vec((count, IMAGE_WIDTH, IMAGE_WIDTH), pixels)
             .expect("Error converting images to Array3 struct")
             .map(|x| *x as f32 / 256.0);
 
-        MnistData {
+        Ok(MnistData {
             section_size,
-            training_data: train_data
+            sampler,
+            preprocessing: None,
+            training_data: Arc::new(train_data),
+            indices: None,
+        })
+    }
+
+    // Splits the images into disjoint training, validation and test sets with the given ratios. The images are assigned by a
+    // shuffle seeded with `seed`, so the same seed always gives the same split. The parts share the images in memory, and keep
+    // the sampler and preprocessing of this dataset, which should be fitted on the training set only.
+    pub fn split(&self, ratios: SplitRatios, seed: u64) -> Result<Splits<MnistData>> {
+        let parts = [ratios.train, ratios.validation, ratios.test];
+        let total: f32 = parts.iter().sum();
+        if parts.iter().any(|ratio| !ratio.is_finite() || *ratio < 0.0) || total <= 0.0 {
+            return Err(Error::InvalidConfig(format!("split ratios must be non-negative and not all zero, got {:?}", ratios)));
+        }
+
+        let mut indices: Vec<usize> = (0..self.len()).map(|index| self.image(index)).collect();
+        indices.shuffle(&mut StdRng::seed_from_u64(seed));
+        let train = (indices.len() as f32 * ratios.train / total).round() as usize;
+        let validation = ((indices.len() as f32 * ratios.validation / total).round() as usize).min(indices.len() - train);
+        let test = indices.split_off(train + validation);
+        let validation_indices = indices.split_off(train);
+
+        Ok(Splits { train: self.part(indices), validation: self.part(validation_indices), test: self.part(test) })
+    }
+
+    fn part(&self, indices: Vec<usize>) -> MnistData {
+        MnistData {
+            section_size: self.section_size,
+            sampler: self.sampler,
+            preprocessing: self.preprocessing.clone(),
+            training_data: Arc::clone(&self.training_data),
+            indices: Some(Arc::new(indices)),
+        }
+    }
+
+    // Position of the image with the given sample index in the loaded images.
+    fn image(&self, index: usize) -> usize {
+        match &self.indices {
+            Some(indices) => indices[index],
+            None => index,
         }
     }
 
+    // Changes how patches are placed within the images, see `ImageSampler::set`. Drops the preprocessing, which was fitted to
+    // the patches of the previous sampler.
+    pub fn set_sampler(&mut self, sampler: PatchSampler) {
+        self.sampler.set(sampler);
+        self.preprocessing = None;
+    }
+
+    // Sets the preprocessing applied to every patch before it is handed to training. Must match the patch size of the sampler.
+    pub fn set_preprocessing(&mut self, preprocessing: Option<Whitening>) {
+        if let Some(preprocessing) = &preprocessing {
+            assert_eq!(preprocessing.mean().len(), self.patch_size(), "preprocessing does not match the patch size");
+        }
+        self.preprocessing = preprocessing;
+    }
 
-    pub fn get_section_vector(&self, index: usize) -> Vec<[f32; PATCH_SIZE]>{
-        let mut sections: Vec<[f32; PATCH_SIZE]> = Vec::new();
+    // Fits mean subtraction, and ZCA whitening if requested, on the given number of randomly sampled patches and applies it from now on.
+    pub fn fit_preprocessing<R: Rng>(&mut self, samples: usize, zca: bool, rng: &mut R) {
+        self.preprocessing = None;
+        let patches: Vec<Patch> = (0..samples).map(|_| {
+            let index = rng.gen_range(0..self.len());
+            self.get_random_patch(index, rng)
+        }).collect();
+
+        self.preprocessing = Some(if zca {
+            Whitening::zca(&patches, DEFAULT_EPSILON)
+        } else {
+            Whitening::centering(&patches)
+        });
+    }
+
+    pub fn preprocessing(&self) -> Option<&Whitening> {
+        self.preprocessing.as_ref()
+    }
+
+    pub fn patch_width(&self) -> usize {
+        self.sampler.patch_width()
+    }
+
+    pub fn patch_size(&self) -> usize {
+        self.sampler.patch_size()
+    }
+
+    pub fn get_section_vector<R: Rng>(&self, index: usize, rng: &mut R) -> Vec<Patch>{
+        let mut sections: Vec<Patch> = Vec::new();
         for section_index in 0..self.section_size{
-            sections.push(self.get_random_patch(index + section_index))
+            sections.push(self.get_random_patch(index + section_index, rng))
         }
-        return sections;
+        sections
     }
 
-    pub fn get_sized_patch(&self, length: usize) -> Vec<[f32; PATCH_SIZE]>{
-        let mut sections: Vec<[f32; PATCH_SIZE]> = Vec::new();
+    pub fn get_sized_patch<R: Rng>(&self, length: usize, rng: &mut R) -> Vec<Patch>{
+        let mut sections: Vec<Patch> = Vec::new();
         for i in 0..length{
-            sections.push(self.get_random_patch(i))
+            sections.push(self.get_random_patch(i, rng))
         }
-        return sections;
+        sections
     }
 
-    fn get_random_patch(&self, index: usize) -> [f32; PATCH_SIZE]{
-        let mut rng = rand::thread_rng();
+    fn get_random_patch<R: Rng>(&self, index: usize, rng: &mut R) -> Patch{
+        let image_size = IMAGE_WIDTH * IMAGE_WIDTH;
+        let image = &self.training_data.as_slice().expect("MNIST images are stored contiguously")[self.image(index) * image_size..][..image_size];
+        let patch = self.sampler.sample(image, 1.0, rng);
+        match &self.preprocessing {
+            Some(preprocessing) => preprocessing.apply(&patch),
+            None => patch,
+        }
+    }
+}
 
-        let mut training_randomized_patches: [f32; PATCH_SIZE] = rng.gen();
+impl Dataset for MnistData {
+    fn len(&self) -> usize {
+        match &self.indices {
+            Some(indices) => indices.len(),
+            None => self.training_data.dim().0,
+        }
+    }
 
-        let rand_training_column = rng.gen_range(0..22);
-        let rand_training_row = rng.gen_range(0..22);
+    fn patch_dim(&self) -> usize {
+        self.patch_size()
+    }
 
-        for row in 0..5 {
-            for column in 0..5 {
-                training_randomized_patches[row * 5 + column] = *self.training_data.slice(s![index,rand_training_row + row, rand_training_column + column]).into_scalar();
-            }
-        }
+    fn sample_patch(&self, index: usize, mut rng: &mut dyn RngCore) -> Patch {
+        self.get_random_patch(index, &mut rng)
+    }
+}
+
+#[cfg(test)]
+mod test{
+    use super::*;
 
-        training_randomized_patches
+    #[test]
+    fn whole_images_are_sampled_unchanged(){
+        let mnist = MnistData::whole_images(1, "data/").unwrap();
+        assert_eq!(mnist.patch_dim(), IMAGE_WIDTH * IMAGE_WIDTH);
+        let image_size = IMAGE_WIDTH * IMAGE_WIDTH;
+        let image: Vec<f32> = mnist.training_data.as_slice().unwrap()[3 * image_size..4 * image_size].to_vec();
+        assert_eq!(mnist.sample_patch(3, &mut rand::thread_rng()), image);
     }
 
+    #[test]
+    fn splits_are_disjoint_and_reproducible(){
+        let mnist = MnistData::new(1, 5).unwrap();
+        let splits = mnist.split(SplitRatios::new(3.0, 1.0, 1.0), 7).unwrap();
+        assert_eq!(splits.train.len(), 30000);
+        assert_eq!(splits.validation.len() + splits.test.len(), 20000);
 
+        let images = |data: &MnistData| data.indices.as_ref().unwrap().to_vec();
+        let mut all: Vec<usize> = [images(&splits.train), images(&splits.validation), images(&splits.test)].concat();
+        all.sort_unstable();
+        all.dedup();
+        assert_eq!(all.len(), mnist.len());
 
-}
\ No newline at end of file
+        assert_eq!(images(&mnist.split(SplitRatios::new(3.0, 1.0, 1.0), 7).unwrap().test), images(&splits.test));
+        assert_ne!(images(&mnist.split(SplitRatios::new(3.0, 1.0, 1.0), 8).unwrap().test), images(&splits.test));
+        assert!(mnist.split(SplitRatios::new(0.0, 0.0, 0.0), 7).is_err());
+    }
+}
@@ -0,0 +1,302 @@
+//! Loader for the whitened natural images Olshausen and Field learned sparse codes on (`IMAGES.mat` of sparsenet), so the
+//! receptive fields learned with Oja's rule or ICA can be compared with the published Gabor-like filters. The file is a
+//! MATLAB level 5 MAT-file holding a rows x columns x images array; only as much of the format as numeric arrays need is read.
+use std::fs::File;
+use std::io::{self, BufReader, Read};
+use std::path::Path;
+use flate2::read::ZlibDecoder;
+use rand::RngCore;
+use crate::data::dataset::Dataset;
+use crate::data::patch::Patch;
+use crate::data::sampler::{ImageSampler, PatchSampler};
+
+const HEADER: usize = 128;
+const MI_INT8: u32 = 1;
+const MI_UINT8: u32 = 2;
+const MI_INT16: u32 = 3;
+const MI_UINT16: u32 = 4;
+const MI_INT32: u32 = 5;
+const MI_UINT32: u32 = 6;
+const MI_SINGLE: u32 = 7;
+const MI_DOUBLE: u32 = 9;
+const MI_INT64: u32 = 12;
+const MI_UINT64: u32 = 13;
+const MI_MATRIX: u32 = 14;
+const MI_COMPRESSED: u32 = 15;
+/// Array classes from `mxDOUBLE_CLASS` to `mxUINT64_CLASS`, the numeric ones.
+const NUMERIC_CLASSES: std::ops::RangeInclusive<u32> = 6..=15;
+
+/// A numeric array read from a MAT-file, with the values in MATLAB's column major order.
+#[derive(Debug, Clone, PartialEq)]
+pub struct MatArray {
+    pub name: String,
+    pub dims: Vec<usize>,
+    pub data: Vec<f64>,
+}
+
+fn invalid(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message.to_string())
+}
+
+pub fn read_mat_arrays<P: AsRef<Path>>(path: P) -> io::Result<Vec<MatArray>> {
+    read_mat_arrays_from(&mut BufReader::new(File::open(path)?))
+}
+
+/// Reads the numeric arrays of a MAT-file, skipping variables of any other class such as cells or structs.
+pub fn read_mat_arrays_from<R: Read>(reader: &mut R) -> io::Result<Vec<MatArray>> {
+    let mut bytes = Vec::new();
+    reader.read_to_end(&mut bytes)?;
+    if bytes.len() < HEADER {
+        return Err(invalid("too short for a MAT-file"));
+    }
+    // The writer stores "IM" in its own byte order, so reading "MI" means every number has to be byte swapped.
+    let big_endian = match &bytes[HEADER - 2..HEADER] {
+        b"IM" => false,
+        b"MI" => true,
+        _ => return Err(invalid("not a level 5 MAT-file")),
+    };
+    let mut arrays = Vec::new();
+    read_elements(&bytes[HEADER..], big_endian, &mut arrays)?;
+    Ok(arrays)
+}
+
+fn read_elements(bytes: &[u8], big_endian: bool, arrays: &mut Vec<MatArray>) -> io::Result<()> {
+    let mut elements = Elements { bytes, position: 0, big_endian };
+    while let Some((kind, data)) = elements.next()? {
+        match kind {
+            MI_MATRIX => arrays.extend(read_matrix(data, big_endian)?),
+            MI_COMPRESSED => {
+                let mut decompressed = Vec::new();
+                ZlibDecoder::new(data).read_to_end(&mut decompressed)?;
+                read_elements(&decompressed, big_endian, arrays)?;
+            }
+            _ => {}
+        }
+    }
+    Ok(())
+}
+
+// A matrix holds its flags, dimensions, name and real part as data elements of their own, in this order.
+fn read_matrix(bytes: &[u8], big_endian: bool) -> io::Result<Option<MatArray>> {
+    let mut elements = Elements { bytes, position: 0, big_endian };
+    let mut next = || elements.next()?.ok_or_else(|| invalid("truncated array"));
+    let (_, flags) = next()?;
+    if flags.len() < 4 || !NUMERIC_CLASSES.contains(&(u32::from_le_bytes(word::<4>(&flags[..4], big_endian)[0]) & 0xff)) {
+        return Ok(None);
+    }
+    let (kind, dims) = next()?;
+    let dims = numbers(kind, dims, big_endian)?.into_iter().map(|dim| dim as usize).collect();
+    let (_, name) = next()?;
+    let name = String::from_utf8_lossy(name).into_owned();
+    let (kind, real) = next()?;
+    Ok(Some(MatArray { name, dims, data: numbers(kind, real, big_endian)? }))
+}
+
+struct Elements<'a> {
+    bytes: &'a [u8],
+    position: usize,
+    big_endian: bool,
+}
+
+impl<'a> Elements<'a> {
+    fn u32(&self, at: usize) -> u32 {
+        u32::from_le_bytes(word::<4>(&self.bytes[at..at + 4], self.big_endian)[0])
+    }
+
+    // Returns the type and the contents of the next data element. Elements of up to four bytes can be packed into the tag, and
+    // everything but compressed elements is padded to a multiple of eight bytes.
+    fn next(&mut self) -> io::Result<Option<(u32, &'a [u8])>> {
+        if self.position == self.bytes.len() {
+            return Ok(None);
+        }
+        if self.position + 8 > self.bytes.len() {
+            return Err(invalid("truncated data element"));
+        }
+        let tag = self.u32(self.position);
+        if tag >> 16 != 0 {
+            let (kind, size) = (tag & 0xffff, (tag >> 16) as usize);
+            if size > 4 {
+                return Err(invalid("invalid small data element"));
+            }
+            let data = &self.bytes[self.position + 4..self.position + 4 + size];
+            self.position += 8;
+            return Ok(Some((kind, data)));
+        }
+        let size = self.u32(self.position + 4) as usize;
+        let start = self.position + 8;
+        let data = self.bytes.get(start..start + size).ok_or_else(|| invalid("truncated data element"))?;
+        self.position = if tag == MI_COMPRESSED { start + size } else { (start + size).div_ceil(8) * 8 }.min(self.bytes.len());
+        Ok(Some((tag, data)))
+    }
+}
+
+/// Splits the bytes into little endian words of `N` bytes.
+fn word<const N: usize>(bytes: &[u8], big_endian: bool) -> Vec<[u8; N]> {
+    bytes.chunks_exact(N).map(|chunk| {
+        let mut word = [0u8; N];
+        word.copy_from_slice(chunk);
+        if big_endian {
+            word.reverse();
+        }
+        word
+    }).collect()
+}
+
+fn numbers(kind: u32, bytes: &[u8], big_endian: bool) -> io::Result<Vec<f64>> {
+    Ok(match kind {
+        MI_INT8 => bytes.iter().map(|value| *value as i8 as f64).collect(),
+        MI_UINT8 => bytes.iter().map(|value| *value as f64).collect(),
+        MI_INT16 => word::<2>(bytes, big_endian).into_iter().map(|word| i16::from_le_bytes(word) as f64).collect(),
+        MI_UINT16 => word::<2>(bytes, big_endian).into_iter().map(|word| u16::from_le_bytes(word) as f64).collect(),
+        MI_INT32 => word::<4>(bytes, big_endian).into_iter().map(|word| i32::from_le_bytes(word) as f64).collect(),
+        MI_UINT32 => word::<4>(bytes, big_endian).into_iter().map(|word| u32::from_le_bytes(word) as f64).collect(),
+        MI_SINGLE => word::<4>(bytes, big_endian).into_iter().map(|word| f32::from_le_bytes(word) as f64).collect(),
+        MI_DOUBLE => word::<8>(bytes, big_endian).into_iter().map(f64::from_le_bytes).collect(),
+        MI_INT64 => word::<8>(bytes, big_endian).into_iter().map(|word| i64::from_le_bytes(word) as f64).collect(),
+        MI_UINT64 => word::<8>(bytes, big_endian).into_iter().map(|word| u64::from_le_bytes(word) as f64).collect(),
+        _ => return Err(invalid("unsupported numeric type")),
+    })
+}
+
+/// Grayscale images of real values, such as the whitened images of sparsenet, sampled as square patches without rescaling.
+pub struct NaturalImages {
+    pixels: Vec<f32>,
+    count: usize,
+    rows: usize,
+    columns: usize,
+    sampler: ImageSampler,
+}
+
+impl NaturalImages {
+    /// Loads the whitened images from the `IMAGES` variable of sparsenet's `IMAGES.mat`.
+    pub fn olshausen<P: AsRef<Path>>(path: P, patch_width: usize) -> io::Result<NaturalImages> {
+        NaturalImages::from_mat(path, "IMAGES", patch_width)
+    }
+
+    /// Loads the images from a variable of a MAT-file, e.g. `IMAGES_RAW` for the unwhitened images of sparsenet.
+    pub fn from_mat<P: AsRef<Path>>(path: P, variable: &str, patch_width: usize) -> io::Result<NaturalImages> {
+        let array = read_mat_arrays(path)?.into_iter().find(|array| array.name == variable)
+            .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, format!("no numeric variable {} in the file", variable)))?;
+        NaturalImages::new(array, patch_width)
+    }
+
+    /// The array holds a single image of rows x columns, or rows x columns x images.
+    pub fn new(array: MatArray, patch_width: usize) -> io::Result<NaturalImages> {
+        let (rows, columns, count) = match array.dims[..] {
+            [rows, columns] => (rows, columns, 1),
+            [rows, columns, count] => (rows, columns, count),
+            _ => return Err(invalid("the images need two or three dimensions")),
+        };
+        if array.data.len() != rows * columns * count {
+            return Err(invalid("the values do not match the dimensions of the array"));
+        }
+        // MATLAB stores the arrays column by column, which is transposed into the row by row order of the samplers.
+        let mut pixels = vec![0.0f32; array.data.len()];
+        for image in 0..count {
+            for row in 0..rows {
+                for column in 0..columns {
+                    pixels[(image * rows + row) * columns + column] = array.data[(image * columns + column) * rows + row] as f32;
+                }
+            }
+        }
+        NaturalImages::from_pixels(pixels, rows, columns, patch_width)
+    }
+
+    /// Images of rows x columns already in memory, e.g. handed over by a browser, stored row by row and image after image.
+    pub fn from_pixels(pixels: Vec<f32>, rows: usize, columns: usize, patch_width: usize) -> io::Result<NaturalImages> {
+        let size = rows * columns;
+        let count = pixels.len().checked_div(size).unwrap_or(0);
+        if count * size != pixels.len() {
+            return Err(invalid("the values do not match the dimensions of the images"));
+        }
+        if count == 0 {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, "there are no images to sample from"));
+        }
+        let sampler = ImageSampler::new(patch_width, rows, columns)?;
+        Ok(NaturalImages { pixels, count, rows, columns, sampler })
+    }
+
+    pub fn dimensions(&self) -> (usize, usize) {
+        (self.rows, self.columns)
+    }
+
+    /// Changes how patches are placed within the images, e.g. leaving out a border like sparsenet does, see `ImageSampler::set`.
+    pub fn set_sampler(&mut self, sampler: PatchSampler) {
+        self.sampler.set(sampler);
+    }
+
+    pub fn patch_width(&self) -> usize {
+        self.sampler.patch_width()
+    }
+}
+
+impl Dataset for NaturalImages {
+    fn len(&self) -> usize {
+        self.count
+    }
+
+    fn patch_dim(&self) -> usize {
+        self.sampler.patch_size()
+    }
+
+    fn sample_patch(&self, index: usize, rng: &mut dyn RngCore) -> Patch {
+        let size = self.rows * self.columns;
+        let image = &self.pixels[(index % self.count) * size..][..size];
+        self.sampler.sample(image, 1.0, rng)
+    }
+}
+
+#[cfg(test)]
+mod test{
+    use super::*;
+    use std::io::Write;
+    use flate2::Compression;
+    use flate2::write::ZlibEncoder;
+
+    fn element(kind: u32, data: &[u8]) -> Vec<u8> {
+        let mut element = [kind.to_le_bytes(), (data.len() as u32).to_le_bytes()].concat();
+        element.extend_from_slice(data);
+        element.resize(element.len().div_ceil(8) * 8, 0);
+        element
+    }
+
+    fn matrix(name: &str, class: u32, dims: &[i32], real: Vec<u8>, kind: u32) -> Vec<u8> {
+        let dims: Vec<u8> = dims.iter().flat_map(|dim| dim.to_le_bytes()).collect();
+        // Names of up to four bytes are packed into the tag of their element.
+        let name = match name.len() {
+            0..=4 => [((name.len() as u32) << 16 | MI_INT8).to_le_bytes().to_vec(), name.as_bytes().to_vec(), vec![0; 4 - name.len()]].concat(),
+            _ => element(MI_INT8, name.as_bytes()),
+        };
+        let contents = [element(MI_UINT32, &[class.to_le_bytes(), 0u32.to_le_bytes()].concat()), element(MI_INT32, &dims), name, element(kind, &real)].concat();
+        element(MI_MATRIX, &contents)
+    }
+
+    fn header() -> Vec<u8> {
+        let mut header = vec![b' '; HEADER];
+        header[124..].copy_from_slice(&[0x00, 0x01, b'I', b'M']);
+        header
+    }
+
+    #[test]
+    fn read_arrays_and_transpose_the_images(){
+        // Two 2x3 images, stored column by column.
+        let values: Vec<f64> = vec![1.0, 4.0, 2.0, 5.0, 3.0, 6.0, -1.0, -4.0, -2.0, -5.0, -3.0, -6.0];
+        let images = matrix("IMAGES", 6, &[2, 3, 2], values.iter().flat_map(|value| value.to_le_bytes()).collect(), MI_DOUBLE);
+        let mut encoder = ZlibEncoder::new(Vec::new(), Compression::default());
+        encoder.write_all(&matrix("raw", 9, &[1, 2], vec![7, 9], MI_UINT8)).unwrap();
+        let compressed = encoder.finish().unwrap();
+        let bytes = [header(), images, [MI_COMPRESSED.to_le_bytes(), (compressed.len() as u32).to_le_bytes()].concat(), compressed].concat();
+
+        let arrays = read_mat_arrays_from(&mut bytes.as_slice()).unwrap();
+        assert_eq!(arrays[1], MatArray { name: "raw".to_string(), dims: vec![1, 2], data: vec![7.0, 9.0] });
+        assert_eq!(arrays[0].dims, vec![2, 3, 2]);
+        assert!(read_mat_arrays_from(&mut &bytes[..HEADER - 1]).is_err());
+
+        let images = NaturalImages::new(arrays[0].clone(), 2).unwrap();
+        assert_eq!((images.len(), images.dimensions()), (2, (2, 3)));
+        assert_eq!(images.pixels, vec![1.0, 2.0, 3.0, 4.0, 5.0, 6.0, -1.0, -2.0, -3.0, -4.0, -5.0, -6.0]);
+        let patch = images.sample_patch(1, &mut rand::thread_rng());
+        assert!(patch == vec![-1.0, -2.0, -4.0, -5.0] || patch == vec![-2.0, -3.0, -5.0, -6.0]);
+        assert!(NaturalImages::new(arrays[0].clone(), 3).is_err());
+    }
+}
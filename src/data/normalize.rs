@@ -0,0 +1,119 @@
+//! Scaling of individual patches, applied on top of any dataset so the raw values don't have to be pre-processed by hand.
+use rand::RngCore;
+use crate::data::dataset::Dataset;
+use crate::data::patch::Patch;
+
+/// Patches with (almost) no spread are left centered instead of being divided by zero.
+const MIN_SPREAD: f32 = 1e-8;
+
+/// How the values of every patch are rescaled.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Normalizer {
+    /// Scales the values linearly into the range 0..=1.
+    MinMax,
+    /// Subtracts the mean of the patch and divides by its standard deviation.
+    ZScore,
+    /// Divides the patch by its L2 norm.
+    UnitNorm,
+}
+
+impl Normalizer {
+    pub fn apply(&self, patch: &mut [f32]) {
+        if patch.is_empty() {
+            return;
+        }
+        match self {
+            Normalizer::MinMax => {
+                let min = patch.iter().cloned().fold(f32::INFINITY, f32::min);
+                let max = patch.iter().cloned().fold(f32::NEG_INFINITY, f32::max);
+                let range = max - min;
+                for value in patch.iter_mut() {
+                    *value = if range > MIN_SPREAD { (*value - min) / range } else { 0.0 };
+                }
+            }
+            Normalizer::ZScore => {
+                let mean = patch.iter().sum::<f32>() / patch.len() as f32;
+                let std = (patch.iter().map(|x| (x - mean) * (x - mean)).sum::<f32>() / patch.len() as f32).sqrt();
+                for value in patch.iter_mut() {
+                    *value = if std > MIN_SPREAD { (*value - mean) / std } else { *value - mean };
+                }
+            }
+            Normalizer::UnitNorm => {
+                let norm = patch.iter().map(|x| x * x).sum::<f32>().sqrt();
+                if norm > MIN_SPREAD {
+                    patch.iter_mut().for_each(|value| *value /= norm);
+                }
+            }
+        }
+    }
+}
+
+/// A dataset whose patches are normalized before they are handed to training.
+pub struct Normalized<D: Dataset> {
+    dataset: D,
+    normalizer: Normalizer,
+}
+
+impl<D: Dataset> Normalized<D> {
+    pub fn new(dataset: D, normalizer: Normalizer) -> Normalized<D> {
+        Normalized { dataset, normalizer }
+    }
+
+    pub fn normalizer(&self) -> Normalizer {
+        self.normalizer
+    }
+
+    pub fn inner(&self) -> &D {
+        &self.dataset
+    }
+
+    pub fn into_inner(self) -> D {
+        self.dataset
+    }
+}
+
+impl<D: Dataset> Dataset for Normalized<D> {
+    fn len(&self) -> usize {
+        self.dataset.len()
+    }
+
+    fn patch_dim(&self) -> usize {
+        self.dataset.patch_dim()
+    }
+
+    fn sample_patch(&self, index: usize, rng: &mut dyn RngCore) -> Patch {
+        let mut patch = self.dataset.sample_patch(index, rng);
+        self.normalizer.apply(&mut patch);
+        patch
+    }
+}
+
+#[cfg(test)]
+mod test{
+    use super::*;
+
+    #[test]
+    fn normalizers_rescale_the_patch(){
+        let mut min_max = vec![2.0, 4.0, 6.0];
+        Normalizer::MinMax.apply(&mut min_max);
+        assert_eq!(min_max, vec![0.0, 0.5, 1.0]);
+
+        let mut z_score = vec![2.0, 4.0, 6.0];
+        Normalizer::ZScore.apply(&mut z_score);
+        assert!(z_score.iter().sum::<f32>().abs() < 1e-6);
+        assert!((z_score.iter().map(|x| x * x).sum::<f32>() / 3.0 - 1.0).abs() < 1e-5);
+
+        let mut unit_norm = vec![3.0, 4.0];
+        Normalizer::UnitNorm.apply(&mut unit_norm);
+        assert_eq!(unit_norm, vec![0.6, 0.8]);
+    }
+
+    #[test]
+    fn constant_patches_are_not_divided_by_zero(){
+        for normalizer in [Normalizer::MinMax, Normalizer::ZScore, Normalizer::UnitNorm] {
+            let mut patch = vec![0.0; 4];
+            normalizer.apply(&mut patch);
+            assert!(patch.iter().all(|x| x.is_finite()));
+        }
+    }
+}
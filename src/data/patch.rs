@@ -0,0 +1,15 @@
+//! Runtime sized patches, so the patch dimensions can be chosen without recompiling.
+
+/// A square patch of pixels flattened in row-major order. Weight vectors share the same layout, one value per pixel.
+pub type Patch = Vec<f32>;
+
+/// Number of values in a square patch with the given width.
+pub fn patch_size(patch_width: usize) -> usize {
+    patch_width * patch_width
+}
+
+/// Width of a square patch with the given number of values, or `None` if the patch can not be square.
+pub fn patch_width(patch_size: usize) -> Option<usize> {
+    let width = (patch_size as f64).sqrt().round() as usize;
+    if width * width == patch_size { Some(width) } else { None }
+}
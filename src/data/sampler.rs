@@ -0,0 +1,287 @@
+//! Placement of square patches within an image.
+//! Patches are placed on a grid with a configurable stride, so neighbouring candidate patches overlap by `patch_width - stride` pixels.
+//! A stride of one considers every pixel position, which is the default.
+//! Digits have wide empty borders, so sampling can be restricted to the center of the images, and patches whose variance is too
+//! low to carry any signal can be replaced by another position of the same image.
+use std::io;
+use rand::{Rng, RngCore};
+use crate::data::patch::{Patch, patch_size};
+
+/// How many positions of an image are tried to find a patch reaching the minimum variance.
+const VARIANCE_ATTEMPTS: usize = 16;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct PatchSampler {
+    patch_width: usize,
+    stride: usize,
+    random_offset: bool,
+    border: usize,
+    min_variance: f32,
+}
+
+impl PatchSampler {
+    /// Samples patches at any pixel position.
+    pub fn new(patch_width: usize) -> PatchSampler {
+        PatchSampler::with_stride(patch_width, 1)
+    }
+
+    /// Samples patches on a grid where neighbouring positions are `stride` pixels apart.
+    pub fn with_stride(patch_width: usize, stride: usize) -> PatchSampler {
+        assert!(patch_width > 0, "the patch width must be at least one pixel");
+        assert!(stride > 0, "the stride must be at least one pixel");
+        PatchSampler { patch_width, stride, random_offset: false, border: 0, min_variance: 0.0 }
+    }
+
+    /// Samples patches on a grid where neighbouring patches share `overlap` rows or columns of pixels.
+    pub fn with_overlap(patch_width: usize, overlap: usize) -> PatchSampler {
+        assert!(overlap < patch_width, "the overlap must be smaller than the patch width");
+        PatchSampler::with_stride(patch_width, patch_width - overlap)
+    }
+
+    /// Shifts the grid by a random offset for every sampled patch, so that a coarse stride still covers every pixel over time.
+    pub fn random_offset(mut self, random_offset: bool) -> PatchSampler {
+        self.random_offset = random_offset;
+        self
+    }
+
+    /// Only samples patches from the center of the images, leaving out `border` pixels on every side.
+    pub fn border(mut self, border: usize) -> PatchSampler {
+        self.border = border;
+        self
+    }
+
+    /// Samples another position of the same image when a patch's variance is below `min_variance`, measured on the pixel
+    /// values after scaling, see `sample`. Zero, the default, accepts every patch.
+    pub fn min_variance(mut self, min_variance: f32) -> PatchSampler {
+        assert!(min_variance >= 0.0, "the minimum variance can not be negative");
+        self.min_variance = min_variance;
+        self
+    }
+
+    pub fn patch_width(&self) -> usize {
+        self.patch_width
+    }
+
+    pub fn stride(&self) -> usize {
+        self.stride
+    }
+
+    pub fn patch_size(&self) -> usize {
+        patch_size(self.patch_width)
+    }
+
+    /// Whether a patch fits into the center of an image of the given size.
+    pub fn fits(&self, rows: usize, columns: usize) -> bool {
+        self.patch_width + 2 * self.border <= rows.min(columns)
+    }
+
+    /// Top left corners of every grid position in an image of the given size, without any random offset.
+    pub fn positions(&self, rows: usize, columns: usize) -> Vec<(usize, usize)> {
+        let mut positions = Vec::new();
+        for row in self.grid(rows) {
+            for column in self.grid(columns) {
+                positions.push((row, column));
+            }
+        }
+        positions
+    }
+
+    /// Picks the top left corner of a random grid position in an image of the given size.
+    pub fn sample_position(&self, rows: usize, columns: usize, rng: &mut dyn RngCore) -> (usize, usize) {
+        (self.sample_axis(rows, rng), self.sample_axis(columns, rng))
+    }
+
+    /// Extracts a patch at a random position of an image stored row by row, with every value multiplied by `scale`.
+    /// With a minimum variance up to 16 positions are tried, and the patch with the highest variance is kept if none of them
+    /// reaches it, e.g. for an almost empty image.
+    pub fn sample<T: Copy + Into<f32>>(&self, image: &[T], rows: usize, columns: usize, scale: f32, rng: &mut dyn RngCore) -> Patch {
+        self.sample_channels(image, 1, rows, columns, scale, rng)
+    }
+
+    /// Like `sample`, for an image stored channel after channel such as the color planes of CIFAR-10. The patch holds the
+    /// values of every channel at the same position, one channel after another.
+    pub fn sample_channels<T: Copy + Into<f32>>(&self, image: &[T], channels: usize, rows: usize, columns: usize, scale: f32, rng: &mut dyn RngCore) -> Patch {
+        let mut best: Option<(f32, Patch)> = None;
+        for _ in 0..VARIANCE_ATTEMPTS {
+            let position = self.sample_position(rows, columns, rng);
+            let mut patch: Patch = image.chunks(rows * columns).take(channels).flat_map(|plane| self.extract(plane, columns, position)).collect();
+            if scale != 1.0 {
+                patch.iter_mut().for_each(|value| *value *= scale);
+            }
+            if self.min_variance <= 0.0 {
+                return patch;
+            }
+            let variance = variance(&patch);
+            if variance >= self.min_variance {
+                return patch;
+            }
+            if !matches!(&best, Some((best, _)) if *best >= variance) {
+                best = Some((variance, patch));
+            }
+        }
+        best.expect("at least one position is tried").1
+    }
+
+    /// Copies the patch with its top left corner at `position` out of an image stored row by row.
+    pub fn extract<T: Copy + Into<f32>>(&self, image: &[T], columns: usize, position: (usize, usize)) -> Patch {
+        let (top, left) = position;
+        let mut patch = Vec::with_capacity(self.patch_size());
+        for row in top..top + self.patch_width {
+            patch.extend(image[row * columns + left..][..self.patch_width].iter().map(|value| (*value).into()));
+        }
+        patch
+    }
+
+    /// Extracts the patches at every grid position of an image stored row by row.
+    pub fn extract_all<T: Copy + Into<f32>>(&self, image: &[T], rows: usize, columns: usize) -> Vec<Patch> {
+        self.positions(rows, columns).into_iter().map(|position| self.extract(image, columns, position)).collect()
+    }
+
+    fn sample_axis(&self, length: usize, rng: &mut dyn RngCore) -> usize {
+        let span = self.span(length);
+        // Offsets past the span would leave no position on the grid.
+        let offset = if self.random_offset { rng.gen_range(0..self.stride.min(span + 1)) } else { 0 };
+        self.border + offset + self.stride * rng.gen_range(0..(span - offset) / self.stride + 1)
+    }
+
+    fn grid(&self, length: usize) -> impl Iterator<Item = usize> {
+        (self.border..=self.border + self.span(length)).step_by(self.stride)
+    }
+
+    /// Distance between the first and the last position a patch fits at along an axis of `length` pixels.
+    fn span(&self, length: usize) -> usize {
+        assert!(self.patch_width + 2 * self.border <= length, "patch width {} does not fit in {} pixels with a border of {}", self.patch_width, length, self.border);
+        length - 2 * self.border - self.patch_width
+    }
+}
+
+/// The `PatchSampler` of a dataset together with the size of its images, so a replacement sampler is checked against them in
+/// one place. A dataset of differently sized images gives the size of its smallest image.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ImageSampler {
+    sampler: PatchSampler,
+    rows: usize,
+    columns: usize,
+}
+
+impl ImageSampler {
+    /// Samples patches at any pixel position of images of `rows` x `columns` pixels. Fails with `InvalidInput` if a patch of
+    /// `patch_width` does not fit them.
+    pub fn new(patch_width: usize, rows: usize, columns: usize) -> io::Result<ImageSampler> {
+        if patch_width == 0 || patch_width > rows.min(columns) {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, format!("patch width {} does not fit {}x{} images", patch_width, rows, columns)));
+        }
+        Ok(ImageSampler { sampler: PatchSampler::new(patch_width), rows, columns })
+    }
+
+    /// Changes how patches are placed within the images, e.g. on a grid with a coarser stride or leaving out a border.
+    pub fn set(&mut self, sampler: PatchSampler) {
+        assert!(sampler.fits(self.rows, self.columns), "patch width {} with a border of {} does not fit {}x{} images",
+                sampler.patch_width, sampler.border, self.rows, self.columns);
+        self.sampler = sampler;
+    }
+
+    pub fn sampler(&self) -> &PatchSampler {
+        &self.sampler
+    }
+
+    pub fn patch_width(&self) -> usize {
+        self.sampler.patch_width()
+    }
+
+    pub fn patch_size(&self) -> usize {
+        self.sampler.patch_size()
+    }
+
+    /// Samples a patch from an image of the size the sampler was created for, see `PatchSampler::sample`.
+    pub fn sample<T: Copy + Into<f32>>(&self, image: &[T], scale: f32, rng: &mut dyn RngCore) -> Patch {
+        self.sampler.sample(image, self.rows, self.columns, scale, rng)
+    }
+
+    /// Like `sample`, for an image stored channel after channel, see `PatchSampler::sample_channels`.
+    pub fn sample_channels<T: Copy + Into<f32>>(&self, image: &[T], channels: usize, scale: f32, rng: &mut dyn RngCore) -> Patch {
+        self.sampler.sample_channels(image, channels, self.rows, self.columns, scale, rng)
+    }
+}
+
+/// Variance of the values of a patch.
+pub fn variance(patch: &[f32]) -> f32 {
+    if patch.is_empty() {
+        return 0.0;
+    }
+    let mean = patch.iter().sum::<f32>() / patch.len() as f32;
+    patch.iter().map(|value| (value - mean) * (value - mean)).sum::<f32>() / patch.len() as f32
+}
+
+#[cfg(test)]
+mod test{
+    use super::*;
+
+    #[test]
+    fn grid_positions_follow_stride_and_overlap(){
+        assert_eq!(PatchSampler::new(3).positions(4, 4).len(), 4);
+        assert_eq!(PatchSampler::with_stride(2, 2).positions(5, 4), vec![(0, 0), (0, 2), (2, 0), (2, 2)]);
+        assert_eq!(PatchSampler::with_overlap(3, 1), PatchSampler::with_stride(3, 2));
+
+        let sampler = PatchSampler::with_stride(2, 3).random_offset(true);
+        let mut rng = rand::thread_rng();
+        for _ in 0..100 {
+            let (row, column) = sampler.sample_position(6, 6, &mut rng);
+            assert!(row <= 4 && column <= 4);
+        }
+
+        // A stride longer than the image still reaches every position through the offset.
+        let sampler = PatchSampler::with_stride(2, 5).border(1).random_offset(true);
+        let mut seen = [false; 6];
+        for _ in 0..200 {
+            let (row, _) = sampler.sample_position(6, 6, &mut rng);
+            seen[row] = true;
+        }
+        assert_eq!(seen, [false, true, true, true, false, false]);
+    }
+
+    use rand::SeedableRng;
+    use rand::rngs::StdRng;
+
+    #[test]
+    fn image_samplers_only_take_patches_which_fit(){
+        assert!(ImageSampler::new(0, 4, 6).is_err());
+        assert_eq!(ImageSampler::new(5, 4, 6).unwrap_err().kind(), io::ErrorKind::InvalidInput);
+
+        let mut sampler = ImageSampler::new(2, 4, 6).unwrap();
+        sampler.set(PatchSampler::new(2).border(1));
+        let image: Vec<u8> = (0..24).collect();
+        // Only the two center rows are left, so every patch covers rows 1 and 2.
+        let patch = sampler.sample(&image, 1.0, &mut StdRng::seed_from_u64(1));
+        assert!(patch.iter().all(|&value| (6.0..18.0).contains(&value)));
+        assert!(std::panic::catch_unwind(move || sampler.set(PatchSampler::new(2).border(2))).is_err());
+    }
+
+    #[test]
+    fn border_and_minimum_variance_restrict_the_patches(){
+        let sampler = PatchSampler::new(2).border(1);
+        assert_eq!(sampler.positions(5, 5), vec![(1, 1), (1, 2), (2, 1), (2, 2)]);
+        assert!(sampler.fits(4, 4) && !sampler.fits(3, 4));
+
+        // Only the bottom right corner of the image varies, which four of the nine positions overlap.
+        let mut image = vec![0u8; 16];
+        image[10] = 200;
+        image[11] = 100;
+        image[14] = 100;
+        image[15] = 200;
+        let sampler = PatchSampler::new(2).min_variance(0.01);
+        let mut rng = StdRng::seed_from_u64(3);
+        for _ in 0..20 {
+            assert!(sampler.sample(&image, 4, 4, 1.0 / 256.0, &mut rng).iter().any(|&value| value > 0.0));
+        }
+        assert_eq!(variance(&[1.0, 3.0]), 1.0);
+    }
+
+    #[test]
+    fn extract_patches(){
+        let image: Vec<u8> = (0..12).collect();
+        let sampler = PatchSampler::with_stride(2, 2);
+        assert_eq!(sampler.extract(&image, 4, (1, 1)), vec![5.0, 6.0, 9.0, 10.0]);
+        assert_eq!(sampler.extract_all(&image, 3, 4), vec![vec![0.0, 1.0, 4.0, 5.0], vec![2.0, 3.0, 6.0, 7.0]]);
+    }
+}
@@ -0,0 +1,114 @@
+//! Samples drawn from a multivariate Gaussian with a known covariance matrix. Oja's rule converges to the top eigenvector of
+//! the covariance of its input, so with a known covariance the learned weights can be checked against the exact answer.
+use rand::RngCore;
+use crate::data::dataset::Dataset;
+use crate::data::patch::Patch;
+use crate::error::{Error, Result};
+use crate::model::init::standard_normal;
+use crate::utils::linalg::{cholesky, sorted_eigen};
+
+/// A dataset of Gaussian samples. Every call to `sample_patch` draws a fresh sample from the rng, so the index only matters
+/// for how many samples make up an epoch.
+pub struct GaussianData {
+    mean: Patch,
+    covariance: Vec<Vec<f64>>,
+    factor: Vec<Vec<f64>>,
+    len: usize,
+}
+
+impl GaussianData {
+    /// Zero mean samples with the given covariance, which has to be symmetric and positive definite.
+    pub fn new(covariance: Vec<Vec<f64>>, len: usize) -> Result<GaussianData> {
+        let dim = covariance.len();
+        if dim == 0 || covariance.iter().any(|row| row.len() != dim) {
+            return Err(Error::InvalidConfig("the covariance has to be a non-empty square matrix".to_string()));
+        }
+        if (0..dim).any(|row| (0..row).any(|column| (covariance[row][column] - covariance[column][row]).abs() > 1e-9)) {
+            return Err(Error::InvalidConfig("the covariance has to be symmetric".to_string()));
+        }
+        let factor = cholesky(&covariance).ok_or_else(|| Error::InvalidConfig("the covariance has to be positive definite".to_string()))?;
+        Ok(GaussianData { mean: vec![0.0; dim], covariance, factor, len })
+    }
+
+    /// Independent dimensions with the given variances.
+    pub fn with_variances(variances: &[f64], len: usize) -> Result<GaussianData> {
+        let covariance = (0..variances.len()).map(|row| (0..variances.len()).map(|column| if row == column { variances[row] } else { 0.0 }).collect()).collect();
+        GaussianData::new(covariance, len)
+    }
+
+    /// Shifts the samples by the given mean.
+    pub fn mean(mut self, mean: Patch) -> GaussianData {
+        assert_eq!(mean.len(), self.mean.len(), "the mean has to have one value per dimension");
+        self.mean = mean;
+        self
+    }
+
+    pub fn covariance(&self) -> &[Vec<f64>] {
+        &self.covariance
+    }
+
+    /// Eigenvalues of the covariance in descending order, with the matching eigenvectors.
+    pub fn principal_components(&self) -> Vec<(f64, Vec<f64>)> {
+        sorted_eigen(self.covariance.clone())
+    }
+
+    /// The unit length direction of the largest variance, which a single neuron trained with Oja's rule converges to up to its sign.
+    pub fn top_eigenvector(&self) -> Patch {
+        self.principal_components()[0].1.iter().map(|value| *value as f32).collect()
+    }
+}
+
+impl Dataset for GaussianData {
+    fn len(&self) -> usize {
+        self.len
+    }
+
+    fn patch_dim(&self) -> usize {
+        self.mean.len()
+    }
+
+    // Transforms standard normal samples with the Cholesky factor `L`, as `L z` has the covariance `L L^T`.
+    fn sample_patch(&self, _index: usize, rng: &mut dyn RngCore) -> Patch {
+        let normal: Vec<f64> = (0..self.mean.len()).map(|_| standard_normal(rng) as f64).collect();
+        self.factor.iter().zip(self.mean.iter())
+            .map(|(row, mean)| mean + row.iter().zip(normal.iter()).map(|(l, z)| l * z).sum::<f64>() as f32)
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod test{
+    use super::*;
+    use crate::model::network::MtNetwork;
+    use crate::utils::linalg::{covariance, mean_patch};
+    use rand::SeedableRng;
+    use rand::rngs::StdRng;
+
+    fn gaussian() -> GaussianData {
+        GaussianData::new(vec![vec![3.0, 1.0, 0.0], vec![1.0, 2.0, 0.5], vec![0.0, 0.5, 1.0]], 1000).unwrap()
+    }
+
+    #[test]
+    fn samples_have_the_given_covariance(){
+        let data = gaussian().mean(vec![1.0, 0.0, -1.0]);
+        let patches = data.sample_patches(0, 20000, &mut StdRng::seed_from_u64(4));
+        let mean = mean_patch(&patches);
+        assert!((mean[0] - 1.0).abs() < 0.05 && (mean[2] + 1.0).abs() < 0.05);
+        for (estimated, exact) in covariance(&patches, &mean).iter().flatten().zip(data.covariance().iter().flatten()) {
+            assert!((estimated - exact).abs() < 0.1, "{} != {}", estimated, exact);
+        }
+        assert!(GaussianData::new(vec![vec![1.0, 2.0], vec![2.0, 1.0]], 10).is_err());
+        assert!(GaussianData::new(vec![vec![1.0, 0.5], vec![0.0, 1.0]], 10).is_err());
+    }
+
+    #[test]
+    fn oja_converges_to_the_top_eigenvector(){
+        let data = gaussian();
+        let expected = data.top_eigenvector();
+        let mut network = MtNetwork::with_data(1, 1, 1, 0.005, data, Some(3)).unwrap();
+        let weights = network.train_complete_iterations(20000).unwrap();
+        let cosine: f32 = weights[0].iter().zip(expected.iter()).map(|(w, e)| w * e).sum::<f32>()
+            / weights[0].iter().map(|w| w * w).sum::<f32>().sqrt();
+        assert!(cosine.abs() > 0.99, "cosine similarity {} with the top eigenvector", cosine);
+    }
+}
@@ -0,0 +1,169 @@
+//! Patches from the frames of a video file, decoded with FFmpeg, for experiments on the statistics of natural movies.
+//! A patch covers the same square of one or more consecutive frames, so with a depth above one the neurons learn
+//! spatio-temporal receptive fields. Requires the `video` feature, which links against the FFmpeg libraries.
+use std::io;
+use std::path::Path;
+use ffmpeg_next as ffmpeg;
+use rand::RngCore;
+use tracing::info;
+use crate::data::dataset::Dataset;
+use crate::data::patch::Patch;
+use crate::data::sampler::{ImageSampler, PatchSampler};
+
+/// Which frames of a video are decoded.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FrameOptions {
+    /// Keeps every `step`-th frame, so neighbouring frames differ by more than a few pixels.
+    pub step: usize,
+    /// Stops decoding after this many frames were kept, as long videos do not fit into memory.
+    pub max_frames: Option<usize>,
+}
+
+impl Default for FrameOptions {
+    fn default() -> Self {
+        FrameOptions { step: 1, max_frames: None }
+    }
+}
+
+/// Grayscale frames of a video, stored row by row and frame after frame.
+#[derive(Debug, Clone, PartialEq)]
+pub struct VideoFrames {
+    pub count: usize,
+    pub rows: usize,
+    pub columns: usize,
+    pub pixels: Vec<u8>,
+}
+
+fn ffmpeg_error(error: ffmpeg::Error) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, error.to_string())
+}
+
+/// Decodes the frames of the best video stream of a file and converts them to grayscale.
+pub fn read_frames<P: AsRef<Path>>(path: P, options: FrameOptions) -> io::Result<VideoFrames> {
+    assert!(options.step > 0, "the frame step must be at least one");
+    ffmpeg::init().map_err(ffmpeg_error)?;
+    let mut input = ffmpeg::format::input(&path).map_err(ffmpeg_error)?;
+    let stream = input.streams().best(ffmpeg::media::Type::Video)
+        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, "the file has no video stream"))?;
+    let index = stream.index();
+    let mut decoder = ffmpeg::codec::context::Context::from_parameters(stream.parameters()).map_err(ffmpeg_error)?
+        .decoder().video().map_err(ffmpeg_error)?;
+    let mut scaler = ffmpeg::software::scaling::Context::get(decoder.format(), decoder.width(), decoder.height(),
+        ffmpeg::format::Pixel::GRAY8, decoder.width(), decoder.height(), ffmpeg::software::scaling::Flags::BILINEAR).map_err(ffmpeg_error)?;
+
+    let mut frames = VideoFrames { count: 0, rows: decoder.height() as usize, columns: decoder.width() as usize, pixels: Vec::new() };
+    let mut decoded = 0;
+    for (stream, packet) in input.packets() {
+        if frames.count == options.max_frames.unwrap_or(usize::MAX) {
+            break;
+        }
+        if stream.index() == index {
+            decoder.send_packet(&packet).map_err(ffmpeg_error)?;
+            receive_frames(&mut decoder, &mut scaler, &mut frames, &mut decoded, options).map_err(ffmpeg_error)?;
+        }
+    }
+    decoder.send_eof().map_err(ffmpeg_error)?;
+    receive_frames(&mut decoder, &mut scaler, &mut frames, &mut decoded, options).map_err(ffmpeg_error)?;
+    info!(frames = frames.count, decoded, rows = frames.rows, columns = frames.columns, "decoded video");
+    Ok(frames)
+}
+
+// Drains the frames the decoder has ready, keeping every `step`-th one. Rows of a decoded frame are padded to the stride of
+// the plane, so they are copied one by one.
+fn receive_frames(decoder: &mut ffmpeg::decoder::Video, scaler: &mut ffmpeg::software::scaling::Context, frames: &mut VideoFrames,
+                  decoded: &mut usize, options: FrameOptions) -> Result<(), ffmpeg::Error> {
+    let mut frame = ffmpeg::util::frame::video::Video::empty();
+    while decoder.receive_frame(&mut frame).is_ok() {
+        let keep = decoded.checked_rem(options.step) == Some(0) && frames.count < options.max_frames.unwrap_or(usize::MAX);
+        *decoded += 1;
+        if !keep {
+            continue;
+        }
+        let mut gray = ffmpeg::util::frame::video::Video::empty();
+        scaler.run(&frame, &mut gray)?;
+        let stride = gray.stride(0);
+        for row in gray.data(0).chunks(stride).take(frames.rows) {
+            frames.pixels.extend_from_slice(&row[..frames.columns]);
+        }
+        frames.count += 1;
+    }
+    Ok(())
+}
+
+/// Patches sampled from the frames of a video, `depth` consecutive frames at a time.
+pub struct VideoDataset {
+    frames: VideoFrames,
+    depth: usize,
+    sampler: ImageSampler,
+}
+
+impl VideoDataset {
+    pub fn open<P: AsRef<Path>>(path: P, options: FrameOptions, patch_width: usize, depth: usize) -> io::Result<VideoDataset> {
+        VideoDataset::new(read_frames(path, options)?, patch_width, depth)
+    }
+
+    /// The video needs at least `depth` frames of at least `patch_width` pixels in both directions.
+    pub fn new(frames: VideoFrames, patch_width: usize, depth: usize) -> io::Result<VideoDataset> {
+        if frames.pixels.len() != frames.count * frames.rows * frames.columns {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, format!("{} bytes of pixels for {} frames", frames.pixels.len(), frames.count)));
+        }
+        if depth == 0 || depth > frames.count {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, format!("a depth of {} frames does not fit a video of {} frames", depth, frames.count)));
+        }
+        let sampler = ImageSampler::new(patch_width, frames.rows, frames.columns)?;
+        Ok(VideoDataset { frames, depth, sampler })
+    }
+
+    pub fn frames(&self) -> &VideoFrames {
+        &self.frames
+    }
+
+    pub fn depth(&self) -> usize {
+        self.depth
+    }
+
+    /// Changes how patches are placed within the frames, see `ImageSampler::set`.
+    pub fn set_sampler(&mut self, sampler: PatchSampler) {
+        self.sampler.set(sampler);
+    }
+
+    pub fn patch_width(&self) -> usize {
+        self.sampler.patch_width()
+    }
+}
+
+impl Dataset for VideoDataset {
+    // Number of positions in time a patch can start at.
+    fn len(&self) -> usize {
+        self.frames.count - self.depth + 1
+    }
+
+    fn patch_dim(&self) -> usize {
+        self.sampler.patch_size() * self.depth
+    }
+
+    // Consecutive frames are stored one after another, so they are sampled like the channels of a single image.
+    fn sample_patch(&self, index: usize, rng: &mut dyn RngCore) -> Patch {
+        let frame = self.frames.rows * self.frames.columns;
+        let frames = &self.frames.pixels[(index % self.len()) * frame..][..self.depth * frame];
+        self.sampler.sample_channels(frames, self.depth, 1.0 / 256.0, rng)
+    }
+}
+
+#[cfg(test)]
+mod test{
+    use super::*;
+
+    #[test]
+    fn patches_span_consecutive_frames(){
+        // Every pixel of a frame holds the number of the frame.
+        let frames = VideoFrames { count: 4, rows: 3, columns: 3, pixels: (0..4u8).flat_map(|frame| vec![frame * 64; 9]).collect() };
+        let dataset = VideoDataset::new(frames.clone(), 2, 3).unwrap();
+        assert_eq!((dataset.len(), dataset.patch_dim()), (2, 12));
+        let patch = dataset.sample_patch(1, &mut rand::thread_rng());
+        assert_eq!(patch, [vec![0.25; 4], vec![0.5; 4], vec![0.75; 4]].concat());
+
+        assert!(VideoDataset::new(frames.clone(), 2, 5).is_err());
+        assert!(VideoDataset::new(frames, 4, 1).is_err());
+    }
+}
@@ -0,0 +1,106 @@
+//! Preprocessing of patches before training: mean subtraction and optional ZCA whitening.
+//! Oja's rule converges much more reliably on zero-mean, decorrelated input, since otherwise the
+//! first component is dominated by the mean intensity of the patches.
+use crate::data::patch::Patch;
+use crate::utils::linalg::{covariance, mean_patch, symmetric_eigen};
+
+/// Regularization added to the eigenvalues before whitening, keeping near-constant directions from being amplified into noise.
+pub const DEFAULT_EPSILON: f32 = 0.1;
+
+/// Transformation fitted on a sample of patches and applied to every patch handed to training.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Whitening {
+    mean: Patch,
+    transform: Option<Vec<Patch>>,
+}
+
+impl Whitening {
+    /// Only subtracts the mean patch of the sample.
+    pub fn centering(patches: &[Patch]) -> Whitening {
+        Whitening { mean: mean_patch(patches), transform: None }
+    }
+
+    /// Subtracts the mean patch and applies ZCA whitening, so that the covariance of the transformed patches is close to the identity
+    /// while they stay as similar as possible to the original patches.
+    pub fn zca(patches: &[Patch], epsilon: f32) -> Whitening {
+        let mean = mean_patch(patches);
+        let (eigenvalues, eigenvectors) = symmetric_eigen(covariance(patches, &mean));
+        let dim = mean.len();
+
+        // W = U diag(1 / sqrt(lambda + epsilon)) U^T
+        let mut transform = vec![vec![0.0f32; dim]; dim];
+        for (row, transform_row) in transform.iter_mut().enumerate() {
+            for (column, value) in transform_row.iter_mut().enumerate() {
+                let mut sum = 0.0;
+                for k in 0..dim {
+                    sum += eigenvectors[row][k] * eigenvectors[column][k] / (eigenvalues[k].max(0.0) + epsilon as f64).sqrt();
+                }
+                *value = sum as f32;
+            }
+        }
+
+        Whitening { mean, transform: Some(transform) }
+    }
+
+    pub fn mean(&self) -> &[f32] {
+        &self.mean
+    }
+
+    pub fn is_whitening(&self) -> bool {
+        self.transform.is_some()
+    }
+
+    pub fn apply(&self, patch: &[f32]) -> Patch {
+        assert_eq!(patch.len(), self.mean.len(), "patch does not match the size the preprocessing was fitted on");
+        let centered: Patch = patch.iter().zip(self.mean.iter()).map(|(x, m)| x - m).collect();
+        match &self.transform {
+            Some(transform) => transform.iter()
+                .map(|row| row.iter().zip(centered.iter()).map(|(w, x)| w * x).sum())
+                .collect(),
+            None => centered,
+        }
+    }
+}
+
+#[cfg(test)]
+mod test{
+    use super::*;
+    use rand::{Rng, SeedableRng};
+    use rand::rngs::StdRng;
+
+    fn correlated_patches() -> Vec<Patch> {
+        let mut rng = StdRng::seed_from_u64(3);
+        (0..2000).map(|_| {
+            let a: f32 = rng.gen::<f32>() - 0.5;
+            let b: f32 = rng.gen::<f32>() - 0.5;
+            let c: f32 = rng.gen::<f32>() - 0.5;
+            vec![a + 2.0, a + 0.5 * b, 3.0 * b + c - 1.0]
+        }).collect()
+    }
+
+    #[test]
+    fn centering_removes_the_mean(){
+        let patches = correlated_patches();
+        let centering = Whitening::centering(&patches);
+        let centered: Vec<Patch> = patches.iter().map(|p| centering.apply(p)).collect();
+
+        for value in mean_patch(&centered) {
+            assert!(value.abs() < 1e-4);
+        }
+    }
+
+    #[test]
+    fn zca_decorrelates_the_patches(){
+        let patches = correlated_patches();
+        let zca = Whitening::zca(&patches, 0.0);
+        let whitened: Vec<Patch> = patches.iter().map(|p| zca.apply(p)).collect();
+        let covariance = covariance(&whitened, &mean_patch(&whitened));
+
+        for (i, row) in covariance.iter().enumerate() {
+            for (j, value) in row.iter().enumerate() {
+                let expected = if i == j { 1.0 } else { 0.0 };
+                assert!((value - expected).abs() < 1e-2, "covariance[{}][{}] = {}", i, j, value);
+            }
+        }
+    }
+}
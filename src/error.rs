@@ -0,0 +1,65 @@
+//! Errors returned by the library, so consumers can handle failures instead of the library panicking.
+use std::{fmt, io};
+use crate::threading::thread_pool::PoolCreationError;
+
+/// Everything that can go wrong when creating or training a network.
+#[derive(Debug)]
+pub enum Error {
+    /// The parameters of the network are invalid, e.g. the neurons can not be split into sections of the given size.
+    InvalidConfig(String),
+    /// The threads used for training could not be started.
+    ThreadPool(String),
+    /// A worker thread panicked, so only some of the neurons were trained.
+    WorkerPanicked { trained: usize, expected: usize },
+    /// The weights of a neuron became NaN or infinite, usually because the learning rate is too high. `epoch` is the number
+    /// of patches the neuron had been trained on when the divergence was detected. The network keeps the weights it had before.
+    Diverged { neuron: usize, epoch: usize },
+    /// Training was cancelled through a `CancellationToken`. The network keeps the weights trained up to that point.
+    Cancelled,
+    /// Data loaded from disk, such as a checkpoint, does not fit the network.
+    Incompatible(String),
+    /// Rendering an image failed.
+    Image(String),
+    /// No GPU could be found, or it failed while training.
+    Gpu(String),
+    Io(io::Error),
+}
+
+pub type Result<T> = std::result::Result<T, Error>;
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Error::InvalidConfig(message) => write!(f, "invalid configuration: {}", message),
+            Error::ThreadPool(message) => write!(f, "unable to start worker threads: {}", message),
+            Error::WorkerPanicked { trained, expected } => write!(f, "a worker thread panicked, only {} of {} neurons were trained", trained, expected),
+            Error::Diverged { neuron, epoch } => write!(f, "the weights of neuron {} diverged to NaN or infinity by epoch {}, try a lower learning rate", neuron, epoch),
+            Error::Cancelled => write!(f, "training was cancelled"),
+            Error::Incompatible(message) => write!(f, "incompatible data: {}", message),
+            Error::Image(message) => write!(f, "unable to render image: {}", message),
+            Error::Gpu(message) => write!(f, "gpu error: {}", message),
+            Error::Io(error) => write!(f, "{}", error),
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::Io(error) => Some(error),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for Error {
+    fn from(error: io::Error) -> Error {
+        Error::Io(error)
+    }
+}
+
+impl From<PoolCreationError> for Error {
+    fn from(error: PoolCreationError) -> Error {
+        Error::ThreadPool(error.to_string())
+    }
+}
@@ -0,0 +1,355 @@
+//! Declarative description of a training run, read from a TOML file so experiments can be repeated exactly.
+//!
+//! ```toml
+//! [network]
+//! neurons = 64
+//! threads = 4           # or "auto" for one per available core
+//! lr = 0.01
+//! rule = "oja"          # oja, oja-momentum, bcm, hebbian, ica or sanger
+//! epochs = 50000
+//! seed = 42
+//!
+//! [dataset]
+//! kind = "mnist"        # mnist, fashion, emnist or csv
+//! path = "data/"
+//!
+//! [schedule]
+//! kind = "cosine"       # constant, exponential, step or cosine
+//! min_lr = 0.001
+//!
+//! [output]
+//! checkpoint = "weights.bin"
+//! ```
+//!
+//! Every setting is optional and defaults to the same value as the command line. Unknown tables and keys are rejected, so
+//! a misspelled setting does not silently fall back to its default.
+use std::convert::TryFrom;
+use std::fs;
+use std::path::{Path, PathBuf};
+use serde::Deserialize;
+use crate::data::idx::EmnistSplit;
+use crate::error::{Error, Result};
+use crate::model::ica::Nonlinearity;
+use crate::model::init::Init;
+use crate::model::learning_rule::LearningRule;
+use crate::threading::thread_pool::Threads;
+use crate::utils::constants::PATCH_WIDTH;
+
+/// How the neurons are trained, see the training methods of `MtNetwork`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TrainingMethod {
+    Pool,
+    Rayon,
+    Scoped,
+    Matrix,
+    /// All neurons in lockstep on this many shared patches per epoch.
+    Synchronous { patches_per_epoch: usize },
+    /// Sanger's rule, training the neurons one after the other.
+    Hierarchical,
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(try_from = "NetworkTable")]
+pub struct NetworkConfig {
+    pub neurons: usize,
+    pub threads: Threads,
+    pub section_size: Option<usize>,
+    pub lr: f32,
+    pub rule: LearningRule,
+    pub method: TrainingMethod,
+    pub init: Init,
+    pub batch_size: usize,
+    pub shuffle: bool,
+    /// Number of training patches every neuron is trained on.
+    pub epochs: usize,
+    pub patch_width: usize,
+    pub seed: Option<u64>,
+}
+
+impl Default for NetworkConfig {
+    fn default() -> NetworkConfig {
+        NetworkConfig {
+            neurons: 1000,
+            threads: Threads::Fixed(8),
+            section_size: None,
+            lr: 0.01,
+            rule: LearningRule::Oja,
+            method: TrainingMethod::Pool,
+            init: Init::Uniform,
+            batch_size: 1,
+            shuffle: false,
+            epochs: 50000,
+            patch_width: PATCH_WIDTH,
+            seed: None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DatasetKind {
+    Mnist,
+    FashionMnist,
+    Emnist(EmnistSplit),
+    /// One sample per line, optionally below a header line.
+    Csv { header: bool },
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(try_from = "DatasetTable")]
+pub struct DatasetConfig {
+    pub kind: DatasetKind,
+    /// Directory holding the image files, or the CSV file.
+    pub path: PathBuf,
+}
+
+impl Default for DatasetConfig {
+    fn default() -> DatasetConfig {
+        DatasetConfig { kind: DatasetKind::Mnist, path: PathBuf::from("data/") }
+    }
+}
+
+/// The learning rate schedule, see `model::schedule`. The cosine schedule anneals over all epochs of the run.
+#[derive(Debug, Clone, Copy, PartialEq, Default, Deserialize)]
+#[serde(try_from = "ScheduleTable")]
+pub enum ScheduleConfig {
+    #[default]
+    Constant,
+    Exponential { gamma: f32 },
+    Step { step_size: usize, gamma: f32 },
+    Cosine { min_lr: f32 },
+}
+
+/// Files the results of the run are written to, each one only if it is set.
+#[derive(Debug, Clone, Default, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct OutputConfig {
+    pub checkpoint: Option<PathBuf>,
+    pub npy: Option<PathBuf>,
+    pub onnx: Option<PathBuf>,
+    pub receptive_fields: Option<PathBuf>,
+    pub history: Option<PathBuf>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ExperimentConfig {
+    pub network: NetworkConfig,
+    pub dataset: DatasetConfig,
+    pub schedule: ScheduleConfig,
+    pub output: OutputConfig,
+}
+
+impl ExperimentConfig {
+    pub fn from_toml<P: AsRef<Path>>(path: P) -> Result<ExperimentConfig> {
+        ExperimentConfig::parse(&fs::read_to_string(path)?)
+    }
+
+    pub fn parse(text: &str) -> Result<ExperimentConfig> {
+        toml::from_str(text).map_err(|error| Error::InvalidConfig(error.to_string()))
+    }
+}
+
+// The tables as they are written in the file. Every setting is optional, and the settings of a table are only combined into
+// the types above once the whole table has been read, e.g. `tau` into the BCM rule.
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct NetworkTable {
+    neurons: Option<usize>,
+    threads: Option<ThreadsSetting>,
+    section_size: Option<usize>,
+    lr: Option<f32>,
+    rule: Option<String>,
+    tau: Option<f32>,
+    momentum: Option<f32>,
+    normalize_every: Option<usize>,
+    nonlinearity: Option<String>,
+    method: Option<String>,
+    patches_per_epoch: Option<usize>,
+    init: Option<String>,
+    init_scale: Option<f32>,
+    batch_size: Option<usize>,
+    shuffle: Option<bool>,
+    epochs: Option<usize>,
+    patch_width: Option<usize>,
+    seed: Option<u64>,
+}
+
+/// Either a number of threads or "auto".
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum ThreadsSetting {
+    Count(usize),
+    Name(String),
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct DatasetTable {
+    kind: Option<String>,
+    path: Option<PathBuf>,
+    split: Option<String>,
+    header: Option<bool>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct ScheduleTable {
+    kind: Option<String>,
+    gamma: Option<f32>,
+    step_size: Option<usize>,
+    min_lr: Option<f32>,
+}
+
+impl TryFrom<NetworkTable> for NetworkConfig {
+    type Error = String;
+
+    fn try_from(table: NetworkTable) -> std::result::Result<NetworkConfig, String> {
+        let defaults = NetworkConfig::default();
+        let rule = match table.rule.as_deref().unwrap_or("oja") {
+            "oja" | "sanger" => LearningRule::Oja,
+            "oja-momentum" => LearningRule::OjaMomentum { beta: table.momentum.unwrap_or(0.9) },
+            "bcm" => LearningRule::Bcm { tau: table.tau.unwrap_or(100.0) },
+            "hebbian" => LearningRule::Hebbian { normalize_every: table.normalize_every.unwrap_or(1).max(1) },
+            "ica" => LearningRule::Ica {
+                nonlinearity: match table.nonlinearity.as_deref().unwrap_or("tanh") {
+                    "tanh" => Nonlinearity::Tanh,
+                    "cube" => Nonlinearity::Cube,
+                    other => return Err(unknown("network.nonlinearity", other, "tanh or cube")),
+                },
+            },
+            other => return Err(unknown("network.rule", other, "oja, oja-momentum, bcm, hebbian, ica or sanger")),
+        };
+        let method = match (table.rule.as_deref(), table.method.as_deref().unwrap_or("pool")) {
+            (Some("sanger"), _) => TrainingMethod::Hierarchical,
+            (_, "pool") => TrainingMethod::Pool,
+            (_, "rayon") => TrainingMethod::Rayon,
+            (_, "scoped") => TrainingMethod::Scoped,
+            (_, "matrix") => TrainingMethod::Matrix,
+            (_, "synchronous") => TrainingMethod::Synchronous { patches_per_epoch: table.patches_per_epoch.unwrap_or(100).max(1) },
+            (_, other) => return Err(unknown("network.method", other, "pool, rayon, scoped, matrix or synchronous")),
+        };
+        let scale = table.init_scale.unwrap_or(0.1);
+        let init = match table.init.as_deref().unwrap_or("uniform") {
+            "uniform" => Init::Uniform,
+            "symmetric" => Init::UniformSymmetric { scale },
+            "gaussian" => Init::Gaussian { std: scale },
+            "xavier" => Init::Xavier,
+            "unit-norm" => Init::UnitNorm,
+            other => return Err(unknown("network.init", other, "uniform, symmetric, gaussian, xavier or unit-norm")),
+        };
+
+        Ok(NetworkConfig {
+            neurons: table.neurons.unwrap_or(defaults.neurons),
+            threads: match table.threads {
+                Some(ThreadsSetting::Count(threads)) => Threads::Fixed(threads),
+                Some(ThreadsSetting::Name(threads)) => threads.parse().map_err(|_| unknown("network.threads", &threads, "a number of threads or auto"))?,
+                None => defaults.threads,
+            },
+            section_size: table.section_size,
+            lr: table.lr.unwrap_or(defaults.lr),
+            rule,
+            method,
+            init,
+            batch_size: table.batch_size.unwrap_or(defaults.batch_size),
+            shuffle: table.shuffle.unwrap_or(defaults.shuffle),
+            epochs: table.epochs.unwrap_or(defaults.epochs),
+            patch_width: table.patch_width.unwrap_or(defaults.patch_width),
+            seed: table.seed,
+        })
+    }
+}
+
+impl TryFrom<DatasetTable> for DatasetConfig {
+    type Error = String;
+
+    fn try_from(table: DatasetTable) -> std::result::Result<DatasetConfig, String> {
+        let kind = match table.kind.as_deref().unwrap_or("mnist") {
+            "mnist" => DatasetKind::Mnist,
+            "fashion" => DatasetKind::FashionMnist,
+            "emnist" => {
+                let split = table.split.as_deref().unwrap_or("balanced");
+                DatasetKind::Emnist(split.parse().map_err(|_| unknown("dataset.split", split, "byclass, bymerge, balanced, letters, digits or mnist"))?)
+            }
+            "csv" => DatasetKind::Csv { header: table.header.unwrap_or(false) },
+            other => return Err(unknown("dataset.kind", other, "mnist, fashion, emnist or csv")),
+        };
+        Ok(DatasetConfig { kind, path: table.path.unwrap_or_else(|| DatasetConfig::default().path) })
+    }
+}
+
+impl TryFrom<ScheduleTable> for ScheduleConfig {
+    type Error = String;
+
+    fn try_from(table: ScheduleTable) -> std::result::Result<ScheduleConfig, String> {
+        Ok(match table.kind.as_deref().unwrap_or("constant") {
+            "constant" => ScheduleConfig::Constant,
+            "exponential" => ScheduleConfig::Exponential { gamma: table.gamma.unwrap_or(0.9999) },
+            "step" => ScheduleConfig::Step { step_size: table.step_size.unwrap_or(10000), gamma: table.gamma.unwrap_or(0.9999) },
+            "cosine" => ScheduleConfig::Cosine { min_lr: table.min_lr.unwrap_or(0.0) },
+            other => return Err(unknown("schedule.kind", other, "constant, exponential, step or cosine")),
+        })
+    }
+}
+
+fn unknown(setting: &str, value: &str, expected: &str) -> String {
+    format!("unknown value `{}` for `{}`, expected {}", value, setting, expected)
+}
+
+#[cfg(test)]
+mod test{
+    use super::*;
+
+    #[test]
+    fn missing_settings_use_the_defaults(){
+        assert_eq!(ExperimentConfig::parse("").unwrap(), ExperimentConfig::default());
+
+        let config = ExperimentConfig::parse(r#"
+            [network]
+            neurons = 64
+            rule = "bcm"
+            tau = 50
+            method = "synchronous"
+            init = "gaussian"
+            init_scale = 0.5
+            seed = 7
+
+            [dataset]
+            kind = "emnist"
+            split = "letters"
+            path = "emnist/"
+
+            [schedule]
+            kind = "step"
+            step_size = 100
+
+            [output]
+            checkpoint = "weights.bin"
+        "#).unwrap();
+        assert_eq!(config.network.neurons, 64);
+        assert_eq!(config.network.threads, Threads::Fixed(8));
+        assert_eq!(config.network.rule, LearningRule::Bcm { tau: 50.0 });
+        assert_eq!(config.network.method, TrainingMethod::Synchronous { patches_per_epoch: 100 });
+        assert_eq!(config.network.init, Init::Gaussian { std: 0.5 });
+        assert_eq!(config.network.seed, Some(7));
+        assert_eq!(config.network.lr, 0.01);
+        assert_eq!(config.dataset, DatasetConfig { kind: DatasetKind::Emnist(EmnistSplit::Letters), path: PathBuf::from("emnist/") });
+        assert_eq!(config.schedule, ScheduleConfig::Step { step_size: 100, gamma: 0.9999 });
+        assert_eq!(config.output.checkpoint, Some(PathBuf::from("weights.bin")));
+        assert_eq!(config.output.npy, None);
+    }
+
+    #[test]
+    fn unknown_settings_are_rejected(){
+        assert!(ExperimentConfig::parse("[network]\nneuronz = 4\n").is_err());
+        assert!(ExperimentConfig::parse("[networks]\n").is_err());
+        assert!(ExperimentConfig::parse("neurons = 4\n").is_err());
+        assert!(matches!(ExperimentConfig::parse("[network]\nneurons = \n"), Err(Error::InvalidConfig(_))));
+        assert!(ExperimentConfig::parse("[network]\nthreads = \"many\"\n").is_err());
+        assert_eq!(ExperimentConfig::parse("[network]\nthreads = \"auto\"\n").unwrap().network.threads, Threads::Auto);
+        assert!(ExperimentConfig::parse("[network]\nrule = \"backprop\"\n").is_err());
+        assert!(ExperimentConfig::parse("[network]\nneurons = -4\n").is_err());
+        assert_eq!(ExperimentConfig::parse("[network]\nrule = \"sanger\"\n").unwrap().network.method, TrainingMethod::Hierarchical);
+        assert_eq!(ExperimentConfig::parse("[network]\nrule = \"oja-momentum\"\nmomentum = 0.5\n").unwrap().network.rule, LearningRule::OjaMomentum { beta: 0.5 });
+    }
+}
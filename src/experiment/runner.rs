@@ -0,0 +1,167 @@
+//! Runs the experiment described by an `ExperimentConfig`: loads the dataset, builds and trains the network, and writes the
+//! configured outputs.
+use std::time::{Duration, Instant};
+use rand::SeedableRng;
+use rand::rngs::StdRng;
+use tracing::info;
+use crate::data::csv::CsvData;
+use crate::data::dataset::Dataset;
+use crate::data::idx::IdxDataset;
+use crate::data::mnist::MnistData;
+use crate::data::patch::Patch;
+use crate::error::{Error, Result};
+use crate::experiment::config::{DatasetKind, ExperimentConfig, ScheduleConfig, TrainingMethod};
+use crate::model::eval::Evaluation;
+use crate::model::history::TrainingHistory;
+use crate::model::network::{auto_section_size, MtNetwork, NetworkBuilder};
+use crate::model::npy::save_npy;
+use crate::model::onnx::save_onnx;
+use crate::model::schedule::{CosineAnnealing, ExponentialDecay, StepDecay};
+use crate::model::timing::Timing;
+
+/// What an experiment produced, besides the files it wrote.
+#[derive(Debug, Clone)]
+pub struct ExperimentResult {
+    pub weights: Vec<Patch>,
+    pub history: TrainingHistory,
+    pub timing: Timing,
+    pub elapsed: Duration,
+    /// Reconstruction error of the final weights on held-out patches, only measured by `evaluate_experiment`.
+    pub reconstruction_error: Option<f32>,
+}
+
+/// Trains a network as described by the configuration and writes every configured output.
+pub fn run_experiment(config: &ExperimentConfig) -> Result<ExperimentResult> {
+    load(config, None)
+}
+
+/// Same as `run_experiment`, and measures the reconstruction error of the trained weights on `samples` held-out patches. The
+/// patches are drawn with their own seed, so runs on the same dataset are evaluated on the same patches whatever their seed.
+pub fn evaluate_experiment(config: &ExperimentConfig, samples: usize, seed: u64) -> Result<ExperimentResult> {
+    if samples == 0 {
+        return Err(Error::InvalidConfig("at least one evaluation patch is needed".to_string()));
+    }
+    load(config, Some((samples, seed)))
+}
+
+fn load(config: &ExperimentConfig, evaluation: Option<(usize, u64)>) -> Result<ExperimentResult> {
+    let network = &config.network;
+    let path = &config.dataset.path;
+    match config.dataset.kind {
+        DatasetKind::Mnist => {
+            let path = path.to_str().ok_or_else(|| Error::InvalidConfig(format!("{} is not a valid path", path.display())))?;
+            let section_size = network.section_size.unwrap_or_else(|| auto_section_size(network.neurons, network.threads.count()));
+            run(config, MnistData::from_path(section_size, network.patch_width, path)?, evaluation)
+        }
+        DatasetKind::FashionMnist => run(config, IdxDataset::fashion_mnist(path, network.patch_width)?, evaluation),
+        DatasetKind::Emnist(split) => run(config, IdxDataset::emnist(path, split, network.patch_width)?, evaluation),
+        DatasetKind::Csv { header } => run(config, CsvData::from_path(path, header)?, evaluation),
+    }
+}
+
+fn run<D: Dataset + 'static>(config: &ExperimentConfig, dataset: D, evaluation: Option<(usize, u64)>) -> Result<ExperimentResult> {
+    let settings = &config.network;
+    let evaluation = evaluation.map(|(samples, seed)| Evaluation::held_out(&dataset, samples, 1, &mut StdRng::seed_from_u64(seed)));
+    let mut builder = NetworkBuilder::new()
+        .neurons(settings.neurons)
+        .threads(settings.threads)
+        .lr(settings.lr)
+        .rule(settings.rule)
+        .init(settings.init)
+        .batch_size(settings.batch_size)
+        .shuffle(settings.shuffle)
+        .patch_width(settings.patch_width)
+        .dataset(dataset);
+    if let Some(section_size) = settings.section_size {
+        builder = builder.section_size(section_size);
+    }
+    if let Some(seed) = settings.seed {
+        builder = builder.seed(seed);
+    }
+    builder = match config.schedule {
+        ScheduleConfig::Constant => builder,
+        ScheduleConfig::Exponential { gamma } => builder.schedule(ExponentialDecay { gamma }),
+        ScheduleConfig::Step { step_size, gamma } => builder.schedule(StepDecay { step_size, gamma }),
+        ScheduleConfig::Cosine { min_lr } => builder.schedule(CosineAnnealing { total_epochs: settings.epochs, min_lr }),
+    };
+    let mut network = builder.build()?;
+
+    let now = Instant::now();
+    train(&mut network, settings.method, settings.epochs)?;
+    let elapsed = now.elapsed();
+    info!(elapsed_ms = elapsed.as_millis() as u64, method = ?settings.method, "experiment trained");
+
+    let output = &config.output;
+    if let Some(path) = &output.checkpoint {
+        let mut metadata = network.metadata();
+        metadata.dataset = Some(format!("{:?} ({})", config.dataset.kind, config.dataset.path.display()));
+        network.save_checkpoint_with(path, metadata)?;
+    }
+    if let Some(path) = &output.npy {
+        save_npy(&network.weights().views(), path)?;
+    }
+    if let Some(path) = &output.onnx {
+        save_onnx(&network.weights().to_patches(), path)?;
+    }
+    if let Some(path) = &output.receptive_fields {
+        network.save_receptive_fields(path, 10)?;
+    }
+    if let Some(path) = &output.history {
+        network.history().save(path)?;
+    }
+    let reconstruction_error = evaluation.map(|evaluation| evaluation.evaluate(&network.weights().views()));
+    Ok(ExperimentResult { weights: network.weights().to_patches(), history: network.history().clone(), timing: network.timing().clone(), elapsed, reconstruction_error })
+}
+
+// Trains with the given method, where `epochs` is the number of patches every neuron is trained on, also for the
+// synchronous method.
+pub(crate) fn train<D: Dataset>(network: &mut MtNetwork<D>, method: TrainingMethod, epochs: usize) -> Result<Vec<Patch>> {
+    match method {
+        TrainingMethod::Pool => network.train_complete_iterations(epochs),
+        #[cfg(feature = "rayon")]
+        TrainingMethod::Rayon => network.train_parallel_rayon(epochs),
+        #[cfg(not(feature = "rayon"))]
+        TrainingMethod::Rayon => Err(Error::InvalidConfig("the rayon method requires building with `--features rayon`".to_string())),
+        TrainingMethod::Scoped => network.train_scoped(epochs),
+        TrainingMethod::Matrix => network.train_matrix(epochs),
+        TrainingMethod::Synchronous { patches_per_epoch } => network.train_synchronous(epochs.div_ceil(patches_per_epoch), patches_per_epoch),
+        TrainingMethod::Hierarchical => network.train_hierarchical(epochs),
+    }
+}
+
+#[cfg(test)]
+mod test{
+    use super::*;
+    use crate::experiment::config::ExperimentConfig;
+
+    #[test]
+    fn runs_a_configured_experiment(){
+        let dir = std::env::temp_dir().join(format!("rust_ml_experiment_test_{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let csv = dir.join("samples.csv");
+        let rows: Vec<String> = (0..20).map(|i| format!("{},{},{},{}", i % 3, i % 5, 1, i % 2)).collect();
+        std::fs::write(&csv, rows.join("\n")).unwrap();
+        let checkpoint = dir.join("weights.bin");
+
+        let config = ExperimentConfig::parse(&format!(r#"
+            [network]
+            neurons = 4
+            threads = 2
+            epochs = 200
+            seed = 3
+            [dataset]
+            kind = "csv"
+            path = '{}'
+            [schedule]
+            kind = "cosine"
+            [output]
+            checkpoint = '{}'
+        "#, csv.display(), checkpoint.display())).unwrap();
+
+        let result = run_experiment(&config).unwrap();
+        assert_eq!(result.weights.len(), 4);
+        assert!(checkpoint.exists());
+        assert_eq!(run_experiment(&config).unwrap().weights, result.weights);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,312 @@
+//! Repeats an experiment with different seeds and summarizes the results, so a change to the threading or the learning rule
+//! can be judged against the noise between runs instead of a single run. Two configurations are compared with Welch's t-test,
+//! which does not assume that both have the same variance.
+use std::fmt;
+use std::time::Duration;
+use tracing::info;
+use crate::error::{Error, Result};
+use crate::experiment::config::{ExperimentConfig, OutputConfig};
+use crate::experiment::runner::evaluate_experiment;
+
+/// Mean and spread of a metric over the runs which produced a finite value.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Summary {
+    pub runs: usize,
+    pub mean: f64,
+    /// Sample standard deviation, zero for a single run.
+    pub std_dev: f64,
+    pub min: f64,
+    pub max: f64,
+}
+
+impl Summary {
+    /// Summarizes the finite values, NaN for every statistic when there are none.
+    pub fn of(values: &[f64]) -> Summary {
+        let values: Vec<f64> = values.iter().copied().filter(|value| value.is_finite()).collect();
+        let runs = values.len();
+        let mean = values.iter().sum::<f64>() / runs as f64;
+        let std_dev = if runs > 1 {
+            (values.iter().map(|value| (value - mean) * (value - mean)).sum::<f64>() / (runs - 1) as f64).sqrt()
+        } else if runs == 1 {
+            0.0
+        } else {
+            f64::NAN
+        };
+        let min = values.iter().copied().reduce(f64::min).unwrap_or(f64::NAN);
+        let max = values.iter().copied().reduce(f64::max).unwrap_or(f64::NAN);
+        Summary { runs, mean, std_dev, min, max }
+    }
+
+    /// Standard error of the mean.
+    pub fn standard_error(&self) -> f64 {
+        self.std_dev / (self.runs as f64).sqrt()
+    }
+}
+
+impl fmt::Display for Summary {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:.6} ± {:.6} (min {:.6}, max {:.6}, {} runs)", self.mean, self.std_dev, self.min, self.max, self.runs)
+    }
+}
+
+/// Result of Welch's t-test for a difference between the means of two summaries.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Comparison {
+    /// Mean of the second summary minus the mean of the first.
+    pub difference: f64,
+    pub t: f64,
+    /// Welch–Satterthwaite approximation of the degrees of freedom.
+    pub degrees_of_freedom: f64,
+    /// Two-sided probability of a difference at least this large if both means were equal.
+    pub p_value: f64,
+}
+
+impl Comparison {
+    /// Welch's t-test, `None` unless both summaries have at least two runs.
+    pub fn welch(a: &Summary, b: &Summary) -> Option<Comparison> {
+        if a.runs < 2 || b.runs < 2 {
+            return None;
+        }
+        let difference = b.mean - a.mean;
+        let (variance_a, variance_b) = (a.std_dev * a.std_dev / a.runs as f64, b.std_dev * b.std_dev / b.runs as f64);
+        let variance = variance_a + variance_b;
+        if variance == 0.0 {
+            // Both metrics are constant, so any difference is certain.
+            let p_value = if difference == 0.0 { 1.0 } else { 0.0 };
+            return Some(Comparison { difference, t: difference / variance, degrees_of_freedom: (a.runs + b.runs - 2) as f64, p_value });
+        }
+        let t = difference / variance.sqrt();
+        let degrees_of_freedom = variance * variance
+            / (variance_a * variance_a / (a.runs - 1) as f64 + variance_b * variance_b / (b.runs - 1) as f64);
+        let p_value = incomplete_beta(degrees_of_freedom / 2.0, 0.5, degrees_of_freedom / (degrees_of_freedom + t * t));
+        Some(Comparison { difference, t, degrees_of_freedom, p_value })
+    }
+
+    pub fn is_significant(&self, alpha: f64) -> bool {
+        self.p_value < alpha
+    }
+}
+
+impl fmt::Display for Comparison {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "difference {:+.6}, t = {:.3}, df = {:.1}, p = {:.4}", self.difference, self.t, self.degrees_of_freedom, self.p_value)
+    }
+}
+
+/// Natural logarithm of the gamma function, with the Lanczos approximation.
+fn ln_gamma(x: f64) -> f64 {
+    const COEFFICIENTS: [f64; 6] = [76.18009172947146, -86.50532032941677, 24.01409824083091, -1.231739572450155, 0.1208650973866179e-2, -0.5395239384953e-5];
+    let tmp = x + 5.5 - (x + 0.5) * (x + 5.5).ln();
+    let series = COEFFICIENTS.iter().enumerate().fold(1.000000000190015, |sum, (i, c)| sum + c / (x + 1.0 + i as f64));
+    -tmp + (2.5066282746310005 * series / x).ln()
+}
+
+/// Regularized incomplete beta function `I_x(a, b)`, evaluated with its continued fraction.
+fn incomplete_beta(a: f64, b: f64, x: f64) -> f64 {
+    if x <= 0.0 {
+        return 0.0;
+    }
+    if x >= 1.0 {
+        return 1.0;
+    }
+    let front = (ln_gamma(a + b) - ln_gamma(a) - ln_gamma(b) + a * x.ln() + b * (1.0 - x).ln()).exp();
+    // The continued fraction converges quickly only below this point, above it the symmetry `I_x(a, b) = 1 - I_{1-x}(b, a)` is used.
+    if x < (a + 1.0) / (a + b + 2.0) {
+        front * beta_fraction(a, b, x) / a
+    } else {
+        1.0 - front * beta_fraction(b, a, 1.0 - x) / b
+    }
+}
+
+// Modified Lentz's method for the continued fraction of the incomplete beta function.
+fn beta_fraction(a: f64, b: f64, x: f64) -> f64 {
+    const TINY: f64 = 1e-300;
+    let clamp = |value: f64| if value.abs() < TINY { TINY } else { value };
+    let mut c = 1.0;
+    let mut d = 1.0 / clamp(1.0 - (a + b) * x / (a + 1.0));
+    let mut fraction = d;
+    for m in 1..=200 {
+        let m = m as f64;
+        let even = m * (b - m) * x / ((a + 2.0 * m - 1.0) * (a + 2.0 * m));
+        d = 1.0 / clamp(1.0 + even * d);
+        c = clamp(1.0 + even / c);
+        fraction *= d * c;
+        let odd = -(a + m) * (a + b + m) * x / ((a + 2.0 * m) * (a + 2.0 * m + 1.0));
+        d = 1.0 / clamp(1.0 + odd * d);
+        c = clamp(1.0 + odd / c);
+        let delta = d * c;
+        fraction *= delta;
+        if (delta - 1.0).abs() < 1e-12 {
+            break;
+        }
+    }
+    fraction
+}
+
+/// Final metrics of a single run.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct RunMetrics {
+    pub seed: u64,
+    /// Reconstruction error on the held-out patches, NaN if the run diverged.
+    pub reconstruction_error: f32,
+    /// Wall time of the training, excluding loading and evaluation.
+    pub elapsed: Duration,
+}
+
+/// How often a configuration is repeated and how the runs are evaluated.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Repetitions {
+    pub runs: usize,
+    /// Seed of the first run, every following run adds one. The held-out patches are always drawn with this seed.
+    pub seed: u64,
+    /// Number of held-out patches the reconstruction error is measured on.
+    pub evaluation_samples: usize,
+}
+
+impl Repetitions {
+    pub fn new(runs: usize) -> Repetitions {
+        Repetitions { runs, seed: 0, evaluation_samples: 1000 }
+    }
+
+    // Trains the configuration once per seed. The seed of the configuration is replaced, and its outputs are not written as
+    // every run would overwrite them. Runs which diverge get a reconstruction error of NaN and are left out of the summaries.
+    pub fn run(&self, config: &ExperimentConfig) -> Result<RepeatedRuns> {
+        if self.runs == 0 {
+            return Err(Error::InvalidConfig("at least one run is needed".to_string()));
+        }
+        let mut config = config.clone();
+        config.output = OutputConfig::default();
+        let mut runs = Vec::with_capacity(self.runs);
+        for seed in (self.seed..).take(self.runs) {
+            config.network.seed = Some(seed);
+            let metrics = match evaluate_experiment(&config, self.evaluation_samples, self.seed) {
+                Ok(result) => RunMetrics { seed, reconstruction_error: result.reconstruction_error.unwrap_or(f32::NAN), elapsed: result.elapsed },
+                Err(Error::Diverged { .. }) => RunMetrics { seed, reconstruction_error: f32::NAN, elapsed: Duration::ZERO },
+                Err(error) => return Err(error),
+            };
+            info!(seed, reconstruction_error = metrics.reconstruction_error, elapsed_ms = metrics.elapsed.as_millis() as u64, "repeated run completed");
+            runs.push(metrics);
+        }
+        Ok(RepeatedRuns { runs })
+    }
+}
+
+/// The runs of one configuration, in the order of their seeds.
+#[derive(Debug, Clone, PartialEq)]
+pub struct RepeatedRuns {
+    runs: Vec<RunMetrics>,
+}
+
+impl RepeatedRuns {
+    pub fn new(runs: Vec<RunMetrics>) -> RepeatedRuns {
+        RepeatedRuns { runs }
+    }
+
+    pub fn runs(&self) -> &[RunMetrics] {
+        &self.runs
+    }
+
+    pub fn diverged(&self) -> usize {
+        self.runs.iter().filter(|run| !run.reconstruction_error.is_finite()).count()
+    }
+
+    pub fn reconstruction_error(&self) -> Summary {
+        Summary::of(&self.runs.iter().map(|run| run.reconstruction_error as f64).collect::<Vec<_>>())
+    }
+
+    /// Training time in seconds of the runs which did not diverge.
+    pub fn elapsed(&self) -> Summary {
+        Summary::of(&self.runs.iter().filter(|run| run.reconstruction_error.is_finite()).map(|run| run.elapsed.as_secs_f64()).collect::<Vec<_>>())
+    }
+
+    /// Compares both metrics against the runs of another configuration, which is taken as the second one.
+    pub fn compare(&self, other: &RepeatedRuns) -> RunComparison {
+        RunComparison {
+            reconstruction_error: Comparison::welch(&self.reconstruction_error(), &other.reconstruction_error()),
+            elapsed: Comparison::welch(&self.elapsed(), &other.elapsed()),
+        }
+    }
+}
+
+impl fmt::Display for RepeatedRuns {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "reconstruction error  {}\ntime (s)              {}", self.reconstruction_error(), self.elapsed())?;
+        if self.diverged() > 0 {
+            write!(f, "\n{} of {} runs diverged", self.diverged(), self.runs.len())?;
+        }
+        Ok(())
+    }
+}
+
+/// Welch's t-test of both metrics of two configurations, `None` for a metric with fewer than two finite runs on either side.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct RunComparison {
+    pub reconstruction_error: Option<Comparison>,
+    pub elapsed: Option<Comparison>,
+}
+
+impl fmt::Display for RunComparison {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let line = |comparison: Option<Comparison>| comparison.map_or_else(|| "too few runs to compare".to_string(), |comparison| comparison.to_string());
+        write!(f, "reconstruction error  {}\ntime (s)              {}", line(self.reconstruction_error), line(self.elapsed))
+    }
+}
+
+#[cfg(test)]
+mod test{
+    use super::*;
+
+    #[test]
+    fn summaries_skip_diverged_runs(){
+        let summary = Summary::of(&[1.0, 2.0, f64::NAN, 3.0, 4.0]);
+        assert_eq!((summary.runs, summary.mean, summary.min, summary.max), (4, 2.5, 1.0, 4.0));
+        assert!((summary.std_dev - 1.6666666666666667f64.sqrt()).abs() < 1e-12);
+        assert_eq!(Summary::of(&[5.0]).std_dev, 0.0);
+        assert!(Summary::of(&[]).mean.is_nan());
+    }
+
+    #[test]
+    fn welch_test_matches_reference_values(){
+        // The first example of the Wikipedia article on Welch's t-test.
+        let a = Summary::of(&[27.5, 21.0, 19.0, 23.6, 17.0, 17.9, 16.9, 20.1, 21.9, 22.6, 23.1, 19.6, 19.0, 21.7, 21.4]);
+        let b = Summary::of(&[27.1, 22.0, 20.8, 23.4, 23.4, 23.5, 25.8, 22.0, 24.8, 20.2, 21.9, 22.1, 22.9, 20.5, 24.4]);
+        let comparison = Comparison::welch(&a, &b).unwrap();
+        assert!((comparison.t - 2.46).abs() < 0.01, "{}", comparison);
+        assert!((comparison.degrees_of_freedom - 24.988).abs() < 0.01, "{}", comparison);
+        assert!((comparison.p_value - 0.021).abs() < 0.001, "{}", comparison);
+        assert!(comparison.is_significant(0.05) && !comparison.is_significant(0.01));
+
+        assert_eq!(Comparison::welch(&Summary::of(&[1.0, 1.0]), &Summary::of(&[1.0, 1.0])).unwrap().p_value, 1.0);
+        assert!(Comparison::welch(&Summary::of(&[1.0]), &b).is_none());
+    }
+
+    #[test]
+    fn repeats_an_experiment_with_different_seeds(){
+        let dir = std::env::temp_dir().join(format!("rust_ml_stats_test_{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let csv = dir.join("samples.csv");
+        let rows: Vec<String> = (0..50).map(|i| format!("{},{},{},{}", i % 3, i % 5, i % 7, i % 2)).collect();
+        std::fs::write(&csv, rows.join("\n")).unwrap();
+        let config = |lr: f32| ExperimentConfig::parse(&format!(r#"
+            [network]
+            neurons = 2
+            threads = 1
+            epochs = 200
+            lr = {}
+            [dataset]
+            kind = "csv"
+            path = '{}'
+        "#, lr, csv.display())).unwrap();
+
+        let mut repetitions = Repetitions::new(3);
+        repetitions.evaluation_samples = 20;
+        let slow = repetitions.run(&config(0.001)).unwrap();
+        let fast = repetitions.run(&config(0.01)).unwrap();
+        std::fs::remove_dir_all(&dir).unwrap();
+        assert_eq!(slow.runs().iter().map(|run| run.seed).collect::<Vec<_>>(), vec![0, 1, 2]);
+        assert_ne!(slow.runs()[0].reconstruction_error, slow.runs()[1].reconstruction_error);
+        assert_eq!(slow.reconstruction_error().runs, 3);
+        assert!(slow.compare(&fast).reconstruction_error.is_some());
+        assert!(Repetitions::new(0).run(&config(0.01)).is_err());
+    }
+}
@@ -0,0 +1,163 @@
+//! Hyperparameter sweeps: every combination of the given learning rates, neuron counts and batch sizes is trained on the
+//! same thread pool, and compared by the reconstruction error of the final weights on the same held-out patches.
+use std::fmt::{self, Write as _};
+use std::fs;
+use std::io;
+use std::path::Path;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use rand::SeedableRng;
+use rand::rngs::StdRng;
+use tracing::info;
+use crate::data::dataset::Dataset;
+use crate::error::{Error, Result};
+use crate::model::eval::Evaluation;
+use crate::model::network::{auto_section_size, MtNetwork};
+use crate::threading::thread_pool::ThreadPool;
+
+/// A single combination of hyperparameters.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SweepPoint {
+    pub lr: f32,
+    pub neurons: usize,
+    pub batch_size: usize,
+}
+
+/// The grids of hyperparameters to sweep, and the settings shared by all runs.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Sweep {
+    pub lrs: Vec<f32>,
+    pub neurons: Vec<usize>,
+    pub batch_sizes: Vec<usize>,
+    /// Number of training patches every neuron is trained on.
+    pub epochs: usize,
+    /// Number of held-out patches the reconstruction error is measured on.
+    pub evaluation_samples: usize,
+    pub seed: u64,
+}
+
+impl Sweep {
+    pub fn new(lrs: Vec<f32>, neurons: Vec<usize>, batch_sizes: Vec<usize>, epochs: usize) -> Sweep {
+        Sweep { lrs, neurons, batch_sizes, epochs, evaluation_samples: 1000, seed: 0 }
+    }
+
+    /// Every combination of the grids, varying the batch size fastest and the learning rate slowest.
+    pub fn points(&self) -> Vec<SweepPoint> {
+        self.lrs.iter().flat_map(|&lr| self.neurons.iter().flat_map(move |&neurons| {
+            self.batch_sizes.iter().map(move |&batch_size| SweepPoint { lr, neurons, batch_size })
+        })).collect()
+    }
+
+    // Trains a network for every point on the given pool, with the dataset created anew for every run. All runs use the
+    // same seed, so they start from the same random state and differ only in their hyperparameters. Runs which diverge get a
+    // reconstruction error of NaN.
+    pub fn run<D: Dataset, F: Fn() -> Result<D>>(&self, pool: Arc<ThreadPool>, dataset: F) -> Result<SweepReport> {
+        let points = self.points();
+        if points.is_empty() {
+            return Err(Error::InvalidConfig("every grid of the sweep needs at least one value".to_string()));
+        }
+        if self.evaluation_samples == 0 {
+            return Err(Error::InvalidConfig("at least one evaluation patch is needed to compare the runs".to_string()));
+        }
+        let evaluation = Evaluation::held_out(&dataset()?, self.evaluation_samples, 1, &mut StdRng::seed_from_u64(self.seed));
+
+        let mut results = Vec::with_capacity(points.len());
+        for (index, point) in points.into_iter().enumerate() {
+            let section_size = auto_section_size(point.neurons, pool.size());
+            let mut network = MtNetwork::with_pool(section_size, Arc::clone(&pool), point.neurons, point.lr, dataset()?, Some(self.seed))?;
+            network.set_batch_size(point.batch_size)?;
+
+            // A learning rate which is too high for the other settings should not end the sweep, its run is reported as NaN.
+            let now = Instant::now();
+            let trained = network.train_complete_iterations(self.epochs);
+            let elapsed = now.elapsed();
+            let reconstruction_error = match trained {
+                Ok(weights) => evaluation.evaluate(&weights),
+                Err(Error::Diverged { .. }) => f32::NAN,
+                Err(error) => return Err(error),
+            };
+            info!(run = index + 1, lr = point.lr, neurons = point.neurons, batch_size = point.batch_size, reconstruction_error, elapsed_ms = elapsed.as_millis() as u64, "sweep run completed");
+            results.push(SweepResult { point, reconstruction_error, elapsed });
+        }
+        Ok(SweepReport { results })
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SweepResult {
+    pub point: SweepPoint,
+    pub reconstruction_error: f32,
+    /// Wall time of the training, excluding the evaluation.
+    pub elapsed: Duration,
+}
+
+/// The results of all runs of a sweep, in the order they were run.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SweepReport {
+    results: Vec<SweepResult>,
+}
+
+impl SweepReport {
+    pub fn results(&self) -> &[SweepResult] {
+        &self.results
+    }
+
+    /// The run with the lowest reconstruction error.
+    pub fn best(&self) -> Option<&SweepResult> {
+        self.results.iter().filter(|result| result.reconstruction_error.is_finite())
+            .min_by(|a, b| a.reconstruction_error.total_cmp(&b.reconstruction_error))
+    }
+
+    /// One line per run with a header.
+    pub fn to_csv(&self) -> String {
+        let mut csv = String::from("lr,neurons,batch_size,reconstruction_error,elapsed_seconds\n");
+        for result in &self.results {
+            let point = result.point;
+            writeln!(csv, "{},{},{},{},{}", point.lr, point.neurons, point.batch_size, result.reconstruction_error, result.elapsed.as_secs_f64()).unwrap();
+        }
+        csv
+    }
+
+    pub fn save_csv<P: AsRef<Path>>(&self, path: P) -> io::Result<()> {
+        fs::write(path, self.to_csv())
+    }
+}
+
+impl fmt::Display for SweepReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let best = self.best();
+        write!(f, "{:>10}  {:>7}  {:>10}  {:>20}  {:>9}", "lr", "neurons", "batch size", "reconstruction error", "time (s)")?;
+        for result in &self.results {
+            let point = result.point;
+            let marker = if best == Some(result) { " *" } else { "" };
+            write!(f, "\n{:>10}  {:>7}  {:>10}  {:>20.6}  {:>9.2}{}", point.lr, point.neurons, point.batch_size, result.reconstruction_error, result.elapsed.as_secs_f32(), marker)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test{
+    use super::*;
+    use crate::data::mnist::MnistData;
+
+    #[test]
+    fn sweeps_every_combination_on_one_pool(){
+        let mut sweep = Sweep::new(vec![0.001, 50.0], vec![4, 8], vec![1, 4], 300);
+        sweep.evaluation_samples = 50;
+        assert_eq!(sweep.points().len(), 8);
+        assert_eq!(sweep.points()[1], SweepPoint { lr: 0.001, neurons: 4, batch_size: 4 });
+
+        let pool = Arc::new(ThreadPool::new(2).unwrap());
+        let report = sweep.run(Arc::clone(&pool), || MnistData::new(1, 5)).unwrap();
+        assert_eq!(report.results().len(), 8);
+        assert!(report.results()[..4].iter().all(|result| result.reconstruction_error.is_finite()));
+        assert!(report.results()[4..].iter().all(|result| result.reconstruction_error.is_nan()));
+        assert_eq!(report.best().unwrap().point.lr, 0.001);
+        assert_eq!(report.to_csv().lines().count(), 9);
+        assert_eq!(report.to_string().matches(" *").count(), 1);
+        assert_eq!(Arc::strong_count(&pool), 1);
+
+        assert!(Sweep::new(vec![], vec![4], vec![1], 10).run(pool, || MnistData::new(1, 5)).is_err());
+    }
+}
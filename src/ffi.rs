@@ -0,0 +1,417 @@
+//! C bindings, so C and C++ pipelines can embed the trainer. Built with `--features ffi`, which also regenerates the header
+//! `include/rust_ml.h` with cbindgen, and linked against the shared library, e.g. `-lrust_ml`:
+//!
+//! ```c
+//! RustMlConfig config = rust_ml_config_default();
+//! config.neurons = 64;
+//! RustMlNetwork *network = NULL;
+//! if (rust_ml_network_new(&config, samples, count, dim, &network) != RUST_ML_STATUS_OK) {
+//!     fprintf(stderr, "%s\n", rust_ml_last_error());
+//! }
+//! rust_ml_network_train(network, RUST_ML_METHOD_POOL, 50000);
+//! rust_ml_network_weights(network, weights, 64 * dim);
+//! rust_ml_network_free(network);
+//! ```
+//!
+//! Samples are copied row by row out of the caller's buffers, so the buffers can be reused as soon as a call returns. Calls
+//! on the same network must not overlap, but different networks can be trained from different threads.
+use std::cell::RefCell;
+use std::convert::TryFrom;
+use std::ffi::CString;
+use std::os::raw::c_char;
+use std::panic::{catch_unwind, AssertUnwindSafe};
+use std::{mem, ptr, slice};
+use crate::data::csv::CsvData;
+use crate::data::dataset::Dataset;
+use crate::error::Error;
+use crate::experiment::config::TrainingMethod;
+use crate::experiment::runner::train;
+use crate::model::ica::Nonlinearity;
+use crate::model::learning_rule::LearningRule;
+use crate::model::network::{MtNetwork, NetworkBuilder};
+use crate::threading::thread_pool::Threads;
+
+/// Outcome of a call. The message of every status other than `Ok` can be read with `rust_ml_last_error`.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RustMlStatus {
+    Ok = 0,
+    /// A pointer argument was null.
+    NullPointer,
+    /// The configuration is invalid, or a buffer does not match the network.
+    InvalidArgument,
+    /// The weights diverged, usually because the learning rate is too high. The network keeps the weights it had before.
+    Diverged,
+    /// A worker thread panicked, so only some of the neurons were trained.
+    WorkerPanicked,
+    /// The call panicked. The network should not be used anymore, other than to free it.
+    Panicked,
+    /// Any other failure, e.g. the worker threads could not be started.
+    Failed,
+}
+
+/// The learning rules, with the defaults of the experiment files for their parameters. Passed as `uint32_t`, since any other
+/// value a C caller stores in a Rust enum is undefined behaviour, and checked by the library instead.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RustMlRule {
+    Oja = 0,
+    OjaMomentum = 1,
+    Bcm = 2,
+    Hebbian = 3,
+    Ica = 4,
+}
+
+/// How `rust_ml_network_train` trains the network, see `TrainingMethod`, passed as `uint32_t` like `RustMlRule`. `Rayon`
+/// requires the library to be built with the `rayon` feature.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RustMlMethod {
+    Pool = 0,
+    Rayon = 1,
+    Scoped = 2,
+    Matrix = 3,
+    /// Trains with Sanger's rule, so the neurons learn the principal components in order.
+    Hierarchical = 4,
+}
+
+impl TryFrom<u32> for RustMlRule {
+    type Error = u32;
+
+    fn try_from(value: u32) -> Result<RustMlRule, u32> {
+        let rules = [RustMlRule::Oja, RustMlRule::OjaMomentum, RustMlRule::Bcm, RustMlRule::Hebbian, RustMlRule::Ica];
+        rules.iter().copied().find(|rule| *rule as u32 == value).ok_or(value)
+    }
+}
+
+impl TryFrom<u32> for RustMlMethod {
+    type Error = u32;
+
+    fn try_from(value: u32) -> Result<RustMlMethod, u32> {
+        let methods = [RustMlMethod::Pool, RustMlMethod::Rayon, RustMlMethod::Scoped, RustMlMethod::Matrix, RustMlMethod::Hierarchical];
+        methods.iter().copied().find(|method| *method as u32 == value).ok_or(value)
+    }
+}
+
+/// The parameters of a network, starting from `rust_ml_config_default`.
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+pub struct RustMlConfig {
+    pub neurons: usize,
+    /// Number of worker threads, or zero for one per core.
+    pub threads: usize,
+    pub lr: f32,
+    /// One of the `RustMlRule` values.
+    pub rule: u32,
+    pub batch_size: usize,
+    /// Seeds the initial weights and the sampling of patches if `seeded` is set, so runs can be reproduced.
+    pub seed: u64,
+    pub seeded: bool,
+}
+
+/// A network and the samples it trains on, only handled through pointers.
+pub struct RustMlNetwork {
+    network: MtNetwork<CsvData>,
+}
+
+thread_local! {
+    static LAST_ERROR: RefCell<Option<CString>> = const { RefCell::new(None) };
+}
+
+// Why a call failed, turned into its status by `guard`.
+struct Failure {
+    status: RustMlStatus,
+    message: String,
+}
+
+impl From<Error> for Failure {
+    fn from(error: Error) -> Failure {
+        let status = match error {
+            Error::InvalidConfig(_) | Error::Incompatible(_) => RustMlStatus::InvalidArgument,
+            Error::Diverged { .. } => RustMlStatus::Diverged,
+            Error::WorkerPanicked { .. } => RustMlStatus::WorkerPanicked,
+            _ => RustMlStatus::Failed,
+        };
+        Failure { status, message: error.to_string() }
+    }
+}
+
+fn null(name: &str) -> Failure {
+    Failure { status: RustMlStatus::NullPointer, message: format!("`{}` is null", name) }
+}
+
+fn invalid(message: String) -> Failure {
+    Failure { status: RustMlStatus::InvalidArgument, message }
+}
+
+// Runs the body of a call, turning failures and panics into a status and keeping their message for `rust_ml_last_error`,
+// as unwinding into C is undefined behaviour.
+fn guard(body: impl FnOnce() -> Result<(), Failure>) -> RustMlStatus {
+    let failure = match catch_unwind(AssertUnwindSafe(body)) {
+        Ok(Ok(())) => return RustMlStatus::Ok,
+        Ok(Err(failure)) => failure,
+        Err(payload) => {
+            let message = payload.downcast_ref::<&str>().map(|message| message.to_string())
+                .or_else(|| payload.downcast_ref::<String>().cloned())
+                .unwrap_or_else(|| "unknown panic".to_string());
+            Failure { status: RustMlStatus::Panicked, message }
+        }
+    };
+    let message = CString::new(failure.message.replace('\0', " ")).expect("nul bytes were replaced");
+    LAST_ERROR.with(|last| *last.borrow_mut() = Some(message));
+    failure.status
+}
+
+// Copies `count` rows of `dim` floats, stored one after the other.
+unsafe fn rows(samples: *const f32, count: usize, dim: usize) -> Result<CsvData, Failure> {
+    if samples.is_null() {
+        return Err(null("samples"));
+    }
+    if count == 0 || dim == 0 {
+        return Err(invalid(format!("expected at least one sample of at least one value, got {} of {}", count, dim)));
+    }
+    // `slice::from_raw_parts` requires the buffer to span at most `isize::MAX` bytes.
+    let len = count.checked_mul(dim).filter(|len| len.checked_mul(mem::size_of::<f32>()).is_some_and(|bytes| bytes <= isize::MAX as usize))
+        .ok_or_else(|| invalid(format!("{} samples of {} values overflow the address space", count, dim)))?;
+    let rows = slice::from_raw_parts(samples, len).chunks(dim).map(<[f32]>::to_vec).collect();
+    CsvData::from_rows(rows).map_err(|error| invalid(error.to_string()))
+}
+
+// Checks that an output buffer can hold `needed` floats.
+fn output(out: *mut f32, len: usize, needed: usize) -> Result<(), Failure> {
+    if out.is_null() {
+        return Err(null("out"));
+    }
+    if len < needed {
+        return Err(invalid(format!("the output buffer holds {} values, {} are needed", len, needed)));
+    }
+    Ok(())
+}
+
+/// The defaults of the Python bindings: 256 neurons trained with Oja's rule at a learning rate of 0.01, one sample at a time,
+/// with one worker thread per core and an unseeded random number generator.
+#[no_mangle]
+pub extern "C" fn rust_ml_config_default() -> RustMlConfig {
+    RustMlConfig { neurons: 256, threads: 0, lr: 0.01, rule: RustMlRule::Oja as u32, batch_size: 1, seed: 0, seeded: false }
+}
+
+/// The message of the last failed call on this thread, or null if none has failed. The string is owned by the library and
+/// stays valid until the next call on this thread fails.
+#[no_mangle]
+pub extern "C" fn rust_ml_last_error() -> *const c_char {
+    LAST_ERROR.with(|last| last.borrow().as_ref().map_or(ptr::null(), |message| message.as_ptr()))
+}
+
+/// Creates a network training on `count` samples of `dim` floats, stored one after the other in `samples`, and stores it in
+/// `network`. The network must be released with `rust_ml_network_free`.
+///
+/// # Safety
+///
+/// `config` must point to a config, `samples` to `count * dim` floats and `network` to memory a pointer can be written to.
+#[no_mangle]
+pub unsafe extern "C" fn rust_ml_network_new(config: *const RustMlConfig, samples: *const f32, count: usize, dim: usize, network: *mut *mut RustMlNetwork) -> RustMlStatus {
+    guard(|| {
+        let config = config.as_ref().ok_or_else(|| null("config"))?;
+        if network.is_null() {
+            return Err(null("network"));
+        }
+        let mut builder = NetworkBuilder::new()
+            .neurons(config.neurons)
+            .threads(if config.threads == 0 { Threads::Auto } else { Threads::Fixed(config.threads) })
+            .lr(config.lr)
+            .rule(learning_rule(RustMlRule::try_from(config.rule).map_err(|rule| invalid(format!("unknown rule {}", rule)))?))
+            .batch_size(config.batch_size)
+            .dataset(rows(samples, count, dim)?);
+        if config.seeded {
+            builder = builder.seed(config.seed);
+        }
+        *network = Box::into_raw(Box::new(RustMlNetwork { network: builder.build()? }));
+        Ok(())
+    })
+}
+
+/// Replaces the samples the network trains on with `count` new samples of the network's `dim` floats, keeping its weights,
+/// e.g. to train on the next batch of a stream.
+///
+/// # Safety
+///
+/// `network` must have been created by `rust_ml_network_new` and `samples` must point to `count * dim` floats.
+#[no_mangle]
+pub unsafe extern "C" fn rust_ml_network_feed(network: *mut RustMlNetwork, samples: *const f32, count: usize) -> RustMlStatus {
+    guard(|| {
+        let network = &mut network.as_mut().ok_or_else(|| null("network"))?.network;
+        let dim = network.dataset().patch_dim();
+        network.set_dataset(rows(samples, count, dim)?)?;
+        Ok(())
+    })
+}
+
+/// Trains every neuron on `epochs` more samples with one of the `RustMlMethod` values, continuing from its current weights.
+///
+/// # Safety
+///
+/// `network` must have been created by `rust_ml_network_new`.
+#[no_mangle]
+pub unsafe extern "C" fn rust_ml_network_train(network: *mut RustMlNetwork, method: u32, epochs: usize) -> RustMlStatus {
+    guard(|| {
+        let network = &mut network.as_mut().ok_or_else(|| null("network"))?.network;
+        let method = RustMlMethod::try_from(method).map_err(|method| invalid(format!("unknown training method {}", method)))?;
+        train(network, training_method(method), epochs)?;
+        Ok(())
+    })
+}
+
+/// Number of neurons of the network, or zero for a null network.
+///
+/// # Safety
+///
+/// `network` must be null or have been created by `rust_ml_network_new`.
+#[no_mangle]
+pub unsafe extern "C" fn rust_ml_network_neurons(network: *const RustMlNetwork) -> usize {
+    network.as_ref().map_or(0, |network| network.network.weights().len())
+}
+
+/// Number of floats in a sample, and in the weights of a neuron, or zero for a null network.
+///
+/// # Safety
+///
+/// `network` must be null or have been created by `rust_ml_network_new`.
+#[no_mangle]
+pub unsafe extern "C" fn rust_ml_network_dim(network: *const RustMlNetwork) -> usize {
+    network.as_ref().map_or(0, |network| network.network.dataset().patch_dim())
+}
+
+/// Copies the weights into `out`, which holds `len` floats and needs `neurons * dim`, one neuron after the other.
+///
+/// # Safety
+///
+/// `network` must have been created by `rust_ml_network_new` and `out` must point to `len` writable floats.
+#[no_mangle]
+pub unsafe extern "C" fn rust_ml_network_weights(network: *const RustMlNetwork, out: *mut f32, len: usize) -> RustMlStatus {
+    guard(|| {
+        let weights = network.as_ref().ok_or_else(|| null("network"))?.network.weights().as_slice();
+        output(out, len, weights.len())?;
+        slice::from_raw_parts_mut(out, weights.len()).copy_from_slice(weights);
+        Ok(())
+    })
+}
+
+/// Writes the outputs of every neuron for `count` samples of `dim` floats into `out`, which holds `len` floats and needs
+/// `count * neurons`, sample after sample.
+///
+/// # Safety
+///
+/// `network` must have been created by `rust_ml_network_new`, `samples` must point to `count * dim` floats and `out` to
+/// `len` writable floats.
+#[no_mangle]
+pub unsafe extern "C" fn rust_ml_network_encode(network: *const RustMlNetwork, samples: *const f32, count: usize, out: *mut f32, len: usize) -> RustMlStatus {
+    guard(|| {
+        let network = &network.as_ref().ok_or_else(|| null("network"))?.network;
+        let samples = rows(samples, count, network.dataset().patch_dim())?;
+        output(out, len, count.saturating_mul(network.weights().len()))?;
+        let outputs = network.encode_batch(samples.rows())?.concat();
+        slice::from_raw_parts_mut(out, outputs.len()).copy_from_slice(&outputs);
+        Ok(())
+    })
+}
+
+/// Releases a network. Null is ignored.
+///
+/// # Safety
+///
+/// `network` must be null or have been created by `rust_ml_network_new`, and must not be used afterwards.
+#[no_mangle]
+pub unsafe extern "C" fn rust_ml_network_free(network: *mut RustMlNetwork) {
+    if !network.is_null() {
+        drop(Box::from_raw(network));
+    }
+}
+
+fn learning_rule(rule: RustMlRule) -> LearningRule {
+    match rule {
+        RustMlRule::Oja => LearningRule::Oja,
+        RustMlRule::OjaMomentum => LearningRule::OjaMomentum { beta: 0.9 },
+        RustMlRule::Bcm => LearningRule::Bcm { tau: 100.0 },
+        RustMlRule::Hebbian => LearningRule::Hebbian { normalize_every: 1 },
+        RustMlRule::Ica => LearningRule::Ica { nonlinearity: Nonlinearity::Tanh },
+    }
+}
+
+fn training_method(method: RustMlMethod) -> TrainingMethod {
+    match method {
+        RustMlMethod::Pool => TrainingMethod::Pool,
+        RustMlMethod::Rayon => TrainingMethod::Rayon,
+        RustMlMethod::Scoped => TrainingMethod::Scoped,
+        RustMlMethod::Matrix => TrainingMethod::Matrix,
+        RustMlMethod::Hierarchical => TrainingMethod::Hierarchical,
+    }
+}
+
+#[cfg(test)]
+mod test{
+    use super::*;
+    use std::ffi::CStr;
+    use rand::{Rng, SeedableRng};
+    use rand::rngs::StdRng;
+
+    fn samples(count: usize, dim: usize) -> Vec<f32> {
+        let mut rng = StdRng::seed_from_u64(3);
+        (0..count * dim).map(|_| rng.gen_range(-1.0..1.0)).collect()
+    }
+
+    fn last_error() -> String {
+        unsafe { CStr::from_ptr(rust_ml_last_error()) }.to_str().unwrap().to_string()
+    }
+
+    #[test]
+    fn trains_on_buffers_and_copies_the_weights_out(){
+        let config = RustMlConfig { neurons: 4, threads: 2, seed: 1, seeded: true, ..rust_ml_config_default() };
+        let data = samples(50, 3);
+        let mut network = ptr::null_mut();
+        unsafe {
+            assert_eq!(rust_ml_network_new(&config, data.as_ptr(), 50, 3, &mut network), RustMlStatus::Ok);
+            assert_eq!((rust_ml_network_neurons(network), rust_ml_network_dim(network)), (4, 3));
+            assert_eq!(rust_ml_network_train(network, RustMlMethod::Pool as u32, 200), RustMlStatus::Ok);
+            let mut weights = vec![f32::NAN; 12];
+            assert_eq!(rust_ml_network_weights(network, weights.as_mut_ptr(), 12), RustMlStatus::Ok);
+            assert!(weights.iter().all(|w| w.is_finite()));
+            assert_eq!(weights, (*network).network.weights().as_slice());
+
+            let batch = samples(20, 3);
+            assert_eq!(rust_ml_network_feed(network, batch.as_ptr(), 20), RustMlStatus::Ok);
+            assert_eq!(rust_ml_network_train(network, RustMlMethod::Matrix as u32, 20), RustMlStatus::Ok);
+            let mut codes = vec![0.0; 8];
+            assert_eq!(rust_ml_network_encode(network, batch.as_ptr(), 2, codes.as_mut_ptr(), 8), RustMlStatus::Ok);
+            assert_eq!(codes[..4], (*network).network.encode(&batch[..3]).unwrap()[..]);
+            rust_ml_network_free(network);
+        }
+    }
+
+    #[test]
+    fn reports_invalid_arguments_instead_of_panicking(){
+        let data = samples(10, 2);
+        let mut network = ptr::null_mut();
+        unsafe {
+            assert_eq!(rust_ml_network_new(ptr::null(), data.as_ptr(), 10, 2, &mut network), RustMlStatus::NullPointer);
+            assert_eq!(last_error(), "`config` is null");
+            let config = RustMlConfig { batch_size: 0, ..rust_ml_config_default() };
+            assert_eq!(rust_ml_network_new(&config, data.as_ptr(), 10, 2, &mut network), RustMlStatus::InvalidArgument);
+            let config = RustMlConfig { rule: 5, ..rust_ml_config_default() };
+            assert_eq!(rust_ml_network_new(&config, data.as_ptr(), 10, 2, &mut network), RustMlStatus::InvalidArgument);
+            assert_eq!(last_error(), "unknown rule 5");
+            assert!(network.is_null());
+
+            let config = RustMlConfig { neurons: 2, threads: 1, ..rust_ml_config_default() };
+            assert_eq!(rust_ml_network_new(&config, data.as_ptr(), 10, 2, &mut network), RustMlStatus::Ok);
+            let mut weights = [0.0; 3];
+            assert_eq!(rust_ml_network_weights(network, weights.as_mut_ptr(), 3), RustMlStatus::InvalidArgument);
+            assert_eq!(last_error(), "the output buffer holds 3 values, 4 are needed");
+            assert_eq!(rust_ml_network_feed(network, data.as_ptr(), 0), RustMlStatus::InvalidArgument);
+            assert_eq!(rust_ml_network_feed(network, data.as_ptr(), usize::MAX / 4), RustMlStatus::InvalidArgument);
+            assert_eq!(rust_ml_network_train(network, 99, 10), RustMlStatus::InvalidArgument);
+            assert_eq!(last_error(), "unknown training method 99");
+            rust_ml_network_free(network);
+            rust_ml_network_free(ptr::null_mut());
+        }
+    }
+}
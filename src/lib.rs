@@ -1,20 +1,104 @@
+#[cfg(feature = "blas")]
+extern crate blas_src;
+
+pub mod error;
+
+pub mod experiment {
+   pub mod config;
+   pub mod runner;
+   pub mod sweep;
+   pub mod stats;
+}
+
 pub mod threading {
    pub mod thread_pool;
+   pub mod cancellation;
 }
 
+#[cfg(not(target_arch = "wasm32"))]
 pub mod plotting{
    pub mod cpu_plotting;
 }
 
 pub mod model {
    pub mod oja;
+   pub mod bcm;
+   pub mod hebbian;
+   pub mod init;
+   pub mod ica;
+   pub mod float;
+   pub mod learning_rule;
+   pub mod clipping;
    pub mod network;
+   pub mod st_network;
+   pub mod sanger;
+   pub mod checkpoint;
+   pub mod early_stopping;
+   pub mod schedule;
+   pub mod eval;
+   pub mod history;
+   pub mod simd;
+   pub mod progress;
+   pub mod som;
+   pub mod kmeans;
+   pub mod matrix;
+   pub mod onnx;
+   pub mod npy;
+   pub mod redundancy;
+   pub mod orthogonalize;
+   pub mod readout;
+   pub mod field_stats;
+   pub mod timing;
+   pub mod arena;
+   pub mod weights;
+   pub mod hooks;
+   #[cfg(feature = "async")]
+   pub mod async_training;
+   #[cfg(feature = "gpu")]
+   pub mod gpu;
 }
 
 pub mod data {
    pub mod mnist;
+   pub mod patch;
+   pub mod sampler;
+   pub mod dataset;
+   pub mod idx;
+   pub mod cifar;
+   pub mod natural_images;
+   #[cfg(feature = "images")]
+   pub mod image_folder;
+   #[cfg(feature = "video")]
+   pub mod video;
+   #[cfg(feature = "mmap")]
+   pub mod mmap;
+   pub mod csv;
+   pub mod whitening;
+   pub mod normalize;
+   pub mod augment;
+   pub mod synthetic;
+   pub mod filter;
+}
+
+pub mod visualization {
+   pub mod receptive_fields;
+   #[cfg(feature = "tui")]
+   pub mod dashboard;
+   #[cfg(feature = "monitor")]
+   pub mod monitor;
 }
 
 pub mod utils {
    pub mod constants;
-}
\ No newline at end of file
+   pub mod linalg;
+   pub mod profiling;
+}
+
+#[cfg(feature = "python")]
+pub mod python;
+
+#[cfg(feature = "wasm")]
+pub mod wasm;
+
+#[cfg(feature = "ffi")]
+pub mod ffi;
@@ -0,0 +1,1314 @@
+//! Command line front-end for configuring and running training on image patches.
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use tracing_subscriber::EnvFilter;
+use clap::{Parser, Subcommand, ValueEnum};
+use rand::SeedableRng;
+use rand::rngs::StdRng;
+use rust_ml::data::augment::{Augmentation, Augmented};
+use rust_ml::data::cifar::{CifarDataset, Color};
+use rust_ml::data::csv::CsvData;
+use rust_ml::data::dataset::Dataset;
+use rust_ml::data::filter::VarianceFilter;
+use rust_ml::data::idx::{EmnistSplit, IdxDataset};
+use rust_ml::data::mnist::{MnistData, SplitRatios};
+use rust_ml::data::natural_images::NaturalImages;
+#[cfg(feature = "images")]
+use rust_ml::data::image_folder::ImageFolder;
+#[cfg(feature = "mmap")]
+use rust_ml::data::mmap::MappedIdxDataset;
+#[cfg(feature = "video")]
+use rust_ml::data::video::{FrameOptions, VideoDataset};
+use rust_ml::data::normalize::{Normalized, Normalizer};
+use rust_ml::data::patch::{patch_width, Patch};
+use rust_ml::data::sampler::PatchSampler;
+use rust_ml::error::{self, Error};
+use rust_ml::experiment::config::ExperimentConfig;
+use rust_ml::experiment::runner::run_experiment;
+use rust_ml::experiment::stats::Repetitions;
+use rust_ml::experiment::sweep::Sweep;
+use rust_ml::model::checkpoint::{Checkpoint, Metadata};
+use rust_ml::model::clipping::Clipping;
+use rust_ml::model::eval::{evaluate_checkpoint, Evaluation};
+use rust_ml::model::float::Precision;
+#[cfg(feature = "gpu")]
+use rust_ml::model::gpu::GpuNetwork;
+use rust_ml::model::ica::Nonlinearity;
+use rust_ml::model::init::Init;
+use rust_ml::model::learning_rule::LearningRule;
+use rust_ml::model::network::{auto_section_size, MtNetwork, TrainedSection};
+use rust_ml::model::npy::save_npy;
+use rust_ml::model::onnx::save_onnx;
+use rust_ml::model::orthogonalize::{Orthogonalization, OrthogonalizationMethod};
+use rust_ml::model::progress::Progress;
+use rust_ml::model::readout::{evaluate_readout, LabelledImages};
+#[cfg(feature = "tui")]
+use rust_ml::model::progress::progress_channel;
+#[cfg(feature = "tui")]
+use rust_ml::visualization::dashboard::Dashboard;
+#[cfg(feature = "monitor")]
+use rust_ml::visualization::monitor::Monitor;
+use rust_ml::model::schedule::{Constant, CosineAnnealing, ExponentialDecay, StepDecay};
+use rust_ml::threading::cancellation::CancellationToken;
+use rust_ml::threading::thread_pool::{ThreadPool, Threads};
+use rust_ml::utils::constants::{IMAGE_WIDTH, PATCH_WIDTH};
+#[cfg(feature = "profiling")]
+use rust_ml::utils::profiling::Recording;
+
+#[derive(Clone, Copy, Debug, PartialEq, ValueEnum)]
+enum Rule {
+    /// Oja's rule, training every neuron independently on multiple threads
+    Oja,
+    /// Oja's rule with a momentum buffer per neuron, decaying by --momentum per update
+    OjaMomentum,
+    /// BCM rule with a sliding threshold, training every neuron independently on multiple threads
+    Bcm,
+    /// Plain Hebbian rule with periodic weight renormalization, training every neuron independently on multiple threads
+    Hebbian,
+    /// Sanger's rule, training the neurons hierarchically on a single thread
+    Sanger,
+    /// Nonlinear Hebbian rule extracting independent components, best combined with `--preprocessing zca`
+    Ica,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, ValueEnum)]
+enum Contrast {
+    /// Robust contrast based on log cosh
+    Tanh,
+    /// Kurtosis, faster but sensitive to outliers
+    Cube,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, ValueEnum)]
+enum WeightInit {
+    /// Uniform in [0, 1)
+    Uniform,
+    /// Uniform in [-scale, scale)
+    Symmetric,
+    /// Normally distributed with a standard deviation of scale
+    Gaussian,
+    /// Uniform scaled by the number of inputs, so the initial output has about unit variance
+    Xavier,
+    /// Random direction with unit length
+    UnitNorm,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, ValueEnum)]
+enum Orthogonalize {
+    /// Remove from every neuron its projections onto the neurons before it
+    GramSchmidt,
+    /// Replace all neurons with the closest orthonormal set
+    Symmetric,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, ValueEnum)]
+enum FloatType {
+    /// f32 on the vectorized kernels
+    Single,
+    /// f64, to check that the learned weights do not depend on rounding
+    Double,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, ValueEnum)]
+enum Backend {
+    /// The crate's own thread pool
+    Pool,
+    /// Scoped threads started for the run, which train the weights of the network in place
+    Scoped,
+    /// Rayon's work-stealing thread pool. Requires the `rayon` feature
+    Rayon,
+    /// A compute shader running every neuron in parallel on the GPU. Only supports Oja's rule. Requires the `gpu` feature
+    Gpu,
+    /// Matrix multiplications updating all neurons at once on the calling thread, using BLAS with the `blas` feature.
+    /// Only supports Oja's rule
+    Matrix,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, ValueEnum)]
+enum Schedule {
+    /// Keep the learning rate fixed
+    Constant,
+    /// Multiply the learning rate by gamma every epoch
+    Exponential,
+    /// Multiply the learning rate by gamma every step-size epochs
+    Step,
+    /// Anneal the learning rate down to min-lr along half a cosine
+    Cosine,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, ValueEnum)]
+enum Preprocessing {
+    /// Train on the raw pixel values
+    None,
+    /// Subtract the mean patch
+    Center,
+    /// Subtract the mean patch and decorrelate the pixels with ZCA whitening
+    Zca,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, ValueEnum)]
+enum Normalize {
+    /// Scale every patch into the range 0 to 1
+    MinMax,
+    /// Standardize every patch to zero mean and unit variance
+    ZScore,
+    /// Scale every patch to unit length
+    UnitNorm,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, ValueEnum)]
+enum DatasetKind {
+    /// MNIST handwritten digits
+    Mnist,
+    /// Fashion-MNIST clothing images
+    Fashion,
+    /// EMNIST handwritten characters, using the split given by --emnist-split
+    Emnist,
+    /// Rows of comma separated floats, read from the file given by --data
+    Csv,
+    /// CIFAR-10 photographs, converted to grayscale unless --rgb is given
+    Cifar,
+    /// Olshausen and Field's whitened natural images, read from the IMAGES.mat file of sparsenet given by --data
+    Natural,
+    /// PNG and JPEG images in the directory given by --data, converted to grayscale. Requires the `images` feature
+    Images,
+    /// Frames of the video file given by --data, converted to grayscale. Requires the `video` feature
+    Video,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, ValueEnum)]
+enum Split {
+    Byclass,
+    Bymerge,
+    Balanced,
+    Letters,
+    Digits,
+    Mnist,
+}
+
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// Print what a saved checkpoint holds and the metadata saved with it: the rule and dataset its weights were trained
+    /// with, for how many epochs, the statistics after the last epoch and when and by which version it was saved
+    Describe {
+        /// Checkpoint written with --output
+        checkpoint: PathBuf,
+    },
+    /// Evaluate the weights of a saved checkpoint on the test set of the dataset given by --dataset and --data, instead of training.
+    /// Preprocessing is fitted on the training set with the same seed, so pass the same options as for training
+    Evaluate {
+        /// Checkpoint written with --output
+        checkpoint: PathBuf,
+
+        /// Number of test patches to evaluate on
+        #[arg(long, default_value_t = 10000)]
+        samples: usize,
+    },
+    /// Run the experiment described by a TOML file, ignoring the other options
+    Experiment {
+        /// TOML file with the [network], [dataset], [schedule] and [output] settings
+        config: PathBuf,
+    },
+    /// Train a softmax classifier on the features of a saved checkpoint and report its accuracy on the MNIST test set.
+    /// Every image is cut into non-overlapping patches, and the outputs of the neurons for all of them are the features
+    Readout {
+        /// Checkpoint written with --output
+        checkpoint: PathBuf,
+
+        /// Number of training images the classifier is trained on
+        #[arg(long, default_value_t = 10000)]
+        train_samples: usize,
+
+        /// Number of test images the accuracy is measured on
+        #[arg(long, default_value_t = 10000)]
+        test_samples: usize,
+
+        /// Number of passes over the training images
+        #[arg(long, default_value_t = 10)]
+        readout_epochs: usize,
+
+        /// Learning rate of the classifier
+        #[arg(long, default_value_t = 0.01)]
+        readout_lr: f32,
+    },
+    /// Train a network on MNIST for every combination of the given hyperparameters, all on one thread pool, and compare the
+    /// reconstruction error on the same held-out patches and the training time of every run. Uses --epochs, --threads and --seed
+    Sweep {
+        /// Learning rates to try, e.g. `0.001,0.01`
+        #[arg(long, value_delimiter = ',', required = true)]
+        lrs: Vec<f32>,
+
+        /// Neuron counts to try
+        #[arg(long, value_delimiter = ',', required = true)]
+        neurons: Vec<usize>,
+
+        /// Batch sizes to try
+        #[arg(long, value_delimiter = ',', default_value = "1")]
+        batch_sizes: Vec<usize>,
+
+        /// Number of held-out patches the reconstruction error is measured on
+        #[arg(long, default_value_t = 1000)]
+        samples: usize,
+
+        /// Also write the comparison as CSV to this file
+        #[arg(long)]
+        csv: Option<PathBuf>,
+    },
+    /// Run the experiment described by a TOML file several times with consecutive seeds starting at --seed, and report the
+    /// mean and standard deviation of the reconstruction error and the training time. With a second file, both are compared
+    /// with Welch's t-test
+    Stats {
+        /// TOML file with the [network] and [dataset] settings, the outputs are not written
+        config: PathBuf,
+
+        /// Second configuration to compare against the first
+        #[arg(long)]
+        compare: Option<PathBuf>,
+
+        /// Number of runs of every configuration
+        #[arg(long, default_value_t = 5)]
+        runs: usize,
+
+        /// Number of held-out patches the reconstruction error is measured on
+        #[arg(long, default_value_t = 1000)]
+        samples: usize,
+    },
+}
+
+/// Train a network of neurons on image patches using Hebbian learning rules.
+#[derive(Parser, Debug)]
+#[command(name = "ml_project_rust")]
+struct Args {
+    #[command(subcommand)]
+    command: Option<Command>,
+
+    /// Learning rule used for training
+    #[arg(long, value_enum, default_value_t = Rule::Oja)]
+    rule: Rule,
+
+    /// Time constant of the sliding threshold of the BCM rule, in number of updates
+    #[arg(long, default_value_t = 100.0)]
+    tau: f32,
+
+    /// Decay of the momentum buffer of the oja-momentum rule per update, in [0, 1)
+    #[arg(long, default_value_t = 0.9)]
+    momentum: f32,
+
+    /// Number of updates between each renormalization of the weights with the Hebbian rule
+    #[arg(long, default_value_t = 1)]
+    normalize_every: usize,
+
+    /// Nonlinearity of the ICA rule
+    #[arg(long, value_enum, default_value_t = Contrast::Tanh)]
+    nonlinearity: Contrast,
+
+    /// How the weights of every neuron are drawn before training
+    #[arg(long, value_enum, default_value_t = WeightInit::Uniform)]
+    init: WeightInit,
+
+    /// Scale of the symmetric and Gaussian weight initialization
+    #[arg(long, default_value_t = 0.1)]
+    init_scale: f32,
+
+    /// Number of threads in the pool, or `auto` for one per available core
+    #[arg(short, long, default_value_t = Threads::Auto)]
+    threads: Threads,
+
+    /// Pin every worker of the thread pool to a core of its own, which steadies the timing on NUMA and hybrid-core machines.
+    /// Only the pool backend uses the thread pool
+    #[arg(long)]
+    pin_cores: bool,
+
+    /// Thread pool used to train the neurons independently of each other. Can not be combined with --sync-patches or Sanger's
+    /// rule, which always train on the thread pool and the calling thread
+    #[arg(long, value_enum, default_value_t = Backend::Pool)]
+    backend: Backend,
+
+    /// Train all neurons in lockstep on this many shared patches per epoch, continuing from the current weights after every epoch.
+    /// The number of patches over all epochs is still given by --epochs
+    #[arg(long)]
+    sync_patches: Option<usize>,
+
+    /// Orthogonalize the weights during training with --sync-patches or the matrix backend, so the neurons learn different components
+    #[arg(long, value_enum)]
+    orthogonalize: Option<Orthogonalize>,
+
+    /// Number of training patches between orthogonalizations
+    #[arg(long, default_value_t = 1000)]
+    orthogonalize_every: usize,
+
+    /// Number of neurons to train
+    #[arg(short, long, default_value_t = 1000)]
+    neurons: usize,
+
+    /// Number of neurons scheduled to a thread at a time. The last section holds the remainder if it does not divide the number of neurons.
+    /// Defaults to about four sections per thread
+    #[arg(long)]
+    section_size: Option<usize>,
+
+    /// Number of neurons an idle thread takes from the work queue at a time, at most the section size. Defaults to about four chunks per thread
+    #[arg(long)]
+    chunk_size: Option<usize>,
+
+    /// Learning rate
+    #[arg(long, default_value_t = 0.01)]
+    lr: f32,
+
+    /// Number of patches whose updates are averaged before they are applied to the weights
+    #[arg(long, default_value_t = 1)]
+    batch_size: usize,
+
+    /// Let every neuron visit the training patches in its own random order, derived from --seed
+    #[arg(long)]
+    shuffle: bool,
+
+    /// Scale every weight update down to at most this L2 norm
+    #[arg(long, conflicts_with = "clip_value")]
+    clip_norm: Option<f32>,
+
+    /// Clamp every element of a weight update to at most this magnitude
+    #[arg(long)]
+    clip_value: Option<f32>,
+
+    /// Shrink the weights by this fraction of themselves on every update, a leaky forgetting term
+    #[arg(long, default_value_t = 0.0)]
+    weight_decay: f32,
+
+    /// Give every neuron its own Adagrad-style learning rate, which shrinks with the updates the neuron has made so far
+    #[arg(long)]
+    adaptive_lr: bool,
+
+    /// Floating point type every neuron is trained in, double precision requires the pool, rayon or scoped backend
+    #[arg(long, value_enum, default_value_t = FloatType::Single)]
+    precision: FloatType,
+
+    /// How the learning rate anneals over the epochs
+    #[arg(long, value_enum, default_value_t = Schedule::Constant)]
+    schedule: Schedule,
+
+    /// Decay factor of the exponential and step schedules
+    #[arg(long, default_value_t = 0.9999)]
+    gamma: f32,
+
+    /// Number of epochs between each decay of the step schedule
+    #[arg(long, default_value_t = 10000)]
+    step_size: usize,
+
+    /// Final learning rate of the cosine schedule
+    #[arg(long, default_value_t = 0.0)]
+    min_lr: f32,
+
+    /// Number of training patches each neuron is trained on
+    #[arg(short, long, default_value_t = 50000)]
+    epochs: usize,
+
+    /// Width of the square patches sampled from the images
+    #[arg(short, long, default_value_t = PATCH_WIDTH)]
+    patch_width: usize,
+
+    /// Train on whole flattened 28x28 images instead of patches, so the receptive fields show digit prototypes
+    #[arg(long, conflicts_with_all = ["patch_width", "stride", "random_offset"])]
+    whole_image: bool,
+
+    /// Distance in pixels between the positions patches are sampled at
+    #[arg(long, default_value_t = 1)]
+    stride: usize,
+
+    /// Shift the sampling grid by a random offset for every patch instead of anchoring it at the top left corner
+    #[arg(long)]
+    random_offset: bool,
+
+    /// Only sample patches from the center of the images, leaving out this many pixels on every side, e.g. 4 for the natural
+    /// images like sparsenet does
+    #[arg(long, default_value_t = 0, conflicts_with = "whole_image")]
+    border: usize,
+
+    /// Resample patches whose pixel variance is below this threshold, skipping the empty background of the digits
+    #[arg(long, default_value_t = 0.0)]
+    min_variance: f32,
+
+    /// Memory map the MNIST training images and extract patches on demand instead of loading every image. Requires the `mmap` feature
+    #[arg(long)]
+    mmap: bool,
+
+    /// Preprocessing applied to the MNIST patches before training
+    #[arg(long, value_enum, default_value_t = Preprocessing::None)]
+    preprocessing: Preprocessing,
+
+    /// Number of patches the preprocessing is fitted on
+    #[arg(long, default_value_t = 10000)]
+    preprocessing_samples: usize,
+
+    /// Normalization applied to every patch, after any preprocessing
+    #[arg(long, value_enum)]
+    normalize: Option<Normalize>,
+
+    /// Drop training patches whose variance is below this threshold, reporting how many were rejected after training
+    #[arg(long)]
+    variance_filter: Option<f32>,
+
+    /// Shift every training patch by up to this many pixels in each direction, filling in zeros
+    #[arg(long, default_value_t = 0)]
+    shift: usize,
+
+    /// Probability of mirroring a training patch horizontally
+    #[arg(long, default_value_t = 0.0)]
+    flip: f32,
+
+    /// Scale the contrast of every training patch by a random factor between 1 - contrast and 1 + contrast
+    #[arg(long, default_value_t = 0.0)]
+    contrast: f32,
+
+    /// Standard deviation of the Gaussian noise added to every training patch
+    #[arg(long, default_value_t = 0.0)]
+    noise: f32,
+
+    /// Image corpus the patches are sampled from
+    #[arg(long, value_enum, default_value_t = DatasetKind::Mnist)]
+    dataset: DatasetKind,
+
+    /// Split of EMNIST to train on
+    #[arg(long, value_enum, default_value_t = Split::Balanced)]
+    emnist_split: Split,
+
+    /// Keep the color channels of CIFAR-10 as separate dimensions of the patches instead of converting to grayscale
+    #[arg(long)]
+    rgb: bool,
+
+    /// Number of consecutive video frames every patch spans, one for purely spatial patches
+    #[arg(long, default_value_t = 1)]
+    frame_depth: usize,
+
+    /// Only keep every n-th frame of the video
+    #[arg(long, default_value_t = 1)]
+    frame_step: usize,
+
+    /// Stop decoding the video after this many frames were kept
+    #[arg(long)]
+    max_frames: Option<usize>,
+
+    /// Directory containing the IDX files of the dataset, or the file to read for CSV data
+    #[arg(short, long, default_value = "data/")]
+    data: String,
+
+    /// Skip the first line of the CSV file
+    #[arg(long)]
+    csv_header: bool,
+
+    /// Seed for weight initialization and patch sampling, making runs reproducible
+    #[arg(long)]
+    seed: Option<u64>,
+
+    /// Split the MNIST images into training, validation and test sets with these ratios, e.g. `0.8,0.1,0.1`, seeded with --seed.
+    /// Training only samples from the training set, and evaluation from the validation set
+    #[arg(long, value_delimiter = ',', num_args = 3)]
+    split: Option<Vec<f32>>,
+
+    /// Number of epochs between each evaluation of the reconstruction error on held-out patches. Disabled when not given.
+    /// After training it also reports how much of the variance of exact PCA the weights capture
+    #[arg(long)]
+    eval_interval: Option<usize>,
+
+    /// Number of held-out patches the reconstruction error is measured on
+    #[arg(long, default_value_t = 1000)]
+    eval_samples: usize,
+
+    /// Number of times a chunk of neurons whose training panicked is trained again before giving up
+    #[arg(long, default_value_t = 0)]
+    retries: usize,
+
+    /// Show the progress of the training on stderr
+    #[arg(long)]
+    progress: bool,
+
+    /// Monitor the training in a terminal dashboard showing the progress, the learning rate, the utilization of every thread and the
+    /// reconstruction error recorded with --eval-interval. Replaces --progress. Requires the `tui` feature
+    #[arg(long)]
+    dashboard: bool,
+
+    /// Serve the progress and the receptive fields of the run over HTTP on this address, e.g. 127.0.0.1:8080, so it can be
+    /// watched from a browser. Requires the `monitor` feature
+    #[arg(long, conflicts_with_all = ["dashboard", "progress"])]
+    monitor: Option<String>,
+
+    /// Record where the workers, the data loading and the aggregation spend their time, and save the recording to this file
+    /// for puffin_viewer. Requires the `profiling` feature
+    #[arg(long)]
+    profile: Option<PathBuf>,
+
+    /// Checkpoint to continue training from instead of initializing the weights, e.g. with a lower --lr. It must hold --neurons
+    /// neurons of the patch size of the dataset
+    #[arg(long)]
+    resume: Option<PathBuf>,
+
+    /// File to write a checkpoint of the trained weights to
+    #[arg(short, long)]
+    output: Option<PathBuf>,
+
+    /// File to write the weights to as an ONNX model, multiplying a batch of flattened patches with the weight matrix
+    #[arg(long)]
+    onnx: Option<PathBuf>,
+
+    /// File to write the weights to as a NumPy array of shape `(neurons, patch_size)`, for loading with `numpy.load`
+    #[arg(long)]
+    npy: Option<PathBuf>,
+
+    /// Report neurons whose weights have at least this absolute cosine similarity to an earlier neuron after training
+    #[arg(long)]
+    duplicates: Option<f32>,
+
+    /// Remove the duplicate neurons found with --duplicates before the weights are saved
+    #[arg(long, requires = "duplicates")]
+    prune: bool,
+
+    /// Print the norm, sparsity, dominant spatial frequency and orientation of every neuron's weights after training
+    #[arg(long)]
+    field_stats: bool,
+
+    /// Print how long training spent loading data, waiting in the queue, training the sections and collecting the results,
+    /// and with the pool backend how many jobs every worker ran and how long it was busy
+    #[arg(long)]
+    timing: bool,
+
+    /// Image file to write the learned receptive fields to
+    #[arg(long)]
+    receptive_fields: Option<PathBuf>,
+
+    /// File to write the training history to, as JSON if it ends in `.json` and as CSV otherwise. Every record holds the mean
+    /// weight norm, how far the weights moved, the reconstruction error and the elapsed time
+    #[arg(long)]
+    history: Option<PathBuf>,
+
+    /// Number of epochs between each record of the training history. Defaults to the evaluation interval
+    #[arg(long)]
+    history_interval: Option<usize>,
+}
+
+impl Args {
+    fn section_size(&self) -> usize {
+        self.section_size.unwrap_or_else(|| auto_section_size(self.neurons, self.threads.count()))
+    }
+
+    fn patch_width(&self) -> usize {
+        if self.whole_image { IMAGE_WIDTH } else { self.patch_width }
+    }
+}
+
+fn main() {
+    let args = Args::parse();
+    tracing_subscriber::fmt()
+        .with_env_filter(EnvFilter::try_from_default_env().unwrap_or_else(|_| EnvFilter::new("info")))
+        .with_thread_ids(true)
+        .init();
+
+    if let Some(Command::Experiment { config }) = &args.command {
+        return experiment(config);
+    }
+    if let Some(Command::Stats { config, compare, runs, samples }) = &args.command {
+        let repetitions = Repetitions { runs: *runs, seed: args.seed.unwrap_or(0), evaluation_samples: *samples };
+        return stats(repetitions, config, compare.as_deref());
+    }
+    if args.threads.count() == 0 {
+        eprintln!("At least one thread is needed for training");
+        std::process::exit(2);
+    }
+    if args.backend == Backend::Rayon && cfg!(not(feature = "rayon")) {
+        eprintln!("The rayon backend requires building with `--features rayon`");
+        std::process::exit(2);
+    }
+    if args.backend == Backend::Gpu && cfg!(not(feature = "gpu")) {
+        eprintln!("The gpu backend requires building with `--features gpu`");
+        std::process::exit(2);
+    }
+    if matches!(args.backend, Backend::Gpu | Backend::Matrix) && args.rule != Rule::Oja {
+        eprintln!("The {:?} backend only supports Oja's rule", args.backend);
+        std::process::exit(2);
+    }
+    // Both train on a backend of their own, which would silently replace the one asked for.
+    if args.sync_patches.is_some() && args.backend != Backend::Pool {
+        eprintln!("Training in lockstep with --sync-patches always uses the thread pool, so it can not be combined with the {:?} backend", args.backend);
+        std::process::exit(2);
+    }
+    if args.rule == Rule::Sanger && args.backend != Backend::Pool {
+        eprintln!("Sanger's rule trains every neuron hierarchically on the calling thread, so it can not be combined with the {:?} backend", args.backend);
+        std::process::exit(2);
+    }
+    if args.rule == Rule::Sanger && args.sync_patches.is_some() {
+        eprintln!("Sanger's rule trains on one patch at a time, so it can not be combined with --sync-patches");
+        std::process::exit(2);
+    }
+    if args.dashboard && cfg!(not(feature = "tui")) {
+        eprintln!("The dashboard requires building with `--features tui`");
+        std::process::exit(2);
+    }
+    if args.monitor.is_some() && cfg!(not(feature = "monitor")) {
+        eprintln!("The monitor requires building with `--features monitor`");
+        std::process::exit(2);
+    }
+    if args.profile.is_some() && cfg!(not(feature = "profiling")) {
+        eprintln!("Profiling requires building with `--features profiling`");
+        std::process::exit(2);
+    }
+    if args.mmap && cfg!(not(feature = "mmap")) {
+        eprintln!("Memory mapping the dataset requires building with `--features mmap`");
+        std::process::exit(2);
+    }
+    if args.mmap && (args.dataset != DatasetKind::Mnist || args.preprocessing != Preprocessing::None) {
+        eprintln!("Only MNIST without preprocessing can be memory mapped");
+        std::process::exit(2);
+    }
+    if args.split.is_some() && (args.dataset != DatasetKind::Mnist || args.mmap) {
+        eprintln!("Only MNIST loaded into memory can be split");
+        std::process::exit(2);
+    }
+    if args.dataset == DatasetKind::Images && cfg!(not(feature = "images")) {
+        eprintln!("Loading a folder of images requires building with `--features images`");
+        std::process::exit(2);
+    }
+    if args.dataset == DatasetKind::Video && cfg!(not(feature = "video")) {
+        eprintln!("Reading video frames requires building with `--features video`");
+        std::process::exit(2);
+    }
+    if args.frame_depth == 0 || args.frame_step == 0 || args.max_frames == Some(0) {
+        eprintln!("The frame depth, frame step and maximum number of frames must be at least one frame");
+        std::process::exit(2);
+    }
+    if args.rgb && args.dataset != DatasetKind::Cifar {
+        eprintln!("Only CIFAR-10 has color channels to keep");
+        std::process::exit(2);
+    }
+    if args.section_size == Some(0) {
+        eprintln!("The section size must be at least one neuron");
+        std::process::exit(2);
+    }
+
+    if args.stride == 0 {
+        eprintln!("The stride must be at least one pixel");
+        std::process::exit(2);
+    }
+    if !(args.min_variance >= 0.0 && args.min_variance.is_finite()) {
+        eprintln!("The minimum variance must be a non-negative number");
+        std::process::exit(2);
+    }
+    if args.variance_filter.is_some_and(|threshold| !(threshold >= 0.0 && threshold.is_finite())) {
+        eprintln!("The variance filter threshold must be a non-negative number");
+        std::process::exit(2);
+    }
+    if let Some(Command::Describe { checkpoint }) = &args.command {
+        return describe(checkpoint);
+    }
+    if let Some(Command::Evaluate { checkpoint, samples }) = &args.command {
+        return evaluate(&args, checkpoint, *samples);
+    }
+    if let Some(Command::Readout { checkpoint, train_samples, test_samples, readout_epochs, readout_lr }) = &args.command {
+        return readout(&args, checkpoint, (*train_samples, *test_samples), *readout_epochs, *readout_lr);
+    }
+    if let Some(Command::Sweep { lrs, neurons, batch_sizes, samples, csv }) = &args.command {
+        let mut sweep = Sweep::new(lrs.clone(), neurons.clone(), batch_sizes.clone(), args.epochs);
+        sweep.evaluation_samples = *samples;
+        sweep.seed = args.seed.unwrap_or(0);
+        return run_sweep(&args, sweep, csv.as_deref());
+    }
+    let sampler = PatchSampler::with_stride(args.patch_width(), args.stride).random_offset(args.random_offset)
+        .border(args.border).min_variance(args.min_variance);
+
+    #[cfg(feature = "profiling")]
+    let recording = args.profile.as_ref().map(|_| Recording::start());
+    train_dataset(&args, sampler);
+    #[cfg(feature = "profiling")]
+    if let (Some(recording), Some(path)) = (recording, &args.profile) {
+        if let Err(error) = recording.save(path) {
+            eprintln!("Unable to write the profile to {}: {}", path.display(), error);
+            std::process::exit(1);
+        }
+    }
+}
+
+// Loads the selected dataset and trains on it.
+fn train_dataset(args: &Args, sampler: PatchSampler) {
+    match args.dataset {
+        DatasetKind::Mnist => {
+            #[cfg(feature = "download")]
+            if let Err(error) = rust_ml::data::mnist::download(&args.data) {
+                eprintln!("Unable to download MNIST: {}", error);
+                std::process::exit(1);
+            }
+            #[cfg(feature = "mmap")]
+            if args.mmap {
+                let mut dataset = load(MappedIdxDataset::mnist(&args.data, args.patch_width()));
+                dataset.set_sampler(sampler);
+                return run(args, dataset, None);
+            }
+            let mut dataset = MnistData::from_path(args.section_size(), args.patch_width(), &args.data).unwrap_or_else(|error| exit_with(error));
+            dataset.set_sampler(sampler);
+            let (mut dataset, validation) = match &args.split {
+                Some(ratios) => {
+                    let ratios = SplitRatios::new(ratios[0], ratios[1], ratios[2]);
+                    let splits = dataset.split(ratios, args.seed.unwrap_or(0)).unwrap_or_else(|error| exit_with(error));
+                    (splits.train, Some(splits.validation))
+                }
+                None => (dataset, None),
+            };
+            if args.preprocessing != Preprocessing::None {
+                dataset.fit_preprocessing(args.preprocessing_samples.max(1), args.preprocessing == Preprocessing::Zca, &mut rng(args));
+            }
+            let validation = validation.map(|mut validation| {
+                validation.set_preprocessing(dataset.preprocessing().cloned());
+                validation
+            });
+            run(args, dataset, validation)
+        }
+        DatasetKind::Fashion => {
+            let mut dataset = load(IdxDataset::fashion_mnist(&args.data, args.patch_width()));
+            dataset.set_sampler(sampler);
+            run(args, dataset, None)
+        }
+        DatasetKind::Emnist => {
+            let mut dataset = load(IdxDataset::emnist(&args.data, emnist_split(args), args.patch_width()));
+            dataset.set_sampler(sampler);
+            run(args, dataset, None)
+        }
+        DatasetKind::Csv => run(args, load(CsvData::from_path(&args.data, args.csv_header)), None),
+        DatasetKind::Cifar => {
+            let mut dataset = load(CifarDataset::training(&args.data, color(args), args.patch_width()));
+            dataset.set_sampler(sampler);
+            run(args, dataset, None)
+        }
+        DatasetKind::Natural => {
+            let mut dataset = load(NaturalImages::olshausen(&args.data, args.patch_width()));
+            dataset.set_sampler(sampler);
+            run(args, dataset, None)
+        }
+        DatasetKind::Images => {
+            #[cfg(feature = "images")]
+            {
+                let mut dataset = load(ImageFolder::open(&args.data, args.patch_width()));
+                dataset.set_sampler(sampler);
+                run(args, dataset, None)
+            }
+        }
+        DatasetKind::Video => {
+            #[cfg(feature = "video")]
+            {
+                let mut dataset = load(VideoDataset::open(&args.data, frame_options(args), args.patch_width(), args.frame_depth));
+                dataset.set_sampler(sampler);
+                run(args, dataset, None)
+            }
+        }
+    }
+}
+
+fn load<D: Dataset>(dataset: std::io::Result<D>) -> D {
+    dataset.unwrap_or_else(|error| {
+        eprintln!("Unable to load dataset: {}", error);
+        std::process::exit(1);
+    })
+}
+
+fn normalizer(args: &Args) -> Option<Normalizer> {
+    match args.normalize {
+        Some(Normalize::MinMax) => Some(Normalizer::MinMax),
+        Some(Normalize::ZScore) => Some(Normalizer::ZScore),
+        Some(Normalize::UnitNorm) => Some(Normalizer::UnitNorm),
+        None => None,
+    }
+}
+
+fn augmentation(args: &Args) -> Augmentation {
+    Augmentation::new().shift(args.shift).flip(args.flip).contrast(args.contrast).noise(args.noise)
+}
+
+// Filters the training patches before augmenting them, so the threshold applies to the patches of the dataset.
+fn run<D: Dataset>(args: &Args, dataset: D, validation: Option<D>) {
+    match args.variance_filter {
+        Some(threshold) => {
+            let dataset = VarianceFilter::new(dataset, threshold);
+            let counts = dataset.counts();
+            augment(args, dataset, validation);
+            println!("Rejected {} of {} patches with a variance below {} ({:.1}%)", counts.rejected(), counts.accepted() + counts.rejected(),
+                     threshold, counts.rejection_rate() * 100.0);
+        }
+        None => augment(args, dataset, validation),
+    }
+}
+
+// Augments the training patches, the validation patches are left as they are.
+fn augment<D: Dataset, V: Dataset>(args: &Args, dataset: D, validation: Option<V>) {
+    let dataset = Augmented::new(dataset, augmentation(args)).unwrap_or_else(|error| exit_with(error));
+    match normalizer(args) {
+        Some(normalizer) => train(args, Normalized::new(dataset, normalizer), validation.map(|validation| Normalized::new(validation, normalizer))),
+        None => train(args, dataset, validation),
+    }
+}
+
+fn emnist_split(args: &Args) -> EmnistSplit {
+    match args.emnist_split {
+        Split::Byclass => EmnistSplit::ByClass,
+        Split::Bymerge => EmnistSplit::ByMerge,
+        Split::Balanced => EmnistSplit::Balanced,
+        Split::Letters => EmnistSplit::Letters,
+        Split::Digits => EmnistSplit::Digits,
+        Split::Mnist => EmnistSplit::Mnist,
+    }
+}
+
+// Prints the dimensions of a checkpoint and how its weights were trained.
+fn describe(checkpoint: &Path) {
+    let loaded = Checkpoint::load(checkpoint).unwrap_or_else(|error| exit_with(Error::from(error)));
+    let patch_size = loaded.patch_size();
+    match patch_width(patch_size) {
+        Some(width) => println!("{}: {} neurons, {}x{} patches", checkpoint.display(), loaded.weights.len(), width, width),
+        None => println!("{}: {} neurons, patches of {} values", checkpoint.display(), loaded.weights.len(), patch_size),
+    }
+    match &loaded.metadata {
+        Some(metadata) => print!("{}", metadata),
+        None => println!("No metadata, the checkpoint was saved before it was recorded"),
+    }
+}
+
+// The metadata of the network with the dataset it was trained on, which only the arguments know.
+fn metadata(args: &Args, mut metadata: Metadata) -> Metadata {
+    metadata.dataset = Some(format!("{:?} ({})", args.dataset, args.data));
+    metadata
+}
+
+// Loads the test set of the selected dataset with the patch width of the checkpoint, and prints how well the weights describe it.
+fn evaluate(args: &Args, checkpoint: &Path, samples: usize) {
+    let weights = Checkpoint::load(checkpoint).unwrap_or_else(|error| exit_with(Error::from(error)));
+    let width = patch_width(weights.patch_size() / color(args).channels() / args.frame_depth);
+    let sampler = |width: usize| PatchSampler::with_stride(width, args.stride).random_offset(args.random_offset)
+        .border(args.border).min_variance(args.min_variance);
+    let square = || width.unwrap_or_else(|| exit_with(Error::Incompatible("image datasets need a checkpoint of square patches".to_string())));
+
+    match args.dataset {
+        DatasetKind::Mnist => {
+            let mut dataset = MnistData::test_set(square(), &args.data).unwrap_or_else(|error| exit_with(error));
+            dataset.set_sampler(sampler(square()));
+            if args.preprocessing != Preprocessing::None {
+                let mut training = MnistData::from_path(1, square(), &args.data).unwrap_or_else(|error| exit_with(error));
+                training.set_sampler(sampler(square()));
+                training.fit_preprocessing(args.preprocessing_samples.max(1), args.preprocessing == Preprocessing::Zca, &mut rng(args));
+                dataset.set_preprocessing(training.preprocessing().cloned());
+            }
+            report(args, checkpoint, samples, dataset)
+        }
+        DatasetKind::Fashion => {
+            let mut dataset = load(IdxDataset::fashion_mnist_test(&args.data, square()));
+            dataset.set_sampler(sampler(square()));
+            report(args, checkpoint, samples, dataset)
+        }
+        DatasetKind::Emnist => {
+            let mut dataset = load(IdxDataset::emnist_test(&args.data, emnist_split(args), square()));
+            dataset.set_sampler(sampler(square()));
+            report(args, checkpoint, samples, dataset)
+        }
+        DatasetKind::Csv => report(args, checkpoint, samples, load(CsvData::from_path(&args.data, args.csv_header))),
+        DatasetKind::Cifar => {
+            let mut dataset = load(CifarDataset::test(&args.data, color(args), square()));
+            dataset.set_sampler(sampler(square()));
+            report(args, checkpoint, samples, dataset)
+        }
+        // Neither the natural images nor a folder of images have a separate test set, so the weights are assessed on the images
+        // they were trained on.
+        DatasetKind::Natural => {
+            let mut dataset = load(NaturalImages::olshausen(&args.data, square()));
+            dataset.set_sampler(sampler(square()));
+            report(args, checkpoint, samples, dataset)
+        }
+        DatasetKind::Images => {
+            #[cfg(feature = "images")]
+            {
+                let mut dataset = load(ImageFolder::open(&args.data, square()));
+                dataset.set_sampler(sampler(square()));
+                report(args, checkpoint, samples, dataset)
+            }
+        }
+        DatasetKind::Video => {
+            #[cfg(feature = "video")]
+            {
+                let mut dataset = load(VideoDataset::open(&args.data, frame_options(args), square(), args.frame_depth));
+                dataset.set_sampler(sampler(square()));
+                report(args, checkpoint, samples, dataset)
+            }
+        }
+    }
+}
+
+#[cfg(feature = "video")]
+fn frame_options(args: &Args) -> FrameOptions {
+    FrameOptions { step: args.frame_step, max_frames: args.max_frames }
+}
+
+fn color(args: &Args) -> Color {
+    if args.rgb { Color::Rgb } else { Color::Grayscale }
+}
+
+fn experiment(path: &Path) {
+    let config = ExperimentConfig::from_toml(path).unwrap_or_else(|error| exit_with(error));
+    match run_experiment(&config) {
+        Ok(result) => println!("Trained {} neurons in {:.1}s", result.weights.len(), result.elapsed.as_secs_f32()),
+        Err(error) => exit_with(error),
+    }
+}
+
+fn stats(repetitions: Repetitions, config: &Path, compare: Option<&Path>) {
+    let repeat = |path: &Path| {
+        let config = ExperimentConfig::from_toml(path).unwrap_or_else(|error| exit_with(error));
+        repetitions.run(&config).unwrap_or_else(|error| exit_with(error))
+    };
+    let first = repeat(config);
+    println!("{}\n{}", config.display(), first);
+    if let Some(path) = compare {
+        let second = repeat(path);
+        println!("\n{}\n{}\n\n{} compared to {}\n{}", path.display(), second, path.display(), config.display(), first.compare(&second));
+    }
+}
+
+// Trains the readout on the MNIST training images and reports the accuracy on the test images.
+fn readout(args: &Args, checkpoint: &Path, (train_samples, test_samples): (usize, usize), epochs: usize, lr: f32) {
+    if args.dataset != DatasetKind::Mnist {
+        eprintln!("The readout is only available for MNIST");
+        std::process::exit(2);
+    }
+    let weights = Checkpoint::load(checkpoint).unwrap_or_else(|error| exit_with(Error::from(error)));
+    let images = |test| LabelledImages::mnist(&args.data, test).unwrap_or_else(|error| {
+        eprintln!("Unable to load dataset: {}", error);
+        std::process::exit(1);
+    });
+    let (mut train, mut test) = (images(false), images(true));
+    train.truncate(train_samples);
+    test.truncate(test_samples);
+    match evaluate_readout(&weights.weights, &train, &test, epochs, lr, &mut rng(args)) {
+        Ok(report) => println!("{}", report),
+        Err(error) => exit_with(error),
+    }
+}
+
+fn run_sweep(args: &Args, sweep: Sweep, csv: Option<&Path>) {
+    if args.dataset != DatasetKind::Mnist {
+        eprintln!("Sweeps are only available for MNIST");
+        std::process::exit(2);
+    }
+    let pool = ThreadPool::builder(args.threads.count()).label("sweep").queue_capacity(args.threads.count()).build().unwrap_or_else(|error| exit_with(Error::from(error)));
+    let report = sweep.run(Arc::new(pool), || MnistData::from_path(1, args.patch_width(), &args.data)).unwrap_or_else(|error| exit_with(error));
+    println!("{}", report);
+    if let Some(path) = csv {
+        if let Err(error) = report.save_csv(path) {
+            eprintln!("Unable to write the sweep results to {}: {}", path.display(), error);
+            std::process::exit(1);
+        }
+    }
+}
+
+fn report<D: Dataset>(args: &Args, checkpoint: &Path, samples: usize, dataset: D) {
+    let result = match normalizer(args) {
+        Some(normalizer) => evaluate_checkpoint(checkpoint, &Normalized::new(dataset, normalizer), samples, &mut rng(args)),
+        None => evaluate_checkpoint(checkpoint, &dataset, samples, &mut rng(args)),
+    };
+    match result {
+        Ok(report) => println!("{}", report),
+        Err(error) => exit_with(error),
+    }
+}
+
+fn rng(args: &Args) -> StdRng {
+    match args.seed {
+        Some(seed) => StdRng::seed_from_u64(seed),
+        None => StdRng::from_entropy(),
+    }
+}
+
+// Trains a network on the dataset. Evaluation uses held-out patches from the validation set if one is given, and from the end of
+// the training set otherwise.
+fn train<D: Dataset, V: Dataset>(args: &Args, dataset: D, validation: Option<V>) {
+    #[cfg(feature = "gpu")]
+    if args.backend == Backend::Gpu {
+        return train_gpu(args, dataset);
+    }
+
+    // The training methods submit at most one job per thread at a time, so a queue of that size never blocks them.
+    let pool = ThreadPool::builder(args.threads.count()).label("oja").queue_capacity(args.threads.count()).pin_cores(args.pin_cores).build()
+        .unwrap_or_else(|error| exit_with(Error::from(error)));
+    let mut network = MtNetwork::with_pool(args.section_size(), Arc::new(pool), args.neurons, args.lr, dataset, args.seed)
+        .unwrap_or_else(|error| exit_with(error));
+    network.set_cancellation_token(Some(cancel_on_ctrl_c()));
+    network.set_max_retries(args.retries);
+
+    match args.schedule {
+        Schedule::Constant => network.set_schedule(Constant),
+        Schedule::Exponential => network.set_schedule(ExponentialDecay { gamma: args.gamma }),
+        Schedule::Step => network.set_schedule(StepDecay { step_size: args.step_size, gamma: args.gamma }),
+        Schedule::Cosine => network.set_schedule(CosineAnnealing { total_epochs: args.epochs, min_lr: args.min_lr }),
+    }
+
+    network.set_init(init(args));
+    if let Some(path) = &args.resume {
+        network.load_checkpoint(path).unwrap_or_else(|error| exit_with(error));
+    }
+    network.set_batch_size(args.batch_size.max(1)).unwrap_or_else(|error| exit_with(error));
+    network.set_shuffle(args.shuffle);
+    network.set_clipping(args.clip_norm.map(Clipping::Norm).or(args.clip_value.map(Clipping::Value))).unwrap_or_else(|error| exit_with(error));
+    network.set_weight_decay(args.weight_decay).unwrap_or_else(|error| exit_with(error));
+    network.set_adaptive_lr(args.adaptive_lr);
+    if !(0.0..1.0).contains(&args.momentum) {
+        eprintln!("The momentum must be at least 0 and less than 1");
+        std::process::exit(2);
+    }
+    network.set_precision(match args.precision {
+        FloatType::Single => Precision::Single,
+        FloatType::Double => Precision::Double,
+    });
+    network.set_orthogonalization(args.orthogonalize.map(|method| {
+        let method = match method {
+            Orthogonalize::GramSchmidt => OrthogonalizationMethod::GramSchmidt,
+            Orthogonalize::Symmetric => OrthogonalizationMethod::Symmetric,
+        };
+        Orthogonalization::new(method, args.orthogonalize_every.max(1))
+    }));
+    network.set_history_interval(args.history_interval.map(|interval| interval.max(1))).unwrap_or_else(|error| exit_with(error));
+    if args.progress {
+        network.set_progress_callback(Some(Arc::new(|progress: Progress| {
+            eprint!("\r{:>5.1}% done ({}/{}) after {:.1}s", 100.0 * progress.fraction(), progress.completed, progress.total, progress.elapsed.as_secs_f32());
+            if progress.completed == progress.total {
+                eprintln!();
+            }
+        })));
+    }
+    network.set_chunk_size(args.chunk_size.map(|chunk_size| chunk_size.clamp(1, args.section_size()))).unwrap_or_else(|error| exit_with(error));
+    #[cfg(feature = "tui")]
+    let dashboard = start_dashboard(args, &mut network);
+    #[cfg(feature = "monitor")]
+    let monitor = start_monitor(args, &mut network);
+    #[cfg(feature = "monitor")]
+    let mut on_section = |section: TrainedSection| {
+        if let Some(monitor) = &monitor {
+            monitor.update_section(section.start, &section.weights);
+        }
+    };
+    #[cfg(not(feature = "monitor"))]
+    let mut on_section = |_: TrainedSection| {};
+
+    if let Some(interval) = args.eval_interval {
+        let samples = args.eval_samples.max(1);
+        let evaluation = match &validation {
+            Some(validation) => Evaluation::held_out(validation, samples, interval.max(1), &mut rng(args)),
+            None => Evaluation::held_out(network.dataset(), samples, interval.max(1), &mut rng(args)),
+        };
+        network.set_evaluation(Some(evaluation)).unwrap_or_else(|error| exit_with(error));
+    }
+
+    let trained = match args.rule {
+        Rule::Oja => train_independently(&mut network, args, &mut on_section),
+        Rule::OjaMomentum => {
+            network.set_rule(LearningRule::OjaMomentum { beta: args.momentum });
+            train_independently(&mut network, args, &mut on_section)
+        }
+        Rule::Bcm => {
+            network.set_rule(LearningRule::Bcm { tau: args.tau });
+            train_independently(&mut network, args, &mut on_section)
+        }
+        Rule::Hebbian => {
+            network.set_rule(LearningRule::Hebbian { normalize_every: args.normalize_every.max(1) });
+            train_independently(&mut network, args, &mut on_section)
+        }
+        Rule::Ica => {
+            let nonlinearity = match args.nonlinearity {
+                Contrast::Tanh => Nonlinearity::Tanh,
+                Contrast::Cube => Nonlinearity::Cube,
+            };
+            network.set_rule(LearningRule::Ica { nonlinearity });
+            train_independently(&mut network, args, &mut on_section)
+        }
+        Rule::Sanger => network.train_hierarchical(args.epochs),
+    };
+    #[cfg(feature = "monitor")]
+    if let Some(monitor) = &monitor {
+        monitor.update_weights(&network.weights().to_patches());
+    }
+    #[cfg(feature = "tui")]
+    if let Some(dashboard) = dashboard {
+        network.set_progress_callback(None);
+        let _ = dashboard.join();
+    }
+    match trained {
+        Err(Error::Cancelled) => eprintln!("Training was cancelled, continuing with the partially trained weights"),
+        Err(error) => exit_with(error),
+        Ok(_) => {}
+    }
+    if args.timing {
+        println!("{}", network.timing());
+        if args.backend == Backend::Pool {
+            println!("{}", network.pool_metrics());
+        }
+    }
+
+    if let Some(evaluation) = network.evaluation() {
+        let explained = evaluation.explained_variance(&network.weights().views());
+        println!("Weights capture {:.1}% of the variance of the top {} principal components ({:.1}% of the total variance)",
+                 100.0 * explained.ratio(), network.weights().len().min(network.dataset().patch_dim()), 100.0 * explained.captured / explained.total);
+    }
+
+    if let Some(threshold) = args.duplicates {
+        let duplicates = if args.prune { network.prune_duplicates(threshold) } else { network.duplicates(threshold) };
+        for duplicate in duplicates.iter() {
+            println!("Neuron {} duplicates neuron {} (cosine similarity {:.4})", duplicate.neuron, duplicate.original, duplicate.similarity);
+        }
+        match (duplicates.len(), args.prune) {
+            (0, _) => println!("No duplicate neurons found"),
+            (count, true) => println!("Removed {} duplicate neurons, {} remain", count, network.weights().len()),
+            (count, false) => println!("Found {} duplicate neurons", count),
+        }
+    }
+
+    if args.field_stats {
+        match network.field_stats() {
+            Ok(report) => println!("{}", report),
+            Err(error) => exit_with(error),
+        }
+    }
+
+    if let Some(output) = &args.output {
+        if let Err(error) = network.save_checkpoint_with(output, metadata(args, network.metadata())) {
+            exit_with(error);
+        }
+        println!("Wrote weights to {}", output.display());
+    }
+    export(args, &network.weights().to_patches());
+    if let Some(receptive_fields) = &args.receptive_fields {
+        if let Err(error) = network.save_receptive_fields(receptive_fields, 10) {
+            exit_with(error);
+        }
+        println!("Wrote receptive fields to {}", receptive_fields.display());
+    }
+    if let Some(history) = &args.history {
+        if let Err(error) = network.history().save(history) {
+            exit_with(Error::from(error));
+        }
+        println!("Wrote training history to {}", history.display());
+    }
+}
+
+// The first Ctrl-C stops the training, so the partially trained weights are still evaluated and saved. A second one exits at once.
+fn cancel_on_ctrl_c() -> CancellationToken {
+    let token = CancellationToken::new();
+    let handler = token.clone();
+    let registered = ctrlc::set_handler(move || {
+        if handler.is_cancelled() {
+            std::process::exit(130);
+        }
+        eprintln!("\nStopping the training, press Ctrl-C again to exit immediately");
+        handler.cancel();
+    });
+    if let Err(error) = registered {
+        eprintln!("Unable to handle Ctrl-C: {}", error);
+    }
+    token
+}
+
+fn init(args: &Args) -> Init {
+    match args.init {
+        WeightInit::Uniform => Init::Uniform,
+        WeightInit::Symmetric => Init::UniformSymmetric { scale: args.init_scale },
+        WeightInit::Gaussian => Init::Gaussian { std: args.init_scale },
+        WeightInit::Xavier => Init::Xavier,
+        WeightInit::UnitNorm => Init::UnitNorm,
+    }
+}
+
+// Trains every neuron with Oja's rule in a compute shader. Evaluation and the training history are only available on the CPU.
+#[cfg(feature = "gpu")]
+fn train_gpu<D: Dataset>(args: &Args, dataset: D) {
+    let mut network = GpuNetwork::with_data(args.neurons, args.lr, dataset, args.seed).unwrap_or_else(|error| exit_with(error));
+    match args.schedule {
+        Schedule::Constant => network.set_schedule(Constant),
+        Schedule::Exponential => network.set_schedule(ExponentialDecay { gamma: args.gamma }),
+        Schedule::Step => network.set_schedule(StepDecay { step_size: args.step_size, gamma: args.gamma }),
+        Schedule::Cosine => network.set_schedule(CosineAnnealing { total_epochs: args.epochs, min_lr: args.min_lr }),
+    }
+    network.set_init(init(args));
+    if let Err(error) = network.train(args.epochs) {
+        exit_with(error);
+    }
+
+    if let Some(output) = &args.output {
+        if let Err(error) = network.save_checkpoint_with(output, metadata(args, network.metadata())) {
+            exit_with(error);
+        }
+        println!("Wrote weights to {}", output.display());
+    }
+    export(args, &network.weights().to_patches());
+    if let Some(receptive_fields) = &args.receptive_fields {
+        if let Err(error) = network.save_receptive_fields(receptive_fields, 10) {
+            exit_with(error);
+        }
+        println!("Wrote receptive fields to {}", receptive_fields.display());
+    }
+}
+
+// Serves the progress and the weights of the run over HTTP, starting with the weights the network holds before training.
+#[cfg(feature = "monitor")]
+fn start_monitor<D: Dataset>(args: &Args, network: &mut MtNetwork<D>) -> Option<Monitor> {
+    let address = args.monitor.as_ref()?;
+    let monitor = Monitor::start(address.as_str(), args.epochs).unwrap_or_else(|error| exit_with(Error::from(error)));
+    network.set_progress_callback(Some(monitor.progress_callback()));
+    monitor.update_weights(&network.weights().to_patches());
+    eprintln!("Monitoring the training on http://{}", monitor.address());
+    Some(monitor)
+}
+
+// Shows the dashboard on its own thread, fed by the progress events of the network. It closes once the network drops its
+// progress callback after training.
+#[cfg(feature = "tui")]
+fn start_dashboard<D: Dataset>(args: &Args, network: &mut MtNetwork<D>) -> Option<std::thread::JoinHandle<()>> {
+    if !args.dashboard {
+        return None;
+    }
+    let (callback, receiver) = progress_channel();
+    network.set_progress_callback(Some(callback));
+    let dashboard = Dashboard::new(format!("{:?} rule, {} neurons, {} epochs", args.rule, args.neurons, args.epochs), receiver);
+    Some(std::thread::spawn(move || match dashboard.run() {
+        Ok(state) if state.interrupted => std::process::exit(130),
+        Ok(_) => {}
+        Err(error) => eprintln!("Unable to show the dashboard: {}", error),
+    }))
+}
+
+fn export(args: &Args, weights: &[Patch]) {
+    if let Some(onnx) = &args.onnx {
+        if let Err(error) = save_onnx(weights, onnx) {
+            exit_with(Error::from(error));
+        }
+        println!("Wrote ONNX model to {}", onnx.display());
+    }
+    if let Some(npy) = &args.npy {
+        if let Err(error) = save_npy(weights, npy) {
+            exit_with(Error::from(error));
+        }
+        println!("Wrote weights to {}", npy.display());
+    }
+}
+
+// Trains every neuron independently on the selected backend. The sections trained on the thread pool are handed to
+// `on_section` as they complete.
+fn train_independently<D: Dataset>(network: &mut MtNetwork<D>, args: &Args, on_section: &mut dyn FnMut(TrainedSection)) -> error::Result<Vec<Patch>> {
+    if let Some(patches) = args.sync_patches {
+        let patches = patches.max(1);
+        return network.train_synchronous(args.epochs.div_ceil(patches), patches);
+    }
+    match args.backend {
+        #[cfg(feature = "rayon")]
+        Backend::Rayon => network.train_parallel_rayon(args.epochs),
+        Backend::Scoped => network.train_scoped(args.epochs),
+        Backend::Matrix => network.train_matrix(args.epochs),
+        _ => {
+            let mut stream = network.train_streaming(args.epochs);
+            stream.by_ref().for_each(on_section);
+            stream.finish()
+        }
+    }
+}
+
+fn exit_with(error: Error) -> ! {
+    eprintln!("Error: {}", error);
+    std::process::exit(1);
+}
@@ -0,0 +1,116 @@
+//! Buffers the workers train their sections of neurons in, allocated once and reused by every iteration of a training run.
+//! Every section has its own buffer behind a lock which only the worker training the section takes, so the workers never
+//! wait on each other, and copying weights into a buffer of the same shape reuses its allocations.
+use std::sync::{Mutex, MutexGuard};
+use rand::RngCore;
+use crate::data::dataset::Dataset;
+use crate::model::learning_rule::NeuronState;
+use crate::model::weights::Weights;
+
+/// The weights and rule states of the neurons of one section, and the patches they are trained on if the section samples its
+/// own, see `sample`.
+#[derive(Debug)]
+pub struct SectionBuffer {
+    pub weights: Weights,
+    pub states: Vec<NeuronState>,
+    pub patches: Weights,
+}
+
+impl SectionBuffer {
+    fn new(neurons: usize, patch_dim: usize) -> SectionBuffer {
+        let state = || NeuronState { velocity: Vec::with_capacity(patch_dim), ..NeuronState::default() };
+        SectionBuffer { weights: Weights::zeros(neurons, patch_dim), states: (0..neurons).map(|_| state()).collect(), patches: Weights::new(patch_dim) }
+    }
+
+    /// Samples one patch for every neuron of the section starting at the neuron `start` into the buffer, from consecutive samples
+    /// of `dataset` wrapping around at its end like `Dataset::sample_patches`. The buffer is allocated on the first call only,
+    /// though every patch is still returned by `Dataset::sample_patch` as a vector of its own before it is copied.
+    pub fn sample<D: Dataset + ?Sized>(&mut self, dataset: &D, start: usize, rng: &mut dyn RngCore) {
+        if self.patches.len() != self.weights.len() {
+            self.patches = Weights::zeros(self.weights.len(), self.weights.dim());
+        }
+        for (neuron, patch) in self.patches.iter_mut().enumerate() {
+            patch.copy_from_slice(&dataset.sample_patch((start + neuron) % dataset.len(), rng));
+        }
+    }
+
+    /// Copies the weights of a section, one neuron after the other, into the buffer and resets the states of its neurons.
+    pub fn load(&mut self, weights: &[f32]) {
+        self.weights.copy_from(weights);
+        self.states.resize_with(self.weights.len(), NeuronState::default);
+        self.states.iter_mut().for_each(NeuronState::reset);
+    }
+
+    /// Copies the weights and the states of a section into the buffer.
+    pub fn load_with_states(&mut self, weights: &[f32], states: &[NeuronState]) {
+        self.weights.copy_from(weights);
+        self.states.resize_with(self.weights.len(), NeuronState::default);
+        self.states.clone_from_slice(states);
+    }
+}
+
+/// One buffer for every section of `section_size` neurons.
+#[derive(Debug)]
+pub struct Arena {
+    neurons: usize,
+    section_size: usize,
+    patch_dim: usize,
+    sections: Vec<Mutex<SectionBuffer>>,
+}
+
+impl Arena {
+    pub fn new(neurons: usize, section_size: usize, patch_dim: usize) -> Arena {
+        assert!(section_size > 0, "the section size must be at least one");
+        let sections = (0..neurons).step_by(section_size)
+            .map(|start| Mutex::new(SectionBuffer::new(section_size.min(neurons - start), patch_dim)))
+            .collect();
+        Arena { neurons, section_size, patch_dim, sections }
+    }
+
+    /// Whether the arena holds buffers for the given layout, or a new one has to be allocated.
+    pub fn fits(&self, neurons: usize, section_size: usize, patch_dim: usize) -> bool {
+        self.neurons == neurons && self.section_size == section_size && self.patch_dim == patch_dim
+    }
+
+    /// The buffer of the section starting at the neuron `start`, which must be a multiple of the section size.
+    pub fn section(&self, start: usize) -> MutexGuard<'_, SectionBuffer> {
+        debug_assert_eq!(start % self.section_size, 0, "sections start at multiples of the section size");
+        self.sections[start / self.section_size].lock().unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+}
+
+#[cfg(test)]
+mod test{
+    use super::*;
+    use crate::data::csv::CsvData;
+
+    #[test]
+    fn loading_reuses_the_buffers(){
+        let arena = Arena::new(5, 2, 3);
+        assert_eq!(arena.section(4).weights.len(), 1);
+        let weights = [1.0, 2.0, 3.0, 4.0, 5.0, 6.0];
+        let mut section = arena.section(2);
+        let buffer = section.weights.as_slice().as_ptr();
+        section.states[0].velocity.extend_from_slice(&[1.0, 1.0, 1.0]);
+        section.load(&weights);
+        assert_eq!(section.weights.as_slice(), &weights);
+        assert!(section.states[0].velocity.is_empty() && section.states[0].velocity.capacity() >= 3);
+        section.load(&[0.0; 6]);
+        assert_eq!(section.weights.as_slice().as_ptr(), buffer);
+        assert!(arena.fits(5, 2, 3) && !arena.fits(5, 3, 3));
+    }
+
+    #[test]
+    fn sampling_reuses_the_patch_buffer(){
+        let dataset = CsvData::from_rows(vec![vec![1.0, 2.0, 3.0], vec![4.0, 5.0, 6.0]]).unwrap();
+        let arena = Arena::new(5, 2, 3);
+        let mut rng = rand::thread_rng();
+        let mut section = arena.section(2);
+        section.sample(&dataset, 2, &mut rng);
+        assert_eq!(section.patches.as_slice(), &[1.0, 2.0, 3.0, 4.0, 5.0, 6.0]);
+        let buffer = section.patches.as_slice().as_ptr();
+        section.sample(&dataset, 3, &mut rng);
+        assert_eq!(section.patches.as_slice(), &[4.0, 5.0, 6.0, 1.0, 2.0, 3.0]);
+        assert_eq!(section.patches.as_slice().as_ptr(), buffer);
+    }
+}
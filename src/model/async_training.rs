@@ -0,0 +1,96 @@
+//! Async facade over the training of an `MtNetwork`, so the crate can be embedded in async services such as a web dashboard.
+//! The run is driven by a thread of its own which waits for the thread pool, and hands the trained sections and the final result
+//! to the async side through tokio channels. The channels work on any runtime, and awaiting them never blocks it.
+use std::future::Future;
+use std::panic;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+use std::thread::{self, JoinHandle};
+use tokio::sync::{mpsc, oneshot};
+use crate::data::dataset::Dataset;
+use crate::data::patch::Patch;
+use crate::error::{Error, Result};
+use crate::model::network::{MtNetwork, TrainedSection};
+
+/// A run started with `MtNetwork::train_async`. Awaiting it gives back the network together with the result of the run, like
+/// `train_complete_iterations` would have returned it. While it trains the sections can be awaited as they finish with
+/// `next_section`. Dropping it does not stop the run, which is what the network's `CancellationToken` is for.
+pub struct AsyncTraining<D: Dataset> {
+    sections: mpsc::UnboundedReceiver<TrainedSection>,
+    result: oneshot::Receiver<(MtNetwork<D>, Result<Vec<Patch>>)>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl<D: Dataset + Send + 'static> MtNetwork<D> {
+    // Starts training the network like `train_streaming` on a thread of its own and returns immediately, see `AsyncTraining`.
+    // Only fails when the thread can not be started.
+    pub fn train_async(self, epochs: usize) -> Result<AsyncTraining<D>> {
+        let (section_sender, sections) = mpsc::unbounded_channel();
+        let (result_sender, result) = oneshot::channel();
+        let thread = thread::Builder::new().name("train-async".to_string()).spawn(move || {
+            let mut network = self;
+            let mut stream = network.train_streaming(epochs);
+            for section in stream.by_ref() {
+                // Nobody may be listening for the sections.
+                let _ = section_sender.send(section);
+            }
+            let trained = stream.finish();
+            // The sections end before the result arrives.
+            drop(section_sender);
+            let _ = result_sender.send((network, trained));
+        }).map_err(|error| Error::ThreadPool(error.to_string()))?;
+        Ok(AsyncTraining { sections, result, thread: Some(thread) })
+    }
+}
+
+impl<D: Dataset> AsyncTraining<D> {
+    /// Waits for the next section of neurons to finish training, `None` once every section has been handed out.
+    pub async fn next_section(&mut self) -> Option<TrainedSection> {
+        self.sections.recv().await
+    }
+}
+
+impl<D: Dataset> Future for AsyncTraining<D> {
+    type Output = (MtNetwork<D>, Result<Vec<Patch>>);
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        match Pin::new(&mut self.result).poll(cx) {
+            Poll::Ready(Ok(trained)) => Poll::Ready(trained),
+            // The thread only goes away without sending the result when it panicked, which is passed on to the awaiting task.
+            Poll::Ready(Err(_)) => {
+                let thread = self.thread.take().expect("an async training can not be polled after it completed");
+                match thread.join() {
+                    Err(payload) => panic::resume_unwind(payload),
+                    Ok(()) => unreachable!("the training thread always sends the result before it exits"),
+                }
+            }
+            Poll::Pending => Poll::Pending,
+        }
+    }
+}
+
+#[cfg(test)]
+mod test{
+    use super::*;
+    use crate::data::mnist::MnistData;
+
+    #[test]
+    fn async_training_matches_blocking_training(){
+        let mut blocking = MtNetwork::with_data(3, 2, 9, 0.01, MnistData::new(3, 5).unwrap(), Some(6)).unwrap();
+        let expected = blocking.train_complete_iterations(300).unwrap();
+
+        let network = MtNetwork::with_data(3, 2, 9, 0.01, MnistData::new(3, 5).unwrap(), Some(6)).unwrap();
+        let (network, sections, trained) = pollster::block_on(async {
+            let mut training = network.train_async(300).unwrap();
+            let mut sections = Vec::new();
+            while let Some(section) = training.next_section().await {
+                sections.push(section);
+            }
+            let (network, trained) = training.await;
+            (network, sections, trained)
+        });
+        assert_eq!(trained.unwrap(), expected);
+        assert_eq!(network.weights(), &expected[..]);
+        assert_eq!(sections.iter().map(|section| section.weights.len()).sum::<usize>(), 9);
+    }
+}
@@ -0,0 +1,39 @@
+//! The Bienenstock–Cooper–Munro (BCM) rule.
+//! Weights grow when the output of the neuron is above a sliding threshold and shrink when it is below it.
+//! The threshold follows a running average of the squared output, which keeps the neuron from growing without bounds.
+use crate::model::float::Float;
+use crate::model::oja::oja_y;
+
+/// Updates the weights of a single neuron for one patch, and moves its threshold `theta` towards the squared output.
+/// `tau` is the time constant of the threshold in number of updates; larger values make the threshold slide slower.
+pub fn bcm_learning_rule<F: Float>(patch_x: &[F], weights: &mut [F], theta: &mut F, lr: F, tau: F){
+    let y = oja_y(patch_x, weights);
+
+    for (w, &x) in weights.iter_mut().zip(patch_x.iter()) {
+        *w += lr * x * y * (y - *theta);
+    }
+    *theta += (y * y - *theta) / tau;
+}
+
+#[cfg(test)]
+mod test{
+    use super::*;
+
+    #[test]
+    fn bcm_depresses_below_threshold_and_potentiates_above(){
+        let patch = vec![1.0f32, 0.0];
+
+        let mut weights = vec![0.5f32, 0.5];
+        let mut theta = 1.0;
+        bcm_learning_rule(&patch, &mut weights, &mut theta, 0.1, 10.0);
+        assert!(weights[0] < 0.5);
+        assert_eq!(weights[1], 0.5);
+        assert!(theta < 1.0);
+
+        let mut weights = vec![2.0f32, 0.5];
+        let mut theta = 1.0;
+        bcm_learning_rule(&patch, &mut weights, &mut theta, 0.1, 10.0);
+        assert!(weights[0] > 2.0);
+        assert!(theta > 1.0);
+    }
+}
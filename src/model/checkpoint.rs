@@ -0,0 +1,302 @@
+//! Saving and loading of trained weights to disk.
+//! Checkpoints are stored in a small binary format: a magic number and version header, followed by the network dimensions,
+//! the metadata and the weights as little endian `f32` values, one neuron after the other. The metadata is a block of
+//! `key = value` lines, preceded by its length in bytes, so it can be read with any text tool and keys added later are
+//! skipped by older readers. Checkpoints of version 1, written before the metadata was recorded, can still be loaded.
+use std::fmt;
+use std::fs::File;
+use std::io::{self, BufReader, BufWriter, Read, Write};
+use std::path::Path;
+use std::str::FromStr;
+use std::time::{SystemTime, UNIX_EPOCH};
+use crate::data::patch::Patch;
+
+const MAGIC: &[u8; 4] = b"OJAW";
+const VERSION: u32 = 2;
+
+/// Snapshot of the weights of a network. Every weight vector must have the same length.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Checkpoint {
+    pub weights: Vec<Patch>,
+    /// How the weights were trained, None for checkpoints saved without it or by version 1 of the format.
+    pub metadata: Option<Metadata>,
+}
+
+/// Describes how the weights of a checkpoint were trained, so a saved model documents itself.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct Metadata {
+    /// The learning rule with its parameters, e.g. `Bcm { tau: 100.0 }`.
+    pub rule: String,
+    /// The dataset the weights were trained on, if the caller named it.
+    pub dataset: Option<String>,
+    /// Number of epochs of the run which produced the weights, in the units of its training method.
+    pub epochs: Option<usize>,
+    /// Statistics of the weights after the last epoch by name, such as the reconstruction error on the held-out patches.
+    pub metrics: Vec<(String, f32)>,
+    /// Version of the library which saved the weights.
+    pub version: String,
+    /// When the weights were saved, in seconds since the Unix epoch.
+    pub timestamp: u64,
+}
+
+impl Metadata {
+    /// Metadata for weights trained with the given rule, saved now by this version of the library.
+    pub fn new<S: Into<String>>(rule: S) -> Metadata {
+        let timestamp = SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |elapsed| elapsed.as_secs());
+        Metadata { rule: rule.into(), version: env!("CARGO_PKG_VERSION").to_string(), timestamp, ..Metadata::default() }
+    }
+
+    fn to_text(&self) -> String {
+        let mut lines = vec![("rule".to_string(), self.rule.clone())];
+        if let Some(dataset) = &self.dataset {
+            lines.push(("dataset".to_string(), dataset.clone()));
+        }
+        if let Some(epochs) = self.epochs {
+            lines.push(("epochs".to_string(), epochs.to_string()));
+        }
+        lines.extend(self.metrics.iter().map(|(name, value)| (format!("metrics.{}", name), value.to_string())));
+        lines.push(("version".to_string(), self.version.clone()));
+        lines.push(("timestamp".to_string(), self.timestamp.to_string()));
+        // Line breaks would start a new key, so values are kept on one line.
+        lines.iter().map(|(key, value)| format!("{} = {}\n", key, value.replace(['\n', '\r'], " "))).collect()
+    }
+
+    fn from_text(text: &str) -> io::Result<Metadata> {
+        let mut metadata = Metadata::default();
+        for line in text.lines().filter(|line| !line.trim().is_empty()) {
+            let (key, value) = line.split_once(" = ").ok_or_else(|| invalid_data(&format!("malformed metadata line `{}`", line)))?;
+            match key {
+                "rule" => metadata.rule = value.to_string(),
+                "dataset" => metadata.dataset = Some(value.to_string()),
+                "epochs" => metadata.epochs = Some(parse(key, value)?),
+                "version" => metadata.version = value.to_string(),
+                "timestamp" => metadata.timestamp = parse(key, value)?,
+                _ => {
+                    if let Some(name) = key.strip_prefix("metrics.") {
+                        metadata.metrics.push((name.to_string(), parse(key, value)?));
+                    }
+                }
+            }
+        }
+        Ok(metadata)
+    }
+}
+
+impl fmt::Display for Metadata {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "Rule: {}", self.rule)?;
+        writeln!(f, "Dataset: {}", self.dataset.as_deref().unwrap_or("unknown"))?;
+        match self.epochs {
+            Some(epochs) => writeln!(f, "Epochs: {}", epochs)?,
+            None => writeln!(f, "Epochs: unknown")?,
+        }
+        for (name, value) in self.metrics.iter() {
+            writeln!(f, "{}: {}", name, value)?;
+        }
+        writeln!(f, "Saved by version {} at {}", self.version, format_timestamp(self.timestamp))
+    }
+}
+
+impl Checkpoint {
+    pub fn new(weights: Vec<Patch>) -> Checkpoint {
+        Checkpoint { weights, metadata: None }
+    }
+
+    pub fn with_metadata(mut self, metadata: Metadata) -> Checkpoint {
+        self.metadata = Some(metadata);
+        self
+    }
+
+    pub fn patch_size(&self) -> usize {
+        self.weights.first().map_or(0, |w| w.len())
+    }
+
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> io::Result<()> {
+        let mut writer = BufWriter::new(File::create(path)?);
+        self.write_to(&mut writer)?;
+        writer.flush()
+    }
+
+    pub fn load<P: AsRef<Path>>(path: P) -> io::Result<Checkpoint> {
+        Checkpoint::read_from(&mut BufReader::new(File::open(path)?))
+    }
+
+    pub fn write_to<W: Write>(&self, writer: &mut W) -> io::Result<()> {
+        if self.weights.iter().any(|w| w.len() != self.patch_size()) {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, "all weight vectors must have the same length"));
+        }
+        // Values are kept on one line when written, but a name which can not be told apart from its value is rejected rather
+        // than written into a checkpoint which could not be read back.
+        let mut metrics = self.metadata.iter().flat_map(|metadata| metadata.metrics.iter());
+        if let Some((name, _)) = metrics.find(|(name, _)| name.is_empty() || name.contains(['=', '\n', '\r'])) {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, format!("invalid metric name `{}`, names must not be empty or contain `=` or line breaks", name)));
+        }
+
+        writer.write_all(MAGIC)?;
+        writer.write_all(&VERSION.to_le_bytes())?;
+        writer.write_all(&(self.weights.len() as u32).to_le_bytes())?;
+        writer.write_all(&(self.patch_size() as u32).to_le_bytes())?;
+        let metadata = self.metadata.as_ref().map_or_else(String::new, Metadata::to_text);
+        writer.write_all(&(metadata.len() as u32).to_le_bytes())?;
+        writer.write_all(metadata.as_bytes())?;
+
+        for weight in self.weights.iter() {
+            for value in weight.iter() {
+                writer.write_all(&value.to_le_bytes())?;
+            }
+        }
+        Ok(())
+    }
+
+    pub fn read_from<R: Read>(reader: &mut R) -> io::Result<Checkpoint> {
+        let mut magic = [0u8; 4];
+        reader.read_exact(&mut magic)?;
+        if &magic != MAGIC {
+            return Err(invalid_data("not a weight checkpoint"));
+        }
+
+        let version = read_u32(reader)?;
+        if version != 1 && version != VERSION {
+            return Err(invalid_data(&format!("unsupported checkpoint version {}", version)));
+        }
+
+        let neurons = read_u32(reader)? as usize;
+        let patch_size = read_u32(reader)? as usize;
+        let mut metadata = None;
+        if version >= 2 {
+            let len = read_u32(reader)? as usize;
+            let mut text = Vec::new();
+            reader.take(len as u64).read_to_end(&mut text)?;
+            if text.len() != len {
+                return Err(invalid_data("the checkpoint is truncated in its metadata"));
+            }
+            if !text.is_empty() {
+                let text = String::from_utf8(text).map_err(|_| invalid_data("the metadata is not valid UTF-8"))?;
+                metadata = Some(Metadata::from_text(&text)?);
+            }
+        }
+
+        // The dimensions come from the file, so nothing is allocated up front: the buffer only grows with the bytes actually
+        // read, and a corrupt header fails as truncated instead of exhausting the memory.
+        if patch_size == 0 && neurons > 0 {
+            return Err(invalid_data("the weight vectors of the checkpoint are empty"));
+        }
+        let len = neurons.checked_mul(patch_size).and_then(|values| values.checked_mul(4))
+            .ok_or_else(|| invalid_data(&format!("{} neurons with a patch size of {} overflow the address space", neurons, patch_size)))?;
+        let mut bytes = Vec::new();
+        reader.take(len as u64).read_to_end(&mut bytes)?;
+        if bytes.len() != len {
+            return Err(invalid_data(&format!("the checkpoint is truncated, expected {} bytes of weights but found {}", len, bytes.len())));
+        }
+        let weights = bytes.chunks_exact(4 * patch_size.max(1))
+            .map(|weight| weight.chunks_exact(4).map(|b| f32::from_le_bytes([b[0], b[1], b[2], b[3]])).collect())
+            .collect();
+
+        Ok(Checkpoint { weights, metadata })
+    }
+}
+
+fn read_u32<R: Read>(reader: &mut R) -> io::Result<u32> {
+    let mut buffer = [0u8; 4];
+    reader.read_exact(&mut buffer)?;
+    Ok(u32::from_le_bytes(buffer))
+}
+
+fn invalid_data(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message.to_string())
+}
+
+fn parse<T: FromStr>(key: &str, value: &str) -> io::Result<T> {
+    value.parse().map_err(|_| invalid_data(&format!("metadata `{}` is not a number: {}", key, value)))
+}
+
+// Formats seconds since the Unix epoch as a UTC date and time, converting days to a civil date as in Howard Hinnant's
+// `civil_from_days`.
+fn format_timestamp(timestamp: u64) -> String {
+    let (days, seconds) = (timestamp / 86400, timestamp % 86400);
+    let z = days as i64 + 719468;
+    let era = z.div_euclid(146097);
+    let day_of_era = z.rem_euclid(146097);
+    let year_of_era = (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let shifted_month = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * shifted_month + 2) / 5 + 1;
+    let month = if shifted_month < 10 { shifted_month + 3 } else { shifted_month - 9 };
+    let year = year_of_era + era * 400 + i64::from(month <= 2);
+    format!("{:04}-{:02}-{:02} {:02}:{:02}:{:02} UTC", year, month, day, seconds / 3600, seconds / 60 % 60, seconds % 60)
+}
+
+#[cfg(test)]
+mod test{
+    use super::*;
+
+    #[test]
+    fn checkpoint_round_trip(){
+        let weight: Patch = (0..64).map(|i| i as f32 * 0.5 - 3.0).collect();
+        let checkpoint = Checkpoint::new(vec![weight, vec![1.0; 64]]);
+
+        let mut bytes = Vec::new();
+        checkpoint.write_to(&mut bytes).unwrap();
+        let loaded = Checkpoint::read_from(&mut bytes.as_slice()).unwrap();
+
+        assert_eq!(checkpoint, loaded);
+    }
+
+    #[test]
+    fn metadata_round_trips_and_version_one_still_loads(){
+        let metadata = Metadata {
+            dataset: Some("Mnist (data/)".to_string()),
+            epochs: Some(5000),
+            metrics: vec![("reconstruction_error".to_string(), 0.25), ("mean_weight_norm".to_string(), 1.0)],
+            timestamp: 1_700_000_000,
+            ..Metadata::new("Bcm { tau: 100.0 }")
+        };
+        let checkpoint = Checkpoint::new(vec![vec![0.5; 4]; 2]).with_metadata(metadata.clone());
+        let mut bytes = Vec::new();
+        checkpoint.write_to(&mut bytes).unwrap();
+        assert_eq!(Checkpoint::read_from(&mut bytes.as_slice()).unwrap(), checkpoint);
+        let description = metadata.to_string();
+        assert!(description.contains("Dataset: Mnist (data/)\nEpochs: 5000\nreconstruction_error: 0.25\n"));
+        assert!(description.ends_with(" at 2023-11-14 22:13:20 UTC\n"));
+
+        let named = Metadata { metrics: vec![("error on held-out patches (l2)".to_string(), 0.5)], ..Metadata::new("Oja") };
+        let checkpoint = Checkpoint::new(vec![vec![0.5; 4]]).with_metadata(named);
+        let mut bytes = Vec::new();
+        checkpoint.write_to(&mut bytes).unwrap();
+        assert_eq!(Checkpoint::read_from(&mut bytes.as_slice()).unwrap(), checkpoint);
+        for name in ["a = b", "two\nlines", ""].iter() {
+            let metadata = Metadata { metrics: vec![(name.to_string(), 1.0)], ..Metadata::new("Oja") };
+            let error = Checkpoint::new(vec![vec![0.5; 4]]).with_metadata(metadata).write_to(&mut Vec::new()).unwrap_err();
+            assert_eq!(error.kind(), io::ErrorKind::InvalidInput);
+        }
+
+        let mut version_one = b"OJAW\x01\x00\x00\x00\x01\x00\x00\x00\x02\x00\x00\x00".to_vec();
+        version_one.extend([1.0f32, 2.0].iter().flat_map(|value| value.to_le_bytes()));
+        let loaded = Checkpoint::read_from(&mut version_one.as_slice()).unwrap();
+        assert_eq!(loaded, Checkpoint::new(vec![vec![1.0, 2.0]]));
+    }
+
+    #[test]
+    fn checkpoint_rejects_dimensions_it_does_not_hold(){
+        let huge = b"OJAW\x01\x00\x00\x00\xff\xff\xff\xff\xff\xff\xff\x7f\x00\x00\x80\x3f".to_vec();
+        let error = Checkpoint::read_from(&mut huge.as_slice()).unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+
+        let mut bytes = Vec::new();
+        Checkpoint::new(vec![vec![1.0; 8]; 3]).write_to(&mut bytes).unwrap();
+        bytes.truncate(bytes.len() - 2);
+        let error = Checkpoint::read_from(&mut bytes.as_slice()).unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+
+        let metadata = b"OJAW\x02\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\xff\xff\xff\xffrule = Oja\n".to_vec();
+        let error = Checkpoint::read_from(&mut metadata.as_slice()).unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn checkpoint_rejects_bad_header(){
+        let bytes = b"NOPE\x01\x00\x00\x00".to_vec();
+        let error = Checkpoint::read_from(&mut bytes.as_slice()).unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+    }
+}
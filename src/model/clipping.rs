@@ -0,0 +1,78 @@
+//! Clipping of the weight updates, which keeps a single large step from throwing the weights far off, so training stays stable
+//! at learning rates where the rules would otherwise diverge.
+use crate::error::{Error, Result};
+use crate::model::float::Float;
+
+/// How the change of a neuron's weights in a single update is limited.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Clipping {
+    /// Scales the update down to at most this L2 norm, keeping its direction.
+    Norm(f32),
+    /// Clamps every element of the update to `[-limit, limit]`.
+    Value(f32),
+}
+
+impl Clipping {
+    pub(crate) fn validate(&self) -> Result<()> {
+        match *self {
+            Clipping::Norm(limit) | Clipping::Value(limit) if limit > 0.0 && limit.is_finite() => Ok(()),
+            _ => Err(Error::InvalidConfig(format!("the clipping limit must be a positive number, got {:?}", self))),
+        }
+    }
+
+    /// Clips an update in place.
+    pub fn clip<F: Float>(&self, update: &mut [F]) {
+        match *self {
+            Clipping::Norm(limit) => {
+                let limit = F::from_f32(limit);
+                let norm = update.iter().map(|&u| u * u).sum::<F>().sqrt();
+                if norm > limit {
+                    let scale = limit / norm;
+                    update.iter_mut().for_each(|u| *u *= scale);
+                }
+            }
+            Clipping::Value(limit) => {
+                let limit = F::from_f32(limit);
+                for u in update.iter_mut() {
+                    if *u > limit {
+                        *u = limit;
+                    } else if *u < -limit {
+                        *u = -limit;
+                    }
+                }
+            }
+        }
+    }
+
+    /// Clips the change from `previous` to `weights`, which a rule has just updated in place.
+    pub fn apply<F: Float>(&self, previous: &[F], weights: &mut [F]) {
+        let mut update: Vec<F> = weights.iter().zip(previous.iter()).map(|(&w, &p)| w - p).collect();
+        self.clip(&mut update);
+        for ((w, &p), &u) in weights.iter_mut().zip(previous.iter()).zip(update.iter()) {
+            *w = p + u;
+        }
+    }
+}
+
+#[cfg(test)]
+mod test{
+    use super::*;
+
+    #[test]
+    fn clips_by_norm_and_by_value(){
+        let mut update = vec![3.0f32, -4.0];
+        Clipping::Norm(1.0).clip(&mut update);
+        assert!((update[0] - 0.6).abs() < 1e-6 && (update[1] + 0.8).abs() < 1e-6);
+
+        let mut small = vec![0.1f64, 0.2];
+        Clipping::Norm(1.0).clip(&mut small);
+        assert_eq!(small, vec![0.1, 0.2]);
+
+        let mut weights = vec![5.0f32, 0.0, 1.05];
+        Clipping::Value(0.1).apply(&[1.0, 1.0, 1.0], &mut weights);
+        assert!((weights[0] - 1.1).abs() < 1e-6 && (weights[1] - 0.9).abs() < 1e-6 && (weights[2] - 1.05).abs() < 1e-6);
+
+        assert!(Clipping::Value(0.0).validate().is_err());
+        assert!(Clipping::Norm(f32::NAN).validate().is_err());
+    }
+}
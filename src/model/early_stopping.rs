@@ -0,0 +1,88 @@
+//! Convergence detection for the training loops.
+//! A neuron is considered converged once its weights moved less than a threshold (L2 norm) over a window of updates.
+use crate::data::patch::Patch;
+
+/// Configuration for stopping training early once the weights stop changing.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct EarlyStopping {
+    pub threshold: f32,
+    pub window: usize,
+}
+
+impl EarlyStopping {
+    pub fn new(threshold: f32, window: usize) -> EarlyStopping {
+        assert!(window > 0, "the early stopping window must be at least one update");
+        EarlyStopping { threshold, window }
+    }
+}
+
+impl Default for EarlyStopping {
+    fn default() -> EarlyStopping {
+        EarlyStopping { threshold: 1e-3, window: 1000 }
+    }
+}
+
+/// L2 norm of the difference between two weight vectors.
+pub fn weight_delta(previous: &[f32], current: &[f32]) -> f32 {
+    previous.iter().zip(current.iter()).map(|(p, c)| (c - p) * (c - p)).sum::<f32>().sqrt()
+}
+
+/// Tracks the convergence of a single neuron across updates.
+#[derive(Debug, Clone)]
+pub struct ConvergenceTracker {
+    config: EarlyStopping,
+    snapshot: Patch,
+    snapshot_step: usize,
+    steps: usize,
+    converged_at: Option<usize>,
+}
+
+impl ConvergenceTracker {
+    pub fn new(config: EarlyStopping, weights: &[f32]) -> ConvergenceTracker {
+        ConvergenceTracker { config, snapshot: weights.to_vec(), snapshot_step: 0, steps: 0, converged_at: None }
+    }
+
+    /// Records one update of the neuron and returns true once it has converged.
+    pub fn step(&mut self, weights: &[f32]) -> bool {
+        if self.converged_at.is_some() {
+            return true;
+        }
+
+        self.steps += 1;
+        if self.steps - self.snapshot_step >= self.config.window {
+            if weight_delta(&self.snapshot, weights) < self.config.threshold {
+                self.converged_at = Some(self.steps);
+            } else {
+                self.snapshot.copy_from_slice(weights);
+                self.snapshot_step = self.steps;
+            }
+        }
+        self.converged_at.is_some()
+    }
+
+    /// The epoch at which the neuron converged, if it has.
+    pub fn converged_at(&self) -> Option<usize> {
+        self.converged_at
+    }
+}
+
+#[cfg(test)]
+mod test{
+    use super::*;
+
+    #[test]
+    fn tracker_detects_convergence_after_window(){
+        let mut weights = vec![1.0f32, 0.0];
+        let mut tracker = ConvergenceTracker::new(EarlyStopping::new(0.01, 10), &weights);
+
+        for _ in 0..10 {
+            weights[0] += 0.1;
+            assert!(!tracker.step(&weights));
+        }
+        for _ in 0..9 {
+            assert!(!tracker.step(&weights));
+        }
+        assert!(tracker.step(&weights));
+        assert_eq!(tracker.converged_at(), Some(20));
+    }
+}
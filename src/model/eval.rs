@@ -0,0 +1,250 @@
+//! Evaluation of learned weights by how well they reconstruct patches the network was not trained on.
+//! A patch is projected onto every weight vector, and reconstructed as the sum of the weight vectors scaled by those projections.
+//! As a correctness check of the learning rules, the variance captured by the weights can be compared to exact PCA of the same patches.
+use std::fmt;
+use std::path::Path;
+use rand::RngCore;
+use crate::data::dataset::Dataset;
+use crate::data::patch::Patch;
+use crate::error::{Error, Result};
+use crate::model::checkpoint::Checkpoint;
+use crate::model::simd::{dot, scale_add};
+use crate::utils::linalg::{covariance, mean_patch, sorted_eigen};
+
+/// Variance of the patches captured by the learned weights, compared to the exact principal components.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ExplainedVariance {
+    /// Variance of the patches within the subspace spanned by the weights.
+    pub captured: f32,
+    /// Variance captured by as many exact principal components as there are weight vectors.
+    pub optimal: f32,
+    /// Total variance of the patches.
+    pub total: f32,
+}
+
+impl ExplainedVariance {
+    /// Fraction of the variance of the optimal subspace the weights capture, 1 when they span the top principal components.
+    pub fn ratio(&self) -> f32 {
+        if self.optimal > 0.0 { self.captured / self.optimal } else { 0.0 }
+    }
+}
+
+/// Held-out patches and how often the network is evaluated on them during training.
+#[derive(Debug, Clone)]
+pub struct Evaluation {
+    patches: Vec<Patch>,
+    interval: usize,
+}
+
+impl Evaluation {
+    pub fn new(patches: Vec<Patch>, interval: usize) -> Evaluation {
+        assert!(!patches.is_empty(), "at least one patch is needed for evaluation");
+        assert!(interval > 0, "the evaluation interval must be at least one epoch");
+        Evaluation { patches, interval }
+    }
+
+    /// Samples the evaluation patches from the last samples of the dataset, which training reaches last if at all.
+    pub fn held_out<D: Dataset>(dataset: &D, samples: usize, interval: usize, rng: &mut dyn RngCore) -> Evaluation {
+        let start = dataset.len().saturating_sub(samples);
+        Evaluation::new(dataset.sample_patches(start, samples, rng), interval)
+    }
+
+    pub fn patches(&self) -> &[Patch] {
+        &self.patches
+    }
+
+    pub fn interval(&self) -> usize {
+        self.interval
+    }
+
+    pub fn evaluate<P: AsRef<[f32]>>(&self, weights: &[P]) -> f32 {
+        reconstruction_error(&self.patches, weights)
+    }
+
+    pub fn explained_variance<P: AsRef<[f32]>>(&self, weights: &[P]) -> ExplainedVariance {
+        explained_variance(&self.patches, weights)
+    }
+}
+
+/// Summary of how well a set of trained weights describes patches from a test set.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Report {
+    pub patches: usize,
+    pub neurons: usize,
+    /// Number of values in every patch.
+    pub dimension: usize,
+    pub reconstruction_error: f32,
+    /// Error of reconstructing every patch as zeros, the mean squared value of the patches, to put the reconstruction error in perspective.
+    pub baseline_error: f32,
+    pub explained_variance: ExplainedVariance,
+}
+
+impl Report {
+    pub fn new<P: AsRef<[f32]>>(patches: &[Patch], weights: &[P]) -> Report {
+        Report {
+            patches: patches.len(),
+            neurons: weights.len(),
+            dimension: patches.first().map_or(0, |patch| patch.len()),
+            reconstruction_error: reconstruction_error(patches, weights),
+            baseline_error: reconstruction_error::<Patch>(patches, &[]),
+            explained_variance: explained_variance(patches, weights),
+        }
+    }
+}
+
+impl fmt::Display for Report {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let explained = &self.explained_variance;
+        let total = if explained.total > 0.0 { explained.captured / explained.total } else { 0.0 };
+        writeln!(f, "Evaluated {} neurons on {} test patches", self.neurons, self.patches)?;
+        writeln!(f, "Reconstruction error: {:.6} (baseline without weights: {:.6})", self.reconstruction_error, self.baseline_error)?;
+        writeln!(f, "Explained variance:   {:.1}% of the total variance", 100.0 * total)?;
+        write!(f, "                      {:.1}% of the variance of the top {} principal components", 100.0 * explained.ratio(), self.neurons.min(self.dimension))
+    }
+}
+
+/// Loads the weights of a checkpoint and evaluates them on `samples` patches sampled from the dataset, e.g. a test split.
+pub fn evaluate_checkpoint<P: AsRef<Path>, D: Dataset>(path: P, dataset: &D, samples: usize, rng: &mut dyn RngCore) -> Result<Report> {
+    let checkpoint = Checkpoint::load(path)?;
+    if checkpoint.patch_size() != dataset.patch_dim() {
+        return Err(Error::Incompatible(format!("the checkpoint holds weights for {} values, but the test patches have {}", checkpoint.patch_size(), dataset.patch_dim())));
+    }
+    if samples == 0 || dataset.is_empty() {
+        return Err(Error::InvalidConfig("at least one test patch is needed for evaluation".to_string()));
+    }
+    Ok(Report::new(&dataset.sample_patches(0, samples, rng), &checkpoint.weights))
+}
+
+/// Output of every neuron for the given patch.
+pub fn project<P: AsRef<[f32]>>(patch: &[f32], weights: &[P]) -> Vec<f32> {
+    weights.iter().map(|w| dot(w.as_ref(), patch)).collect()
+}
+
+/// Sum of the weight vectors scaled by the output of their neuron for the given patch.
+pub fn reconstruct<P: AsRef<[f32]>>(patch: &[f32], weights: &[P]) -> Patch {
+    let mut reconstruction = combine(&project(patch, weights), weights);
+    reconstruction.resize(patch.len(), 0.0);
+    reconstruction
+}
+
+/// Sum of the weight vectors scaled by the given activations, the inverse of `project` when the weights are orthonormal.
+pub fn combine<P: AsRef<[f32]>>(activations: &[f32], weights: &[P]) -> Patch {
+    let mut patch = vec![0.0; weights.first().map_or(0, |w| w.as_ref().len())];
+    for (y, w) in activations.iter().zip(weights.iter()) {
+        scale_add(&mut patch, 1.0, w.as_ref(), *y);
+    }
+    patch
+}
+
+/// Mean squared error between the patches and their reconstructions, averaged over every value of every patch.
+pub fn reconstruction_error<P: AsRef<[f32]>>(patches: &[Patch], weights: &[P]) -> f32 {
+    let mut total = 0.0;
+    let mut count = 0;
+    for patch in patches {
+        let reconstruction = reconstruct(patch, weights);
+        total += patch.iter().zip(reconstruction.iter()).map(|(x, r)| (x - r) * (x - r)).sum::<f32>();
+        count += patch.len();
+    }
+    if count == 0 { 0.0 } else { total / count as f32 }
+}
+
+/// The top `k` principal components of the patches with the variance along each of them, in descending order of variance.
+pub fn principal_components(patches: &[Patch], k: usize) -> Vec<(f32, Patch)> {
+    let mean = mean_patch(patches);
+    sorted_eigen(covariance(patches, &mean)).into_iter()
+        .take(k)
+        .map(|(variance, component)| (variance as f32, component.iter().map(|x| *x as f32).collect()))
+        .collect()
+}
+
+/// Compares the variance captured by the subspace the weights span against exact PCA of the same patches.
+/// Weights that point in (almost) the same direction only count once, so neurons that all learned the first component capture
+/// no more variance than a single one.
+pub fn explained_variance<P: AsRef<[f32]>>(patches: &[Patch], weights: &[P]) -> ExplainedVariance {
+    let mean = mean_patch(patches);
+    let covariance = covariance(patches, &mean);
+    let pairs = sorted_eigen(covariance.clone());
+    let k = weights.len().min(mean.len());
+
+    let basis = orthonormal_basis(weights);
+    let captured: f64 = basis.iter()
+        .map(|u| covariance.iter().zip(u.iter()).map(|(row, ui)| ui * row.iter().zip(u.iter()).map(|(c, uj)| c * uj).sum::<f64>()).sum::<f64>())
+        .sum();
+
+    ExplainedVariance {
+        captured: captured as f32,
+        optimal: pairs.iter().take(k).map(|(value, _)| value.max(0.0)).sum::<f64>() as f32,
+        total: pairs.iter().map(|(value, _)| value.max(0.0)).sum::<f64>() as f32,
+    }
+}
+
+/// Orthonormalizes the weights with Gram-Schmidt, dropping vectors that are nearly dependent on the previous ones.
+fn orthonormal_basis<P: AsRef<[f32]>>(weights: &[P]) -> Vec<Vec<f64>> {
+    let mut basis: Vec<Vec<f64>> = Vec::new();
+    for weight in weights {
+        let mut v: Vec<f64> = weight.as_ref().iter().map(|x| *x as f64).collect();
+        let norm = v.iter().map(|x| x * x).sum::<f64>().sqrt();
+        for u in &basis {
+            let dot: f64 = u.iter().zip(v.iter()).map(|(a, b)| a * b).sum();
+            v.iter_mut().zip(u.iter()).for_each(|(x, u)| *x -= dot * u);
+        }
+        let remaining = v.iter().map(|x| x * x).sum::<f64>().sqrt();
+        if norm > 0.0 && remaining > 1e-3 * norm {
+            v.iter_mut().for_each(|x| *x /= remaining);
+            basis.push(v);
+        }
+    }
+    basis
+}
+
+#[cfg(test)]
+mod test{
+    use super::*;
+
+    #[test]
+    fn orthonormal_basis_reconstructs_exactly(){
+        let weights = vec![vec![1.0, 0.0], vec![0.0, 1.0]];
+        let patches = vec![vec![0.3, -2.0], vec![1.5, 0.5]];
+        assert!(reconstruction_error(&patches, &weights) < 1e-10);
+
+        let first_axis = vec![vec![1.0, 0.0]];
+        let error = reconstruction_error(&patches, &first_axis);
+        assert!((error - (4.0 + 0.25) / 4.0).abs() < 1e-6);
+    }
+
+    #[test]
+    fn top_principal_component_explains_all_optimal_variance(){
+        let patches: Vec<Patch> = (0..100).map(|i| {
+            let t = i as f32 / 10.0 - 5.0;
+            vec![t, 0.5 * t + if i % 2 == 0 { 0.1 } else { -0.1 }]
+        }).collect();
+
+        let (_, component) = &principal_components(&patches, 1)[0];
+        let exact = explained_variance(&patches, std::slice::from_ref(component));
+        assert!((exact.ratio() - 1.0).abs() < 1e-4);
+
+        let duplicates = explained_variance(&patches, &[component.clone(), component.clone()]);
+        assert!(duplicates.captured < duplicates.total);
+        assert!((duplicates.captured - exact.captured).abs() < 1e-3);
+
+        let orthogonal = explained_variance(&patches, &[vec![-component[1], component[0]]]);
+        assert!(orthogonal.ratio() < 0.1);
+    }
+
+    #[test]
+    fn checkpoints_are_evaluated_on_the_test_patches(){
+        let patches = vec![vec![0.3, -2.0], vec![1.5, 0.5], vec![-1.0, 1.0]];
+        let report = Report::new(&patches, &[vec![1.0, 0.0], vec![0.0, 1.0]]);
+        assert!(report.reconstruction_error < 1e-10);
+        assert!((report.baseline_error - (0.09 + 4.0 + 2.25 + 0.25 + 1.0 + 1.0) / 6.0).abs() < 1e-6);
+        assert!((report.explained_variance.ratio() - 1.0).abs() < 1e-4);
+        assert!(report.to_string().contains("2 neurons on 3 test patches"));
+
+        let path = std::env::temp_dir().join(format!("rust_ml_eval_test_{}", std::process::id()));
+        Checkpoint::new(vec![vec![1.0; 4]]).save(&path).unwrap();
+        let mnist = crate::data::mnist::MnistData::new(1, 3).unwrap();
+        let result = evaluate_checkpoint(&path, &mnist, 10, &mut rand::thread_rng());
+        std::fs::remove_file(&path).unwrap();
+        assert!(matches!(result, Err(Error::Incompatible(_))));
+    }
+}
@@ -0,0 +1,153 @@
+//! Statistics characterizing the receptive field every neuron learned: how strong and how sparse its weights are, which
+//! spatial frequency dominates the reshaped patch, and the orientation of the stripes at that frequency. The frequency content
+//! is taken from a discrete Fourier transform of the patch with its mean removed.
+use std::f32::consts::PI;
+use std::fmt::{self, Write as _};
+use std::fs;
+use std::io;
+use std::path::Path;
+use crate::data::patch::{patch_width, Patch};
+use crate::error::{Error, Result};
+
+/// Statistics of the weights of a single neuron.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct FieldStats {
+    pub neuron: usize,
+    /// L2 norm of the weight vector.
+    pub norm: f32,
+    /// Hoyer sparsity, 0 when all weights have the same magnitude and 1 when a single weight is non-zero.
+    pub sparsity: f32,
+    /// Spatial frequency with the most power, in cycles per pixel. 0 for a constant patch.
+    pub frequency: f32,
+    /// Orientation of the stripes at the dominant frequency in degrees, counterclockwise from horizontal, in `[0, 180)`.
+    pub orientation: f32,
+}
+
+impl FieldStats {
+    /// Computes the statistics of a square patch of the given width.
+    pub fn new(neuron: usize, weights: &[f32], width: usize) -> FieldStats {
+        let l2 = weights.iter().map(|w| w * w).sum::<f32>().sqrt();
+        let l1 = weights.iter().map(|w| w.abs()).sum::<f32>();
+        let n = (weights.len() as f32).sqrt();
+        let sparsity = if l2 > 0.0 && n > 1.0 { (n - l1 / l2) / (n - 1.0) } else { 0.0 };
+        let (frequency, orientation) = dominant_frequency(weights, width);
+        FieldStats { neuron, norm: l2, sparsity, frequency, orientation }
+    }
+}
+
+// The frequency and stripe orientation of the Fourier component with the most power, ignoring the mean of the patch.
+// Rows grow downwards in the patch, so they are flipped to measure the angle with the y axis pointing up.
+fn dominant_frequency(weights: &[f32], width: usize) -> (f32, f32) {
+    let mean = weights.iter().sum::<f32>() / weights.len().max(1) as f32;
+    let signed = |k: usize| if 2 * k > width { k as f32 - width as f32 } else { k as f32 };
+    let mut best = (0.0, 0.0, 0.0);
+    for ky in 0..width {
+        for kx in 0..width {
+            if kx == 0 && ky == 0 {
+                continue;
+            }
+            let (mut real, mut imaginary) = (0.0, 0.0);
+            for (index, w) in weights.iter().enumerate() {
+                let phase = -2.0 * PI * (kx * (index % width) + ky * (index / width)) as f32 / width as f32;
+                real += (w - mean) * phase.cos();
+                imaginary += (w - mean) * phase.sin();
+            }
+            let power = real * real + imaginary * imaginary;
+            if power > best.0 + 1e-9 {
+                best = (power, signed(kx), signed(ky));
+            }
+        }
+    }
+    let (power, fx, fy) = best;
+    if power <= 0.0 {
+        return (0.0, 0.0);
+    }
+    let frequency = (fx * fx + fy * fy).sqrt() / width as f32;
+    // The stripes run perpendicular to the wave vector.
+    let orientation = ((-fy).atan2(fx).to_degrees() + 90.0).rem_euclid(180.0);
+    (frequency, orientation)
+}
+
+/// The statistics of every neuron of a network.
+#[derive(Debug, Clone, PartialEq)]
+pub struct FieldReport {
+    fields: Vec<FieldStats>,
+}
+
+impl FieldReport {
+    /// Fails when the weight vectors are not square patches.
+    pub fn new(weights: &[Patch]) -> Result<FieldReport> {
+        let width = weights.first().and_then(|w| patch_width(w.len()))
+            .ok_or_else(|| Error::Incompatible("receptive field statistics need weights of square patches".to_string()))?;
+        if weights.iter().any(|w| w.len() != width * width) {
+            return Err(Error::Incompatible("all weight vectors must have the same length".to_string()));
+        }
+        Ok(FieldReport { fields: weights.iter().enumerate().map(|(neuron, w)| FieldStats::new(neuron, w, width)).collect() })
+    }
+
+    pub fn fields(&self) -> &[FieldStats] {
+        &self.fields
+    }
+
+    /// One line per neuron with a header.
+    pub fn to_csv(&self) -> String {
+        let mut csv = String::from("neuron,norm,sparsity,frequency,orientation\n");
+        for field in &self.fields {
+            writeln!(csv, "{},{},{},{},{}", field.neuron, field.norm, field.sparsity, field.frequency, field.orientation).unwrap();
+        }
+        csv
+    }
+
+    pub fn save_csv<P: AsRef<Path>>(&self, path: P) -> io::Result<()> {
+        fs::write(path, self.to_csv())
+    }
+}
+
+impl fmt::Display for FieldReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:>6}  {:>8}  {:>8}  {:>9}  {:>11}", "neuron", "norm", "sparsity", "frequency", "orientation")?;
+        for field in &self.fields {
+            write!(f, "\n{:>6}  {:>8.4}  {:>8.4}  {:>9.4}  {:>10.1}°", field.neuron, field.norm, field.sparsity, field.frequency, field.orientation)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test{
+    use super::*;
+
+    fn grating(width: usize, kx: usize, ky: usize) -> Patch {
+        (0..width * width).map(|i| (2.0 * PI * (kx * (i % width) + ky * (i / width)) as f32 / width as f32).cos()).collect()
+    }
+
+    #[test]
+    fn gratings_give_their_frequency_and_orientation(){
+        let vertical = FieldStats::new(0, &grating(8, 1, 0), 8);
+        assert!((vertical.frequency - 0.125).abs() < 1e-5);
+        assert!((vertical.orientation - 90.0).abs() < 1e-3);
+
+        let horizontal = FieldStats::new(1, &grating(8, 0, 2), 8);
+        assert!((horizontal.frequency - 0.25).abs() < 1e-5);
+        assert!(horizontal.orientation.abs() < 1e-3 || (horizontal.orientation - 180.0).abs() < 1e-3);
+
+        // Stripes running from the bottom left to the top right, rows growing downwards.
+        let diagonal = FieldStats::new(2, &grating(8, 1, 1), 8);
+        assert!((diagonal.orientation - 45.0).abs() < 1e-3);
+    }
+
+    #[test]
+    fn sparsity_and_norm(){
+        let mut single = vec![0.0; 9];
+        single[4] = -2.0;
+        let stats = FieldStats::new(0, &single, 3);
+        assert_eq!((stats.norm, stats.sparsity), (2.0, 1.0));
+        assert_eq!(FieldStats::new(0, &[0.5; 9], 3).sparsity, 0.0);
+        assert_eq!(FieldStats::new(0, &[0.5; 9], 3).frequency, 0.0);
+
+        let report = FieldReport::new(&[single, vec![1.0; 9]]).unwrap();
+        assert_eq!(report.to_csv().lines().count(), 3);
+        assert_eq!(report.to_string().lines().count(), 3);
+        assert!(FieldReport::new(&[vec![1.0; 3]]).is_err());
+    }
+}
@@ -0,0 +1,159 @@
+//! The floating point types the learning rules can train in. Weights and patches are stored as `f32`, but every rule is generic
+//! over `Float`, so a network can train each neuron in `f64` instead to check that the result does not depend on rounding, at the
+//! cost of converting the patches and roughly twice the memory traffic.
+use std::borrow::Cow;
+use std::fmt::Debug;
+use std::iter::Sum;
+use std::ops::{Add, AddAssign, Div, DivAssign, Mul, MulAssign, Neg, Sub, SubAssign};
+use crate::model::simd;
+
+/// A floating point type the learning rules are computed in.
+pub trait Float:
+    Copy + Debug + Default + PartialOrd + Send + Sync + 'static + Sum
+    + Add<Output = Self> + Sub<Output = Self> + Mul<Output = Self> + Div<Output = Self> + Neg<Output = Self>
+    + AddAssign + SubAssign + MulAssign + DivAssign
+{
+    const ZERO: Self;
+    const ONE: Self;
+
+    fn from_f32(value: f32) -> Self;
+    fn to_f32(self) -> f32;
+    fn from_usize(value: usize) -> Self;
+    fn sqrt(self) -> Self;
+    fn abs(self) -> Self;
+    fn tanh(self) -> Self;
+    fn is_finite(self) -> bool;
+
+    /// Dot product of two vectors, ignoring the trailing values of the longer one.
+    fn dot(a: &[Self], b: &[Self]) -> Self {
+        a.iter().zip(b.iter()).map(|(&a, &b)| a * b).sum()
+    }
+
+    /// Computes `w = alpha * w + beta * x` in place.
+    fn scale_add(w: &mut [Self], alpha: Self, x: &[Self], beta: Self) {
+        for (w, &x) in w.iter_mut().zip(x.iter()) {
+            *w = alpha * *w + beta * x;
+        }
+    }
+
+    /// The values as `f32`, borrowed when they already are.
+    fn to_f32_slice(values: &[Self]) -> Cow<'_, [f32]> {
+        Cow::Owned(values.iter().map(|value| value.to_f32()).collect())
+    }
+
+    fn from_f32_slice(values: &[f32]) -> Vec<Self> {
+        values.iter().map(|&value| Self::from_f32(value)).collect()
+    }
+}
+
+impl Float for f32 {
+    const ZERO: f32 = 0.0;
+    const ONE: f32 = 1.0;
+
+    fn from_f32(value: f32) -> f32 {
+        value
+    }
+
+    fn to_f32(self) -> f32 {
+        self
+    }
+
+    fn from_usize(value: usize) -> f32 {
+        value as f32
+    }
+
+    fn sqrt(self) -> f32 {
+        f32::sqrt(self)
+    }
+
+    fn abs(self) -> f32 {
+        f32::abs(self)
+    }
+
+    fn tanh(self) -> f32 {
+        f32::tanh(self)
+    }
+
+    fn is_finite(self) -> bool {
+        f32::is_finite(self)
+    }
+
+    // Single precision uses the vectorized kernels.
+    fn dot(a: &[f32], b: &[f32]) -> f32 {
+        simd::dot(a, b)
+    }
+
+    fn scale_add(w: &mut [f32], alpha: f32, x: &[f32], beta: f32) {
+        simd::scale_add(w, alpha, x, beta)
+    }
+
+    fn to_f32_slice(values: &[f32]) -> Cow<'_, [f32]> {
+        Cow::Borrowed(values)
+    }
+
+    fn from_f32_slice(values: &[f32]) -> Vec<f32> {
+        values.to_vec()
+    }
+}
+
+impl Float for f64 {
+    const ZERO: f64 = 0.0;
+    const ONE: f64 = 1.0;
+
+    fn from_f32(value: f32) -> f64 {
+        value as f64
+    }
+
+    fn to_f32(self) -> f32 {
+        self as f32
+    }
+
+    fn from_usize(value: usize) -> f64 {
+        value as f64
+    }
+
+    fn sqrt(self) -> f64 {
+        f64::sqrt(self)
+    }
+
+    fn abs(self) -> f64 {
+        f64::abs(self)
+    }
+
+    fn tanh(self) -> f64 {
+        f64::tanh(self)
+    }
+
+    fn is_finite(self) -> bool {
+        f64::is_finite(self)
+    }
+}
+
+/// The type a network trains each neuron in. The weights are stored as `f32` either way.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Precision {
+    /// Train in `f32` on the vectorized kernels.
+    #[default]
+    Single,
+    /// Train every neuron in `f64`, with its patches converted once per run, and round the weights to `f32` afterwards.
+    Double,
+}
+
+#[cfg(test)]
+mod test{
+    use super::*;
+
+    #[test]
+    fn single_and_double_kernels_agree(){
+        let a: Vec<f32> = (0..19).map(|i| (i as f32 * 0.3).sin()).collect();
+        let b: Vec<f32> = (0..19).map(|i| (i as f32 * 0.7).cos()).collect();
+        let (a64, b64) = (f64::from_f32_slice(&a), f64::from_f32_slice(&b));
+        assert!((f32::dot(&a, &b) - f64::dot(&a64, &b64).to_f32()).abs() < 1e-5);
+
+        let (mut w, mut w64) = (a.clone(), a64.clone());
+        f32::scale_add(&mut w, 0.5, &b, 2.0);
+        f64::scale_add(&mut w64, 0.5, &b64, 2.0);
+        assert!(w.iter().zip(f64::to_f32_slice(&w64).iter()).all(|(x, y)| (x - y).abs() < 1e-6));
+        assert!(matches!(f32::to_f32_slice(&w), Cow::Borrowed(_)));
+    }
+}
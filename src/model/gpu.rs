@@ -0,0 +1,253 @@
+//! GPU backend training every neuron with Oja's rule in a WGSL compute shader, one invocation per neuron.
+//! The weights stay on the GPU for the whole run, only batches of patches and their learning rates are uploaded between
+//! dispatches, so for large numbers of neurons the throughput is far beyond the CPU thread pool.
+//! Neurons are initialized and trained on the same patches as `MtNetwork`, so both backends learn the same components for
+//! the same seed, up to differences in floating point rounding. Like `MtNetwork` every run continues from the weights it holds.
+use std::path::Path;
+use std::sync::mpsc;
+use std::time::Instant;
+use rand::{Rng, SeedableRng};
+use rand::rngs::StdRng;
+use tracing::{debug, info, info_span};
+use wgpu::util::DeviceExt;
+use crate::data::dataset::Dataset;
+use crate::data::mnist::MnistData;
+use crate::data::patch::{Patch, patch_width};
+use crate::error::{Error, Result};
+use crate::model::checkpoint::{Checkpoint, Metadata};
+use crate::model::init::Init;
+use crate::model::schedule::{Constant, LrSchedule};
+use crate::model::weights::Weights;
+use crate::visualization::receptive_fields::save_montage;
+
+/// Must match the workgroup size declared in the shader.
+const WORKGROUP_SIZE: usize = 64;
+
+/// Number of neurons, patch dimension and number of patches in the current batch, as laid out in the uniform buffer.
+fn params(neurons: usize, dim: usize, patches: usize) -> Vec<u8> {
+    [neurons as u32, dim as u32, patches as u32, 0].iter().flat_map(|value| value.to_le_bytes()).collect()
+}
+
+fn bytes(values: &[f32]) -> Vec<u8> {
+    values.iter().flat_map(|value| value.to_le_bytes()).collect()
+}
+
+pub struct GpuNetwork<D: Dataset = MnistData> {
+    neurons: usize,
+    lr: f32,
+    schedule: Box<dyn LrSchedule>,
+    init: Init,
+    batch_size: usize,
+    dataset: D,
+    weights: Weights,
+    rng: StdRng,
+    device: wgpu::Device,
+    queue: wgpu::Queue,
+    pipeline: wgpu::ComputePipeline,
+}
+
+impl GpuNetwork<MnistData> {
+    pub fn new(neurons: usize, lr: f32, patch_width: usize, seed: Option<u64>) -> Result<GpuNetwork> {
+        GpuNetwork::with_data(neurons, lr, MnistData::new(1, patch_width)?, seed)
+    }
+}
+
+impl<D: Dataset> GpuNetwork<D> {
+    // Creates a network on the first GPU found, preferring a discrete one. Fails if no GPU is available.
+    pub fn with_data(neurons: usize, lr: f32, dataset: D, seed: Option<u64>) -> Result<GpuNetwork<D>> {
+        if neurons == 0 {
+            return Err(Error::InvalidConfig("at least one neuron is needed".to_string()));
+        }
+        if dataset.is_empty() {
+            return Err(Error::InvalidConfig("the dataset does not contain any samples".to_string()));
+        }
+
+        let instance = wgpu::Instance::default();
+        let adapter = pollster::block_on(instance.request_adapter(&wgpu::RequestAdapterOptions {
+            power_preference: wgpu::PowerPreference::HighPerformance,
+            ..Default::default()
+        })).ok_or_else(|| Error::Gpu("no suitable GPU adapter found".to_string()))?;
+        let (device, queue) = pollster::block_on(adapter.request_device(&wgpu::DeviceDescriptor {
+            label: Some("oja"),
+            required_features: wgpu::Features::empty(),
+            required_limits: adapter.limits(),
+        }, None)).map_err(|error| Error::Gpu(error.to_string()))?;
+        info!(adapter = %adapter.get_info().name, "using gpu");
+
+        let module = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: Some("oja"),
+            source: wgpu::ShaderSource::Wgsl(include_str!("oja.wgsl").into()),
+        });
+        let pipeline = device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+            label: Some("oja"),
+            layout: None,
+            module: &module,
+            entry_point: "main",
+        });
+
+        // The weights are drawn in the same order as by `MtNetwork`, so both backends consume the same random numbers.
+        let mut rng = match seed {
+            Some(seed) => StdRng::seed_from_u64(seed),
+            None => StdRng::from_entropy(),
+        };
+        let init = Init::default();
+        let mut weights = Weights::with_capacity(neurons, dataset.patch_dim());
+        for _ in 0..neurons {
+            weights.push(&init.weights(dataset.patch_dim(), &mut rng));
+        }
+        Ok(GpuNetwork { neurons, lr, schedule: Box::new(Constant), init, batch_size: 1024, dataset, weights, rng, device, queue, pipeline })
+    }
+
+    pub fn set_schedule<S: LrSchedule + 'static>(&mut self, schedule: S) {
+        self.schedule = Box::new(schedule);
+    }
+
+    pub fn set_init(&mut self, init: Init) {
+        self.init = init;
+        let patch_size = self.dataset.patch_dim();
+        for weights in self.weights.iter_mut() {
+            weights.copy_from_slice(&init.weights(patch_size, &mut self.rng));
+        }
+    }
+
+    // Sets the number of patches uploaded to the GPU per dispatch. Larger batches need fewer round trips but more memory.
+    pub fn set_batch_size(&mut self, batch_size: usize) -> Result<()> {
+        if batch_size == 0 {
+            return Err(Error::InvalidConfig("the batch size must be at least one patch".to_string()));
+        }
+        self.batch_size = batch_size;
+        Ok(())
+    }
+
+    // Trains every neuron on the same sequence of patches like `MtNetwork::train_complete_iterations`, and returns the weights.
+    pub fn train(&mut self, epochs: usize) -> Result<Vec<Patch>> {
+        let _span = info_span!("train", backend = "gpu", neurons = self.neurons, epochs = epochs).entered();
+        let now = Instant::now();
+        let dim = self.dataset.patch_dim();
+        let training_data = self.dataset.sample_patches(0, epochs, &mut self.rng);
+        // Drawn like the seed of a run of `MtNetwork`, so both sample the same patches in later runs.
+        let _seed: u64 = self.rng.gen();
+
+        let weights_buffer = self.device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("weights"),
+            contents: &bytes(self.weights.as_slice()),
+            usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_SRC,
+        });
+        let batch_size = self.batch_size.min(epochs.max(1));
+        let patches_buffer = self.buffer("patches", batch_size * dim * 4, wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_DST);
+        let lrs_buffer = self.buffer("learning rates", batch_size * 4, wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_DST);
+        let params_buffer = self.buffer("params", 16, wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST);
+        let bind_group = self.device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("oja"),
+            layout: &self.pipeline.get_bind_group_layout(0),
+            entries: &[
+                wgpu::BindGroupEntry { binding: 0, resource: weights_buffer.as_entire_binding() },
+                wgpu::BindGroupEntry { binding: 1, resource: patches_buffer.as_entire_binding() },
+                wgpu::BindGroupEntry { binding: 2, resource: lrs_buffer.as_entire_binding() },
+                wgpu::BindGroupEntry { binding: 3, resource: params_buffer.as_entire_binding() },
+            ],
+        });
+
+        // Like the CPU backends, every neuron is trained on all but the last sampled patch.
+        let workgroups = self.neurons.div_ceil(WORKGROUP_SIZE) as u32;
+        for (batch, patches) in training_data[..epochs.saturating_sub(1)].chunks(batch_size).enumerate() {
+            let flat: Vec<f32> = patches.iter().flatten().copied().collect();
+            let lrs: Vec<f32> = (0..patches.len()).map(|index| self.schedule.lr(self.lr, batch * batch_size + index)).collect();
+            self.queue.write_buffer(&patches_buffer, 0, &bytes(&flat));
+            self.queue.write_buffer(&lrs_buffer, 0, &bytes(&lrs));
+            self.queue.write_buffer(&params_buffer, 0, &params(self.neurons, dim, patches.len()));
+
+            let mut encoder = self.device.create_command_encoder(&wgpu::CommandEncoderDescriptor { label: Some("oja") });
+            {
+                let mut pass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor { label: Some("oja"), timestamp_writes: None });
+                pass.set_pipeline(&self.pipeline);
+                pass.set_bind_group(0, &bind_group, &[]);
+                pass.dispatch_workgroups(workgroups, 1, 1);
+            }
+            self.queue.submit(Some(encoder.finish()));
+            debug!(epoch = batch * batch_size + patches.len(), "batch dispatched");
+        }
+
+        self.weights = Weights::from_flat(self.read(&weights_buffer, self.weights.as_slice().len())?, dim);
+        info!(elapsed_ms = now.elapsed().as_millis() as u64, "gpu training completed");
+        Ok(self.weights.to_patches())
+    }
+
+    fn buffer(&self, label: &str, size: usize, usage: wgpu::BufferUsages) -> wgpu::Buffer {
+        self.device.create_buffer(&wgpu::BufferDescriptor { label: Some(label), size: size.max(4) as u64, usage, mapped_at_creation: false })
+    }
+
+    // Copies the buffer into a mappable one and waits until its contents can be read on the CPU.
+    fn read(&self, buffer: &wgpu::Buffer, length: usize) -> Result<Vec<f32>> {
+        let size = (length * 4) as u64;
+        let staging = self.buffer("staging", length * 4, wgpu::BufferUsages::MAP_READ | wgpu::BufferUsages::COPY_DST);
+        let mut encoder = self.device.create_command_encoder(&wgpu::CommandEncoderDescriptor { label: Some("read") });
+        encoder.copy_buffer_to_buffer(buffer, 0, &staging, 0, size);
+        self.queue.submit(Some(encoder.finish()));
+
+        let slice = staging.slice(..size);
+        let (sender, receiver) = mpsc::channel();
+        slice.map_async(wgpu::MapMode::Read, move |result| {
+            let _ = sender.send(result);
+        });
+        self.device.poll(wgpu::Maintain::Wait);
+        receiver.recv()
+            .map_err(|error| Error::Gpu(error.to_string()))?
+            .map_err(|error| Error::Gpu(error.to_string()))?;
+
+        let values = slice.get_mapped_range().chunks_exact(4).map(|b| f32::from_le_bytes([b[0], b[1], b[2], b[3]])).collect();
+        staging.unmap();
+        Ok(values)
+    }
+
+    pub fn weights(&self) -> &Weights {
+        &self.weights
+    }
+
+    pub fn dataset(&self) -> &D {
+        &self.dataset
+    }
+
+    // Writes the weights in the same checkpoint format as `MtNetwork`, so they can be loaded into a CPU network.
+    pub fn save_checkpoint<P: AsRef<Path>>(&self, path: P) -> Result<()> {
+        self.save_checkpoint_with(path, self.metadata())
+    }
+
+    pub fn save_checkpoint_with<P: AsRef<Path>>(&self, path: P, metadata: Metadata) -> Result<()> {
+        Ok(Checkpoint::new(self.weights.to_patches()).with_metadata(metadata).save(path)?)
+    }
+
+    // Only the rule is known, as the GPU network keeps no training history.
+    pub fn metadata(&self) -> Metadata {
+        Metadata::new("Oja")
+    }
+
+    pub fn save_receptive_fields<P: AsRef<Path>>(&self, path: P, scale: usize) -> Result<()> {
+        let patch_width = patch_width(self.dataset.patch_dim()).ok_or_else(|| Error::Incompatible("receptive fields can only be drawn for square patches".to_string()))?;
+        save_montage(path, &self.weights.to_patches(), patch_width, scale).map_err(|error| Error::Image(error.to_string()))
+    }
+}
+
+#[cfg(test)]
+mod test{
+    use super::*;
+    use crate::model::network::MtNetwork;
+
+    #[test]
+    fn gpu_matches_the_thread_pool(){
+        // Machines without a GPU, such as most CI runners, can not run this test.
+        let mut gpu = match GpuNetwork::new(40, 0.01, 5, Some(6)) {
+            Ok(gpu) => gpu,
+            Err(Error::Gpu(_)) => return,
+            Err(error) => panic!("{}", error),
+        };
+        gpu.set_batch_size(64).unwrap();
+        let mut cpu = MtNetwork::new(10, 2, 40, 0.01, 5, Some(6)).unwrap();
+
+        let expected = cpu.train_complete_iterations(500).unwrap();
+        let weights = gpu.train(500).unwrap();
+        for (gpu, cpu) in weights.iter().zip(expected.iter()) {
+            assert!(gpu.iter().zip(cpu.iter()).all(|(g, c)| (g - c).abs() < 1e-3), "{:?} != {:?}", gpu, cpu);
+        }
+    }
+}
@@ -0,0 +1,40 @@
+//! Plain Hebbian learning, used as a baseline for Oja's rule.
+//! Without a decay term the weights grow without bounds, so they are explicitly renormalized to unit length every few updates.
+use crate::model::float::Float;
+use crate::model::oja::oja_y;
+
+/// Updates the weights of a single neuron for one patch, strengthening each weight by the correlation of its input and the output.
+pub fn hebbian_learning_rule<F: Float>(patch_x: &[F], weights: &mut [F], lr: F){
+    let y = oja_y(patch_x, weights);
+
+    for (w, &x) in weights.iter_mut().zip(patch_x.iter()) {
+        *w += lr * y * x;
+    }
+}
+
+/// Scales the weights of a neuron to unit L2 norm. Weights which are all zero are left untouched.
+pub fn normalize_weights<F: Float>(weights: &mut [F]){
+    let norm = weights.iter().map(|&w| w * w).sum::<F>().sqrt();
+    if norm > F::ZERO {
+        for w in weights.iter_mut() {
+            *w /= norm;
+        }
+    }
+}
+
+#[cfg(test)]
+mod test{
+    use super::*;
+
+    #[test]
+    fn normalize_weights_to_unit_length(){
+        let mut weights = vec![3.0f32, 4.0];
+        normalize_weights(&mut weights);
+        assert!((weights[0] - 0.6).abs() < 1e-6);
+        assert!((weights[1] - 0.8).abs() < 1e-6);
+
+        let mut zeros = vec![0.0f32; 3];
+        normalize_weights(&mut zeros);
+        assert_eq!(zeros, vec![0.0; 3]);
+    }
+}
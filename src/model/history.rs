@@ -0,0 +1,124 @@
+//! Statistics of the weights recorded at regular intervals during training, exportable as CSV or JSON for plotting in external tools.
+use std::fmt::Write as _;
+use std::fs;
+use std::io;
+use std::path::Path;
+use std::time::Duration;
+
+/// Statistics of all neurons at a given epoch of training.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct EpochRecord {
+    pub epoch: usize,
+    /// Mean L2 norm of the weight vectors.
+    pub mean_weight_norm: f32,
+    /// Mean L2 distance the weight vectors moved since the previous record, or since initialization for the first one.
+    pub weight_delta: f32,
+    /// Reconstruction error on the held-out patches, if evaluation was enabled.
+    pub reconstruction_error: Option<f32>,
+    /// Wall time spent training up to this epoch.
+    pub elapsed: Duration,
+}
+
+impl EpochRecord {
+    /// Computes the statistics of the weights, compared to the weights of the previous record.
+    pub fn new<P: AsRef<[f32]>>(epoch: usize, weights: &[P], previous: &[P], reconstruction_error: Option<f32>, elapsed: Duration) -> EpochRecord {
+        let neurons = weights.len().max(1) as f32;
+        let mean_weight_norm = weights.iter().map(|w| norm(w.as_ref().iter().copied())).sum::<f32>() / neurons;
+        let weight_delta = weights.iter().zip(previous.iter())
+            .map(|(w, p)| norm(w.as_ref().iter().zip(p.as_ref().iter()).map(|(w, p)| w - p)))
+            .sum::<f32>() / neurons;
+        EpochRecord { epoch, mean_weight_norm, weight_delta, reconstruction_error, elapsed }
+    }
+}
+
+fn norm<I: Iterator<Item = f32>>(values: I) -> f32 {
+    values.map(|value| value * value).sum::<f32>().sqrt()
+}
+
+/// The records of a training run in order of their epoch.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct TrainingHistory {
+    records: Vec<EpochRecord>,
+}
+
+impl TrainingHistory {
+    pub fn new() -> TrainingHistory {
+        TrainingHistory::default()
+    }
+
+    pub fn push(&mut self, record: EpochRecord) {
+        self.records.push(record);
+    }
+
+    pub fn clear(&mut self) {
+        self.records.clear();
+    }
+
+    pub fn records(&self) -> &[EpochRecord] {
+        &self.records
+    }
+
+    pub fn last(&self) -> Option<&EpochRecord> {
+        self.records.last()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.records.is_empty()
+    }
+
+    /// One line per record with a header, leaving the reconstruction error empty when it was not measured.
+    pub fn to_csv(&self) -> String {
+        let mut csv = String::from("epoch,mean_weight_norm,weight_delta,reconstruction_error,elapsed_seconds\n");
+        for record in &self.records {
+            let error = record.reconstruction_error.map_or(String::new(), |error| error.to_string());
+            writeln!(csv, "{},{},{},{},{}", record.epoch, record.mean_weight_norm, record.weight_delta, error, record.elapsed.as_secs_f64()).unwrap();
+        }
+        csv
+    }
+
+    /// An array with one object per record, using null for values which were not measured or are not finite.
+    pub fn to_json(&self) -> String {
+        let records: Vec<String> = self.records.iter().map(|record| {
+            format!("{{\"epoch\":{},\"mean_weight_norm\":{},\"weight_delta\":{},\"reconstruction_error\":{},\"elapsed_seconds\":{}}}",
+                    record.epoch, json_number(Some(record.mean_weight_norm)), json_number(Some(record.weight_delta)),
+                    json_number(record.reconstruction_error), record.elapsed.as_secs_f64())
+        }).collect();
+        format!("[{}]", records.join(","))
+    }
+
+    /// Writes the history to a file, as JSON if the path ends in `.json` and as CSV otherwise.
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> io::Result<()> {
+        let path = path.as_ref();
+        let content = match path.extension().and_then(|extension| extension.to_str()) {
+            Some("json") => self.to_json(),
+            _ => self.to_csv(),
+        };
+        fs::write(path, content)
+    }
+}
+
+pub(crate) fn json_number(value: Option<f32>) -> String {
+    match value {
+        Some(value) if value.is_finite() => value.to_string(),
+        _ => "null".to_string(),
+    }
+}
+
+#[cfg(test)]
+mod test{
+    use super::*;
+
+    #[test]
+    fn records_are_exported(){
+        let mut history = TrainingHistory::new();
+        history.push(EpochRecord::new(10, &[vec![3.0, 4.0]], &[vec![3.0, 3.0]], None, Duration::from_millis(500)));
+        history.push(EpochRecord::new(20, &[vec![0.0, 1.0]], &[vec![0.0, 1.0]], Some(0.25), Duration::from_secs(1)));
+
+        let first = history.records()[0];
+        assert_eq!((first.mean_weight_norm, first.weight_delta), (5.0, 1.0));
+
+        assert_eq!(history.to_csv(), "epoch,mean_weight_norm,weight_delta,reconstruction_error,elapsed_seconds\n10,5,1,,0.5\n20,1,0,0.25,1\n");
+        assert_eq!(history.to_json(), "[{\"epoch\":10,\"mean_weight_norm\":5,\"weight_delta\":1,\"reconstruction_error\":null,\"elapsed_seconds\":0.5},\
+                                       {\"epoch\":20,\"mean_weight_norm\":1,\"weight_delta\":0,\"reconstruction_error\":0.25,\"elapsed_seconds\":1}]");
+    }
+}
@@ -0,0 +1,36 @@
+//! Hooks into the training loops of `MtNetwork`, so checkpointing, logging or early stopping can be added to a run without
+//! copying its loop.
+use std::ops::ControlFlow;
+use std::sync::Arc;
+use crate::model::history::TrainingHistory;
+use crate::model::network::TrainedSection;
+use crate::model::weights::Weights;
+
+/// Called by the training methods at the boundaries of their epochs. Every method has a default which does nothing, so
+/// implementations only override the events they are interested in.
+///
+/// What an epoch is depends on the training method: an epoch of `patches_per_epoch` patches for `train_synchronous` and a
+/// single patch for `train_hierarchical`. The methods which train every neuron independently, on the thread pool, scoped
+/// threads or rayon, `train_iteration` and `train_matrix` run a single epoch, whose sections are reported as they finish.
+/// Sections can be reported from worker threads. Epochs and sections finished before a run fails or is cancelled are still
+/// reported, but `on_complete` is only called for runs which complete.
+pub trait TrainingHooks: Send + Sync {
+    /// Called before the epoch with the given index starts.
+    fn on_epoch_start(&self, _epoch: usize) {}
+
+    /// Called once a section of neurons has finished training, with its trained weights.
+    fn on_section_done(&self, _section: &TrainedSection) {}
+
+    /// Called after `epochs` epochs have completed, with the weights of the network at that point. Returning
+    /// `ControlFlow::Break` ends the run early as if it had been given that many epochs, for the methods which train in more
+    /// than one epoch.
+    fn on_epoch_end(&self, _epochs: usize, _weights: &Weights) -> ControlFlow<()> {
+        ControlFlow::Continue(())
+    }
+
+    /// Called once a run has completed, with the weights and the history the network holds after it.
+    fn on_complete(&self, _weights: &Weights, _history: &TrainingHistory) {}
+}
+
+/// Hooks shared between the network, which calls them, and the caller, which may want to read what they collected.
+pub type SharedHooks = Arc<dyn TrainingHooks>;
@@ -0,0 +1,69 @@
+//! Nonlinear Hebbian learning for independent component analysis, the online one-unit rule of Hyvärinen and Oja.
+//! On whitened patches a neuron whose output is made as non-Gaussian as possible finds an independent component instead of a principal one.
+//! Only the direction of the weights matters on whitened data, so they are kept at unit length after every update.
+use crate::model::float::Float;
+use crate::model::hebbian::normalize_weights;
+use crate::model::oja::oja_y;
+
+/// Contrast function of the output, both look for sparse (super-Gaussian) components such as the edges in natural image patches.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Nonlinearity {
+    /// Minimizes the mean of `log cosh(y)`. Robust against outliers.
+    #[default]
+    Tanh,
+    /// Maximizes the kurtosis of the output. Converges faster, but is sensitive to outliers.
+    Cube,
+}
+
+impl Nonlinearity {
+    /// Direction in which a single output moves the weights, scaled by the input.
+    pub fn gradient<F: Float>(&self, y: F) -> F {
+        match self {
+            Nonlinearity::Tanh => -y.tanh(),
+            Nonlinearity::Cube => y * y * y,
+        }
+    }
+}
+
+/// Updates the weights of a single neuron for one whitened patch and renormalizes them to unit length.
+pub fn ica_learning_rule<F: Float>(patch_x: &[F], weights: &mut [F], lr: F, nonlinearity: Nonlinearity){
+    let g = nonlinearity.gradient(oja_y(patch_x, weights));
+
+    for (w, &x) in weights.iter_mut().zip(patch_x.iter()) {
+        *w += lr * g * x;
+    }
+    normalize_weights(weights);
+}
+
+#[cfg(test)]
+mod test{
+    use super::*;
+    use rand::{Rng, SeedableRng};
+    use rand::rngs::StdRng;
+
+    /// Laplace distributed sample with unit variance, a sparse source.
+    fn laplace(rng: &mut StdRng) -> f32 {
+        let u: f32 = rng.gen::<f32>() - 0.5;
+        -u.signum() * (1.0 - 2.0 * u.abs()).max(f32::MIN_POSITIVE).ln() / 2f32.sqrt()
+    }
+
+    #[test]
+    fn recovers_a_source_from_a_rotated_mixture(){
+        // A rotation keeps the mixture of two unit variance sources white, so its columns are the independent components.
+        let (sin, cos) = 0.5f32.sin_cos();
+        let components = [[cos, sin], [-sin, cos]];
+
+        for nonlinearity in [Nonlinearity::Tanh, Nonlinearity::Cube] {
+            let mut rng = StdRng::seed_from_u64(3);
+            let mut weights = vec![1.0, 0.0];
+            for _ in 0..20000 {
+                let (s1, s2) = (laplace(&mut rng), laplace(&mut rng));
+                let x = [cos * s1 - sin * s2, sin * s1 + cos * s2];
+                ica_learning_rule(&x, &mut weights, 0.005, nonlinearity);
+            }
+
+            let alignment = components.iter().map(|c| (c[0] * weights[0] + c[1] * weights[1]).abs()).fold(0.0, f32::max);
+            assert!(alignment > 0.98, "{:?} did not find a component: {:?}", nonlinearity, weights);
+        }
+    }
+}
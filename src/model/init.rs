@@ -0,0 +1,80 @@
+//! Strategies for drawing the initial weights of a neuron.
+use rand::Rng;
+use crate::data::patch::Patch;
+use crate::model::hebbian::normalize_weights;
+
+/// How the weights of every neuron are drawn before training.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum Init {
+    /// Uniform in `[0, 1)`. Every weight is positive, so all neurons start out biased towards the mean patch.
+    #[default]
+    Uniform,
+    /// Uniform in `[-scale, scale)`.
+    UniformSymmetric { scale: f32 },
+    /// Normally distributed around zero with the given standard deviation.
+    Gaussian { std: f32 },
+    /// Uniform in `±sqrt(6 / (inputs + 1))`, the Xavier/Glorot scaling for a single neuron, so the initial output has about unit variance.
+    Xavier,
+    /// A random direction with unit length, uniformly distributed on the sphere.
+    UnitNorm,
+}
+
+impl Init {
+    /// Draws the weights of a neuron with `size` inputs.
+    pub fn weights<R: Rng + ?Sized>(&self, size: usize, rng: &mut R) -> Patch {
+        match *self {
+            Init::Uniform => (0..size).map(|_| rng.gen()).collect(),
+            Init::UniformSymmetric { scale } => uniform_symmetric(size, scale, rng),
+            Init::Gaussian { std } => (0..size).map(|_| std * standard_normal(rng)).collect(),
+            Init::Xavier => uniform_symmetric(size, (6.0 / (size as f32 + 1.0)).sqrt(), rng),
+            Init::UnitNorm => {
+                let mut weights: Patch = (0..size).map(|_| standard_normal(rng)).collect();
+                normalize_weights(&mut weights);
+                weights
+            }
+        }
+    }
+}
+
+fn uniform_symmetric<R: Rng + ?Sized>(size: usize, scale: f32, rng: &mut R) -> Patch {
+    (0..size).map(|_| scale * (2.0 * rng.gen::<f32>() - 1.0)).collect()
+}
+
+/// Standard normal sample using the Box-Muller transform.
+pub(crate) fn standard_normal<R: Rng + ?Sized>(rng: &mut R) -> f32 {
+    let u1: f32 = 1.0 - rng.gen::<f32>();
+    let u2: f32 = rng.gen();
+    (-2.0 * u1.ln()).sqrt() * (2.0 * std::f32::consts::PI * u2).cos()
+}
+
+#[cfg(test)]
+mod test{
+    use super::*;
+    use rand::SeedableRng;
+    use rand::rngs::StdRng;
+
+    #[test]
+    fn strategies_draw_from_their_distribution(){
+        let mut rng = StdRng::seed_from_u64(0);
+        let size = 10000;
+        let mean = |w: &Patch| w.iter().sum::<f32>() / w.len() as f32;
+        let variance = |w: &Patch| w.iter().map(|x| x * x).sum::<f32>() / w.len() as f32 - mean(w) * mean(w);
+
+        let uniform = Init::Uniform.weights(size, &mut rng);
+        assert!(uniform.iter().all(|w| (0.0..1.0).contains(w)));
+
+        let symmetric = Init::UniformSymmetric { scale: 0.1 }.weights(size, &mut rng);
+        assert!(symmetric.iter().all(|w| w.abs() <= 0.1));
+        assert!(mean(&symmetric).abs() < 0.01);
+
+        let gaussian = Init::Gaussian { std: 2.0 }.weights(size, &mut rng);
+        assert!(mean(&gaussian).abs() < 0.1);
+        assert!((variance(&gaussian) - 4.0).abs() < 0.3);
+
+        let xavier = Init::Xavier.weights(size, &mut rng);
+        assert!((variance(&xavier) * (size as f32 + 1.0) - 2.0).abs() < 0.2);
+
+        let unit = Init::UnitNorm.weights(25, &mut rng);
+        assert!((unit.iter().map(|w| w * w).sum::<f32>() - 1.0).abs() < 1e-5);
+    }
+}
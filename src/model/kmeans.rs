@@ -0,0 +1,242 @@
+//! K-means clustering of patches as an alternative unsupervised model to the Hebbian networks.
+//! The centroids have the same format as the weights of `MtNetwork`, so the receptive field rendering and the evaluation
+//! of reconstruction error work for both models.
+//! Every iteration the thread pool assigns chunks of patches to their nearest centroid and sums them per cluster,
+//! after which every centroid moves to the mean of its patches.
+use std::path::Path;
+use std::sync::Arc;
+use std::time::Instant;
+use rand::{Rng, SeedableRng};
+use rand::rngs::StdRng;
+use tracing::{debug, info, info_span};
+use crate::data::dataset::Dataset;
+use crate::data::mnist::MnistData;
+use crate::data::patch::{Patch, patch_width};
+use crate::error::{Error, Result};
+use crate::threading::thread_pool::ThreadPool;
+use crate::utils::linalg::{nearest, squared_distance};
+use crate::visualization::receptive_fields::save_montage;
+
+/// Number of patches assigned by a worker at a time. Fixed instead of derived from the number of threads, so the partial sums
+/// are added in the same order and the centroids are identical regardless of the number of threads.
+const CHUNK_SIZE: usize = 256;
+
+/// Sum and number of the patches assigned to every cluster by a chunk, together with their squared distances to the centroids.
+struct ClusterSums {
+    start: usize,
+    sums: Vec<Vec<f64>>,
+    counts: Vec<usize>,
+    inertia: f64,
+}
+
+pub struct KMeans<D: Dataset = MnistData> {
+    k: usize,
+    tolerance: f32,
+    thread_pool: ThreadPool,
+    dataset: D,
+    centroids: Vec<Patch>,
+    rng: StdRng,
+}
+
+impl KMeans<MnistData> {
+    // Creates a clustering of MNIST patches. Runs with the same seed produce identical centroids, regardless of the number of threads.
+    pub fn new(k: usize, threads: usize, patch_width: usize, seed: Option<u64>) -> Result<KMeans> {
+        KMeans::with_data(k, threads, MnistData::new(1, patch_width)?, seed)
+    }
+}
+
+impl<D: Dataset> KMeans<D> {
+    pub fn with_data(k: usize, threads: usize, dataset: D, seed: Option<u64>) -> Result<KMeans<D>> {
+        if k == 0 {
+            return Err(Error::InvalidConfig("at least one cluster is needed".to_string()));
+        }
+        if threads == 0 {
+            return Err(Error::InvalidConfig("at least one thread is needed for training".to_string()));
+        }
+        if dataset.is_empty() {
+            return Err(Error::InvalidConfig("the dataset does not contain any samples".to_string()));
+        }
+        let thread_pool = ThreadPool::builder(threads).label("kmeans").build()?;
+        let rng = match seed {
+            Some(seed) => StdRng::seed_from_u64(seed),
+            None => StdRng::from_entropy(),
+        };
+
+        Ok(KMeans { k, tolerance: 1e-4, thread_pool, dataset, centroids: Vec::new(), rng })
+    }
+
+    // Sets how far the centroids may move in an iteration, in total, for the clustering to count as converged.
+    pub fn set_tolerance(&mut self, tolerance: f32) {
+        assert!(tolerance >= 0.0, "the tolerance must not be negative");
+        self.tolerance = tolerance;
+    }
+
+    // Clusters the given number of patches sampled from the dataset, running at most `iterations` iterations of Lloyd's algorithm.
+    // The centroids are initialized with k-means++ and returned in the same format as the weights of a network.
+    pub fn train(&mut self, samples: usize, iterations: usize) -> Result<Vec<Patch>> {
+        if samples < self.k {
+            return Err(Error::InvalidConfig(format!("{} clusters need at least as many patches, got {}", self.k, samples)));
+        }
+        let _span = info_span!("train_kmeans", k = self.k, samples = samples, iterations = iterations).entered();
+        let now = Instant::now();
+        let patches = Arc::new(self.dataset.sample_patches(0, samples, &mut self.rng));
+        self.centroids = self.initial_centroids(&patches);
+
+        for iteration in 0..iterations {
+            let (sums, counts, inertia) = self.assign(&patches)?;
+            let mut shift = 0.0;
+            for ((centroid, sum), count) in self.centroids.iter_mut().zip(sums.iter()).zip(counts.iter()) {
+                // A cluster which lost all of its patches keeps its previous centroid.
+                if *count > 0 {
+                    let mean: Patch = sum.iter().map(|value| (value / *count as f64) as f32).collect();
+                    shift += squared_distance(centroid, &mean).sqrt();
+                    *centroid = mean;
+                }
+            }
+            debug!(iteration = iteration + 1, inertia = inertia, shift = shift, "iteration");
+            if shift <= self.tolerance {
+                info!(iterations = iteration + 1, "k-means converged");
+                break;
+            }
+        }
+
+        info!(elapsed_ms = now.elapsed().as_millis() as u64, "k-means training completed");
+        Ok(self.centroids.clone())
+    }
+
+    // k-means++: every next centroid is a patch picked with a probability proportional to its squared distance to the nearest centroid so far.
+    fn initial_centroids(&mut self, patches: &[Patch]) -> Vec<Patch> {
+        let mut centroids = vec![patches[self.rng.gen_range(0..patches.len())].clone()];
+        let mut distances: Vec<f32> = patches.iter().map(|patch| squared_distance(&centroids[0], patch)).collect();
+
+        while centroids.len() < self.k {
+            let total: f32 = distances.iter().sum();
+            let next = if total > 0.0 {
+                let mut target = self.rng.gen::<f32>() * total;
+                distances.iter().position(|distance| {
+                    target -= distance;
+                    target <= 0.0
+                }).unwrap_or(patches.len() - 1)
+            } else {
+                self.rng.gen_range(0..patches.len())
+            };
+            centroids.push(patches[next].clone());
+            for (distance, patch) in distances.iter_mut().zip(patches.iter()) {
+                *distance = distance.min(squared_distance(&patches[next], patch));
+            }
+        }
+        centroids
+    }
+
+    // Assigns every patch to its nearest centroid on the thread pool, returning the sum and count of the patches of every cluster and the inertia.
+    fn assign(&self, patches: &Arc<Vec<Patch>>) -> Result<(Vec<Vec<f64>>, Vec<usize>, f64)> {
+        let centroids = Arc::new(self.centroids.clone());
+        let shared = Arc::clone(patches);
+        let receiver = self.thread_pool.map_chunked(patches.len(), CHUNK_SIZE, move |range| {
+            let mut sums = vec![vec![0.0; centroids[0].len()]; centroids.len()];
+            let mut counts = vec![0; centroids.len()];
+            let mut inertia = 0.0;
+            for patch in &shared[range.clone()] {
+                let cluster = nearest(&centroids, patch);
+                sums[cluster].iter_mut().zip(patch.iter()).for_each(|(sum, x)| *sum += *x as f64);
+                counts[cluster] += 1;
+                inertia += squared_distance(&centroids[cluster], patch) as f64;
+            }
+            ClusterSums { start: range.start, sums, counts, inertia }
+        });
+
+        let mut chunks: Vec<ClusterSums> = receiver.iter().collect();
+        self.thread_pool.join();
+        // Chunks complete in any order, so they are sorted to add the partial sums in the same order every time.
+        chunks.sort_by_key(|chunk| chunk.start);
+
+        let mut sums = vec![vec![0.0; self.centroids[0].len()]; self.k];
+        let mut counts = vec![0; self.k];
+        let mut inertia = 0.0;
+        for chunk in chunks {
+            for (sum, chunk_sum) in sums.iter_mut().zip(chunk.sums.iter()) {
+                sum.iter_mut().zip(chunk_sum.iter()).for_each(|(sum, value)| *sum += value);
+            }
+            counts.iter_mut().zip(chunk.counts.iter()).for_each(|(count, value)| *count += value);
+            inertia += chunk.inertia;
+        }
+
+        let assigned: usize = counts.iter().sum();
+        if assigned != patches.len() {
+            return Err(Error::WorkerPanicked { trained: assigned, expected: patches.len() });
+        }
+        Ok((sums, counts, inertia))
+    }
+
+    // Index of the cluster whose centroid is nearest to the patch.
+    pub fn predict(&self, patch: &[f32]) -> usize {
+        assert!(!self.centroids.is_empty(), "the clustering has not been trained");
+        nearest(&self.centroids, patch)
+    }
+
+    // Sum of the squared distances between the patches and their nearest centroid.
+    pub fn inertia(&self, patches: &[Patch]) -> f32 {
+        patches.iter().map(|patch| squared_distance(&self.centroids[self.predict(patch)], patch)).sum()
+    }
+
+    // The cluster centroids, in the same format as the weights of a network.
+    pub fn weights(&self) -> &[Patch] {
+        &self.centroids
+    }
+
+    pub fn dataset(&self) -> &D {
+        &self.dataset
+    }
+
+    // Writes the centroids as an image, one tile per cluster, in the same layout as the receptive fields of a network.
+    pub fn save_centroids<P: AsRef<Path>>(&self, path: P, scale: usize) -> Result<()> {
+        let patch_width = patch_width(self.dataset.patch_dim()).ok_or_else(|| Error::Incompatible("centroids can only be drawn for square patches".to_string()))?;
+        save_montage(path, &self.centroids, patch_width, scale).map_err(|error| Error::Image(error.to_string()))
+    }
+}
+
+#[cfg(test)]
+mod test{
+    use super::*;
+    use rand::RngCore;
+
+    /// Points scattered closely around three well separated centers.
+    struct Blobs;
+
+    const CENTERS: [[f32; 2]; 3] = [[0.0, 0.0], [5.0, 5.0], [-5.0, 5.0]];
+
+    impl Dataset for Blobs {
+        fn len(&self) -> usize {
+            3000
+        }
+
+        fn patch_dim(&self) -> usize {
+            2
+        }
+
+        fn sample_patch(&self, index: usize, rng: &mut dyn RngCore) -> Patch {
+            let center = CENTERS[index % 3];
+            center.iter().map(|c| c + (rng.next_u32() % 1000) as f32 / 1000.0 - 0.5).collect()
+        }
+    }
+
+    #[test]
+    fn centroids_find_separated_clusters(){
+        let mut kmeans = KMeans::with_data(3, 4, Blobs, Some(1)).unwrap();
+        let centroids = kmeans.train(3000, 50).unwrap();
+
+        for center in CENTERS {
+            let nearest = &centroids[kmeans.predict(&center)];
+            assert!(squared_distance(nearest, &center) < 0.05, "no centroid near {:?}: {:?}", center, centroids);
+        }
+        assert!(kmeans.inertia(&[vec![0.0, 0.0]]) < 0.05);
+    }
+
+    #[test]
+    fn same_seed_gives_same_centroids_regardless_of_threads(){
+        let mut single = KMeans::with_data(4, 1, Blobs, Some(3)).unwrap();
+        let mut multi = KMeans::with_data(4, 6, Blobs, Some(3)).unwrap();
+        assert_eq!(single.train(2000, 10).unwrap(), multi.train(2000, 10).unwrap());
+        assert!(single.train(2, 10).is_err());
+    }
+}
@@ -0,0 +1,183 @@
+//! Selection of the plasticity rule used to train each neuron of a network.
+use crate::model::bcm::bcm_learning_rule;
+use crate::model::hebbian::{hebbian_learning_rule, normalize_weights};
+use crate::model::ica::{ica_learning_rule, Nonlinearity};
+use crate::error::{Error, Result};
+use crate::model::clipping::Clipping;
+use crate::model::float::Float;
+use crate::model::oja::{oja_batch_learning_rule, oja_learning_rule, oja_momentum_learning_rule};
+
+/// Learning rules which update a single neuron independently of the others, so neurons can be trained on separate threads.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum LearningRule {
+    /// Oja's rule, converging towards the first principal component.
+    #[default]
+    Oja,
+    /// Oja's rule with momentum, where every neuron keeps a running sum of its updates decaying by `beta` per update.
+    /// Smooths the noise of online updates, and usually converges in fewer updates at a lower learning rate.
+    OjaMomentum { beta: f32 },
+    /// BCM rule with a sliding threshold, where `tau` is the time constant of the threshold in number of updates.
+    Bcm { tau: f32 },
+    /// Plain Hebbian rule, with the weights renormalized to unit length every `normalize_every` updates.
+    Hebbian { normalize_every: usize },
+    /// Nonlinear Hebbian rule for independent component analysis. Expects whitened patches, e.g. with ZCA preprocessing.
+    Ica { nonlinearity: Nonlinearity },
+}
+
+/// State a rule keeps for a single neuron between updates, in the type the neuron is trained in.
+#[derive(Debug, Default, PartialEq)]
+pub struct NeuronState<F: Float = f32> {
+    /// Sliding threshold of the BCM rule.
+    pub theta: F,
+    /// Number of updates since the weights were last renormalized.
+    pub since_normalization: usize,
+    /// Momentum buffer of Oja's rule with momentum, empty until the first update.
+    pub velocity: Vec<F>,
+    /// Sum of the squared norms of the neuron's updates, scaling its steps down with the adaptive learning rate.
+    pub squared_updates: F,
+}
+
+impl<F: Float> NeuronState<F> {
+    /// Resets the state to its default, keeping the capacity of the momentum buffer.
+    pub fn reset(&mut self) {
+        self.theta = F::ZERO;
+        self.since_normalization = 0;
+        self.velocity.clear();
+        self.squared_updates = F::ZERO;
+    }
+}
+
+// Copying a state into an existing one reuses its momentum buffer, which the buffers of `model::arena` rely on.
+impl<F: Float> Clone for NeuronState<F> {
+    fn clone(&self) -> Self {
+        NeuronState { theta: self.theta, since_normalization: self.since_normalization, velocity: self.velocity.clone(), squared_updates: self.squared_updates }
+    }
+
+    fn clone_from(&mut self, source: &Self) {
+        self.theta = source.theta;
+        self.since_normalization = source.since_normalization;
+        self.velocity.clone_from(&source.velocity);
+        self.squared_updates = source.squared_updates;
+    }
+}
+
+impl LearningRule {
+    /// Updates the weights of a single neuron for one patch.
+    pub fn update<F: Float>(&self, patch_x: &[F], weights: &mut [F], state: &mut NeuronState<F>, lr: F) {
+        match *self {
+            LearningRule::Oja => oja_learning_rule(patch_x, weights, lr),
+            LearningRule::OjaMomentum { beta } => oja_momentum_learning_rule(&[patch_x], weights, &mut state.velocity, lr, F::from_f32(beta)),
+            LearningRule::Bcm { tau } => bcm_learning_rule(patch_x, weights, &mut state.theta, lr, F::from_f32(tau)),
+            LearningRule::Hebbian { normalize_every } => {
+                hebbian_learning_rule(patch_x, weights, lr);
+                state.since_normalization += 1;
+                if state.since_normalization >= normalize_every {
+                    normalize_weights(weights);
+                    state.since_normalization = 0;
+                }
+            }
+            LearningRule::Ica { nonlinearity } => ica_learning_rule(patch_x, weights, lr, nonlinearity),
+        }
+    }
+
+    /// Updates the weights of a single neuron for a mini-batch of patches.
+    /// Oja's rule, with or without momentum, applies the averaged update of the batch at once, the other rules update on every
+    /// patch of the batch in turn.
+    pub fn update_batch<F: Float>(&self, patches: &[Vec<F>], weights: &mut [F], state: &mut NeuronState<F>, lr: F) {
+        match (self, patches) {
+            (_, [patch_x]) => self.update(patch_x, weights, state, lr),
+            (LearningRule::Oja, _) => oja_batch_learning_rule(patches, weights, lr),
+            (LearningRule::OjaMomentum { beta }, _) => oja_momentum_learning_rule(patches, weights, &mut state.velocity, lr, F::from_f32(*beta)),
+            _ => patches.iter().for_each(|patch_x| self.update(patch_x, weights, state, lr)),
+        }
+    }
+
+    /// Same as `update`, followed by the adaptive learning rate, weight decay and clipping of the given options.
+    pub fn update_with<F: Float>(&self, patch_x: &[F], weights: &mut [F], state: &mut NeuronState<F>, lr: F, options: &UpdateOptions) {
+        options.apply(weights, state, lr, |weights, state| self.update(patch_x, weights, state, lr));
+    }
+
+    /// Same as `update_batch`, followed by the adaptive learning rate, weight decay and clipping of the given options.
+    pub fn update_batch_with<F: Float>(&self, patches: &[Vec<F>], weights: &mut [F], state: &mut NeuronState<F>, lr: F, options: &UpdateOptions) {
+        options.apply(weights, state, lr, |weights, state| self.update_batch(patches, weights, state, lr));
+    }
+}
+
+/// Terms added to every update of a rule. The rules which renormalize the weights are adjusted after renormalizing, so their
+/// weights may end up slightly off unit length.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct UpdateOptions {
+    /// Limit on how far a single update moves the weights, including the decay.
+    pub clipping: Option<Clipping>,
+    /// Leaky forgetting term `lambda`: every update also shrinks the weights by `lambda * w`. Zero disables it.
+    pub weight_decay: f32,
+    /// Scales the steps of every neuron by the size of its earlier updates, Adagrad style, see `adapt`.
+    pub adaptive_lr: bool,
+}
+
+/// Keeps the adaptive learning rate finite before a neuron has made any update.
+const ADAPTIVE_EPSILON: f32 = 1e-8;
+
+impl UpdateOptions {
+    pub(crate) fn validate(&self) -> Result<()> {
+        if let Some(clipping) = &self.clipping {
+            clipping.validate()?;
+        }
+        if !(0.0..1.0).contains(&self.weight_decay) {
+            return Err(Error::InvalidConfig(format!("the weight decay must be in [0, 1), got {}", self.weight_decay)));
+        }
+        Ok(())
+    }
+
+    /// Whether the updates are left as the rule computed them.
+    pub fn is_plain(&self) -> bool {
+        self.clipping.is_none() && self.weight_decay == 0.0 && !self.adaptive_lr
+    }
+
+    /// Whether `adapt` or `finish` need the weights from before the update, to rescale or clip the change.
+    pub fn needs_before(&self) -> bool {
+        self.clipping.is_some() || self.adaptive_lr
+    }
+
+    /// The copy of the weights `adapt` and `finish` need, only taken when `needs_before`.
+    pub fn before<F: Float>(&self, weights: &[F]) -> Option<Vec<F>> {
+        self.needs_before().then(|| weights.to_vec())
+    }
+
+    /// Runs `update` on the weights of a neuron, followed by `adapt` and `finish`.
+    fn apply<F: Float>(&self, weights: &mut [F], state: &mut NeuronState<F>, lr: F, update: impl FnOnce(&mut [F], &mut NeuronState<F>)) {
+        let before = self.before(weights);
+        update(weights, state);
+        if let Some(before) = &before {
+            self.adapt(before, weights, &mut state.squared_updates, lr);
+        }
+        self.finish(before.as_deref(), weights);
+    }
+
+    /// Rescales the update a rule has just made with the adaptive learning rate. The squared norm of the unscaled update
+    /// `g = (w - before) / lr` is added to the neuron's `accumulator` and the step becomes `lr * g / sqrt(accumulator)`, so
+    /// neurons whose updates have been small, e.g. because they started far from any component, keep taking steps of about `lr`
+    /// while the others slow down.
+    pub fn adapt<F: Float>(&self, before: &[F], weights: &mut [F], accumulator: &mut F, lr: F) {
+        if !self.adaptive_lr || lr == F::ZERO {
+            return;
+        }
+        let squared = weights.iter().zip(before.iter()).fold(F::ZERO, |sum, (&w, &b)| sum + (w - b) * (w - b));
+        *accumulator += squared / (lr * lr);
+        let scale = F::ONE / (*accumulator + F::from_f32(ADAPTIVE_EPSILON)).sqrt();
+        for (w, &b) in weights.iter_mut().zip(before.iter()) {
+            *w = b + (*w - b) * scale;
+        }
+    }
+
+    /// Decays weights which a rule has just updated, then clips their change since `before`.
+    pub fn finish<F: Float>(&self, before: Option<&[F]>, weights: &mut [F]) {
+        if self.weight_decay > 0.0 {
+            let keep = F::ONE - F::from_f32(self.weight_decay);
+            weights.iter_mut().for_each(|w| *w *= keep);
+        }
+        if let (Some(clipping), Some(before)) = (self.clipping, before) {
+            clipping.apply(before, weights);
+        }
+    }
+}
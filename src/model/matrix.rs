@@ -0,0 +1,59 @@
+//! Matrix formulation of Oja's rule, updating all neurons of a network at once instead of looping over them one by one.
+//! The weights form an `N x D` matrix `W` and a batch of patches a `B x D` matrix `X`, so the outputs of every neuron for every
+//! patch are `Y = X W^T` and the averaged update is `dW = lr / B * (Y^T X - diag(sum_b y^2) W)`.
+//! Both products are matrix multiplications, which ndarray hands to its optimized GEMM kernels, or to a BLAS library when built
+//! with the `blas` feature.
+use ndarray::{Array2, ArrayView2, ArrayViewMut2, Axis};
+use crate::data::patch::Patch;
+
+/// Updates the weights of every neuron with the average Oja update over a batch of patches, like `oja_batch_learning_rule` does
+/// for a single neuron. With a single patch this is the plain Oja update.
+pub fn oja_matrix_learning_rule(patches: ArrayView2<f32>, mut weights: ArrayViewMut2<f32>, lr: f32) {
+    if patches.nrows() == 0 {
+        return;
+    }
+    let outputs = patches.dot(&weights.t());
+    let hebbian = outputs.t().dot(&patches);
+    let decay = outputs.mapv(|y| y * y).sum_axis(Axis(0));
+
+    let scale = lr / patches.nrows() as f32;
+    for ((mut w, h), d) in weights.rows_mut().into_iter().zip(hebbian.rows()).zip(decay.iter()) {
+        w.zip_mut_with(&h, |w, h| *w += scale * (h - d * *w));
+    }
+}
+
+/// Stacks the weight vectors of the neurons into the rows of a matrix.
+pub fn to_matrix(weights: &[Patch], dim: usize) -> Array2<f32> {
+    let flat: Vec<f32> = weights.iter().flatten().copied().collect();
+    Array2::from_shape_vec((weights.len(), dim), flat).expect("every weight vector must have the patch dimension")
+}
+
+/// Splits a matrix back into the weight vectors of the neurons.
+pub fn from_matrix(weights: &Array2<f32>) -> Vec<Patch> {
+    weights.rows().into_iter().map(|row| row.to_vec()).collect()
+}
+
+#[cfg(test)]
+mod test{
+    use super::*;
+    use crate::model::oja::{oja_batch_learning_rule, oja_learning_rule};
+
+    #[test]
+    fn matches_the_per_neuron_rules(){
+        let mut weights: Vec<Patch> = vec![vec![0.5, 0.1, -0.2], vec![0.3, 0.3, 0.3]];
+        let patches: Vec<Patch> = vec![vec![1.0, 0.0, 0.5], vec![0.2, 0.8, 0.1]];
+        let flat: Vec<f32> = patches.iter().flatten().copied().collect();
+
+        let mut matrix = to_matrix(&weights, 3);
+        oja_matrix_learning_rule(ArrayView2::from_shape((2, 3), &flat).unwrap(), matrix.view_mut(), 0.1);
+        oja_matrix_learning_rule(ArrayView2::from_shape((1, 3), &flat[..3]).unwrap(), matrix.view_mut(), 0.1);
+        for neuron in weights.iter_mut() {
+            oja_batch_learning_rule(&patches, neuron, 0.1);
+            oja_learning_rule(&patches[0], neuron, 0.1);
+        }
+
+        for (expected, actual) in weights.iter().zip(from_matrix(&matrix).iter()) {
+            assert!(expected.iter().zip(actual.iter()).all(|(e, a)| (e - a).abs() < 1e-5), "{:?} != {:?}", expected, actual);
+        }
+    }
+}
@@ -1,111 +1,2410 @@
-//! Multithreaded implementation of a network using Oja's rule for training a given number of neurons.
-use std::sync::{Arc, mpsc, Mutex};
-use std::sync::mpsc::{Sender};
-use std::time::Instant;
-use rand::Rng;
+//! Multithreaded implementation of a network using Oja's rule, or another Hebbian learning rule, for training a given number of neurons.
+use std::sync::{Arc, Mutex};
+use std::sync::mpsc::Receiver;
+use std::borrow::Cow;
+use std::ops::Range;
+use std::thread;
+use std::time::{Duration, Instant};
+use rand::{Rng, SeedableRng};
+use rand::seq::SliceRandom;
+use rand::rngs::StdRng;
+use std::path::Path;
+use ndarray::ArrayView2;
+use crate::data::dataset::Dataset;
 use crate::data::mnist::MnistData;
-use crate::model::oja::oja_learning_rule;
-use crate::threading::thread_pool::ThreadPool;
-use crate::utils::constants::PATCH_SIZE;
+use crate::data::patch::{Patch, patch_width};
+use crate::error::{Error, Result};
+use crate::profile_scope;
+use crate::model::arena::Arena;
+use crate::model::checkpoint::{Checkpoint, Metadata};
+use crate::model::clipping::Clipping;
+use crate::model::early_stopping::{ConvergenceTracker, EarlyStopping};
+use crate::model::eval::{combine, project, Evaluation};
+use crate::model::field_stats::FieldReport;
+use crate::model::float::{Float, Precision};
+use crate::model::history::{EpochRecord, TrainingHistory};
+use crate::model::hooks::SharedHooks;
+use crate::model::init::Init;
+use crate::model::learning_rule::{LearningRule, NeuronState, UpdateOptions};
+use crate::model::matrix::oja_matrix_learning_rule;
+use crate::model::npy::{load_npy, save_npy};
+use crate::model::orthogonalize::Orthogonalization;
+use crate::model::progress::{Progress, ProgressCallback, WorkerTime};
+use crate::model::redundancy::{find_duplicates, prune, Duplicate};
+use crate::model::sanger::sanger_learning_rule;
+use crate::model::schedule::{Constant, LrSchedule};
+use crate::model::timing::{SectionTiming, Timing};
+use crate::model::weights::Weights;
+use crate::threading::cancellation::CancellationToken;
+use crate::threading::thread_pool::{auto_threads, chunks, PoolMetrics, ThreadPool, Threads};
+use crate::utils::constants::{IMAGE_WIDTH, PATCH_WIDTH};
+use crate::visualization::receptive_fields::save_montage;
+use tracing::{debug, debug_span, info, info_span, trace, warn, Span};
+#[cfg(feature = "rayon")]
+use rayon::prelude::*;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+/// Weights trained for a range of neurons by a worker of the thread pool.
+struct SectionResult {
+    start: usize,
+    // The weights of the neurons of the section, one after the other.
+    weights: Vec<f32>,
+    converged_at: Vec<Option<usize>>,
+    // Snapshots of each neuron's weights before training, at every recording interval and after training.
+    snapshots: Vec<Vec<Snapshot>>,
+    worker: WorkerTime,
+    queued: Duration,
+}
+
+/// Weights of a neuron at some point of training, and how long the neuron had been training by then.
+#[derive(Clone)]
+struct Snapshot {
+    weights: Patch,
+    elapsed: Duration,
+}
+
+/// The epoch each neuron of a section converged at, and the snapshots of its weights during training.
+type SectionProgress = (Vec<Option<usize>>, Vec<Vec<Snapshot>>);
+
+/// Number of batches between the checks of a neuron's weights for NaN and infinite values. Checking every few batches keeps the
+/// cost small compared to the updates, at the price of reporting a divergence up to this many batches late.
+const DIVERGENCE_CHECK_INTERVAL: usize = 64;
+
+/// A neuron whose weights became NaN or infinite after training on `epoch` patches.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+struct Divergence {
+    neuron: usize,
+    epoch: usize,
+}
+
+impl Divergence {
+    // Keeps the divergence of the neuron with the lowest index, so the error does not depend on the order sections finish in.
+    fn first(current: Option<Divergence>, divergence: Divergence) -> Option<Divergence> {
+        Some(current.map_or(divergence, |current| current.min(divergence)))
+    }
+}
+
+impl From<Divergence> for Error {
+    fn from(divergence: Divergence) -> Error {
+        Error::Diverged { neuron: divergence.neuron, epoch: divergence.epoch }
+    }
+}
+
+fn is_finite<F: Float>(weights: &[F]) -> bool {
+    weights.iter().all(|w| w.is_finite())
+}
+
+/// Fails with the first row of the weight matrix holding NaN or infinite values.
+fn check_matrix(weights: &Weights, epoch: usize) -> Result<()> {
+    match weights.iter().position(|weights| !is_finite(weights)) {
+        Some(neuron) => Err(Error::Diverged { neuron, epoch }),
+        None => Ok(()),
+    }
+}
+
+/// The sections trained in place by the rayon and scoped backends, or the first divergence among them.
+fn collect_sections<T>(sections: impl Iterator<Item = (std::result::Result<SectionProgress, Divergence>, T)>) -> std::result::Result<Vec<(SectionProgress, T)>, Divergence> {
+    let mut diverged = None;
+    let mut trained = Vec::new();
+    for (result, extra) in sections {
+        match result {
+            Ok(progress) => trained.push((progress, extra)),
+            Err(divergence) => diverged = Divergence::first(diverged, divergence),
+        }
+    }
+    diverged.map_or(Ok(trained), Err)
+}
+
+/// Everything needed to train a section of neurons, shared by the training backends.
+#[derive(Clone)]
+struct SectionTrainer {
+    rule: LearningRule,
+    schedule: Arc<dyn LrSchedule>,
+    lr: f32,
+    batch_size: usize,
+    shuffle: bool,
+    update: UpdateOptions,
+    early_stopping: Option<EarlyStopping>,
+    interval: Option<usize>,
+    snapshot_count: usize,
+    patch_size: usize,
+    seed: u64,
+    training_data: Arc<Vec<Patch>>,
+    // The training data converted once per run when training in double precision.
+    double_data: Option<Arc<Vec<Vec<f64>>>>,
+    cancellation: Option<CancellationToken>,
+}
+
+impl SectionTrainer {
+    fn is_cancelled(&self) -> bool {
+        self.cancellation.as_ref().is_some_and(|token| token.is_cancelled())
+    }
+
+    // Trains the weights of every neuron in the section starting at neuron `start` in place, continuing from the weights they hold.
+    // The task derives the seed of every neuron from the seed of the run and the index of the neuron, so the result does not
+    // depend on how the neurons are split into sections and no random state is shared between the workers.
+    // With shuffling enabled every neuron visits the patches in its own order, drawn from the random generator of the neuron.
+    // Once the run is cancelled the remaining batches are skipped, so the neurons keep the weights trained so far.
+    // With double precision every neuron is trained in f64 and its weights are rounded to f32 once it is done.
+    // Stops at the first neuron whose weights diverge.
+    fn train(&self, start: usize, section: &mut [f32]) -> std::result::Result<SectionProgress, Divergence> {
+        profile_scope!("train_section");
+        let mut local_convergence = Vec::new();
+        let mut local_snapshots = Vec::new();
+        for (index, weights) in section.chunks_exact_mut(self.patch_size).enumerate() {
+            let mut rng = StdRng::seed_from_u64(neuron_seed(self.seed, start + index));
+            let (converged_at, snapshots) = match &self.double_data {
+                Some(training_data) => {
+                    let mut wide = f64::from_f32_slice(weights);
+                    let trained = self.train_neuron(start + index, training_data, &mut wide, &mut rng)?;
+                    weights.copy_from_slice(&f64::to_f32_slice(&wide));
+                    trained
+                }
+                None => self.train_neuron(start + index, &self.training_data, weights, &mut rng)?,
+            };
+            trace!(converged_at = ?converged_at, "neuron trained");
+            local_convergence.push(converged_at);
+            local_snapshots.push(snapshots);
+        }
+        Ok((local_convergence, local_snapshots))
+    }
+
+    // Trains the weights of a single neuron in the type of the training data, returning the epoch it converged at
+    // and the snapshots of its weights. The weights are checked for NaN and infinite values every few batches and at the end.
+    fn train_neuron<F: Float>(&self, neuron: usize, training_data: &[Vec<F>], weights: &mut [F], rng: &mut StdRng) -> std::result::Result<(Option<usize>, Vec<Snapshot>), Divergence> {
+        let patches = &training_data[..training_data.len().saturating_sub(1)];
+        let training_data: Cow<[Vec<F>]> = if self.shuffle {
+            let mut shuffled = patches.to_vec();
+            shuffled.shuffle(rng);
+            Cow::Owned(shuffled)
+        } else {
+            Cow::Borrowed(patches)
+        };
+        let lr = |batch: usize| F::from_f32(self.schedule.lr(self.lr, batch * self.batch_size));
+        let mut state = NeuronState::default();
+        let mut tracker = self.early_stopping.map(|config| ConvergenceTracker::new(config, &F::to_f32_slice(weights)));
+        let started = Instant::now();
+        let mut snapshots = Vec::with_capacity(self.snapshot_count + 2);
+        snapshots.push(Snapshot { weights: F::to_f32_slice(weights).into_owned(), elapsed: Duration::ZERO });
+        let mut since_snapshot = 0;
+        let mut trained = 0;
+        for (batch, patches) in training_data.chunks(self.batch_size).enumerate() {
+            if self.is_cancelled() {
+                break;
+            }
+            self.rule.update_batch_with(patches, weights, &mut state, lr(batch), &self.update);
+            trained += patches.len();
+            if (batch + 1) % DIVERGENCE_CHECK_INTERVAL == 0 && !is_finite(weights) {
+                return Err(Divergence { neuron, epoch: trained });
+            }
+            since_snapshot += patches.len();
+            while let Some(interval) = self.interval.filter(|interval| since_snapshot >= *interval) {
+                snapshots.push(Snapshot { weights: F::to_f32_slice(weights).into_owned(), elapsed: started.elapsed() });
+                since_snapshot -= interval;
+            }
+            if tracker.as_mut().is_some_and(|tracker| tracker.step(&F::to_f32_slice(weights))) {
+                break;
+            }
+        }
+        if !is_finite(weights) {
+            return Err(Divergence { neuron, epoch: trained });
+        }
+        // A neuron that stopped early keeps its final weights for the remaining records.
+        let last = Snapshot { weights: F::to_f32_slice(weights).into_owned(), elapsed: started.elapsed() };
+        snapshots.resize(self.snapshot_count + 1, last.clone());
+        snapshots.push(last);
+        Ok((tracker.and_then(|tracker| tracker.converged_at()), snapshots))
+    }
+}
+
+/// Seed of the neuron with the given index, mixed from the seed of the training run with SplitMix64 so neighbouring neurons
+/// get unrelated random streams.
+pub(crate) fn neuron_seed(seed: u64, neuron: usize) -> u64 {
+    let mut z = seed.wrapping_add((neuron as u64).wrapping_add(1).wrapping_mul(0x9E37_79B9_7F4A_7C15));
+    z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+    z ^ (z >> 31)
+}
+
+/// The ranges of neurons not covered by any of the results, which must be sorted by their start, split into chunks.
+fn missing_ranges(results: &[SectionResult], neurons: usize, chunk_size: usize) -> Vec<Range<usize>> {
+    let mut missing = Vec::new();
+    let mut next = 0;
+    for result in results.iter() {
+        missing.extend(chunks(next..result.start, chunk_size));
+        next = result.start + result.converged_at.len();
+    }
+    missing.extend(chunks(next..neurons, chunk_size));
+    missing
+}
+
+/// Section size that splits the neurons into about four sections per thread, so idle threads can take over work from slow ones.
+pub fn auto_section_size(neurons: usize, threads: usize) -> usize {
+    neurons.div_ceil(threads.max(1) * 4).max(1)
+}
 
 /// Struct for holding all necessary data for training a network.
-pub struct MtNetwork{
+/// The network samples its training patches from any `Dataset`, MNIST by default.
+pub struct MtNetwork<D: Dataset = MnistData>{
     section_size: usize,
     threads: usize,
     neurons: usize,
-    thread_pool: ThreadPool,
+    thread_pool: Arc<ThreadPool>,
     lr: f32,
-    mnist_data: MnistData,
-    weights: Vec<[f32; PATCH_SIZE]>
+    schedule: Arc<dyn LrSchedule>,
+    rule: LearningRule,
+    init: Init,
+    batch_size: usize,
+    shuffle: bool,
+    precision: Precision,
+    update: UpdateOptions,
+    orthogonalization: Option<Orthogonalization>,
+    chunk_size: Option<usize>,
+    dataset: D,
+    weights: Weights,
+    early_stopping: Option<EarlyStopping>,
+    converged_at: Vec<Option<usize>>,
+    evaluation: Option<Evaluation>,
+    history: TrainingHistory,
+    history_interval: Option<usize>,
+    timing: Timing,
+    arena: Option<Arc<Arena>>,
+    progress: Option<ProgressCallback>,
+    hooks: Option<SharedHooks>,
+    cancellation: Option<CancellationToken>,
+    max_retries: usize,
+    rng: StdRng
 }
 
-impl MtNetwork {
-    pub fn new(section_size: usize, threads: usize, neurons: usize, lr: f32) -> MtNetwork {
-        let pool = ThreadPool::new(threads).unwrap();
-        assert_eq!(neurons % section_size, 0);
-        let mnist_data = MnistData::new(section_size);
+impl MtNetwork<MnistData> {
+    // Creates a network training on MNIST. Networks created with the same seed produce identical results, regardless of the number of threads.
+    // Without a seed the network is seeded from the operating system.
+    pub fn new(section_size: usize, threads: usize, neurons: usize, lr: f32, patch_width: usize, seed: Option<u64>) -> Result<MtNetwork> {
+        MtNetwork::with_data(section_size, threads, neurons, lr, MnistData::new(section_size, patch_width)?, seed)
+    }
+}
+
+impl<D: Dataset> MtNetwork<D> {
+    // Creates a network training on an already loaded dataset, e.g. MNIST read from a custom directory or another image corpus.
+    // Any number of neurons can be trained, when the section size does not divide it the last section holds the remaining neurons.
+    // Fails when the section size is zero, or when the threads can not be started.
+    pub fn with_data(section_size: usize, threads: usize, neurons: usize, lr: f32, dataset: D, seed: Option<u64>) -> Result<MtNetwork<D>> {
+        if threads == 0 {
+            return Err(Error::InvalidConfig("at least one thread is needed for training".to_string()));
+        }
+        // The training methods submit at most one job per thread at a time, so a queue of that size never blocks them.
+        let pool = ThreadPool::builder(threads).label("oja").queue_capacity(threads).build()?;
+        MtNetwork::with_pool(section_size, Arc::new(pool), neurons, lr, dataset, seed)
+    }
+
+    // Creates a network that continues training from previously saved weights, e.g. with a lower learning rate, taking every
+    // other setting from the builder. The weights must match the patch size of its dataset.
+    pub fn from_weights(weights: Vec<Patch>, config: NetworkBuilder<D>) -> Result<MtNetwork<D>> {
+        config.weights(weights).build()
+    }
+
+    // Creates a network training on an existing thread pool, with one thread per worker of the pool. Several networks can
+    // share a pool, e.g. to avoid starting new threads for every run of a sweep, as long as they do not train at the same time.
+    pub fn with_pool(section_size: usize, pool: Arc<ThreadPool>, neurons: usize, lr: f32, dataset: D, seed: Option<u64>) -> Result<MtNetwork<D>> {
+        if section_size == 0 {
+            return Err(Error::InvalidConfig("the section size must be at least one neuron".to_string()));
+        }
+        if dataset.is_empty() {
+            return Err(Error::InvalidConfig("the dataset does not contain any samples".to_string()));
+        }
+        let threads = pool.size();
+        let patch_size = dataset.patch_dim();
 
-        let mut rng = rand::thread_rng();
-        let mut weights = Vec::new();
+        let mut rng = match seed {
+            Some(seed) => StdRng::seed_from_u64(seed),
+            None => StdRng::from_entropy(),
+        };
+        let init = Init::default();
+        let mut weights = Weights::with_capacity(neurons, patch_size);
         for _ in 0..neurons{
-            let weight: [f32; PATCH_SIZE] = rng.gen();
-            weights.push(weight);
+            weights.push(&init.weights(patch_size, &mut rng));
+        }
+
+        Ok(MtNetwork { section_size, threads , neurons, thread_pool: pool, lr, schedule: Arc::new(Constant), rule: LearningRule::default(), init, batch_size: 1, shuffle: false, precision: Precision::Single, update: UpdateOptions::default(), orthogonalization: None, chunk_size: None, dataset, weights, early_stopping: None, converged_at: vec![None; neurons], evaluation: None, history: TrainingHistory::new(), history_interval: None, timing: Timing::default(), arena: None, progress: None, hooks: None, cancellation: None, max_retries: 0, rng})
+    }
+
+    // Selects the learning rule used to train each neuron. Defaults to Oja's rule.
+    pub fn set_rule(&mut self, rule: LearningRule) {
+        self.rule = rule;
+    }
+
+    // Selects how the weights of every neuron are drawn before training, and redraws the current weights with it.
+    // Defaults to uniform weights in [0, 1).
+    pub fn set_init(&mut self, init: Init) {
+        self.init = init;
+        let patch_size = self.dataset.patch_dim();
+        for weights in self.weights.iter_mut() {
+            weights.copy_from_slice(&init.weights(patch_size, &mut self.rng));
         }
+    }
+
+    // Sets the number of patches whose updates are accumulated before they are applied to the weights. Defaults to a single patch.
+    pub fn set_batch_size(&mut self, batch_size: usize) -> Result<()> {
+        if batch_size == 0 {
+            return Err(Error::InvalidConfig("the batch size must be at least one patch".to_string()));
+        }
+        self.batch_size = batch_size;
+        Ok(())
+    }
+
+    // Whether every neuron visits the training patches in its own random order instead of the order they were sampled in.
+    // Disabled by default. The order is derived from the seed of the neuron, so results stay reproducible.
+    pub fn set_shuffle(&mut self, shuffle: bool) {
+        self.shuffle = shuffle;
+    }
+
+    // Selects the type every neuron is trained in. Double precision is slower, but shows whether the learned weights depend on
+    // rounding errors. Only training every neuron on its own supports it, `train_complete_iterations`, `train_parallel_rayon`
+    // and `train_scoped`; the other methods fail with double precision. Defaults to single precision.
+    pub fn set_precision(&mut self, precision: Precision) {
+        self.precision = precision;
+    }
+
+    // Limits how far a single update can move the weights of a neuron, see `Clipping`. Applies to every training method.
+    // Disabled by default.
+    pub fn set_clipping(&mut self, clipping: Option<Clipping>) -> Result<()> {
+        if let Some(clipping) = &clipping {
+            clipping.validate()?;
+        }
+        self.update.clipping = clipping;
+        Ok(())
+    }
+
+    // Adds a leaky forgetting term to every update, shrinking the weights by `weight_decay * w`. Unlike the normalization built
+    // into Oja's rule the decay does not depend on the output, which makes it a baseline for studying how the Hebbian rule
+    // stays bounded. Applies to every training method. Defaults to zero, which disables it.
+    pub fn set_weight_decay(&mut self, weight_decay: f32) -> Result<()> {
+        if !(0.0..1.0).contains(&weight_decay) {
+            return Err(Error::InvalidConfig(format!("the weight decay must be in [0, 1), got {}", weight_decay)));
+        }
+        self.update.weight_decay = weight_decay;
+        Ok(())
+    }
+
+    // Gives every neuron its own learning rate, Adagrad style: each step is divided by the root of the summed squared updates
+    // the neuron has made so far, so neurons which barely moved, e.g. because they were poorly initialized, catch up without
+    // raising the global learning rate. Applies to every training method. Disabled by default.
+    pub fn set_adaptive_lr(&mut self, adaptive: bool) {
+        self.update.adaptive_lr = adaptive;
+    }
+
+    // Fails for the training methods which only train in single precision.
+    fn require_single_precision(&self, method: &str) -> Result<()> {
+        match self.precision {
+            Precision::Single => Ok(()),
+            Precision::Double => Err(Error::InvalidConfig(format!("{} only supports single precision", method))),
+        }
+    }
+
+    // Orthogonalizes the weights periodically during `train_synchronous` and `train_matrix`, so the neurons span different
+    // components. The other training methods train every neuron on its own and ignore it.
+    pub fn set_orthogonalization(&mut self, orthogonalization: Option<Orthogonalization>) {
+        self.orthogonalization = orthogonalization;
+    }
+
+    // Registers a callback receiving progress events during training, e.g. one created with `progress_channel`.
+    pub fn set_progress_callback(&mut self, progress: Option<ProgressCallback>) {
+        self.progress = progress;
+    }
+
+    // Registers hooks called at the start and end of every epoch, for every finished section and once a run completes, see
+    // `TrainingHooks`.
+    pub fn set_hooks(&mut self, hooks: Option<SharedHooks>) {
+        self.hooks = hooks;
+    }
+
+    // Registers a token which aborts training when cancelled, e.g. from a Ctrl-C handler. The training methods then return
+    // `Error::Cancelled` and keep the weights trained so far.
+    pub fn set_cancellation_token(&mut self, cancellation: Option<CancellationToken>) {
+        self.cancellation = cancellation;
+    }
+
+    fn is_cancelled(&self) -> bool {
+        self.cancellation.as_ref().is_some_and(|token| token.is_cancelled())
+    }
+
+    // Sets how often a chunk of neurons whose training panicked is trained again before `train_complete_iterations` gives up
+    // with `Error::WorkerPanicked`. Defaults to no retries.
+    pub fn set_max_retries(&mut self, retries: usize) {
+        self.max_retries = retries;
+    }
+
+    // Logs the jobs which panicked on the thread pool since the last check, during the given attempt or epoch.
+    fn log_failures(&self, attempt: usize) {
+        for failure in self.thread_pool.take_failures() {
+            warn!(range = ?failure.range, message = %failure.message, attempt = attempt, "a training job panicked");
+        }
+    }
+
+    fn report_progress(&self, progress: Progress) {
+        if let Some(callback) = &self.progress {
+            callback(progress);
+        }
+    }
+
+    fn epoch_started(&self, epoch: usize) {
+        if let Some(hooks) = &self.hooks {
+            hooks.on_epoch_start(epoch);
+        }
+    }
+
+    // Whether the hooks asked to stop the run after `epochs` epochs.
+    fn epoch_ended(&self, epochs: usize, weights: &Weights) -> bool {
+        let stop = self.hooks.as_ref().is_some_and(|hooks| hooks.on_epoch_end(epochs, weights).is_break());
+        if stop {
+            info!(epochs = epochs, "training stopped by a hook");
+        }
+        stop
+    }
+
+    fn section_done(&self, start: usize, index: usize, weights: &[f32]) {
+        if let Some(hooks) = &self.hooks {
+            hooks.on_section_done(&TrainedSection::new(index, start, weights, self.dataset.patch_dim()));
+        }
+    }
+
+    fn run_completed(&self) {
+        if let Some(hooks) = &self.hooks {
+            hooks.on_complete(&self.weights, &self.history);
+        }
+    }
+
+    // Ends a run which trained every neuron in a single epoch, which is over once its sections are, so the hooks can no
+    // longer stop it.
+    fn single_epoch_completed(&self, epochs: usize) {
+        if let Some(hooks) = &self.hooks {
+            let _ = hooks.on_epoch_end(epochs, &self.weights);
+        }
+        self.run_completed();
+    }
+
+    // Progress after `completed` epochs which every neuron trains on together, with the learning rate the last one trained
+    // with and the latest recorded reconstruction error.
+    fn epoch_progress(&self, completed: usize, total: usize, lr: f32, started: Instant) -> Progress {
+        Progress {
+            lr: Some(lr),
+            reconstruction_error: self.history.last().and_then(|record| record.reconstruction_error),
+            ..Progress::new(completed, total, started.elapsed())
+        }
+    }
+
+    // Sets the number of neurons a worker takes from the queue at a time when training independently.
+    // Smaller chunks balance the load better between the threads at the cost of more synchronization.
+    // By default the neurons are split into about four chunks per thread, at most one section each.
+    pub fn set_chunk_size(&mut self, chunk_size: Option<usize>) -> Result<()> {
+        if chunk_size == Some(0) {
+            return Err(Error::InvalidConfig("the chunk size must be at least one neuron".to_string()));
+        }
+        self.chunk_size = chunk_size;
+        Ok(())
+    }
+
+    pub fn section_size(&self) -> usize {
+        self.section_size
+    }
+
+    // Number of sections the neurons are split into, including a smaller last section if the section size does not divide them.
+    pub fn sections(&self) -> usize {
+        self.neurons.div_ceil(self.section_size)
+    }
+
+    pub fn chunk_size(&self) -> usize {
+        self.chunk_size.unwrap_or_else(|| (self.neurons / (self.threads * 4)).clamp(1, self.section_size))
+    }
 
-        MtNetwork { section_size, threads , neurons, thread_pool: pool, lr, mnist_data, weights}
+    // Sets how the learning rate anneals over the epochs of a training run. Defaults to a constant learning rate.
+    pub fn set_schedule<S: LrSchedule + 'static>(&mut self, schedule: S) {
+        self.schedule = Arc::new(schedule);
+    }
+
+    // Enables or disables stopping the training of a neuron once its weights no longer change.
+    pub fn set_early_stopping(&mut self, early_stopping: Option<EarlyStopping>) {
+        self.early_stopping = early_stopping;
+    }
+
+    // Enables measuring the reconstruction error on held-out patches at a regular interval of epochs during training.
+    pub fn set_evaluation(&mut self, evaluation: Option<Evaluation>) -> Result<()> {
+        if let Some(evaluation) = &evaluation {
+            if evaluation.patches().iter().any(|patch| patch.len() != self.dataset.patch_dim()) {
+                return Err(Error::Incompatible("evaluation patches must match the patch size of the dataset".to_string()));
+            }
+        }
+        self.evaluation = evaluation;
+        Ok(())
     }
 
     // This method will train a network by splitting the work by iteration, not by individual neurons. Horribly inefficient when the patches are small. Not really usable
-    pub fn train_iteration(&mut self, _epoch: usize) -> Vec<[f32; PATCH_SIZE]> {
+    // The updated weights are stored in the network, so consecutive calls continue where the previous one stopped.
+    pub fn train_iteration(&mut self, epoch: usize) -> Result<Vec<Patch>> {
+        self.require_single_precision("training by iteration")?;
+        if self.threads > self.sections() {
+            return Err(Error::InvalidConfig(format!("{} threads need at least {} sections, but {} neurons only fill {}", self.threads, self.threads, self.neurons, self.sections())));
+        }
+
+        // The patches of every section are sampled into the buffer of the section, next to the weights they train.
+        let now = Instant::now();
+        let covered = self.neurons.min(self.threads * self.section_size);
+        let arena = self.arena(covered, self.section_size);
+        for start in (0..covered).step_by(self.section_size) {
+            arena.section(start).sample(&self.dataset, start, &mut self.rng);
+        }
+        let mut timing = Timing { data_loading: now.elapsed(), ..Timing::default() };
+        let lr_new = self.schedule.lr(self.lr, epoch);
+        let rule = self.rule;
+        let update = self.update;
+        self.epoch_started(epoch);
+        for start in (0..covered).step_by(self.section_size) {
+            arena.section(start).load(self.weights.slice(start..covered.min(start + self.section_size)));
+        }
+
+        let buffers = Arc::clone(&arena);
+        let dispatched = Instant::now();
+        let receiver = self.thread_pool.map_chunked(covered, self.section_size, move |section| {
+            let started = Instant::now();
+            let mut buffer = buffers.section(section.start);
+            let buffer = &mut *buffer;
+            for ((weights, state), patch) in buffer.weights.iter_mut().zip(buffer.states.iter_mut()).zip(buffer.patches.iter()) {
+                rule.update_with(patch, weights, state, lr_new, &update);
+            }
+            SectionTiming { start: section.start, neurons: section.len(), queued: started - dispatched, compute: started.elapsed() }
+        });
+        let mut trained = 0;
+        for section in receiver.iter() {
+            trained += section.neurons;
+            self.section_done(section.start, section.start / self.section_size, arena.section(section.start).weights.as_slice());
+            timing.add_section(section);
+        }
+        self.thread_pool.join();
+        self.log_failures(0);
+        let aggregating = Instant::now();
+
+        if trained != covered {
+            return Err(Error::WorkerPanicked { trained, expected: covered });
+        }
+        for start in (0..covered).step_by(self.section_size) {
+            if let Some(neuron) = arena.section(start).weights.iter().position(|weights| !is_finite(weights)) {
+                return Err(Error::Diverged { neuron: start + neuron, epoch });
+            }
+        }
+        for start in (0..covered).step_by(self.section_size) {
+            let section = arena.section(start);
+            self.weights.slice_mut(start..start + section.weights.len()).copy_from_slice(section.weights.as_slice());
+        }
+        timing.aggregation = aggregating.elapsed();
+        timing.total = now.elapsed();
+        self.timing = timing;
+        self.single_epoch_completed(epoch + 1);
+        Ok(self.weights.iter().take(covered).map(|weights| weights.to_vec()).collect())
+    }
+
+    // The buffers the workers train sections of `section_size` neurons in, kept for the following training runs as long as the
+    // layout of the sections does not change.
+    fn arena(&mut self, neurons: usize, section_size: usize) -> Arc<Arena> {
+        let patch_dim = self.dataset.patch_dim();
+        match &self.arena {
+            Some(arena) if arena.fits(neurons, section_size, patch_dim) => Arc::clone(arena),
+            _ => {
+                let arena = Arc::new(Arena::new(neurons, section_size, patch_dim));
+                self.arena = Some(Arc::clone(&arena));
+                arena
+            }
+        }
+    }
 
+    // Trains all neurons in lockstep: every epoch each neuron is updated on the same `patches_per_epoch` patches, starting from
+    // the weights the network holds. The workers read the weights of the previous epoch from one buffer while their results are
+    // collected into a second one, and the buffers are swapped at the end of every epoch, so every epoch builds on the last.
+    // Early stopping is not supported in this mode.
+    pub fn train_synchronous(&mut self, epochs: usize, patches_per_epoch: usize) -> Result<Vec<Patch>> {
+        if patches_per_epoch == 0 {
+            return Err(Error::InvalidConfig("every epoch needs at least one patch".to_string()));
+        }
+        self.require_single_precision("synchronous training")?;
+        if let Some(orthogonalization) = &self.orthogonalization {
+            orthogonalization.validate(self.neurons, self.dataset.patch_dim())?;
+        }
+        let _span = info_span!("train_synchronous", neurons = self.neurons, threads = self.threads, epochs = epochs).entered();
+        profile_scope!("train_synchronous");
+        let now = Instant::now();
+        let chunk_size = self.chunk_size();
+        let interval = self.record_interval();
+        self.history.clear();
 
-        let (w_response, receiver) = mpsc::channel();
-        let w_response = Arc::new(Mutex::new(w_response));
+        let arena = self.arena(self.neurons, chunk_size);
+        let mut front = std::mem::take(&mut self.weights);
+        let mut front_states = vec![NeuronState::default(); self.neurons];
+        let mut back = front.clone();
+        let mut back_states = front_states.clone();
+        let mut previous = front.clone();
+        let mut since_record = 0;
+        let mut since_orthogonalization = 0;
+        let mut timing = Timing::default();
+        let mut completed = epochs;
 
-        for i in 0..self.threads {
-            let thread_sender = w_response.clone();
-            let mut local_weights: Vec<[f32; PATCH_SIZE]> = Vec::from(&self.weights[i*self.section_size..self.section_size + i*self.section_size]);
-            let training_randomized_patches = self.mnist_data.get_section_vector(i);
-            let lr_new = self.lr;
+        for epoch in 0..epochs {
+            // Cancellation is checked between epochs, so the network keeps the weights of the last completed epoch.
+            if self.is_cancelled() {
+                self.weights = front;
+                self.converged_at = vec![None; self.neurons];
+                info!(epoch = epoch, "synchronous training cancelled");
+                return Err(Error::Cancelled);
+            }
+            self.epoch_started(epoch);
+            let loading = Instant::now();
+            let patches = Arc::new(self.dataset.sample_patches(epoch * patches_per_epoch, patches_per_epoch, &mut self.rng));
+            timing.data_loading += loading.elapsed();
+            let lr = self.schedule.lr(self.lr, epoch * patches_per_epoch);
+            let rule = self.rule;
+            let update = self.update;
+            let shared = Arc::new((std::mem::take(&mut front), std::mem::take(&mut front_states)));
 
-            self.thread_pool.execute(move || {
-                for i in 0..local_weights.len() {
-                    oja_learning_rule(&training_randomized_patches[i],&mut local_weights[i], lr_new);
+            let read = Arc::clone(&shared);
+            let buffers = Arc::clone(&arena);
+            let dispatched = Instant::now();
+            let receiver = self.thread_pool.map_chunked(self.neurons, chunk_size, move |neurons| {
+                let started = Instant::now();
+                let (weights, states) = &*read;
+                let mut buffer = buffers.section(neurons.start);
+                buffer.load_with_states(weights.slice(neurons.clone()), &states[neurons.clone()]);
+                let buffer = &mut *buffer;
+                for (index, (weights, state)) in buffer.weights.iter_mut().zip(buffer.states.iter_mut()).enumerate() {
+                    for patch in patches.iter() {
+                        rule.update_batch_with(std::slice::from_ref(patch), weights, state, lr, &update);
+                    }
+                    if !is_finite(weights) {
+                        return Err(Divergence { neuron: neurons.start + index, epoch: (epoch + 1) * patches_per_epoch });
+                    }
                 }
-                thread_sender.lock().unwrap().send(local_weights).unwrap();
+                let section = SectionTiming { start: neurons.start, neurons: neurons.len(), queued: started - dispatched, compute: started.elapsed() };
+                Ok(section)
             });
-        }
 
-        let mut new_weights = Vec::new();
+            let mut trained = 0;
+            let mut diverged = None;
+            for result in receiver.iter() {
+                let section = match result {
+                    Ok(result) => result,
+                    Err(divergence) => {
+                        diverged = Divergence::first(diverged, divergence);
+                        continue;
+                    }
+                };
+                let buffer = arena.section(section.start);
+                back.slice_mut(section.start..section.start + section.neurons).copy_from_slice(buffer.weights.as_slice());
+                back_states[section.start..section.start + section.neurons].clone_from_slice(&buffer.states);
+                self.section_done(section.start, section.start / chunk_size, buffer.weights.as_slice());
+                trained += section.neurons;
+                timing.add_section(section);
+            }
+            self.thread_pool.join();
+            self.log_failures(epoch);
+            let aggregating = Instant::now();
+
+            // The workers have released the buffer of the previous epoch, which becomes the write buffer of the next one.
+            let (weights, states) = Arc::try_unwrap(shared).unwrap_or_else(|shared| (*shared).clone());
+            if let Some(divergence) = diverged {
+                self.weights = weights;
+                warn!(neuron = divergence.neuron, epoch = divergence.epoch, "synchronous training diverged");
+                return Err(divergence.into());
+            }
+            if trained != self.neurons {
+                self.weights = weights;
+                return Err(Error::WorkerPanicked { trained, expected: self.neurons });
+            }
+            front = std::mem::replace(&mut back, weights);
+            front_states = std::mem::replace(&mut back_states, states);
+            since_orthogonalization += patches_per_epoch;
+            if let Some(orthogonalization) = self.orthogonalization.filter(|o| since_orthogonalization >= o.interval) {
+                orthogonalization.apply(&mut front.views_mut());
+                since_orthogonalization = 0;
+            }
+
+            self.report_progress(self.epoch_progress(epoch + 1, epochs, lr, now));
+            since_record += patches_per_epoch;
+            if interval.is_some_and(|interval| since_record >= interval) {
+                self.record((epoch + 1) * patches_per_epoch, &front, &previous, now.elapsed());
+                previous.clone_from(&front);
+                since_record = 0;
+            }
+            timing.aggregation += aggregating.elapsed();
+            if self.epoch_ended(epoch + 1, &front) {
+                completed = epoch + 1;
+                break;
+            }
+        }
 
-        for _ in 0..(self.neurons / self.section_size){
-            new_weights.append(receiver.recv().unwrap().as_mut());
+        let aggregating = Instant::now();
+        if since_record > 0 || self.history.is_empty() {
+            self.record(completed * patches_per_epoch, &front, &previous, now.elapsed());
         }
-        return new_weights;
+        self.weights = front;
+        self.converged_at = vec![None; self.neurons];
+        timing.aggregation += aggregating.elapsed();
+        timing.total = now.elapsed();
+        self.timing = timing;
+        self.run_completed();
+        info!(elapsed_ms = now.elapsed().as_millis() as u64, threads = self.threads, "synchronous training completed");
+        Ok(self.weights.to_patches())
     }
 
-    // Method for training a complete network by splitting the training complete of neurons into batches which will be scheduled to multiple threads.
-    // 1 thread will be reserved for gathering the results. The resulting weights are unused.
-    pub fn train_complete_iterations(&self, _epochs: usize) {
-        let (w_response, receiver) = mpsc::channel();
-        let w_response :Arc<Mutex<Sender<Vec<[f32 ;PATCH_SIZE]>>>> = Arc::new(Mutex::new(w_response));
-        let training_data_root = Arc::new(self.mnist_data.get_sized_patch(_epochs));
-        let neurons = self.neurons;
+    // Method for training a complete network by splitting the neurons into chunks which idle threads take from a shared queue,
+    // so that a slow thread does not stall the run while the others wait.
+    // Every job trains a copy of its chunk of the weights the network holds, and the calling thread waits for the pool to finish
+    // all chunks and writes the trained weights back into the network before returning them. Training continues from the current
+    // weights, so calling it again trains the neurons further.
+    // With early stopping enabled each neuron stops training once it has converged.
+    // With a batch size above one the updates of each mini-batch are applied at once, and convergence is checked once per batch.
+    pub fn train_complete_iterations(&mut self, epochs: usize) -> Result<Vec<Patch>> {
+        self.train_streaming(epochs).finish()
+    }
+
+    // Same training as `train_complete_iterations`, but hands out the sections of neurons as they finish instead of waiting for
+    // all of them, so very long runs can be checkpointed or visualized while they train. The network holds the trained weights
+    // once the stream has ended, and `TrainingStream::finish` returns them or the error which stopped the run.
+    pub fn train_streaming(&mut self, epochs: usize) -> TrainingStream<'_, D> {
+        TrainingStream::new(self, epochs)
+    }
+
+    // Same training as `train_complete_iterations`, but using rayon to train the sections of the weight vector in parallel
+    // instead of the custom thread pool. Both backends produce identical weights for the same seed.
+    #[cfg(feature = "rayon")]
+    pub fn train_parallel_rayon(&mut self, epochs: usize) -> Result<Vec<Patch>> {
+        let span = info_span!("train", backend = "rayon", neurons = self.neurons, threads = self.threads, epochs = epochs);
+        let _entered = span.enter();
+        let now = Instant::now();
+        let trainer = self.section_trainer(epochs);
+        let mut timing = Timing { data_loading: now.elapsed(), ..Timing::default() };
+        self.epoch_started(0);
+        let pool = rayon::ThreadPoolBuilder::new().num_threads(self.threads).build().map_err(|error| Error::ThreadPool(error.to_string()))?;
+
+        let total = self.sections();
+        // The sections are trained in place, so the weights are restored from this copy if a neuron diverges.
+        let previous = self.weights.clone();
+        let dim = self.weights.dim();
+        let weights = self.weights.as_mut_slice();
         let section_size = self.section_size;
-        let threads = self.threads;
+        let completed = AtomicUsize::new(0);
+        let progress = self.progress.clone();
+        let hooks = self.hooks.clone();
+        let dispatched = Instant::now();
+        let results: Vec<Option<(std::result::Result<SectionProgress, Divergence>, SectionTiming)>> = pool.install(|| {
+            weights.par_chunks_mut(section_size * dim)
+                .enumerate()
+                .map(|(index, section)| {
+                    if trainer.is_cancelled() {
+                        return None;
+                    }
+                    let neurons = section.len() / dim;
+                    let _section = debug_span!(parent: &span, "section", start = index * section_size, neurons = neurons).entered();
+                    let started = Instant::now();
+                    let result = trainer.train(index * section_size, section);
+                    debug!(elapsed_ms = started.elapsed().as_millis() as u64, "section trained");
+                    if let (Ok(_), Some(hooks)) = (&result, &hooks) {
+                        hooks.on_section_done(&TrainedSection::new(index, index * section_size, section, dim));
+                    }
+                    if let Some(progress) = &progress {
+                        let worker = WorkerTime { thread: thread::current().id(), busy: started.elapsed() };
+                        progress(Progress { worker: Some(worker), ..Progress::new(completed.fetch_add(1, Ordering::Relaxed) + 1, total, now.elapsed()) });
+                    }
+                    Some((result, SectionTiming { start: index * section_size, neurons, queued: started - dispatched, compute: started.elapsed() }))
+                })
+                .collect()
+        });
+
+        // Sections skipped after the cancellation were left untouched in the weights of the network.
+        if self.is_cancelled() {
+            self.converged_at = vec![None; self.neurons];
+            info!(elapsed_ms = now.elapsed().as_millis() as u64, "training cancelled");
+            return Err(Error::Cancelled);
+        }
+        let results = match collect_sections(results.into_iter().flatten()) {
+            Ok(results) => results,
+            Err(divergence) => {
+                self.weights = previous;
+                warn!(neuron = divergence.neuron, epoch = divergence.epoch, "training diverged");
+                return Err(divergence.into());
+            }
+        };
+
+        let aggregating = Instant::now();
+        let mut converged_at: Vec<Option<usize>> = Vec::new();
+        let mut snapshots: Vec<Vec<Snapshot>> = Vec::new();
+        for ((mut convergence, mut section_snapshots), section) in results {
+            converged_at.append(convergence.as_mut());
+            snapshots.append(section_snapshots.as_mut());
+            timing.add_section(section);
+        }
+
+        self.finish_training(epochs, converged_at, snapshots);
+        self.finish_timing(timing, aggregating, now);
+        info!(elapsed_ms = now.elapsed().as_millis() as u64, threads = self.threads, "training completed");
+        self.log_convergence();
+        self.single_epoch_completed(1);
+        Ok(self.weights.to_patches())
+    }
 
+    // Same training as `train_complete_iterations`, but on scoped threads which borrow the weights of the network and the training
+    // data directly. The workers take chunks of neurons from a shared iterator over the weights and train them in place, and hand
+    // their convergence and snapshots back when they are joined, so neither the weights nor a channel are shared through an
+    // `Arc<Mutex<..>>`. The threads are started for every call instead of being kept in the pool.
+    pub fn train_scoped(&mut self, epochs: usize) -> Result<Vec<Patch>> {
+        let span = info_span!("train", backend = "scoped", neurons = self.neurons, threads = self.threads, epochs = epochs);
+        let _entered = span.enter();
+        let now = Instant::now();
+        let trainer = self.section_trainer(epochs);
+        let mut timing = Timing { data_loading: now.elapsed(), ..Timing::default() };
+        let chunk_size = self.chunk_size();
+        let total = self.neurons.div_ceil(chunk_size);
+        let completed = AtomicUsize::new(0);
+        let threads = self.threads.min(total);
+        self.epoch_started(0);
+        let progress = &self.progress;
+        let hooks = &self.hooks;
+        // The chunks are trained in place, so the weights are restored from this copy if a neuron diverges.
+        let previous = self.weights.clone();
+        let dim = self.weights.dim();
+        let queue = Mutex::new(self.weights.as_mut_slice().chunks_mut(chunk_size * dim).enumerate());
 
-        self.thread_pool.execute(move || {
-            let now = Instant::now();
-            let mut new_weights: Vec<[f32; PATCH_SIZE]> = Vec::new();
-            for i in 0..( neurons/ section_size) {
-                match receiver.recv() {
-                    Ok(mut weights) => {new_weights.append(weights.as_mut())}
-                    Err(_) => {}
+        let dispatched = Instant::now();
+        type Trained = (std::result::Result<SectionProgress, Divergence>, (usize, SectionTiming));
+        let joined: Vec<thread::Result<Vec<Trained>>> = thread::scope(|scope| {
+            let workers: Vec<_> = (0..threads).map(|_| scope.spawn(|| {
+                let mut trained = Vec::new();
+                loop {
+                    // The lock is only held while taking the next chunk, so a panicking worker does not poison it.
+                    let next = queue.lock().unwrap_or_else(|poisoned| poisoned.into_inner()).next();
+                    let (index, section) = match next {
+                        Some(next) if !trainer.is_cancelled() => next,
+                        _ => break,
+                    };
+                    let neurons = section.len() / dim;
+                    let _section = debug_span!(parent: &span, "section", start = index * chunk_size, neurons = neurons).entered();
+                    let started = Instant::now();
+                    let result = trainer.train(index * chunk_size, section);
+                    if let (Ok(_), Some(hooks)) = (&result, hooks) {
+                        hooks.on_section_done(&TrainedSection::new(index, index * chunk_size, section, dim));
+                    }
+                    trained.push((result, (index, SectionTiming { start: index * chunk_size, neurons, queued: started - dispatched, compute: started.elapsed() })));
+                    debug!(elapsed_ms = started.elapsed().as_millis() as u64, "section trained");
+                    if let Some(progress) = progress {
+                        let worker = WorkerTime { thread: thread::current().id(), busy: started.elapsed() };
+                        progress(Progress { worker: Some(worker), ..Progress::new(completed.fetch_add(1, Ordering::Relaxed) + 1, total, now.elapsed()) });
+                    }
                 }
-                //println!("Percentage done: {:?}", i as f32 / neurons as f32 * section_size as f32);
-            }
-            println!("Completed work in: {} milliseconds with {} threads", now.elapsed().as_millis(), threads - 1 );
+                trained
+            })).collect();
+            workers.into_iter().map(|worker| worker.join()).collect()
         });
 
-        for _ in 0..(self.neurons / self.section_size){
-            let w_response_copy = Arc::clone(&w_response);
-            let local_lr = self.lr;
-            let training_data = Arc::clone(&training_data_root);
-            let sections = self.section_size;
-
-            self.thread_pool.execute(move || {
-                let mut local_weights = Vec::new();
-                for _ in 0..sections {
-                    let mut rng = rand::thread_rng();
-                    let mut weights: [f32; PATCH_SIZE] = rng.gen();
-                    for i in 0..((_epochs as i32) - 1) {
-                        oja_learning_rule(&training_data[i as usize], &mut weights, local_lr);
+        // Chunks skipped after the cancellation were left untouched in the weights of the network.
+        if self.is_cancelled() {
+            self.converged_at = vec![None; self.neurons];
+            info!(elapsed_ms = now.elapsed().as_millis() as u64, "training cancelled");
+            return Err(Error::Cancelled);
+        }
+
+        // The chunks of a panicking worker are lost, including the one it was training in place.
+        let mut sections = match collect_sections(joined.into_iter().flatten().flatten()) {
+            Ok(sections) => sections,
+            Err(divergence) => {
+                self.weights = previous;
+                warn!(neuron = divergence.neuron, epoch = divergence.epoch, "training diverged");
+                return Err(divergence.into());
+            }
+        };
+        let trained: usize = sections.iter().map(|((converged_at, _), _)| converged_at.len()).sum();
+        if trained != self.neurons {
+            return Err(Error::WorkerPanicked { trained, expected: self.neurons });
+        }
+
+        let aggregating = Instant::now();
+        sections.sort_by_key(|(_, (index, _))| *index);
+        let mut converged_at: Vec<Option<usize>> = Vec::new();
+        let mut snapshots: Vec<Vec<Snapshot>> = Vec::new();
+        for ((mut convergence, mut section_snapshots), (_, section)) in sections {
+            converged_at.append(convergence.as_mut());
+            snapshots.append(section_snapshots.as_mut());
+            timing.add_section(section);
+        }
+
+        self.finish_training(epochs, converged_at, snapshots);
+        self.finish_timing(timing, aggregating, now);
+        info!(elapsed_ms = now.elapsed().as_millis() as u64, threads = self.threads, "scoped training completed");
+        self.log_convergence();
+        self.single_epoch_completed(1);
+        Ok(self.weights.to_patches())
+    }
+
+    // Same training as `train_complete_iterations` with Oja's rule, but updating all neurons at once with matrix multiplications
+    // on the calling thread, see `model::matrix`. With a large batch size this uses the optimized GEMM kernels of ndarray, or a
+    // BLAS library when built with the `blas` feature, instead of looping over the neurons on the thread pool.
+    // Early stopping is not supported, and the history only holds a record of the final weights.
+    pub fn train_matrix(&mut self, epochs: usize) -> Result<Vec<Patch>> {
+        if self.rule != LearningRule::Oja {
+            return Err(Error::InvalidConfig("matrix training only supports Oja's rule".to_string()));
+        }
+        self.require_single_precision("matrix training")?;
+        if let Some(orthogonalization) = &self.orthogonalization {
+            orthogonalization.validate(self.neurons, self.dataset.patch_dim())?;
+        }
+        let _span = info_span!("train", backend = "matrix", neurons = self.neurons, epochs = epochs).entered();
+        profile_scope!("train_matrix");
+        let now = Instant::now();
+        let trainer = self.section_trainer(epochs);
+        let dim = trainer.patch_size;
+        let initial = self.weights.clone();
+        let mut weights = initial.clone();
+        let flat: Vec<f32> = trainer.training_data[..epochs.saturating_sub(1)].iter().flatten().copied().collect();
+        let mut timing = Timing { data_loading: now.elapsed(), ..Timing::default() };
+        let started = Instant::now();
+        let mut since_orthogonalization = 0;
+        let mut squared_updates = vec![0.0f32; self.neurons];
+        // The weights from before every batch, copied into the same buffer each time.
+        let mut before = self.update.needs_before().then(|| Weights::new(dim));
+        // The batches train every neuron together, so like the other backends the run is reported to the hooks as a single
+        // epoch of a single section.
+        self.epoch_started(0);
+        for (batch, patches) in flat.chunks(self.batch_size * dim).enumerate() {
+            if self.is_cancelled() {
+                self.weights = weights;
+                self.converged_at = vec![None; self.neurons];
+                return Err(Error::Cancelled);
+            }
+            let patches = ArrayView2::from_shape((patches.len() / dim, dim), patches).expect("every patch must have the patch dimension");
+            if let Some(before) = &mut before {
+                before.copy_from(weights.as_slice());
+            }
+            let lr = self.schedule.lr(self.lr, batch * self.batch_size);
+            oja_matrix_learning_rule(patches, weights.matrix_mut(), lr);
+            if !self.update.is_plain() {
+                for (neuron, vector) in weights.iter_mut().enumerate() {
+                    if let Some(before) = &before {
+                        self.update.adapt(&before[neuron], vector, &mut squared_updates[neuron], lr);
                     }
-                    local_weights.push(weights);
+                    self.update.finish(before.as_ref().map(|before| &before[neuron]), vector);
                 }
-                w_response_copy.lock().unwrap().send(local_weights).unwrap();
-            });
+            }
+            if (batch + 1) % DIVERGENCE_CHECK_INTERVAL == 0 {
+                check_matrix(&weights, batch * self.batch_size + patches.nrows())?;
+            }
+            since_orthogonalization += patches.nrows();
+            if let Some(orthogonalization) = self.orthogonalization.filter(|o| since_orthogonalization >= o.interval) {
+                orthogonalization.apply(&mut weights.views_mut());
+                since_orthogonalization = 0;
+            }
+        }
+
+        check_matrix(&weights, epochs.saturating_sub(1))?;
+        // All neurons are trained together on the calling thread, as a single section which never waits in a queue.
+        timing.add_section(SectionTiming { start: 0, neurons: self.neurons, queued: Duration::ZERO, compute: started.elapsed() });
+        let aggregating = Instant::now();
+        self.weights = weights;
+        self.history.clear();
+        let final_weights = self.weights.clone();
+        self.record(epochs, &final_weights, &initial, now.elapsed());
+        self.converged_at = vec![None; self.neurons];
+        self.finish_timing(timing, aggregating, now);
+        info!(elapsed_ms = now.elapsed().as_millis() as u64, "matrix training completed");
+        self.section_done(0, 0, self.weights.as_slice());
+        self.single_epoch_completed(1);
+        Ok(self.weights.to_patches())
+    }
+
+    fn section_trainer(&mut self, epochs: usize) -> SectionTrainer {
+        profile_scope!("sample_patches");
+        let interval = self.record_interval();
+        let training_data = Arc::new(self.dataset.sample_patches(0, epochs, &mut self.rng));
+        let double_data = match self.precision {
+            Precision::Single => None,
+            Precision::Double => Some(Arc::new(training_data.iter().map(|patch| f64::from_f32_slice(patch)).collect())),
+        };
+        SectionTrainer {
+            rule: self.rule,
+            schedule: Arc::clone(&self.schedule),
+            lr: self.lr,
+            batch_size: self.batch_size,
+            shuffle: self.shuffle,
+            update: self.update,
+            early_stopping: self.early_stopping,
+            interval,
+            snapshot_count: interval.map_or(0, |interval| (epochs.max(1) - 1) / interval),
+            patch_size: self.dataset.patch_dim(),
+            training_data,
+            double_data,
+            seed: self.rng.gen(),
+            cancellation: self.cancellation.clone(),
+        }
+    }
+
+    // Records are taken at the history interval if one is set, otherwise at the evaluation interval.
+    fn record_interval(&self) -> Option<usize> {
+        self.history_interval.or_else(|| self.evaluation.as_ref().map(|evaluation| evaluation.interval()))
+    }
+
+    // Stores the convergence of the last run, and turns the weight snapshots taken during training into the training history.
+    // The neurons train in parallel, so the wall time of a record is estimated as the time all neurons spent training up to
+    // that epoch divided by the number of threads.
+    fn finish_training(&mut self, epochs: usize, converged_at: Vec<Option<usize>>, snapshots: Vec<Vec<Snapshot>>) {
+        self.history.clear();
+        let interval = self.record_interval().unwrap_or(epochs);
+        let snapshot_count = snapshots.first().map_or(0, |neuron| neuron.len());
+        let dim = self.dataset.patch_dim();
+        let weights_at = |snapshot: usize| Weights::from_rows(dim, &snapshots.iter().map(|neuron| &neuron[snapshot].weights).collect::<Vec<_>>());
+        let mut previous = weights_at(0);
+        for snapshot in 1..snapshot_count {
+            let epoch = if snapshot + 1 == snapshot_count { epochs } else { snapshot * interval };
+            let weights = weights_at(snapshot);
+            let elapsed = snapshots.iter().map(|neuron| neuron[snapshot].elapsed).sum::<Duration>() / self.threads as u32;
+            self.record(epoch, &weights, &previous, elapsed);
+            previous = weights;
+        }
+        self.converged_at = converged_at;
+    }
+
+    // Stores the timing of a completed run, with the aggregation ending now.
+    fn finish_timing(&mut self, mut timing: Timing, aggregating: Instant, started: Instant) {
+        timing.aggregation = aggregating.elapsed();
+        timing.total = started.elapsed();
+        debug!(data_loading_ms = timing.data_loading.as_millis() as u64, queueing_ms = timing.queueing().as_millis() as u64, compute_ms = timing.compute().as_millis() as u64, aggregation_ms = timing.aggregation.as_millis() as u64, "training timed");
+        self.timing = timing;
+    }
+
+    fn record(&mut self, epoch: usize, weights: &Weights, previous: &Weights, elapsed: Duration) {
+        let weights = weights.views();
+        let reconstruction_error = self.evaluation.as_ref().map(|evaluation| evaluation.evaluate(&weights));
+        let record = EpochRecord::new(epoch, &weights, &previous.views(), reconstruction_error, elapsed);
+        info!(epoch = record.epoch, mean_weight_norm = record.mean_weight_norm, weight_delta = record.weight_delta, reconstruction_error = ?record.reconstruction_error, "recorded");
+        self.history.push(record);
+    }
+
+    fn log_convergence(&self) {
+        let converged: Vec<usize> = self.converged_at.iter().flatten().cloned().collect();
+        if self.early_stopping.is_some() && !converged.is_empty() {
+            info!(converged = converged.len(), neurons = self.neurons, mean_epoch = converged.iter().sum::<usize>() / converged.len(), "neurons converged");
+        }
+    }
+
+    // Method for training all neurons hierarchically with Sanger's rule, so that each neuron extracts a distinct principal component.
+    // Every neuron depends on the output of the neurons before it for each patch, so this runs on the calling thread.
+    // With early stopping enabled training halts once every neuron has converged.
+    pub fn train_hierarchical(&mut self, epochs: usize) -> Result<Vec<Patch>> {
+        self.require_single_precision("hierarchical training")?;
+        let _span = info_span!("train_hierarchical", neurons = self.neurons, epochs = epochs).entered();
+        profile_scope!("train_hierarchical");
+        let now = Instant::now();
+        let training_data = self.dataset.sample_patches(0, epochs, &mut self.rng);
+        let mut timing = Timing { data_loading: now.elapsed(), ..Timing::default() };
+        let started = Instant::now();
+        // The neurons are trained in place, so the weights are restored from this copy if a neuron diverges.
+        let initial = self.weights.clone();
+        let mut trackers: Option<Vec<ConvergenceTracker>> = self.early_stopping
+            .map(|config| self.weights.iter().map(|w| ConvergenceTracker::new(config, w)).collect());
+        self.history.clear();
+        let interval = self.record_interval();
+        let mut previous = self.weights.clone();
+        let mut since_record = 0;
+
+        let progress_step = (epochs / 100).max(1);
+        let mut since_progress = 0;
+        let mut squared_updates = vec![0.0f32; self.neurons];
+        // The weights from before every patch, copied into the same buffer each time.
+        let mut before = self.update.needs_before().then(|| Weights::new(self.dataset.patch_dim()));
+
+        for (epoch, patch) in training_data.iter().enumerate() {
+            if self.is_cancelled() {
+                self.converged_at = vec![None; self.neurons];
+                info!(epoch = epoch, "hierarchical training cancelled");
+                return Err(Error::Cancelled);
+            }
+            self.epoch_started(epoch);
+            if let Some(before) = &mut before {
+                before.copy_from(self.weights.as_slice());
+            }
+            let lr = self.schedule.lr(self.lr, epoch);
+            sanger_learning_rule(patch, self.weights.as_mut_slice(), lr);
+            if !self.update.is_plain() {
+                for (neuron, weights) in self.weights.iter_mut().enumerate() {
+                    if let Some(before) = &before {
+                        self.update.adapt(&before[neuron], weights, &mut squared_updates[neuron], lr);
+                    }
+                    self.update.finish(before.as_ref().map(|before| &before[neuron]), weights);
+                }
+            }
+            if (epoch + 1) % DIVERGENCE_CHECK_INTERVAL == 0 || epoch + 1 == training_data.len() {
+                if let Some(neuron) = self.weights.iter().position(|weights| !is_finite(weights)) {
+                    self.weights = initial;
+                    warn!(neuron = neuron, epoch = epoch + 1, "hierarchical training diverged");
+                    return Err(Error::Diverged { neuron, epoch: epoch + 1 });
+                }
+            }
+
+            since_progress += 1;
+            if since_progress == progress_step {
+                debug!(epoch = epoch + 1, lr = lr, "epoch");
+                self.report_progress(self.epoch_progress(epoch + 1, epochs, lr, now));
+                since_progress = 0;
+            }
+
+            since_record += 1;
+            if Some(since_record) == interval {
+                let weights = self.weights.clone();
+                self.record(epoch + 1, &weights, &previous, now.elapsed());
+                previous = weights;
+                since_record = 0;
+            }
+            if self.epoch_ended(epoch + 1, &self.weights) {
+                break;
+            }
+
+            if let Some(trackers) = trackers.as_mut() {
+                let mut all_converged = true;
+                for (tracker, weights) in trackers.iter_mut().zip(self.weights.iter()) {
+                    all_converged &= tracker.step(weights);
+                }
+                if all_converged {
+                    break;
+                }
+            }
+        }
+
+        timing.add_section(SectionTiming { start: 0, neurons: self.neurons, queued: Duration::ZERO, compute: started.elapsed() });
+        let aggregating = Instant::now();
+        self.converged_at = match trackers {
+            Some(trackers) => trackers.iter().map(|tracker| tracker.converged_at()).collect(),
+            None => vec![None; self.neurons],
+        };
+
+        if since_record > 0 || self.history.is_empty() {
+            let weights = self.weights.clone();
+            self.record(epochs, &weights, &previous, now.elapsed());
+        }
+        self.finish_timing(timing, aggregating, now);
+
+        info!(elapsed_ms = now.elapsed().as_millis() as u64, "hierarchical training completed");
+        self.log_convergence();
+        self.run_completed();
+        Ok(self.weights.to_patches())
+    }
+
+    // The weights of every neuron, stored one neuron after the other, see `Weights`.
+    pub fn weights(&self) -> &Weights {
+        &self.weights
+    }
+
+    // Activation of every neuron for the given patch, i.e. its projection onto each learned weight vector.
+    // Can be used as a feature vector for downstream models once the network has been trained.
+    pub fn encode(&self, patch: &[f32]) -> Result<Vec<f32>> {
+        if patch.len() != self.dataset.patch_dim() {
+            return Err(Error::Incompatible(format!("the patch has {} values, but the network takes patches of {}", patch.len(), self.dataset.patch_dim())));
+        }
+        Ok(project(patch, &self.weights.views()))
+    }
+
+    pub fn encode_batch(&self, patches: &[Patch]) -> Result<Vec<Vec<f32>>> {
+        patches.iter().map(|patch| self.encode(patch)).collect()
+    }
+
+    // Reconstructs a patch from the activations returned by `encode`, as the sum of the weight vectors scaled by their activation.
+    // Encoding a patch and decoding it again keeps only the part of the patch within the subspace the weights span.
+    pub fn decode(&self, activations: &[f32]) -> Result<Patch> {
+        if activations.len() != self.neurons {
+            return Err(Error::Incompatible(format!("expected one activation per neuron, got {} for {} neurons", activations.len(), self.neurons)));
         }
+        Ok(combine(activations, &self.weights.views()))
     }
-}
\ No newline at end of file
+
+    pub fn decode_batch(&self, activations: &[Vec<f32>]) -> Result<Vec<Patch>> {
+        activations.iter().map(|activations| self.decode(activations)).collect()
+    }
+
+    // Neurons whose weights point in (nearly) the same direction as those of an earlier neuron, see `find_duplicates`.
+    pub fn duplicates(&self, threshold: f32) -> Vec<Duplicate> {
+        find_duplicates(&self.weights.to_patches(), threshold)
+    }
+
+    // Removes the duplicate neurons, keeping the first neuron of every group of similar ones. Returns the removed neurons.
+    pub fn prune_duplicates(&mut self, threshold: f32) -> Vec<Duplicate> {
+        let duplicates = self.duplicates(threshold);
+        self.weights = Weights::from_rows(self.weights.dim(), &prune(&self.weights.to_patches(), &duplicates));
+        self.converged_at = duplicates.iter().rev().fold(std::mem::take(&mut self.converged_at), |mut converged_at, duplicate| {
+            converged_at.remove(duplicate.neuron);
+            converged_at
+        });
+        self.neurons = self.weights.len();
+        info!(pruned = duplicates.len(), neurons = self.neurons, "pruned duplicate neurons");
+        duplicates
+    }
+
+    // Initializes the weights of the duplicate neurons again, so further training with `train_synchronous` or
+    // `train_hierarchical` can move them towards other components. Returns the reinitialized neurons.
+    pub fn reinitialize_duplicates(&mut self, threshold: f32) -> Vec<Duplicate> {
+        let duplicates = self.duplicates(threshold);
+        let patch_size = self.dataset.patch_dim();
+        for duplicate in duplicates.iter() {
+            self.weights[duplicate.neuron].copy_from_slice(&self.init.weights(patch_size, &mut self.rng));
+            self.converged_at[duplicate.neuron] = None;
+        }
+        info!(reinitialized = duplicates.len(), "reinitialized duplicate neurons");
+        duplicates
+    }
+
+    // The epoch at which each neuron converged during the last training run, if early stopping was enabled.
+    pub fn converged_epochs(&self) -> &[Option<usize>] {
+        &self.converged_at
+    }
+
+    // Statistics of the weights at every recording interval of the last training run, and after the last epoch.
+    // Includes the reconstruction error on the held-out patches if evaluation was enabled.
+    pub fn history(&self) -> &TrainingHistory {
+        &self.history
+    }
+
+    // Where the time of the last completed training run went, see `Timing`. Runs which were cancelled or failed leave the timing
+    // of the run before them.
+    pub fn timing(&self) -> &Timing {
+        &self.timing
+    }
+
+    // Counters of the thread pool the network trains on, summed over every run on the pool, including the runs of other
+    // networks sharing it. Only `train_complete_iterations`, `train_iteration` and `train_synchronous` submit jobs to the pool.
+    pub fn pool_metrics(&self) -> PoolMetrics {
+        self.thread_pool.metrics()
+    }
+
+    // Sets how often the training history is recorded, in epochs. Defaults to the evaluation interval, or only after the last epoch.
+    pub fn set_history_interval(&mut self, interval: Option<usize>) -> Result<()> {
+        if interval == Some(0) {
+            return Err(Error::InvalidConfig("the history interval must be at least one epoch".to_string()));
+        }
+        self.history_interval = interval;
+        Ok(())
+    }
+
+    pub fn evaluation(&self) -> Option<&Evaluation> {
+        self.evaluation.as_ref()
+    }
+
+    pub fn dataset(&self) -> &D {
+        &self.dataset
+    }
+
+    // Replaces the data the following training runs sample from, e.g. with the next batch of a stream, keeping the weights.
+    // The new data must have the patch size the network was built for.
+    pub fn set_dataset(&mut self, dataset: D) -> Result<()> {
+        if dataset.patch_dim() != self.dataset.patch_dim() {
+            return Err(Error::Incompatible(format!("dataset has patch size {}, network has {}", dataset.patch_dim(), self.dataset.patch_dim())));
+        }
+        self.dataset = dataset;
+        Ok(())
+    }
+
+    // Width of the square patches the network is trained on, or None when the dataset does not produce square patches.
+    pub fn patch_width(&self) -> Option<usize> {
+        patch_width(self.dataset.patch_dim())
+    }
+
+    // Writes the current weights to disk so training can be inspected or resumed later.
+    pub fn save_checkpoint<P: AsRef<Path>>(&self, path: P) -> Result<()> {
+        self.save_checkpoint_with(path, self.metadata())
+    }
+
+    // Same as `save_checkpoint`, with metadata of the caller's, e.g. `metadata` with the name of the dataset filled in.
+    pub fn save_checkpoint_with<P: AsRef<Path>>(&self, path: P, metadata: Metadata) -> Result<()> {
+        Ok(Checkpoint::new(self.weights.to_patches()).with_metadata(metadata).save(path)?)
+    }
+
+    // Describes how the current weights were trained, for the checkpoints saved from them: the rule, the epochs of the last
+    // run and the statistics of its last history record. The network does not know the name of its dataset.
+    pub fn metadata(&self) -> Metadata {
+        let mut metadata = Metadata::new(format!("{:?}", self.rule));
+        if let Some(record) = self.history.last() {
+            metadata.epochs = Some(record.epoch);
+            metadata.metrics.push(("mean_weight_norm".to_string(), record.mean_weight_norm));
+            metadata.metrics.push(("weight_delta".to_string(), record.weight_delta));
+            if let Some(error) = record.reconstruction_error {
+                metadata.metrics.push(("reconstruction_error".to_string(), error));
+            }
+        }
+        metadata
+    }
+
+    // Replaces the current weights with the ones stored in a checkpoint. The checkpoint must hold the same number of neurons and patch size.
+    pub fn load_checkpoint<P: AsRef<Path>>(&mut self, path: P) -> Result<()> {
+        let checkpoint = Checkpoint::load(path)?;
+        self.replace_weights(checkpoint.weights)
+    }
+
+    // Writes the current weights as a `neurons x patch_size` NumPy array.
+    pub fn save_npy<P: AsRef<Path>>(&self, path: P) -> Result<()> {
+        Ok(save_npy(&self.weights.views(), path)?)
+    }
+
+    // Replaces the current weights with a `neurons x patch_size` NumPy array, e.g. components modified in Python.
+    pub fn load_npy<P: AsRef<Path>>(&mut self, path: P) -> Result<()> {
+        let weights = load_npy(path)?;
+        self.replace_weights(weights)
+    }
+
+    fn replace_weights(&mut self, weights: Vec<Patch>) -> Result<()> {
+        if weights.len() != self.neurons {
+            return Err(Error::Incompatible(format!("checkpoint has {} neurons, network has {}", weights.len(), self.neurons)));
+        }
+        if let Some(weight) = weights.iter().find(|w| w.len() != self.dataset.patch_dim()) {
+            return Err(Error::Incompatible(format!("checkpoint has patch size {}, network has {}", weight.len(), self.dataset.patch_dim())));
+        }
+        self.weights = Weights::from_rows(self.dataset.patch_dim(), &weights);
+        Ok(())
+    }
+
+    // Writes every neuron's weights as a grayscale tile in a single image, scaled up by the given factor.
+    pub fn save_receptive_fields<P: AsRef<Path>>(&self, path: P, scale: usize) -> Result<()> {
+        let patch_width = self.patch_width().ok_or_else(|| Error::Incompatible("receptive fields can only be drawn for square patches".to_string()))?;
+        save_montage(path, &self.weights.to_patches(), patch_width, scale).map_err(|error| Error::Image(error.to_string()))
+    }
+
+    // Norm, sparsity, dominant spatial frequency and orientation of every neuron's weights. Needs square patches.
+    pub fn field_stats(&self) -> Result<FieldReport> {
+        FieldReport::new(&self.weights.to_patches())
+    }
+}
+
+/// A section of neurons which finished training, see `MtNetwork::train_streaming`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct TrainedSection {
+    /// Index of the section, the neurons are split into sections of `MtNetwork::chunk_size` neurons.
+    pub index: usize,
+    /// Index of the first neuron of the section.
+    pub start: usize,
+    pub weights: Vec<Patch>,
+}
+
+impl TrainedSection {
+    // Copies the weights of a section stored one neuron after the other.
+    fn new(index: usize, start: usize, weights: &[f32], dim: usize) -> TrainedSection {
+        TrainedSection { index, start, weights: weights.chunks_exact(dim).map(|weights| weights.to_vec()).collect() }
+    }
+}
+
+type SectionReceiver = Receiver<Option<std::result::Result<SectionResult, Divergence>>>;
+
+/// Sections of neurons in the order they finish training on the thread pool, returned by `MtNetwork::train_streaming`.
+/// Sections whose job panicked are trained again once the others are done, up to the configured number of retries, so a
+/// section can only be missing from the stream when the run fails. Dropping the stream early waits for the sections still
+/// training and completes the run.
+pub struct TrainingStream<'a, D: Dataset> {
+    network: &'a mut MtNetwork<D>,
+    trainer: SectionTrainer,
+    // The weights every section starts from.
+    weights: Arc<Weights>,
+    span: Span,
+    epochs: usize,
+    started: Instant,
+    timing: Timing,
+    chunk_size: usize,
+    total: usize,
+    attempt: usize,
+    receiver: Option<SectionReceiver>,
+    results: Vec<SectionResult>,
+    diverged: Option<Divergence>,
+    outcome: Option<Result<Vec<Patch>>>,
+}
+
+impl<'a, D: Dataset> TrainingStream<'a, D> {
+    fn new(network: &'a mut MtNetwork<D>, epochs: usize) -> TrainingStream<'a, D> {
+        let span = info_span!("train", backend = "pool", neurons = network.neurons, threads = network.threads, epochs = epochs);
+        let started = Instant::now();
+        let trainer = network.section_trainer(epochs);
+        let timing = Timing { data_loading: started.elapsed(), ..Timing::default() };
+        let chunk_size = network.chunk_size();
+        let total = network.neurons.div_ceil(chunk_size);
+        let ranges = chunks(0..network.neurons, chunk_size);
+        let weights = Arc::new(network.weights.clone());
+        network.epoch_started(0);
+        let mut stream = TrainingStream { network, trainer, weights, span, epochs, started, timing, chunk_size, total, attempt: 0, receiver: None, results: Vec::with_capacity(total), diverged: None, outcome: None };
+        stream.dispatch(ranges);
+        stream
+    }
+
+    // Queues a job for every range of neurons on the pool. Every job trains a copy of its chunk of the weights.
+    fn dispatch(&mut self, ranges: Vec<Range<usize>>) {
+        let trainer = self.trainer.clone();
+        let weights = Arc::clone(&self.weights);
+        let parent = self.span.clone();
+        let dispatched = Instant::now();
+        self.receiver = Some(self.network.thread_pool.map_ranges(ranges, move |neurons| {
+            // Chunks taken after the run was cancelled are skipped, so their neurons keep the weights the network holds.
+            if trainer.is_cancelled() {
+                return None;
+            }
+            let _section = debug_span!(parent: &parent, "section", start = neurons.start, neurons = neurons.len()).entered();
+            let started = Instant::now();
+            let queued = started - dispatched;
+            let mut local_weights = weights.slice(neurons.clone()).to_vec();
+            let (converged_at, snapshots) = match trainer.train(neurons.start, &mut local_weights) {
+                Ok(progress) => progress,
+                Err(divergence) => return Some(Err(divergence)),
+            };
+            debug!(elapsed_ms = started.elapsed().as_millis() as u64, "section trained");
+            let worker = WorkerTime { thread: thread::current().id(), busy: started.elapsed() };
+            Some(Ok(SectionResult { start: neurons.start, weights: local_weights, converged_at, snapshots, worker, queued }))
+        }));
+    }
+
+    // Waits for the sections still training and returns the weights of the network after the run, or the error which stopped it.
+    pub fn finish(mut self) -> Result<Vec<Patch>> {
+        self.by_ref().for_each(drop);
+        self.outcome.take().expect("the outcome is stored once the stream has ended")
+    }
+
+    // Called once every dispatched chunk has been processed. Chunks whose job panicked are trained again up to the configured
+    // number of retries, every neuron is seeded by its index so a retried chunk gives the same weights as a first attempt would
+    // have. Returns whether chunks were dispatched again, otherwise the run is completed and its outcome stored.
+    fn end_attempt(&mut self) -> bool {
+        self.receiver = None;
+        self.network.thread_pool.join();
+        self.network.log_failures(self.attempt);
+        // The weights of the network are only replaced once every chunk was trained, so they stay as they were.
+        if let Some(divergence) = self.diverged {
+            warn!(neuron = divergence.neuron, epoch = divergence.epoch, "training diverged");
+            self.outcome = Some(Err(divergence.into()));
+            return false;
+        }
+
+        self.results.sort_by_key(|result| result.start);
+        let ranges = missing_ranges(&self.results, self.network.neurons, self.chunk_size);
+        if !ranges.is_empty() && !self.network.is_cancelled() && self.attempt < self.network.max_retries {
+            self.attempt += 1;
+            self.dispatch(ranges);
+            return true;
+        }
+        self.outcome = Some(self.complete());
+        false
+    }
+
+    // Stores the trained chunks, sorted by their first neuron, in the network.
+    fn complete(&mut self) -> Result<Vec<Patch>> {
+        profile_scope!("aggregate");
+        let results = std::mem::take(&mut self.results);
+        let network = &mut *self.network;
+        let dim = network.weights.dim();
+        // The chunks trained before the cancellation are kept, the skipped ones leave the current weights in place.
+        if network.is_cancelled() {
+            for result in results {
+                network.weights.as_mut_slice()[result.start * dim..result.start * dim + result.weights.len()].copy_from_slice(&result.weights);
+            }
+            network.converged_at = vec![None; network.neurons];
+            info!(elapsed_ms = self.started.elapsed().as_millis() as u64, "training cancelled");
+            return Err(Error::Cancelled);
+        }
+
+        // A chunk which still panicked after the last retry leaves neurons untrained.
+        let trained: usize = results.iter().map(|result| result.converged_at.len()).sum();
+        if trained != network.neurons {
+            return Err(Error::WorkerPanicked { trained, expected: network.neurons });
+        }
+
+        let aggregating = Instant::now();
+        let mut new_weights: Vec<f32> = Vec::with_capacity(network.neurons * dim);
+        let mut converged_at: Vec<Option<usize>> = Vec::new();
+        let mut snapshots: Vec<Vec<Snapshot>> = Vec::new();
+        for mut result in results {
+            new_weights.append(result.weights.as_mut());
+            converged_at.append(result.converged_at.as_mut());
+            snapshots.append(result.snapshots.as_mut());
+        }
+
+        network.weights = Weights::from_flat(new_weights, dim);
+        network.finish_training(self.epochs, converged_at, snapshots);
+        network.finish_timing(std::mem::take(&mut self.timing), aggregating, self.started);
+        info!(elapsed_ms = self.started.elapsed().as_millis() as u64, threads = network.threads, "training completed");
+        network.log_convergence();
+        network.single_epoch_completed(1);
+        Ok(network.weights.to_patches())
+    }
+}
+
+impl<D: Dataset> Iterator for TrainingStream<'_, D> {
+    type Item = TrainedSection;
+
+    // Waits for the next section to finish training. Progress is reported as the sections arrive.
+    fn next(&mut self) -> Option<TrainedSection> {
+        let _entered = self.span.clone().entered();
+        loop {
+            let received = self.receiver.as_ref()?.recv();
+            match received {
+                Ok(Some(Ok(result))) => {
+                    let worker = result.worker;
+                    let section = TrainedSection::new(result.start / self.chunk_size, result.start, &result.weights, self.trainer.patch_size);
+                    self.timing.add_section(SectionTiming { start: result.start, neurons: section.weights.len(), queued: result.queued, compute: worker.busy });
+                    if let Some(hooks) = &self.network.hooks {
+                        hooks.on_section_done(&section);
+                    }
+                    self.results.push(result);
+                    if !self.network.is_cancelled() {
+                        self.network.report_progress(Progress { worker: Some(worker), ..Progress::new(self.results.len(), self.total, self.started.elapsed()) });
+                    }
+                    return Some(section);
+                }
+                Ok(Some(Err(divergence))) => self.diverged = Divergence::first(self.diverged, divergence),
+                // A chunk skipped after the cancellation.
+                Ok(None) => {}
+                Err(_) => {
+                    if !self.end_attempt() {
+                        return None;
+                    }
+                }
+            }
+        }
+    }
+}
+
+impl<D: Dataset> Drop for TrainingStream<'_, D> {
+    // Waits for the sections still training, so the pool is idle and the network holds the result of the run.
+    fn drop(&mut self) {
+        self.by_ref().for_each(drop);
+    }
+}
+
+/// Loads the dataset of a network once the builder knows the patch width and section size.
+type DatasetLoader<D> = Box<dyn FnOnce(usize, usize) -> Result<D>>;
+
+/// Configures a network step by step, starting from defaults, so new options can be added without breaking existing callers.
+///
+/// ```no_run
+/// use rust_ml::model::learning_rule::LearningRule;
+/// use rust_ml::model::network::NetworkBuilder;
+///
+/// let mut network = NetworkBuilder::new().neurons(256).threads(8).lr(0.01).rule(LearningRule::Oja).build()?;
+/// network.train_complete_iterations(50000)?;
+/// # Ok::<(), rust_ml::error::Error>(())
+/// ```
+pub struct NetworkBuilder<D: Dataset = MnistData> {
+    neurons: usize,
+    threads: usize,
+    section_size: Option<usize>,
+    chunk_size: Option<usize>,
+    lr: f32,
+    seed: Option<u64>,
+    rule: LearningRule,
+    init: Init,
+    schedule: Arc<dyn LrSchedule>,
+    batch_size: usize,
+    shuffle: bool,
+    precision: Precision,
+    update: UpdateOptions,
+    orthogonalization: Option<Orthogonalization>,
+    early_stopping: Option<EarlyStopping>,
+    evaluation: Option<Evaluation>,
+    history_interval: Option<usize>,
+    progress: Option<ProgressCallback>,
+    hooks: Option<SharedHooks>,
+    cancellation: Option<CancellationToken>,
+    max_retries: usize,
+    weights: Option<Vec<Patch>>,
+    patch_width: usize,
+    thread_pool: Option<Arc<ThreadPool>>,
+    dataset: DatasetLoader<D>,
+}
+
+impl NetworkBuilder<MnistData> {
+    // Starts from 256 neurons trained with Oja's rule at a learning rate of 0.01, on `auto_threads` threads and MNIST patches
+    // of the default width.
+    pub fn new() -> NetworkBuilder {
+        NetworkBuilder {
+            neurons: 256,
+            threads: auto_threads(),
+            section_size: None,
+            chunk_size: None,
+            lr: 0.01,
+            seed: None,
+            rule: LearningRule::default(),
+            init: Init::default(),
+            schedule: Arc::new(Constant),
+            batch_size: 1,
+            shuffle: false,
+            precision: Precision::Single,
+            update: UpdateOptions::default(),
+            orthogonalization: None,
+            early_stopping: None,
+            evaluation: None,
+            history_interval: None,
+            progress: None,
+            hooks: None,
+            cancellation: None,
+            max_retries: 0,
+            weights: None,
+            patch_width: PATCH_WIDTH,
+            thread_pool: None,
+            dataset: Box::new(MnistData::new),
+        }
+    }
+}
+
+impl Default for NetworkBuilder<MnistData> {
+    fn default() -> NetworkBuilder {
+        NetworkBuilder::new()
+    }
+}
+
+impl<D: Dataset> NetworkBuilder<D> {
+    pub fn neurons(mut self, neurons: usize) -> Self {
+        self.neurons = neurons;
+        self
+    }
+
+    // Either a number of threads or `Threads::Auto` for one per available core.
+    pub fn threads<T: Into<Threads>>(mut self, threads: T) -> Self {
+        self.threads = threads.into().count();
+        self
+    }
+
+    // Trains on an existing pool instead of starting new threads, see `MtNetwork::with_pool`. The number of threads is taken
+    // from the pool.
+    pub fn thread_pool(mut self, pool: Arc<ThreadPool>) -> Self {
+        self.threads = pool.size();
+        self.thread_pool = Some(pool);
+        self
+    }
+
+    // Number of neurons scheduled to a thread at a time. Defaults to about four sections per thread.
+    pub fn section_size(mut self, section_size: usize) -> Self {
+        self.section_size = Some(section_size);
+        self
+    }
+
+    pub fn chunk_size(mut self, chunk_size: usize) -> Self {
+        self.chunk_size = Some(chunk_size);
+        self
+    }
+
+    pub fn lr(mut self, lr: f32) -> Self {
+        self.lr = lr;
+        self
+    }
+
+    pub fn seed(mut self, seed: u64) -> Self {
+        self.seed = Some(seed);
+        self
+    }
+
+    pub fn rule(mut self, rule: LearningRule) -> Self {
+        self.rule = rule;
+        self
+    }
+
+    pub fn init(mut self, init: Init) -> Self {
+        self.init = init;
+        self
+    }
+
+    pub fn schedule<S: LrSchedule + 'static>(mut self, schedule: S) -> Self {
+        self.schedule = Arc::new(schedule);
+        self
+    }
+
+    pub fn batch_size(mut self, batch_size: usize) -> Self {
+        self.batch_size = batch_size;
+        self
+    }
+
+    pub fn shuffle(mut self, shuffle: bool) -> Self {
+        self.shuffle = shuffle;
+        self
+    }
+
+    pub fn precision(mut self, precision: Precision) -> Self {
+        self.precision = precision;
+        self
+    }
+
+    pub fn clipping(mut self, clipping: Clipping) -> Self {
+        self.update.clipping = Some(clipping);
+        self
+    }
+
+    pub fn weight_decay(mut self, weight_decay: f32) -> Self {
+        self.update.weight_decay = weight_decay;
+        self
+    }
+
+    pub fn adaptive_lr(mut self, adaptive: bool) -> Self {
+        self.update.adaptive_lr = adaptive;
+        self
+    }
+
+    pub fn orthogonalization(mut self, orthogonalization: Orthogonalization) -> Self {
+        self.orthogonalization = Some(orthogonalization);
+        self
+    }
+
+    pub fn early_stopping(mut self, early_stopping: EarlyStopping) -> Self {
+        self.early_stopping = Some(early_stopping);
+        self
+    }
+
+    pub fn evaluation(mut self, evaluation: Evaluation) -> Self {
+        self.evaluation = Some(evaluation);
+        self
+    }
+
+    pub fn history_interval(mut self, interval: usize) -> Self {
+        self.history_interval = Some(interval);
+        self
+    }
+
+    pub fn progress_callback(mut self, progress: ProgressCallback) -> Self {
+        self.progress = Some(progress);
+        self
+    }
+
+    pub fn hooks(mut self, hooks: SharedHooks) -> Self {
+        self.hooks = Some(hooks);
+        self
+    }
+
+    pub fn cancellation_token(mut self, cancellation: CancellationToken) -> Self {
+        self.cancellation = Some(cancellation);
+        self
+    }
+
+    pub fn max_retries(mut self, retries: usize) -> Self {
+        self.max_retries = retries;
+        self
+    }
+
+    // Starts training from these weights instead of initializing the neurons, and takes the number of neurons from them.
+    pub fn weights(mut self, weights: Vec<Patch>) -> Self {
+        self.neurons = weights.len();
+        self.weights = Some(weights);
+        self
+    }
+
+    // Width of the square MNIST patches. Has no effect once a dataset has been set.
+    pub fn patch_width(mut self, patch_width: usize) -> Self {
+        self.patch_width = patch_width;
+        self
+    }
+
+    // Trains on whole flattened MNIST digits instead of patches. Has no effect once a dataset has been set.
+    pub fn whole_images(self) -> Self {
+        self.patch_width(IMAGE_WIDTH)
+    }
+
+    // Trains on an already loaded dataset instead of MNIST.
+    pub fn dataset<E: Dataset + 'static>(self, dataset: E) -> NetworkBuilder<E> {
+        NetworkBuilder {
+            neurons: self.neurons,
+            threads: self.threads,
+            section_size: self.section_size,
+            chunk_size: self.chunk_size,
+            lr: self.lr,
+            seed: self.seed,
+            rule: self.rule,
+            init: self.init,
+            schedule: self.schedule,
+            batch_size: self.batch_size,
+            shuffle: self.shuffle,
+            precision: self.precision,
+            update: self.update,
+            orthogonalization: self.orthogonalization,
+            early_stopping: self.early_stopping,
+            evaluation: self.evaluation,
+            history_interval: self.history_interval,
+            progress: self.progress,
+            hooks: self.hooks,
+            cancellation: self.cancellation,
+            max_retries: self.max_retries,
+            weights: self.weights,
+            patch_width: self.patch_width,
+            thread_pool: self.thread_pool,
+            dataset: Box::new(move |_, _| Ok(dataset)),
+        }
+    }
+
+    // Validates the configuration, loads the dataset and creates the network.
+    pub fn build(self) -> Result<MtNetwork<D>> {
+        if self.batch_size == 0 {
+            return Err(Error::InvalidConfig("the batch size must be at least one patch".to_string()));
+        }
+        if self.chunk_size == Some(0) || self.history_interval == Some(0) {
+            return Err(Error::InvalidConfig("the chunk size and history interval must be at least one".to_string()));
+        }
+        self.update.validate()?;
+        let section_size = self.section_size.unwrap_or_else(|| auto_section_size(self.neurons, self.threads));
+        let dataset = (self.dataset)(section_size, self.patch_width)?;
+        if let Some(evaluation) = &self.evaluation {
+            if evaluation.patches().iter().any(|patch| patch.len() != dataset.patch_dim()) {
+                return Err(Error::Incompatible("evaluation patches must match the patch size of the dataset".to_string()));
+            }
+        }
+
+        let mut network = match self.thread_pool {
+            Some(pool) => MtNetwork::with_pool(section_size, pool, self.neurons, self.lr, dataset, self.seed)?,
+            None => MtNetwork::with_data(section_size, self.threads, self.neurons, self.lr, dataset, self.seed)?,
+        };
+        if self.init != Init::default() {
+            network.set_init(self.init);
+        }
+        network.rule = self.rule;
+        network.schedule = self.schedule;
+        network.batch_size = self.batch_size;
+        network.shuffle = self.shuffle;
+        network.precision = self.precision;
+        network.update = self.update;
+        network.orthogonalization = self.orthogonalization;
+        network.chunk_size = self.chunk_size;
+        network.early_stopping = self.early_stopping;
+        network.evaluation = self.evaluation;
+        network.history_interval = self.history_interval;
+        network.progress = self.progress;
+        network.hooks = self.hooks;
+        network.cancellation = self.cancellation;
+        network.max_retries = self.max_retries;
+        if let Some(weights) = self.weights {
+            network.replace_weights(weights)?;
+        }
+        Ok(network)
+    }
+}
+
+#[cfg(test)]
+mod test{
+    use super::*;
+    use crate::model::orthogonalize::OrthogonalizationMethod;
+    use crate::model::progress::progress_channel;
+    use crate::model::hooks::TrainingHooks;
+    use crate::data::synthetic::GaussianData;
+    use std::ops::ControlFlow;
+
+    #[test]
+    fn builder_matches_the_constructor(){
+        let mut manual = MtNetwork::new(3, 2, 12, 0.02, 5, Some(8)).unwrap();
+        manual.set_rule(LearningRule::Hebbian { normalize_every: 5 });
+        manual.set_init(Init::Xavier);
+
+        let mut built = NetworkBuilder::new().neurons(12).threads(2).section_size(3).lr(0.02).seed(8).patch_width(5)
+            .rule(LearningRule::Hebbian { normalize_every: 5 })
+            .init(Init::Xavier)
+            .build().unwrap();
+        assert_eq!(built.train_complete_iterations(200).unwrap(), manual.train_complete_iterations(200).unwrap());
+
+        assert!(matches!(NetworkBuilder::new().batch_size(0).build(), Err(Error::InvalidConfig(_))));
+        assert!(matches!(NetworkBuilder::new().dataset(MnistData::new(1, 5).unwrap()).threads(0).build(), Err(Error::InvalidConfig(_))));
+    }
+
+    #[test]
+    fn invalid_configurations_are_rejected(){
+        assert!(matches!(MtNetwork::with_data(0, 2, 10, 0.01, MnistData::new(3, 5).unwrap(), None), Err(Error::InvalidConfig(_))));
+        assert!(matches!(MtNetwork::with_data(5, 0, 10, 0.01, MnistData::new(5, 5).unwrap(), None), Err(Error::InvalidConfig(_))));
+        assert!(matches!(MtNetwork::new(5, 1, 10, 0.01, 0, None), Err(Error::InvalidConfig(_))));
+    }
+
+    #[test]
+    fn invalid_settings_are_rejected(){
+        let mut network = MtNetwork::new(5, 1, 10, 0.01, 1, None).unwrap();
+        assert!(matches!(network.set_batch_size(0), Err(Error::InvalidConfig(_))));
+        assert!(matches!(network.set_clipping(Some(Clipping::Norm(0.0))), Err(Error::InvalidConfig(_))));
+        assert!(matches!(network.set_weight_decay(1.0), Err(Error::InvalidConfig(_))));
+        assert!(matches!(network.set_chunk_size(Some(0)), Err(Error::InvalidConfig(_))));
+        assert!(matches!(network.set_history_interval(Some(0)), Err(Error::InvalidConfig(_))));
+        assert!(matches!(network.set_evaluation(Some(Evaluation::new(vec![vec![0.0; 3]], 10))), Err(Error::Incompatible(_))));
+        assert!(network.evaluation().is_none());
+    }
+
+    #[test]
+    fn ragged_sections_train_every_neuron(){
+        let mut ragged = MtNetwork::new(3, 2, 10, 0.01, 5, Some(4)).unwrap();
+        let mut whole = MtNetwork::new(10, 1, 10, 0.01, 5, Some(4)).unwrap();
+        assert_eq!(ragged.sections(), 4);
+
+        let weights = ragged.train_complete_iterations(200).unwrap();
+        assert_eq!(weights.len(), 10);
+        assert_eq!(weights, whole.train_complete_iterations(200).unwrap());
+        assert_eq!(ragged.train_iteration(0).unwrap().len(), 6);
+    }
+
+    #[test]
+    fn synchronous_training_continues_from_the_current_weights(){
+        let mut single = MtNetwork::new(2, 1, 6, 0.005, 5, Some(3)).unwrap();
+        let mut multi = MtNetwork::new(2, 3, 6, 0.005, 5, Some(3)).unwrap();
+        let initial = single.weights().to_patches();
+
+        let first = single.train_synchronous(5, 10).unwrap();
+        assert_eq!(first, multi.train_synchronous(5, 10).unwrap());
+        assert_ne!(first, initial);
+
+        let second = single.train_synchronous(5, 10).unwrap();
+        assert_ne!(second, first);
+        assert_eq!(single.weights(), &second[..]);
+        assert_eq!(single.history().last().map(|record| record.epoch), Some(50));
+        assert!(single.train_synchronous(1, 0).is_err());
+    }
+
+    #[test]
+    fn iterations_reuse_the_section_buffers(){
+        let mut network = MtNetwork::with_data(2, 2, 4, 0.01, GaussianData::with_variances(&[1.0, 2.0, 3.0], 10).unwrap(), Some(3)).unwrap();
+        let first = network.train_iteration(0).unwrap();
+        let arena = Arc::clone(network.arena.as_ref().unwrap());
+        let second = network.train_iteration(1).unwrap();
+        assert_ne!(first, second);
+        assert_eq!(network.weights(), &second[..]);
+        assert!(Arc::ptr_eq(&arena, network.arena.as_ref().unwrap()));
+        assert_eq!(arena.section(2).weights, second[2..]);
+    }
+
+    #[test]
+    fn orthogonalized_neurons_span_different_components(){
+        let mut network = MtNetwork::new(2, 2, 4, 0.01, 3, Some(5)).unwrap();
+        network.set_orthogonalization(Some(Orthogonalization::new(OrthogonalizationMethod::GramSchmidt, 20)));
+        let weights = network.train_synchronous(6, 10).unwrap();
+        for (i, a) in weights.iter().enumerate() {
+            for (j, b) in weights.iter().enumerate() {
+                let dot: f32 = a.iter().zip(b.iter()).map(|(x, y)| x * y).sum();
+                assert!((dot - if i == j { 1.0 } else { 0.0 }).abs() < 1e-4);
+            }
+        }
+
+        let mut crowded = MtNetwork::new(2, 1, 12, 0.01, 3, Some(5)).unwrap();
+        crowded.set_orthogonalization(Some(Orthogonalization::new(OrthogonalizationMethod::Symmetric, 1)));
+        assert!(crowded.train_synchronous(1, 10).is_err());
+    }
+
+    #[test]
+    fn npy_weights_are_loaded_into_a_matching_network(){
+        let mut trained = MtNetwork::new(2, 1, 4, 0.01, 3, Some(1)).unwrap();
+        trained.train_complete_iterations(50).unwrap();
+        let path = std::env::temp_dir().join(format!("rust_ml_npy_test_{}.npy", std::process::id()));
+        trained.save_npy(&path).unwrap();
+
+        let mut loaded = MtNetwork::new(2, 1, 4, 0.01, 3, Some(2)).unwrap();
+        let mut smaller = MtNetwork::new(2, 1, 2, 0.01, 3, Some(2)).unwrap();
+        let results = (loaded.load_npy(&path), smaller.load_npy(&path));
+        std::fs::remove_file(&path).unwrap();
+        assert!(results.0.is_ok());
+        assert!(matches!(results.1, Err(Error::Incompatible(_))));
+        assert_eq!(loaded.weights(), trained.weights());
+    }
+
+    #[test]
+    fn scoped_training_matches_the_thread_pool(){
+        let mut pool = MtNetwork::new(3, 3, 11, 0.01, 5, Some(21)).unwrap();
+        let mut scoped = MtNetwork::new(3, 3, 11, 0.01, 5, Some(21)).unwrap();
+        scoped.set_chunk_size(Some(2)).unwrap();
+        scoped.set_history_interval(Some(50)).unwrap();
+        pool.set_history_interval(Some(50)).unwrap();
+
+        assert_eq!(pool.train_complete_iterations(200).unwrap(), scoped.train_scoped(200).unwrap());
+        assert_eq!(scoped.history().records().len(), pool.history().records().len());
+    }
+
+    #[test]
+    fn matrix_training_matches_the_thread_pool(){
+        for batch_size in [1, 16] {
+            let mut pool = MtNetwork::new(4, 2, 12, 0.01, 5, Some(2)).unwrap();
+            let mut matrix = MtNetwork::new(4, 2, 12, 0.01, 5, Some(2)).unwrap();
+            pool.set_batch_size(batch_size).unwrap();
+            matrix.set_batch_size(batch_size).unwrap();
+
+            let expected = pool.train_complete_iterations(300).unwrap();
+            let weights = matrix.train_matrix(300).unwrap();
+            for (e, w) in expected.iter().flatten().zip(weights.iter().flatten()) {
+                assert!((e - w).abs() < 1e-4, "{} != {}", e, w);
+            }
+        }
+        let mut hebbian = MtNetwork::new(4, 1, 4, 0.01, 5, None).unwrap();
+        hebbian.set_rule(LearningRule::Hebbian { normalize_every: 10 });
+        assert!(matches!(hebbian.train_matrix(10), Err(Error::InvalidConfig(_))));
+    }
+
+    #[test]
+    fn networks_share_a_thread_pool(){
+        let pool = Arc::new(ThreadPool::new(3).unwrap());
+        let mut first = MtNetwork::with_pool(5, Arc::clone(&pool), 20, 0.01, MnistData::new(5, 5).unwrap(), Some(42)).unwrap();
+        let mut second = NetworkBuilder::new().neurons(20).section_size(5).lr(0.01).patch_width(5).seed(42).thread_pool(Arc::clone(&pool)).build().unwrap();
+        let mut own = MtNetwork::with_data(5, 2, 20, 0.01, MnistData::new(5, 5).unwrap(), Some(42)).unwrap();
+
+        let weights = first.train_complete_iterations(300).unwrap();
+        assert_eq!(weights, second.train_complete_iterations(300).unwrap());
+        assert_eq!(weights, own.train_complete_iterations(300).unwrap());
+        drop((first, second));
+        assert_eq!(Arc::strong_count(&pool), 1);
+    }
+
+    #[test]
+    fn training_records_the_time_of_every_phase(){
+        let mut network = MtNetwork::with_data(5, 2, 20, 0.01, MnistData::new(5, 5).unwrap(), Some(42)).unwrap();
+        assert!(network.timing().sections.is_empty());
+        network.set_chunk_size(Some(5)).unwrap();
+        network.train_complete_iterations(300).unwrap();
+        let timing = network.timing().clone();
+        assert_eq!(timing.sections.iter().map(|section| (section.start, section.neurons)).collect::<Vec<_>>(), vec![(0, 5), (5, 5), (10, 5), (15, 5)]);
+        assert!(timing.total >= timing.data_loading + timing.aggregation);
+
+        network.train_synchronous(3, 10).unwrap();
+        assert_eq!(network.timing().sections.len(), 4);
+        network.train_matrix(300).unwrap();
+        assert_eq!(network.timing().sections.len(), 1);
+        assert_eq!(network.timing().queueing(), Duration::ZERO);
+    }
+
+    #[test]
+    fn same_seed_gives_same_weights_regardless_of_threads(){
+        let data = || MnistData::new(5, 5).unwrap();
+        let mut single = MtNetwork::with_data(5, 1, 20, 0.01, data(), Some(42)).unwrap();
+        let mut multi = MtNetwork::with_data(5, 4, 20, 0.01, data(), Some(42)).unwrap();
+        let mut other_seed = MtNetwork::with_data(5, 4, 20, 0.01, data(), Some(7)).unwrap();
+
+        let single_weights = single.train_complete_iterations(500).unwrap();
+        assert_eq!(single_weights, multi.train_complete_iterations(500).unwrap());
+        assert_ne!(single_weights, other_seed.train_complete_iterations(500).unwrap());
+    }
+
+    #[test]
+    fn shuffled_training_is_reproducible(){
+        let network = |threads, shuffle| {
+            let mut network = MtNetwork::with_data(5, threads, 20, 0.01, MnistData::new(5, 5).unwrap(), Some(42)).unwrap();
+            network.set_shuffle(shuffle);
+            network
+        };
+        let shuffled = network(1, true).train_complete_iterations(500).unwrap();
+        assert_eq!(shuffled, network(4, true).train_complete_iterations(500).unwrap());
+        assert_ne!(shuffled, network(1, false).train_complete_iterations(500).unwrap());
+    }
+
+    #[test]
+    fn double_precision_stays_close_to_single_precision(){
+        let network = |precision| NetworkBuilder::new().neurons(8).threads(2).lr(0.01).patch_width(5).seed(42).precision(precision).build().unwrap();
+        let single = network(Precision::Single).train_complete_iterations(500).unwrap();
+        let mut double = network(Precision::Double);
+        let wide = double.train_complete_iterations(500).unwrap();
+        assert_eq!(wide, network(Precision::Double).train_scoped(500).unwrap());
+        assert_ne!(single, wide);
+        for (a, b) in single.iter().flatten().zip(wide.iter().flatten()) {
+            assert!((a - b).abs() < 1e-3, "{} and {} differ", a, b);
+        }
+        assert!(matches!(double.train_matrix(500), Err(Error::InvalidConfig(_))));
+    }
+
+    #[test]
+    fn diverging_training_fails_and_keeps_the_weights(){
+        let mut network = MtNetwork::with_data(5, 2, 20, 50.0, MnistData::new(5, 5).unwrap(), Some(42)).unwrap();
+        let initial = network.weights().to_patches();
+        assert!(matches!(network.train_complete_iterations(1000), Err(Error::Diverged { .. })));
+        assert!(matches!(network.train_scoped(1000), Err(Error::Diverged { .. })));
+        assert!(matches!(network.train_synchronous(10, 100), Err(Error::Diverged { .. })));
+        assert!(matches!(network.train_matrix(1000), Err(Error::Diverged { .. })));
+        assert_eq!(network.weights(), &initial[..]);
+    }
+
+    #[test]
+    fn clipping_keeps_a_high_learning_rate_stable(){
+        let network = |clipping: Option<Clipping>| {
+            let mut network = MtNetwork::with_data(5, 2, 20, 50.0, MnistData::new(5, 5).unwrap(), Some(42)).unwrap();
+            network.set_clipping(clipping).unwrap();
+            network
+        };
+        assert!(matches!(network(None).train_complete_iterations(1000), Err(Error::Diverged { .. })));
+        let clipped = network(Some(Clipping::Norm(0.01))).train_complete_iterations(1000).unwrap();
+        assert!(clipped.iter().flatten().all(|w| w.is_finite()));
+        assert!(network(Some(Clipping::Value(0.001))).train_synchronous(10, 100).is_ok());
+        assert!(matches!(network(None).train_matrix(1000), Err(Error::Diverged { .. })));
+        assert!(network(Some(Clipping::Norm(0.01))).train_matrix(1000).unwrap().iter().flatten().all(|w| w.is_finite()));
+        assert!(network(Some(Clipping::Norm(0.01))).train_hierarchical(1000).unwrap().iter().flatten().all(|w| w.is_finite()));
+        assert!(matches!(NetworkBuilder::new().clipping(Clipping::Value(-1.0)).build(), Err(Error::InvalidConfig(_))));
+    }
+
+    #[test]
+    fn streamed_sections_add_up_to_the_trained_weights(){
+        let mut network = MtNetwork::with_data(3, 2, 10, 0.01, MnistData::new(3, 5).unwrap(), Some(4)).unwrap();
+        network.set_chunk_size(Some(3)).unwrap();
+        let mut stream = network.train_streaming(300);
+        let mut sections: Vec<TrainedSection> = stream.by_ref().collect();
+        let weights = stream.finish().unwrap();
+        sections.sort_by_key(|section| section.index);
+        assert_eq!(sections.iter().map(|section| section.start).collect::<Vec<_>>(), vec![0, 3, 6, 9]);
+        assert_eq!(sections.into_iter().flat_map(|section| section.weights).collect::<Vec<_>>(), weights);
+        assert_eq!(network.weights(), &weights[..]);
+
+        // A stream dropped after the first section still completes the run.
+        let mut expected = MtNetwork::with_data(3, 2, 10, 0.01, MnistData::new(3, 5).unwrap(), Some(4)).unwrap();
+        let mut streamed = MtNetwork::with_data(3, 2, 10, 0.01, MnistData::new(3, 5).unwrap(), Some(4)).unwrap();
+        assert!(streamed.train_streaming(300).next().is_some());
+        assert_eq!(streamed.weights(), &expected.train_complete_iterations(300).unwrap()[..]);
+    }
+
+    #[test]
+    fn neurons_keep_their_index_whatever_order_the_sections_finish_in(){
+        // Without a learning rate the weights never change, so every neuron must end up exactly where it started, however the
+        // chunks were spread over the workers and in whichever order they finished.
+        let initial: Vec<Patch> = (0..12).map(|neuron| vec![neuron as f32; 25]).collect();
+        let config = NetworkBuilder::new().threads(4).section_size(3).chunk_size(1).lr(0.0).seed(3).patch_width(5);
+        let mut network = MtNetwork::from_weights(initial.clone(), config).unwrap();
+        for _ in 0..5 {
+            assert_eq!(network.train_complete_iterations(20).unwrap(), initial);
+            assert_eq!(network.train_scoped(20).unwrap(), initial);
+            assert_eq!(network.train_synchronous(2, 5).unwrap(), initial);
+            assert_eq!(network.train_iteration(0).unwrap(), initial);
+            #[cfg(feature = "rayon")]
+            assert_eq!(network.train_parallel_rayon(20).unwrap(), initial);
+        }
+        assert_eq!(network.weights(), &initial[..]);
+    }
+
+    #[test]
+    fn training_resumes_from_the_given_weights(){
+        let mut network = MtNetwork::with_data(5, 2, 20, 0.01, MnistData::new(5, 5).unwrap(), Some(42)).unwrap();
+        let trained = network.train_complete_iterations(500).unwrap();
+        assert_eq!(network.weights(), &trained[..]);
+        assert_ne!(network.train_complete_iterations(500).unwrap(), trained, "a second run should continue training");
+
+        // Without a learning rate every neuron keeps the weights it resumed from.
+        let config = || NetworkBuilder::new().threads(2).section_size(5).lr(0.0).seed(7).patch_width(5);
+        let mut resumed = MtNetwork::from_weights(trained.clone(), config()).unwrap();
+        assert_eq!(resumed.train_complete_iterations(500).unwrap(), trained);
+        assert_eq!(resumed.train_scoped(500).unwrap(), trained);
+        assert_eq!(resumed.train_matrix(500).unwrap(), trained);
+
+        assert!(matches!(MtNetwork::from_weights(vec![vec![0.0; 3]], config()), Err(Error::Incompatible(_))));
+    }
+
+    #[test]
+    fn weight_decay_shrinks_the_weights(){
+        let norm = |weight_decay: f32| {
+            let mut network = MtNetwork::with_data(5, 2, 20, 0.01, MnistData::new(5, 5).unwrap(), Some(42)).unwrap();
+            network.set_weight_decay(weight_decay).unwrap();
+            let weights = network.train_complete_iterations(1000).unwrap();
+            weights.iter().map(|w| w.iter().map(|x| x * x).sum::<f32>().sqrt()).sum::<f32>() / weights.len() as f32
+        };
+        assert!(norm(0.001) < norm(0.0));
+        assert!(matches!(NetworkBuilder::new().weight_decay(1.5).build(), Err(Error::InvalidConfig(_))));
+    }
+
+    #[test]
+    fn adaptive_learning_rate_normalizes_the_first_step(){
+        let mut network = MtNetwork::with_data(2, 2, 4, 0.01, GaussianData::with_variances(&[1.0, 2.0, 3.0], 10).unwrap(), Some(4)).unwrap();
+        network.set_adaptive_lr(true);
+        let before = network.weights().to_patches();
+        let after = network.train_complete_iterations(2).unwrap();
+        let steps: Vec<f32> = after.iter().zip(before.iter())
+            .map(|(after, before)| after.iter().zip(before.iter()).map(|(a, b)| (a - b) * (a - b)).sum::<f32>().sqrt())
+            .collect();
+        assert!(steps.iter().all(|step| (step - 0.01).abs() < 1e-4), "first steps {:?}", steps);
+    }
+
+    #[test]
+    fn duplicate_neurons_are_pruned_or_reinitialized(){
+        let mut network = MtNetwork::with_data(2, 1, 4, 0.01, MnistData::new(2, 2).unwrap(), Some(3)).unwrap();
+        network.replace_weights(vec![vec![1.0, 0.0, 0.0, 0.0], vec![0.0, 1.0, 0.0, 0.0], vec![-1.0, 0.0, 0.0, 0.0], vec![0.0, 1.0, 0.0, 0.01]]).unwrap();
+
+        let reinitialized = network.reinitialize_duplicates(0.99);
+        assert_eq!(reinitialized.iter().map(|d| d.neuron).collect::<Vec<_>>(), vec![2, 3]);
+        assert_ne!(network.weights()[2], vec![-1.0, 0.0, 0.0, 0.0]);
+
+        network.replace_weights(vec![vec![1.0, 0.0, 0.0, 0.0], vec![0.0, 1.0, 0.0, 0.0], vec![-1.0, 0.0, 0.0, 0.0], vec![0.0, 0.0, 1.0, 0.0]]).unwrap();
+        assert_eq!(network.prune_duplicates(0.99).len(), 1);
+        assert_eq!(network.weights().len(), 3);
+        assert_eq!(network.converged_epochs().len(), 3);
+        assert_eq!(network.decode(&[1.0, 1.0, 1.0]).unwrap(), vec![1.0, 1.0, 1.0, 0.0]);
+    }
+
+    #[test]
+    fn encode_projects_onto_every_neuron(){
+        let mut network = MtNetwork::new(2, 1, 4, 0.01, 5, Some(6)).unwrap();
+        network.train_complete_iterations(100).unwrap();
+        let patch: Patch = (0..25).map(|i| i as f32 / 25.0).collect();
+
+        let activations = network.encode(&patch).unwrap();
+        assert_eq!(activations.len(), 4);
+        for (activation, weights) in activations.iter().zip(network.weights()) {
+            let expected: f32 = weights.iter().zip(patch.iter()).map(|(w, x)| w * x).sum();
+            assert!((activation - expected).abs() < 1e-5);
+        }
+        assert_eq!(network.encode_batch(&[patch.clone(), patch]).unwrap(), vec![activations.clone(), activations]);
+        assert!(matches!(network.encode(&[0.0; 24]), Err(Error::Incompatible(_))));
+    }
+
+    #[test]
+    fn decode_inverts_encode_within_the_learned_subspace(){
+        let mut network = MtNetwork::new(1, 1, 1, 0.01, 5, Some(2)).unwrap();
+        network.train_complete_iterations(100).unwrap();
+        let norm = network.weights()[0].iter().map(|w| w * w).sum::<f32>().sqrt();
+        let unit: Patch = network.weights()[0].iter().map(|w| w / norm).collect();
+
+        let scaled: Patch = unit.iter().map(|w| 3.0 * w).collect();
+        let decoded = network.decode(&network.encode(&scaled).unwrap()).unwrap();
+        for (d, s) in decoded.iter().zip(scaled.iter()) {
+            assert!((d / (norm * norm) - s).abs() < 1e-4);
+        }
+        assert_eq!(network.decode_batch(&[vec![0.0]]).unwrap(), vec![vec![0.0; 25]]);
+        assert!(matches!(network.decode(&[0.0, 0.0]), Err(Error::Incompatible(_))));
+    }
+
+    #[test]
+    fn cancelled_training_keeps_the_partial_weights(){
+        let mut network = MtNetwork::new(2, 2, 12, 0.01, 5, Some(5)).unwrap();
+        let initial = network.weights().to_patches();
+        let token = CancellationToken::new();
+        network.set_cancellation_token(Some(token.clone()));
+        network.set_chunk_size(Some(1)).unwrap();
+
+        // Cancelling from the progress callback keeps at least the first chunk, while the workers skip the remaining ones.
+        let cancel = token.clone();
+        network.set_progress_callback(Some(Arc::new(move |_| cancel.cancel())));
+        assert!(matches!(network.train_complete_iterations(200), Err(Error::Cancelled)));
+        let kept = network.weights().iter().zip(initial.iter()).filter(|(weights, initial)| weights == initial).count();
+        assert!(kept < 12);
+
+        let before = network.weights().to_patches();
+        assert!(matches!(network.train_synchronous(5, 10), Err(Error::Cancelled)));
+        assert!(matches!(network.train_complete_iterations(200), Err(Error::Cancelled)));
+        assert_eq!(network.weights(), &before[..]);
+    }
+
+    #[test]
+    fn missing_ranges_cover_the_untrained_neurons(){
+        let worker = WorkerTime { thread: thread::current().id(), busy: Duration::ZERO };
+        let result = |start: usize, neurons: usize| SectionResult { start, weights: Vec::new(), converged_at: vec![None; neurons], snapshots: Vec::new(), worker, queued: Duration::ZERO };
+        assert_eq!(missing_ranges(&[result(3, 3), result(9, 1)], 12, 2), vec![0..2, 2..3, 6..8, 8..9, 10..12]);
+        assert!(missing_ranges(&[result(0, 4), result(4, 4)], 8, 4).is_empty());
+    }
+
+    #[test]
+    fn progress_is_reported_for_every_chunk(){
+        let mut network = MtNetwork::new(5, 2, 20, 0.01, 5, Some(3)).unwrap();
+        let (callback, receiver) = progress_channel();
+        network.set_progress_callback(Some(callback));
+        network.set_chunk_size(Some(4)).unwrap();
+        network.train_complete_iterations(100).unwrap();
+
+        let events: Vec<Progress> = receiver.try_iter().collect();
+        assert_eq!(events.iter().map(|event| event.completed).collect::<Vec<_>>(), vec![1, 2, 3, 4, 5]);
+        assert!(events.iter().all(|event| event.total == 5));
+    }
+
+    #[test]
+    fn progress_reports_the_learning_rate_used_in_training(){
+        let schedule = crate::model::schedule::ExponentialDecay { gamma: 0.9 };
+        let mut network = NetworkBuilder::new().neurons(4).threads(2).section_size(2).lr(0.1).seed(3).schedule(schedule)
+            .dataset(GaussianData::with_variances(&[1.0, 2.0, 3.0], 10).unwrap()).build().unwrap();
+        let (callback, receiver) = progress_channel();
+        network.set_progress_callback(Some(callback));
+
+        // Every synchronous epoch trains on 5 patches with the rate of its first one.
+        network.train_synchronous(3, 5).unwrap();
+        let rates: Vec<f32> = receiver.try_iter().map(|event| event.lr.unwrap()).collect();
+        assert_eq!(rates, (0..3).map(|epoch| schedule.lr(0.1, epoch * 5)).collect::<Vec<_>>());
+
+        // Hierarchical training counts every patch as an epoch.
+        network.train_hierarchical(3).unwrap();
+        let rates: Vec<f32> = receiver.try_iter().map(|event| event.lr.unwrap()).collect();
+        assert_eq!(rates, (0..3).map(|patch| schedule.lr(0.1, patch)).collect::<Vec<_>>());
+    }
+
+    // Records the events of a run, and stops it after `stop_after` epochs.
+    #[derive(Default)]
+    struct Recorder {
+        events: Mutex<Vec<String>>,
+        stop_after: Option<usize>,
+    }
+
+    impl TrainingHooks for Recorder {
+        fn on_epoch_start(&self, epoch: usize) {
+            self.events.lock().unwrap().push(format!("start {}", epoch));
+        }
+
+        fn on_section_done(&self, section: &TrainedSection) {
+            self.events.lock().unwrap().push(format!("section {}", section.start));
+        }
+
+        fn on_epoch_end(&self, epochs: usize, _weights: &Weights) -> ControlFlow<()> {
+            self.events.lock().unwrap().push(format!("end {}", epochs));
+            if self.stop_after == Some(epochs) { ControlFlow::Break(()) } else { ControlFlow::Continue(()) }
+        }
+
+        fn on_complete(&self, weights: &Weights, history: &TrainingHistory) {
+            self.events.lock().unwrap().push(format!("complete {} {}", weights.len(), history.records().len()));
+        }
+    }
+
+    #[test]
+    fn hooks_see_every_epoch_and_can_stop_the_run(){
+        let recorder = Arc::new(Recorder { stop_after: Some(2), ..Recorder::default() });
+        let mut network = NetworkBuilder::new().neurons(4).threads(2).section_size(2).chunk_size(2).seed(3)
+            .dataset(GaussianData::with_variances(&[1.0, 2.0, 3.0], 10).unwrap()).hooks(recorder.clone()).build().unwrap();
+        network.train_synchronous(5, 10).unwrap();
+
+        let mut events = recorder.events.lock().unwrap().split_off(0);
+        // The sections of an epoch can finish in any order.
+        events[1..3].sort();
+        events[5..7].sort();
+        assert_eq!(events, vec!["start 0", "section 0", "section 2", "end 1", "start 1", "section 0", "section 2", "end 2", "complete 4 1"]);
+        assert_eq!(network.history().last().unwrap().epoch, 20);
+    }
+
+    #[test]
+    fn matrix_training_is_a_single_epoch_for_the_hooks(){
+        let recorder = Arc::new(Recorder { stop_after: Some(1), ..Recorder::default() });
+        let mut network = NetworkBuilder::new().neurons(2).threads(1).section_size(2).batch_size(4).seed(3)
+            .dataset(GaussianData::with_variances(&[1.0, 2.0, 3.0], 10).unwrap()).hooks(recorder.clone()).build().unwrap();
+        network.train_matrix(50).unwrap();
+
+        assert_eq!(*recorder.events.lock().unwrap(), vec!["start 0", "section 0", "end 1", "complete 2 1"]);
+    }
+
+    #[test]
+    fn hooks_see_the_sections_of_a_single_epoch(){
+        let recorder = Arc::new(Recorder { stop_after: Some(1), ..Recorder::default() });
+        let mut network = MtNetwork::with_data(2, 2, 4, 0.01, GaussianData::with_variances(&[1.0, 2.0, 3.0], 10).unwrap(), Some(3)).unwrap();
+        network.set_chunk_size(Some(1)).unwrap();
+        network.set_hooks(Some(recorder.clone()));
+        let streamed: Vec<TrainedSection> = network.train_streaming(50).collect();
+
+        let events = recorder.events.lock().unwrap().clone();
+        assert_eq!(events.first().map(String::as_str), Some("start 0"));
+        assert_eq!(events[1..5].iter().filter(|event| event.starts_with("section")).count(), streamed.len());
+        assert_eq!(&events[5..], &["end 1", "complete 4 1"]);
+    }
+
+    #[cfg(feature = "rayon")]
+    #[test]
+    fn rayon_backend_matches_thread_pool(){
+        let mut pool = MtNetwork::new(5, 3, 15, 0.01, 5, Some(11)).unwrap();
+        let mut rayon = MtNetwork::new(4, 3, 15, 0.01, 5, Some(11)).unwrap();
+
+        assert_eq!(pool.train_complete_iterations(300).unwrap(), rayon.train_parallel_rayon(300).unwrap());
+    }
+
+    #[test]
+    fn evaluation_records_metrics_at_every_interval(){
+        let mut network = MtNetwork::new(5, 2, 10, 0.01, 5, Some(1)).unwrap();
+        let evaluation = Evaluation::held_out(network.dataset(), 50, 100, &mut StdRng::seed_from_u64(2));
+        network.set_evaluation(Some(evaluation)).unwrap();
+        network.train_complete_iterations(450).unwrap();
+
+        let epochs: Vec<usize> = network.history().records().iter().map(|record| record.epoch).collect();
+        assert_eq!(epochs, vec![100, 200, 300, 400, 450]);
+        assert!(network.history().records().iter().all(|record| record.reconstruction_error.is_some_and(f32::is_finite)));
+    }
+
+    #[test]
+    fn history_is_recorded_without_evaluation(){
+        let mut network = MtNetwork::new(5, 2, 10, 0.01, 5, Some(1)).unwrap();
+        network.train_complete_iterations(250).unwrap();
+        assert_eq!(network.history().records().len(), 1);
+
+        network.set_history_interval(Some(100)).unwrap();
+        network.train_complete_iterations(250).unwrap();
+        let records = network.history().records();
+        assert_eq!(records.iter().map(|record| record.epoch).collect::<Vec<_>>(), vec![100, 200, 250]);
+        assert!(records.iter().all(|record| record.reconstruction_error.is_none() && record.weight_delta > 0.0));
+        assert!(records.windows(2).all(|pair| pair[0].elapsed <= pair[1].elapsed));
+
+        network.train_hierarchical(250).unwrap();
+        assert_eq!(network.history().records().iter().map(|record| record.epoch).collect::<Vec<_>>(), vec![100, 200, 250]);
+    }
+}
@@ -0,0 +1,176 @@
+//! Saving and loading of weights in NumPy's `.npy` format, so the learned components can be analyzed with `numpy.load`.
+//! The weights are stored as a `neurons x patch_size` matrix of little endian `f32` values. Loading also accepts `f64` matrices
+//! and Fortran ordered ones, as written by NumPy by default.
+use std::fs::File;
+use std::io::{self, BufReader, BufWriter, Read, Write};
+use std::path::Path;
+use crate::data::patch::Patch;
+
+const MAGIC: &[u8; 6] = b"\x93NUMPY";
+
+/// Writes the weights to a `.npy` file, one row per neuron.
+pub fn save_npy<W: AsRef<[f32]>, P: AsRef<Path>>(weights: &[W], path: P) -> io::Result<()> {
+    let mut writer = BufWriter::new(File::create(path)?);
+    write_npy(weights, &mut writer)?;
+    writer.flush()
+}
+
+/// Reads the weights from a two dimensional `.npy` file, one row per neuron.
+pub fn load_npy<P: AsRef<Path>>(path: P) -> io::Result<Vec<Patch>> {
+    read_npy(&mut BufReader::new(File::open(path)?))
+}
+
+pub fn write_npy<P: AsRef<[f32]>, W: Write>(weights: &[P], writer: &mut W) -> io::Result<()> {
+    let patch_size = weights.first().map_or(0, |w| w.as_ref().len());
+    if weights.iter().any(|w| w.as_ref().len() != patch_size) {
+        return Err(io::Error::new(io::ErrorKind::InvalidInput, "all weight vectors must have the same length"));
+    }
+
+    // The header is padded with spaces so the data starts at a multiple of 64 bytes, and ends with a newline.
+    let mut header = format!("{{'descr': '<f4', 'fortran_order': False, 'shape': ({}, {}), }}", weights.len(), patch_size);
+    let unpadded = MAGIC.len() + 4 + header.len() + 1;
+    header.push_str(&" ".repeat((64 - unpadded % 64) % 64));
+    header.push('\n');
+
+    writer.write_all(MAGIC)?;
+    writer.write_all(&[1, 0])?;
+    writer.write_all(&(header.len() as u16).to_le_bytes())?;
+    writer.write_all(header.as_bytes())?;
+    for weight in weights.iter() {
+        for value in weight.as_ref().iter() {
+            writer.write_all(&value.to_le_bytes())?;
+        }
+    }
+    Ok(())
+}
+
+pub fn read_npy<R: Read>(reader: &mut R) -> io::Result<Vec<Patch>> {
+    let mut magic = [0u8; 8];
+    reader.read_exact(&mut magic)?;
+    if &magic[..6] != MAGIC {
+        return Err(invalid_data("not a .npy file"));
+    }
+    let header_len = match magic[6] {
+        1 => {
+            let mut buffer = [0u8; 2];
+            reader.read_exact(&mut buffer)?;
+            u16::from_le_bytes(buffer) as usize
+        }
+        2 | 3 => {
+            let mut buffer = [0u8; 4];
+            reader.read_exact(&mut buffer)?;
+            u32::from_le_bytes(buffer) as usize
+        }
+        version => return Err(invalid_data(&format!("unsupported .npy version {}", version))),
+    };
+    let header = read_bytes(reader, header_len)?;
+    let header = String::from_utf8(header).map_err(|_| invalid_data("header is not valid text"))?;
+
+    let double = match value(&header, "descr") {
+        Some("'<f4'") => false,
+        Some("'<f8'") => true,
+        descr => return Err(invalid_data(&format!("unsupported data type {}, expected '<f4' or '<f8'", descr.unwrap_or("none")))),
+    };
+    let fortran_order = value(&header, "fortran_order") == Some("True");
+    let (rows, columns) = shape(&header).ok_or_else(|| invalid_data("expected a two dimensional array"))?;
+
+    let width = if double { 8 } else { 4 };
+    let len = rows.checked_mul(columns).and_then(|values| values.checked_mul(width))
+        .ok_or_else(|| invalid_data(&format!("an array of {}x{} values overflows the address space", rows, columns)))?;
+    let bytes = read_bytes(reader, len)?;
+    let values: Vec<f32> = if double {
+        bytes.chunks_exact(8).map(|b| f64::from_le_bytes([b[0], b[1], b[2], b[3], b[4], b[5], b[6], b[7]]) as f32).collect()
+    } else {
+        bytes.chunks_exact(4).map(|b| f32::from_le_bytes([b[0], b[1], b[2], b[3]])).collect()
+    };
+
+    Ok((0..rows).map(|row| {
+        if fortran_order {
+            (0..columns).map(|column| values[column * rows + row]).collect()
+        } else {
+            values[row * columns..(row + 1) * columns].to_vec()
+        }
+    }).collect())
+}
+
+// Reads the `len` bytes the header announces, growing the buffer only with the bytes actually read, so a corrupt header
+// fails as a truncated file instead of allocating gigabytes first.
+fn read_bytes<R: Read>(reader: &mut R, len: usize) -> io::Result<Vec<u8>> {
+    let mut bytes = Vec::new();
+    reader.take(len as u64).read_to_end(&mut bytes)?;
+    if bytes.len() != len {
+        return Err(io::Error::new(io::ErrorKind::UnexpectedEof, format!("file is truncated, expected {} bytes but found {}", len, bytes.len())));
+    }
+    Ok(bytes)
+}
+
+// The text following a key of the header dictionary, up to the next comma.
+fn value<'a>(header: &'a str, key: &str) -> Option<&'a str> {
+    let start = header.find(&format!("'{}':", key))? + key.len() + 3;
+    header[start..].split(',').next().map(str::trim)
+}
+
+fn shape(header: &str) -> Option<(usize, usize)> {
+    let start = header.find("'shape':")? + "'shape':".len();
+    let tuple = header[start..].trim_start().strip_prefix('(')?;
+    let dims: Vec<&str> = tuple[..tuple.find(')')?].split(',').map(str::trim).filter(|dim| !dim.is_empty()).collect();
+    match dims.as_slice() {
+        [rows, columns] => Some((rows.parse().ok()?, columns.parse().ok()?)),
+        _ => None,
+    }
+}
+
+fn invalid_data(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message.to_string())
+}
+
+#[cfg(test)]
+mod test{
+    use super::*;
+
+    #[test]
+    fn npy_round_trip(){
+        let weights: Vec<Patch> = vec![(0..9).map(|i| i as f32 * 0.25 - 1.0).collect(), vec![2.0; 9]];
+        let mut bytes = Vec::new();
+        write_npy(&weights, &mut bytes).unwrap();
+
+        let header_len = u16::from_le_bytes([bytes[8], bytes[9]]) as usize;
+        assert_eq!((10 + header_len) % 64, 0);
+        assert_eq!(bytes.len(), 10 + header_len + 4 * 18);
+        assert_eq!(read_npy(&mut bytes.as_slice()).unwrap(), weights);
+    }
+
+    #[test]
+    fn reads_fortran_ordered_doubles(){
+        let header = "{'descr': '<f8', 'fortran_order': True, 'shape': (2, 3), }\n";
+        let mut bytes = MAGIC.to_vec();
+        bytes.extend_from_slice(&[1, 0]);
+        bytes.extend_from_slice(&(header.len() as u16).to_le_bytes());
+        bytes.extend_from_slice(header.as_bytes());
+        for value in [1.0f64, 4.0, 2.0, 5.0, 3.0, 6.0].iter() {
+            bytes.extend_from_slice(&value.to_le_bytes());
+        }
+        assert_eq!(read_npy(&mut bytes.as_slice()).unwrap(), vec![vec![1.0, 2.0, 3.0], vec![4.0, 5.0, 6.0]]);
+    }
+
+    #[test]
+    fn rejects_other_arrays(){
+        let header = "{'descr': '<i4', 'fortran_order': False, 'shape': (4,), }\n";
+        let mut bytes = MAGIC.to_vec();
+        bytes.extend_from_slice(&[1, 0]);
+        bytes.extend_from_slice(&(header.len() as u16).to_le_bytes());
+        bytes.extend_from_slice(header.as_bytes());
+        let error = read_npy(&mut bytes.as_slice()).unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+
+        for (shape, kind) in [("(18446744073709551615, 2)", io::ErrorKind::InvalidData), ("(4294967295, 2)", io::ErrorKind::UnexpectedEof)].iter() {
+            let header = format!("{{'descr': '<f4', 'fortran_order': False, 'shape': {}, }}\n", shape);
+            let mut bytes = MAGIC.to_vec();
+            bytes.extend_from_slice(&[1, 0]);
+            bytes.extend_from_slice(&(header.len() as u16).to_le_bytes());
+            bytes.extend_from_slice(header.as_bytes());
+            bytes.extend_from_slice(&[0; 16]);
+            assert_eq!(read_npy(&mut bytes.as_slice()).unwrap_err().kind(), *kind);
+        }
+    }
+}
@@ -1,19 +1,122 @@
-use crate::utils::constants::PATCH_SIZE;
-///
+use crate::model::float::Float;
+use crate::model::simd::dot_scalar;
 
-pub fn oja_learning_rule(patch_x: &[f32; PATCH_SIZE], weights: &mut [f32; PATCH_SIZE], lr: f32){
+/// Updates the weights of a single neuron for one patch using Oja's rule. `patch_x` and `weights` must have the same length.
+/// The update `w += lr * y * (x - y * w)` is computed as `w = (1 - lr * y^2) * w + lr * y * x` with the vectorized kernels.
+pub fn oja_learning_rule<F: Float>(patch_x: &[F], weights: &mut [F], lr: F){
     let y = oja_y(patch_x, weights);
+    F::scale_add(weights, F::ONE - lr * y * y, patch_x, lr * y);
+}
+
+/// Scalar version of `oja_learning_rule`, kept as a reference for the vectorized one.
+pub fn oja_learning_rule_scalar(patch_x: &[f32], weights: &mut [f32], lr: f32){
+    let y = dot_scalar(patch_x, weights);
 
-    for j in 0..(PATCH_SIZE - 1) {
-        let temp_w: f32 = patch_x[j] - y*weights[j];
-        weights[j] = weights[j] + lr*y*temp_w;
+    for (w, x) in weights.iter_mut().zip(patch_x.iter()) {
+        let temp_w: f32 = x - y * *w;
+        *w += lr * y * temp_w;
     }
 }
 
-pub fn oja_y(patch_x: &[f32; PATCH_SIZE], weights: &[f32; PATCH_SIZE]) -> f32{
-    let mut y = 0.0 as f32;
-    for i in 0..PATCH_SIZE {
-        y += weights[i] * patch_x[i];
+/// Updates the weights of a single neuron with the average Oja update over a mini-batch of patches.
+/// Every output is computed with the weights from before the batch, so the order of the patches within the batch does not matter.
+pub fn oja_batch_learning_rule<F: Float>(patches: &[Vec<F>], weights: &mut [F], lr: F){
+    if patches.is_empty() {
+        return;
     }
-    return y;
-}
\ No newline at end of file
+    let mut delta = vec![F::ZERO; weights.len()];
+    for patch_x in patches {
+        let y = oja_y(patch_x, weights);
+        for ((d, &w), &x) in delta.iter_mut().zip(weights.iter()).zip(patch_x.iter()) {
+            *d += y * (x - y * w);
+        }
+    }
+
+    let scale = lr / F::from_usize(patches.len());
+    for (w, &d) in weights.iter_mut().zip(delta.iter()) {
+        *w += scale * d;
+    }
+}
+
+/// Oja's rule with heavy-ball momentum: the averaged update of the batch is accumulated into `velocity` as
+/// `v = beta * v + y * (x - y * w)` and the weights move by `lr * v`, which smooths the noise of single patches.
+/// An empty `velocity` is started at zero.
+pub fn oja_momentum_learning_rule<F: Float, P: AsRef<[F]>>(patches: &[P], weights: &mut [F], velocity: &mut Vec<F>, lr: F, beta: F){
+    if patches.is_empty() {
+        return;
+    }
+    if velocity.len() != weights.len() {
+        velocity.clear();
+        velocity.resize(weights.len(), F::ZERO);
+    }
+    let scale = F::ONE / F::from_usize(patches.len());
+    velocity.iter_mut().for_each(|v| *v *= beta);
+    for patch_x in patches {
+        let patch_x = patch_x.as_ref();
+        let y = oja_y(patch_x, weights);
+        for ((v, &w), &x) in velocity.iter_mut().zip(weights.iter()).zip(patch_x.iter()) {
+            *v += scale * y * (x - y * w);
+        }
+    }
+    for (w, &v) in weights.iter_mut().zip(velocity.iter()) {
+        *w += lr * v;
+    }
+}
+
+pub fn oja_y<F: Float>(patch_x: &[F], weights: &[F]) -> F{
+    F::dot(weights, patch_x)
+}
+
+#[cfg(test)]
+mod test{
+    use super::*;
+
+    #[test]
+    fn vectorized_update_matches_scalar_update(){
+        let patch: Vec<f32> = (0..25).map(|i| (i as f32 * 0.3).sin()).collect();
+        let mut vectorized: Vec<f32> = (0..25).map(|i| (i as f32 * 0.7).cos() * 0.2).collect();
+        let mut scalar = vectorized.clone();
+
+        for _ in 0..100 {
+            oja_learning_rule(&patch, &mut vectorized, 0.01);
+            oja_learning_rule_scalar(&patch, &mut scalar, 0.01);
+        }
+        for (v, s) in vectorized.iter().zip(scalar.iter()) {
+            assert!((v - s).abs() < 1e-4);
+        }
+    }
+
+    #[test]
+    fn momentum_accumulates_the_updates(){
+        let patch = vec![0.2, -0.4, 0.9];
+        let mut plain = vec![0.5, 0.1, -0.3];
+        let mut momentum = plain.clone();
+        let mut velocity = Vec::new();
+
+        // Without any history the first step is a plain Oja update.
+        oja_learning_rule(&patch, &mut plain, 0.1);
+        oja_momentum_learning_rule(&[&patch], &mut momentum, &mut velocity, 0.1, 0.9);
+        for (a, b) in plain.iter().zip(momentum.iter()) {
+            assert!((a - b).abs() < 1e-6);
+        }
+        let first = velocity.clone();
+        oja_momentum_learning_rule(&[&patch], &mut momentum, &mut velocity, 0.1, 0.9);
+        let y: f32 = patch.iter().zip(plain.iter()).map(|(x, w)| x * w).sum();
+        for ((v, f), (x, w)) in velocity.iter().zip(first.iter()).zip(patch.iter().zip(plain.iter())) {
+            assert!((v - (0.9 * f + y * (x - y * w))).abs() < 1e-6);
+        }
+    }
+
+    #[test]
+    fn batch_of_one_matches_single_update(){
+        let patch = vec![0.2, -0.4, 0.9];
+        let mut single = vec![0.5, 0.1, -0.3];
+        let mut batched = single.clone();
+
+        oja_learning_rule(&patch, &mut single, 0.1);
+        oja_batch_learning_rule(&[patch], &mut batched, 0.1);
+        for (a, b) in single.iter().zip(batched.iter()) {
+            assert!((a - b).abs() < 1e-6);
+        }
+    }
+}
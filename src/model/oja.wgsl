@@ -0,0 +1,37 @@
+// Oja's rule for every neuron of a network in parallel, one invocation per neuron.
+// Each invocation applies the patches of the batch in order, so a neuron follows the same trajectory as on the CPU.
+
+struct Params {
+    neurons: u32,
+    dim: u32,
+    patches: u32,
+    padding: u32,
+}
+
+@group(0) @binding(0) var<storage, read_write> weights: array<f32>;
+@group(0) @binding(1) var<storage, read> patches: array<f32>;
+@group(0) @binding(2) var<storage, read> lrs: array<f32>;
+@group(0) @binding(3) var<uniform> params: Params;
+
+@compute @workgroup_size(64)
+fn main(@builtin(global_invocation_id) id: vec3<u32>) {
+    let neuron = id.x;
+    if (neuron >= params.neurons) {
+        return;
+    }
+    let offset = neuron * params.dim;
+
+    for (var p = 0u; p < params.patches; p = p + 1u) {
+        let patch = p * params.dim;
+        var y = 0.0;
+        for (var i = 0u; i < params.dim; i = i + 1u) {
+            y = y + weights[offset + i] * patches[patch + i];
+        }
+
+        let lr = lrs[p];
+        for (var i = 0u; i < params.dim; i = i + 1u) {
+            let w = weights[offset + i];
+            weights[offset + i] = w + lr * y * (patches[patch + i] - y * w);
+        }
+    }
+}
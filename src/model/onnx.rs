@@ -0,0 +1,194 @@
+//! Export of the learned weights as an ONNX model, so the feature extractor can be used by other toolchains.
+//! The model is a single `MatMul` node projecting a batch of flattened patches `[batch, patch_size]` onto the weight vectors,
+//! giving the outputs of every neuron `[batch, neurons]`. The protobuf encoding is written by hand, as the graph only needs a
+//! handful of fields.
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::path::Path;
+use crate::data::patch::Patch;
+
+const IR_VERSION: u64 = 8;
+const OPSET_VERSION: u64 = 13;
+const FLOAT: u64 = 1;
+
+// Protobuf wire types.
+const VARINT: u64 = 0;
+const LENGTH_DELIMITED: u64 = 2;
+
+/// Name of the graph input holding the flattened patches.
+pub const INPUT: &str = "patches";
+/// Name of the graph output holding the outputs of the neurons.
+pub const OUTPUT: &str = "features";
+
+/// Writes the weights as an ONNX model to a file.
+pub fn save_onnx<P: AsRef<Path>>(weights: &[Patch], path: P) -> io::Result<()> {
+    let mut writer = BufWriter::new(File::create(path)?);
+    write_onnx(weights, &mut writer)?;
+    writer.flush()
+}
+
+/// Writes the weights as an ONNX model. Every weight vector must have the same length.
+pub fn write_onnx<W: Write>(weights: &[Patch], writer: &mut W) -> io::Result<()> {
+    let patch_size = weights.first().map_or(0, |w| w.len());
+    if weights.iter().any(|w| w.len() != patch_size) {
+        return Err(io::Error::new(io::ErrorKind::InvalidInput, "all weight vectors must have the same length"));
+    }
+    writer.write_all(&model(weights, patch_size))
+}
+
+fn model(weights: &[Patch], patch_size: usize) -> Vec<u8> {
+    let mut opset = Vec::new();
+    string(&mut opset, 1, "");
+    int(&mut opset, 2, OPSET_VERSION);
+
+    let mut model = Vec::new();
+    int(&mut model, 1, IR_VERSION);
+    string(&mut model, 2, env!("CARGO_PKG_NAME"));
+    string(&mut model, 3, env!("CARGO_PKG_VERSION"));
+    message(&mut model, 7, &graph(weights, patch_size));
+    message(&mut model, 8, &opset);
+    model
+}
+
+fn graph(weights: &[Patch], patch_size: usize) -> Vec<u8> {
+    let mut node = Vec::new();
+    string(&mut node, 1, INPUT);
+    string(&mut node, 1, "weights");
+    string(&mut node, 2, OUTPUT);
+    string(&mut node, 3, "projection");
+    string(&mut node, 4, "MatMul");
+
+    let mut graph = Vec::new();
+    message(&mut graph, 1, &node);
+    string(&mut graph, 2, "receptive_fields");
+    message(&mut graph, 5, &initializer(weights, patch_size));
+    message(&mut graph, 11, &value_info(INPUT, patch_size));
+    message(&mut graph, 12, &value_info(OUTPUT, weights.len()));
+    graph
+}
+
+// The weights transposed to `[patch_size, neurons]`, so the patches can be multiplied from the left.
+fn initializer(weights: &[Patch], patch_size: usize) -> Vec<u8> {
+    let mut raw = Vec::with_capacity(4 * weights.len() * patch_size);
+    for pixel in 0..patch_size {
+        for weight in weights.iter() {
+            raw.extend_from_slice(&weight[pixel].to_le_bytes());
+        }
+    }
+
+    let mut tensor = Vec::new();
+    int(&mut tensor, 1, patch_size as u64);
+    int(&mut tensor, 1, weights.len() as u64);
+    int(&mut tensor, 2, FLOAT);
+    string(&mut tensor, 8, "weights");
+    message(&mut tensor, 9, &raw);
+    tensor
+}
+
+// A float matrix with a dynamic batch dimension and the given number of columns.
+fn value_info(name: &str, columns: usize) -> Vec<u8> {
+    let mut batch = Vec::new();
+    string(&mut batch, 2, "batch");
+    let mut width = Vec::new();
+    int(&mut width, 1, columns as u64);
+
+    let mut shape = Vec::new();
+    message(&mut shape, 1, &batch);
+    message(&mut shape, 1, &width);
+
+    let mut tensor = Vec::new();
+    int(&mut tensor, 1, FLOAT);
+    message(&mut tensor, 2, &shape);
+
+    let mut kind = Vec::new();
+    message(&mut kind, 1, &tensor);
+
+    let mut info = Vec::new();
+    string(&mut info, 1, name);
+    message(&mut info, 2, &kind);
+    info
+}
+
+fn varint(buffer: &mut Vec<u8>, mut value: u64) {
+    while value >= 0x80 {
+        buffer.push((value as u8 & 0x7f) | 0x80);
+        value >>= 7;
+    }
+    buffer.push(value as u8);
+}
+
+fn int(buffer: &mut Vec<u8>, field: u64, value: u64) {
+    varint(buffer, field << 3 | VARINT);
+    varint(buffer, value);
+}
+
+fn message(buffer: &mut Vec<u8>, field: u64, bytes: &[u8]) {
+    varint(buffer, field << 3 | LENGTH_DELIMITED);
+    varint(buffer, bytes.len() as u64);
+    buffer.extend_from_slice(bytes);
+}
+
+fn string(buffer: &mut Vec<u8>, field: u64, value: &str) {
+    message(buffer, field, value.as_bytes());
+}
+
+#[cfg(test)]
+mod test{
+    use super::*;
+
+    // Splits a protobuf message into its fields, as (field number, varint value or bytes).
+    fn fields(mut bytes: &[u8]) -> Vec<(u64, u64, Vec<u8>)> {
+        fn read(bytes: &mut &[u8]) -> u64 {
+            let mut value = 0;
+            for shift in (0..).step_by(7) {
+                let byte = bytes[0];
+                *bytes = &bytes[1..];
+                value |= ((byte & 0x7f) as u64) << shift;
+                if byte < 0x80 {
+                    break;
+                }
+            }
+            value
+        }
+        let mut result = Vec::new();
+        while !bytes.is_empty() {
+            let key = read(&mut bytes);
+            let value = read(&mut bytes);
+            if key & 7 == LENGTH_DELIMITED {
+                result.push((key >> 3, 0, bytes[..value as usize].to_vec()));
+                bytes = &bytes[value as usize..];
+            } else {
+                result.push((key >> 3, value, Vec::new()));
+            }
+        }
+        result
+    }
+
+    fn field(bytes: &[u8], number: u64) -> Vec<u8> {
+        fields(bytes).into_iter().find(|(n, _, _)| *n == number).unwrap().2
+    }
+
+    #[test]
+    fn exports_transposed_weights_as_matmul(){
+        let weights: Vec<Patch> = vec![vec![1.0, 2.0, 3.0], vec![4.0, 5.0, 6.0]];
+        let mut bytes = Vec::new();
+        write_onnx(&weights, &mut bytes).unwrap();
+
+        assert_eq!(fields(&bytes)[0], (1, IR_VERSION, Vec::new()));
+        let graph = field(&bytes, 7);
+        assert_eq!(field(&field(&graph, 1), 4), b"MatMul");
+
+        let tensor = fields(&field(&graph, 5));
+        let dims: Vec<u64> = tensor.iter().filter(|(n, _, _)| *n == 1).map(|(_, v, _)| *v).collect();
+        assert_eq!(dims, vec![3, 2]);
+        let raw = &tensor.iter().find(|(n, _, _)| *n == 9).unwrap().2;
+        let values: Vec<f32> = raw.chunks(4).map(|b| f32::from_le_bytes([b[0], b[1], b[2], b[3]])).collect();
+        assert_eq!(values, vec![1.0, 4.0, 2.0, 5.0, 3.0, 6.0]);
+    }
+
+    #[test]
+    fn rejects_ragged_weights(){
+        let error = write_onnx(&[vec![1.0], vec![1.0, 2.0]], &mut Vec::new()).unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidInput);
+    }
+}
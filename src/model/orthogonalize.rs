@@ -0,0 +1,135 @@
+//! Orthogonalization of the weight vectors between training steps, so that neurons trained with the basic Oja rule, which on
+//! their own all converge to the first principal component, span several components instead.
+use crate::data::patch::Patch;
+use crate::error::{Error, Result};
+use crate::utils::linalg::symmetric_eigen;
+
+/// How the weight vectors are made orthonormal.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OrthogonalizationMethod {
+    /// Every vector has its projections onto the vectors before it removed. The first neuron keeps its direction, so the
+    /// neurons end up ordered like the components found by Sanger's rule.
+    GramSchmidt,
+    /// The whole set is replaced by `(W W^T)^(-1/2) W`, the orthonormal set closest to the current weights, which treats all
+    /// neurons alike.
+    Symmetric,
+}
+
+/// Orthogonalizes the weights every `interval` training patches.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Orthogonalization {
+    pub method: OrthogonalizationMethod,
+    pub interval: usize,
+}
+
+impl Orthogonalization {
+    pub fn new(method: OrthogonalizationMethod, interval: usize) -> Orthogonalization {
+        assert!(interval > 0, "the orthogonalization interval must be at least one patch");
+        Orthogonalization { method, interval }
+    }
+
+    // At most as many vectors as they have dimensions can be orthogonal.
+    pub(crate) fn validate(&self, neurons: usize, patch_size: usize) -> Result<()> {
+        if neurons > patch_size {
+            return Err(Error::InvalidConfig(format!("{} neurons cannot be orthogonal in {} dimensions", neurons, patch_size)));
+        }
+        Ok(())
+    }
+
+    pub fn apply<P: AsRef<[f32]> + AsMut<[f32]>>(&self, weights: &mut [P]) {
+        match self.method {
+            OrthogonalizationMethod::GramSchmidt => gram_schmidt(weights),
+            OrthogonalizationMethod::Symmetric => symmetric_orthogonalization(weights),
+        }
+    }
+}
+
+/// Makes the vectors orthonormal in order. A vector which lies in the span of the ones before it is left unchanged.
+pub fn gram_schmidt<P: AsRef<[f32]> + AsMut<[f32]>>(weights: &mut [P]) {
+    let mut basis: Vec<Vec<f64>> = Vec::with_capacity(weights.len());
+    for w in weights.iter_mut() {
+        let mut v: Vec<f64> = w.as_ref().iter().map(|&x| x as f64).collect();
+        for b in basis.iter() {
+            let projection: f64 = v.iter().zip(b.iter()).map(|(x, y)| x * y).sum();
+            v.iter_mut().zip(b.iter()).for_each(|(x, y)| *x -= projection * y);
+        }
+        let norm = v.iter().map(|x| x * x).sum::<f64>().sqrt();
+        if norm < 1e-9 {
+            continue;
+        }
+        v.iter_mut().for_each(|x| *x /= norm);
+        w.as_mut().iter_mut().zip(v.iter()).for_each(|(w, x)| *w = *x as f32);
+        basis.push(v);
+    }
+}
+
+/// Replaces the vectors with `(W W^T)^(-1/2) W`. Directions in which the vectors are linearly dependent are dropped.
+pub fn symmetric_orthogonalization<P: AsRef<[f32]> + AsMut<[f32]>>(weights: &mut [P]) {
+    let gram: Vec<Vec<f64>> = weights.iter()
+        .map(|a| weights.iter().map(|b| a.as_ref().iter().zip(b.as_ref().iter()).map(|(x, y)| *x as f64 * *y as f64).sum()).collect())
+        .collect();
+    let (values, vectors) = symmetric_eigen(gram);
+
+    // (W W^T)^(-1/2) = E diag(1 / sqrt(values)) E^T, with the eigenvectors as the columns of E.
+    let n = weights.len();
+    let mut inverse_root = vec![vec![0.0f64; n]; n];
+    for (k, value) in values.iter().enumerate() {
+        if *value < 1e-12 {
+            continue;
+        }
+        let scale = 1.0 / value.sqrt();
+        for i in 0..n {
+            for j in 0..n {
+                inverse_root[i][j] += scale * vectors[i][k] * vectors[j][k];
+            }
+        }
+    }
+
+    let original: Vec<Patch> = weights.iter().map(|w| w.as_ref().to_vec()).collect();
+    for (w, row) in weights.iter_mut().zip(inverse_root.iter()) {
+        for (d, value) in w.as_mut().iter_mut().enumerate() {
+            *value = row.iter().zip(original.iter()).map(|(r, o)| r * o[d] as f64).sum::<f64>() as f32;
+        }
+    }
+}
+
+#[cfg(test)]
+mod test{
+    use super::*;
+
+    fn assert_orthonormal(weights: &[Patch]) {
+        for (i, a) in weights.iter().enumerate() {
+            for (j, b) in weights.iter().enumerate() {
+                let dot: f32 = a.iter().zip(b.iter()).map(|(x, y)| x * y).sum();
+                assert!((dot - if i == j { 1.0 } else { 0.0 }).abs() < 1e-5, "{} {} {}", i, j, dot);
+            }
+        }
+    }
+
+    #[test]
+    fn gram_schmidt_keeps_the_first_direction(){
+        let mut weights: Vec<Patch> = vec![vec![2.0, 0.0, 0.0], vec![1.0, 1.0, 0.0], vec![1.0, 1.0, 1.0]];
+        gram_schmidt(&mut weights);
+        assert_eq!(weights, vec![vec![1.0, 0.0, 0.0], vec![0.0, 1.0, 0.0], vec![0.0, 0.0, 1.0]]);
+    }
+
+    #[test]
+    fn symmetric_orthogonalization_gives_an_orthonormal_set(){
+        let mut weights: Vec<Patch> = vec![vec![1.0, 0.2, 0.1, 0.0], vec![0.3, 1.0, 0.0, 0.5], vec![0.0, 0.4, 2.0, 0.1]];
+        symmetric_orthogonalization(&mut weights);
+        assert_orthonormal(&weights);
+
+        // Vectors which are orthogonal already only get normalized.
+        let mut orthogonal: Vec<Patch> = vec![vec![3.0, 0.0], vec![0.0, 0.5]];
+        symmetric_orthogonalization(&mut orthogonal);
+        assert_orthonormal(&orthogonal);
+        assert!((orthogonal[0][0] - 1.0).abs() < 1e-6 && (orthogonal[1][1] - 1.0).abs() < 1e-6);
+    }
+
+    #[test]
+    fn more_neurons_than_dimensions_are_rejected(){
+        let orthogonalization = Orthogonalization::new(OrthogonalizationMethod::GramSchmidt, 1);
+        assert!(orthogonalization.validate(4, 3).is_err());
+        assert!(orthogonalization.validate(3, 3).is_ok());
+    }
+}
@@ -0,0 +1,50 @@
+//! Progress reporting for long training runs, so command line tools and GUIs can render progress bars.
+use std::sync::Arc;
+use std::sync::mpsc::{self, Receiver};
+use std::thread::ThreadId;
+use std::time::Duration;
+
+/// Progress of a training run. For the multithreaded training methods the work is counted in sections of neurons,
+/// for hierarchical training in epochs, reported every percent of the run.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Progress {
+    pub completed: usize,
+    pub total: usize,
+    pub elapsed: Duration,
+    /// The worker thread which completed the unit of work, when it was trained on one.
+    pub worker: Option<WorkerTime>,
+    /// Learning rate of the last completed epoch, when the work is counted in epochs shared by every neuron.
+    pub lr: Option<f32>,
+    /// Latest reconstruction error on the held-out patches, when evaluation is enabled and the history is recorded during training.
+    pub reconstruction_error: Option<f32>,
+}
+
+/// A worker thread and how long it was busy with a unit of work.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct WorkerTime {
+    pub thread: ThreadId,
+    pub busy: Duration,
+}
+
+impl Progress {
+    pub fn new(completed: usize, total: usize, elapsed: Duration) -> Progress {
+        Progress { completed, total, elapsed, worker: None, lr: None, reconstruction_error: None }
+    }
+
+    pub fn fraction(&self) -> f32 {
+        if self.total == 0 { 1.0 } else { self.completed as f32 / self.total as f32 }
+    }
+}
+
+/// Called whenever a unit of work completes. Can be called from any thread.
+pub type ProgressCallback = Arc<dyn Fn(Progress) + Send + Sync>;
+
+/// Creates a callback which forwards every event to the returned receiver, for consuming progress on another thread.
+pub fn progress_channel() -> (ProgressCallback, Receiver<Progress>) {
+    let (sender, receiver) = mpsc::channel();
+    let callback: ProgressCallback = Arc::new(move |progress| {
+        // The receiver may have been dropped when nobody is interested in the progress anymore.
+        let _ = sender.send(progress);
+    });
+    (callback, receiver)
+}
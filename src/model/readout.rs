@@ -0,0 +1,299 @@
+//! Supervised readout on top of the learned features, turning the network into the first layer of a two layer classifier.
+//! Every image is cut into non-overlapping patches, each patch is projected onto the weight vectors, and a softmax classifier
+//! trained with stochastic gradient descent maps the standardized projections of all patches to the labels. The accuracy
+//! of the readout shows how useful the unsupervised features are, e.g. for classifying MNIST digits.
+use std::fmt;
+use std::io;
+use std::path::Path;
+use rand::Rng;
+use rand::seq::SliceRandom;
+use crate::data::idx::{read_idx_images, read_idx_labels, IdxImages};
+use crate::data::patch::{patch_width, Patch};
+use crate::error::{Error, Result};
+use crate::model::eval::project;
+
+/// Whole images with their labels, as needed to train and test the readout.
+#[derive(Debug, Clone)]
+pub struct LabelledImages {
+    images: IdxImages,
+    labels: Vec<u8>,
+}
+
+impl LabelledImages {
+    pub fn new(images: IdxImages, labels: Vec<u8>) -> io::Result<LabelledImages> {
+        if images.count != labels.len() {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, format!("{} images but {} labels", images.count, labels.len())));
+        }
+        Ok(LabelledImages { images, labels })
+    }
+
+    /// Loads an image and label file pair in the IDX format.
+    pub fn from_files<P: AsRef<Path>>(images: P, labels: P) -> io::Result<LabelledImages> {
+        LabelledImages::new(read_idx_images(images)?, read_idx_labels(labels)?)
+    }
+
+    /// Loads the MNIST training set, or the test set, from a directory holding the original file names.
+    pub fn mnist<P: AsRef<Path>>(dir: P, test: bool) -> io::Result<LabelledImages> {
+        let prefix = if test { "t10k" } else { "train" };
+        let dir = dir.as_ref();
+        LabelledImages::from_files(dir.join(format!("{}-images-idx3-ubyte", prefix)), dir.join(format!("{}-labels-idx1-ubyte", prefix)))
+    }
+
+    pub fn len(&self) -> usize {
+        self.labels.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.labels.is_empty()
+    }
+
+    /// Keeps only the first `count` images.
+    pub fn truncate(&mut self, count: usize) {
+        let count = count.min(self.len());
+        self.images.pixels.truncate(count * self.images.rows * self.images.columns);
+        self.images.count = count;
+        self.labels.truncate(count);
+    }
+
+    pub fn labels(&self) -> &[u8] {
+        &self.labels
+    }
+
+    /// Number of classes, assuming the labels count up from zero.
+    pub fn classes(&self) -> usize {
+        self.labels.iter().max().map_or(0, |label| *label as usize + 1)
+    }
+
+    /// The pixels of an image scaled to `[0, 1)`, like the patches sampled for training.
+    pub fn image(&self, index: usize) -> Vec<f32> {
+        let size = self.images.rows * self.images.columns;
+        self.images.pixels[index * size..][..size].iter().map(|pixel| *pixel as f32 / 256.0).collect()
+    }
+}
+
+/// Projections of all non-overlapping square patches of an image onto the weight vectors, patch after patch in row order.
+/// Pixels at the right and bottom edge which do not fill a whole patch are ignored.
+pub fn image_features(image: &[f32], columns: usize, weights: &[Patch], patch_width: usize) -> Vec<f32> {
+    let rows = image.len() / columns;
+    let mut features = Vec::with_capacity((rows / patch_width) * (columns / patch_width) * weights.len());
+    let mut patch = vec![0.0; patch_width * patch_width];
+    for top in (0..=rows - patch_width).step_by(patch_width) {
+        for left in (0..=columns - patch_width).step_by(patch_width) {
+            for (row, values) in patch.chunks_mut(patch_width).enumerate() {
+                values.copy_from_slice(&image[(top + row) * columns + left..][..patch_width]);
+            }
+            features.extend(project(&patch, weights));
+        }
+    }
+    features
+}
+
+/// Multinomial logistic regression, with one weight vector and bias per class.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SoftmaxClassifier {
+    weights: Vec<Vec<f32>>,
+    biases: Vec<f32>,
+}
+
+impl SoftmaxClassifier {
+    pub fn new(features: usize, classes: usize) -> SoftmaxClassifier {
+        SoftmaxClassifier { weights: vec![vec![0.0; features]; classes], biases: vec![0.0; classes] }
+    }
+
+    pub fn classes(&self) -> usize {
+        self.biases.len()
+    }
+
+    pub fn probabilities(&self, features: &[f32]) -> Vec<f32> {
+        let logits: Vec<f32> = self.weights.iter().zip(self.biases.iter())
+            .map(|(weights, bias)| bias + weights.iter().zip(features.iter()).map(|(w, x)| w * x).sum::<f32>())
+            .collect();
+        let max = logits.iter().cloned().fold(f32::NEG_INFINITY, f32::max);
+        let exponentials: Vec<f32> = logits.iter().map(|logit| (logit - max).exp()).collect();
+        let total: f32 = exponentials.iter().sum();
+        exponentials.iter().map(|e| e / total).collect()
+    }
+
+    pub fn predict(&self, features: &[f32]) -> usize {
+        let probabilities = self.probabilities(features);
+        (0..probabilities.len()).fold(0, |best, class| if probabilities[class] > probabilities[best] { class } else { best })
+    }
+
+    // One gradient step on the cross-entropy of a single example. Returns its loss before the step.
+    pub fn step(&mut self, features: &[f32], label: usize, lr: f32) -> f32 {
+        let probabilities = self.probabilities(features);
+        for (class, (weights, bias)) in self.weights.iter_mut().zip(self.biases.iter_mut()).enumerate() {
+            let error = probabilities[class] - if class == label { 1.0 } else { 0.0 };
+            weights.iter_mut().zip(features.iter()).for_each(|(w, x)| *w -= lr * error * x);
+            *bias -= lr * error;
+        }
+        -probabilities[label].max(f32::MIN_POSITIVE).ln()
+    }
+
+    // Trains on the examples in a random order, returning the mean loss of the epoch.
+    pub fn train_epoch<R: Rng>(&mut self, features: &[Vec<f32>], labels: &[u8], lr: f32, rng: &mut R) -> f32 {
+        let mut order: Vec<usize> = (0..features.len()).collect();
+        order.shuffle(rng);
+        let loss: f32 = order.iter().map(|&index| self.step(&features[index], labels[index] as usize, lr)).sum();
+        loss / features.len().max(1) as f32
+    }
+
+    pub fn accuracy(&self, features: &[Vec<f32>], labels: &[u8]) -> f32 {
+        let correct = features.iter().zip(labels.iter()).filter(|(features, label)| self.predict(features) == **label as usize).count();
+        correct as f32 / features.len().max(1) as f32
+    }
+}
+
+/// The learned weights as a fixed first layer, followed by a trained softmax classifier.
+#[derive(Debug, Clone)]
+pub struct Readout {
+    weights: Vec<Patch>,
+    patch_width: usize,
+    columns: usize,
+    mean: Vec<f32>,
+    std: Vec<f32>,
+    classifier: SoftmaxClassifier,
+}
+
+impl Readout {
+    // Trains the classifier on the features of the training images for the given number of epochs.
+    // Fails when the weights are not square patches fitting the images.
+    pub fn fit<R: Rng>(weights: &[Patch], train: &LabelledImages, epochs: usize, lr: f32, rng: &mut R) -> Result<Readout> {
+        let patch_width = weights.first().and_then(|w| patch_width(w.len()))
+            .ok_or_else(|| Error::Incompatible("the readout needs weights of square patches".to_string()))?;
+        let columns = train.images.columns;
+        if patch_width > columns || patch_width > train.images.rows {
+            return Err(Error::Incompatible(format!("patches of width {} do not fit {}x{} images", patch_width, train.images.rows, columns)));
+        }
+        if train.is_empty() {
+            return Err(Error::InvalidConfig("at least one training image is needed for the readout".to_string()));
+        }
+
+        let raw: Vec<Vec<f32>> = (0..train.len()).map(|index| image_features(&train.image(index), columns, weights, patch_width)).collect();
+        let (mean, std) = moments(&raw);
+        let mut readout = Readout {
+            weights: weights.to_vec(),
+            patch_width,
+            columns,
+            classifier: SoftmaxClassifier::new(mean.len(), train.classes()),
+            mean,
+            std,
+        };
+        let features: Vec<Vec<f32>> = raw.into_iter().map(|features| readout.standardize(features)).collect();
+        for _ in 0..epochs {
+            readout.classifier.train_epoch(&features, train.labels(), lr, rng);
+        }
+        Ok(readout)
+    }
+
+    fn standardize(&self, mut features: Vec<f32>) -> Vec<f32> {
+        for ((x, mean), std) in features.iter_mut().zip(self.mean.iter()).zip(self.std.iter()) {
+            *x = (*x - mean) / std;
+        }
+        features
+    }
+
+    pub fn features(&self, image: &[f32]) -> Vec<f32> {
+        self.standardize(image_features(image, self.columns, &self.weights, self.patch_width))
+    }
+
+    pub fn predict(&self, image: &[f32]) -> usize {
+        self.classifier.predict(&self.features(image))
+    }
+
+    /// Fraction of the images whose label is predicted correctly.
+    pub fn accuracy(&self, images: &LabelledImages) -> f32 {
+        let correct = (0..images.len()).filter(|&index| self.predict(&images.image(index)) == images.labels[index] as usize).count();
+        correct as f32 / images.len().max(1) as f32
+    }
+
+    pub fn classifier(&self) -> &SoftmaxClassifier {
+        &self.classifier
+    }
+}
+
+// Mean and standard deviation of every feature, with the deviation of constant features set to one.
+fn moments(features: &[Vec<f32>]) -> (Vec<f32>, Vec<f32>) {
+    let count = features.len() as f32;
+    let dim = features.first().map_or(0, |f| f.len());
+    let mut mean = vec![0.0; dim];
+    features.iter().for_each(|f| mean.iter_mut().zip(f.iter()).for_each(|(m, x)| *m += x / count));
+    let mut variance = vec![0.0; dim];
+    features.iter().for_each(|f| variance.iter_mut().zip(f.iter().zip(mean.iter())).for_each(|(v, (x, m))| *v += (x - m) * (x - m) / count));
+    let std = variance.iter().map(|v| if *v > 1e-12 { v.sqrt() } else { 1.0 }).collect();
+    (mean, std)
+}
+
+/// Accuracy of the readout on the images it was trained on and on held-out test images.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ReadoutReport {
+    pub features: usize,
+    pub classes: usize,
+    pub train_accuracy: f32,
+    pub test_accuracy: f32,
+}
+
+impl fmt::Display for ReadoutReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "Readout on {} features, {} classes", self.features, self.classes)?;
+        writeln!(f, "Training accuracy: {:.2}%", 100.0 * self.train_accuracy)?;
+        write!(f, "Test accuracy: {:.2}%", 100.0 * self.test_accuracy)
+    }
+}
+
+/// Trains a readout on the training images and reports its accuracy on both sets.
+pub fn evaluate_readout<R: Rng>(weights: &[Patch], train: &LabelledImages, test: &LabelledImages, epochs: usize, lr: f32, rng: &mut R) -> Result<ReadoutReport> {
+    let readout = Readout::fit(weights, train, epochs, lr, rng)?;
+    Ok(ReadoutReport {
+        features: readout.mean.len(),
+        classes: readout.classifier.classes(),
+        train_accuracy: readout.accuracy(train),
+        test_accuracy: readout.accuracy(test),
+    })
+}
+
+#[cfg(test)]
+mod test{
+    use super::*;
+    use rand::SeedableRng;
+    use rand::rngs::StdRng;
+
+    // 4x4 images of four classes, each lighting up a different 2x2 quadrant with some noise.
+    fn quadrants(count: usize, rng: &mut StdRng) -> LabelledImages {
+        let mut pixels = Vec::new();
+        let mut labels = Vec::new();
+        for index in 0..count {
+            let label = index % 4;
+            let (top, left) = (2 * (label / 2), 2 * (label % 2));
+            for row in 0..4 {
+                for column in 0..4 {
+                    let lit = (top..top + 2).contains(&row) && (left..left + 2).contains(&column);
+                    pixels.push(if lit { 200 } else { 0 } + rng.gen_range(0..40usize) as u8);
+                }
+            }
+            labels.push(label as u8);
+        }
+        LabelledImages::new(IdxImages { count, rows: 4, columns: 4, pixels }, labels).unwrap()
+    }
+
+    #[test]
+    fn features_project_every_patch(){
+        let image: Vec<f32> = (0..16).map(|x| x as f32).collect();
+        let weights: Vec<Patch> = vec![vec![1.0, 0.0, 0.0, 0.0], vec![0.25; 4]];
+        assert_eq!(image_features(&image, 4, &weights, 2), vec![0.0, 2.5, 2.0, 4.5, 8.0, 10.5, 10.0, 12.5]);
+        assert_eq!(image_features(&image[..12], 4, &weights, 2).len(), 4);
+    }
+
+    #[test]
+    fn readout_classifies_separable_images(){
+        let mut rng = StdRng::seed_from_u64(4);
+        let train = quadrants(200, &mut rng);
+        let test = quadrants(40, &mut rng);
+        let weights: Vec<Patch> = vec![vec![0.5; 4]];
+        let report = evaluate_readout(&weights, &train, &test, 5, 0.1, &mut rng).unwrap();
+        assert_eq!((report.features, report.classes), (4, 4));
+        assert_eq!(report.test_accuracy, 1.0);
+
+        assert!(Readout::fit(&[vec![1.0; 3]], &train, 1, 0.1, &mut rng).is_err());
+    }
+}
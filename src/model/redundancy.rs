@@ -0,0 +1,84 @@
+//! Detection of neurons which learned (nearly) the same component, by the cosine similarity between their weight vectors.
+//! Components are only defined up to their sign, so vectors pointing in opposite directions count as duplicates as well.
+use crate::data::patch::Patch;
+use crate::model::simd::dot;
+
+/// A neuron whose weights point in the same direction as those of an earlier neuron.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Duplicate {
+    pub neuron: usize,
+    /// The earliest neuron the duplicate is similar to, which is itself not a duplicate.
+    pub original: usize,
+    /// Absolute cosine similarity between the two weight vectors.
+    pub similarity: f32,
+}
+
+/// Cosine similarity between two vectors, 0 when either of them is zero.
+pub fn cosine_similarity(a: &[f32], b: &[f32]) -> f32 {
+    let norms = (dot(a, a) * dot(b, b)).sqrt();
+    if norms > 0.0 { dot(a, b) / norms } else { 0.0 }
+}
+
+/// Pairwise cosine similarities between the weight vectors, a symmetric matrix with ones on the diagonal for non-zero vectors.
+pub fn similarity_matrix(weights: &[Patch]) -> Vec<Vec<f32>> {
+    let mut matrix = vec![vec![0.0; weights.len()]; weights.len()];
+    for i in 0..weights.len() {
+        for j in i..weights.len() {
+            let similarity = cosine_similarity(&weights[i], &weights[j]);
+            matrix[i][j] = similarity;
+            matrix[j][i] = similarity;
+        }
+    }
+    matrix
+}
+
+/// Finds the neurons whose absolute cosine similarity to an earlier neuron is at least `threshold`, ordered by neuron.
+/// Every group of similar neurons keeps its first neuron as the original, so removing the duplicates keeps one of each.
+pub fn find_duplicates(weights: &[Patch], threshold: f32) -> Vec<Duplicate> {
+    let mut originals: Vec<usize> = Vec::new();
+    let mut duplicates = Vec::new();
+    for (neuron, w) in weights.iter().enumerate() {
+        let similar = originals.iter()
+            .map(|&original| (original, cosine_similarity(&weights[original], w).abs()))
+            .find(|(_, similarity)| *similarity >= threshold);
+        match similar {
+            Some((original, similarity)) => duplicates.push(Duplicate { neuron, original, similarity }),
+            None => originals.push(neuron),
+        }
+    }
+    duplicates
+}
+
+/// The weights without the given duplicates.
+pub fn prune(weights: &[Patch], duplicates: &[Duplicate]) -> Vec<Patch> {
+    weights.iter().enumerate()
+        .filter(|(neuron, _)| !duplicates.iter().any(|duplicate| duplicate.neuron == *neuron))
+        .map(|(_, w)| w.clone())
+        .collect()
+}
+
+#[cfg(test)]
+mod test{
+    use super::*;
+
+    #[test]
+    fn finds_parallel_and_antiparallel_duplicates(){
+        let weights: Vec<Patch> = vec![vec![1.0, 0.0], vec![0.0, 1.0], vec![-2.0, 0.01], vec![0.0, 0.5], vec![1.0, 1.0]];
+        let duplicates = find_duplicates(&weights, 0.99);
+        assert_eq!(duplicates.iter().map(|d| (d.neuron, d.original)).collect::<Vec<_>>(), vec![(2, 0), (3, 1)]);
+        assert!(duplicates[0].similarity > 0.99 && duplicates[1].similarity == 1.0);
+
+        assert_eq!(prune(&weights, &duplicates), vec![vec![1.0, 0.0], vec![0.0, 1.0], vec![1.0, 1.0]]);
+        assert!(find_duplicates(&weights, 1.1).is_empty());
+    }
+
+    #[test]
+    fn similarity_matrix_is_symmetric(){
+        let weights: Vec<Patch> = vec![vec![3.0, 4.0], vec![4.0, 3.0], vec![0.0, 0.0]];
+        let matrix = similarity_matrix(&weights);
+        assert!((matrix[0][1] - 0.96).abs() < 1e-6);
+        assert_eq!(matrix[0][1], matrix[1][0]);
+        assert!((matrix[0][0] - 1.0).abs() < 1e-6);
+        assert_eq!(matrix[2], vec![0.0; 3]);
+    }
+}
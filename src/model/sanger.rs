@@ -0,0 +1,51 @@
+//! Sanger's rule, also known as the Generalized Hebbian Algorithm.
+//! Unlike Oja's rule, which drives every neuron towards the first principal component, Sanger's rule trains the neurons
+//! hierarchically so that neuron `i` converges to the `i`-th principal component of the input.
+use crate::model::float::Float;
+use crate::model::oja::oja_y;
+
+/// Updates every neuron in `weights`, which holds the weight vectors of the neurons one after the other, for a single patch.
+/// Each neuron only subtracts the reconstruction made by itself and the neurons before it, so the order of `weights` matters.
+pub fn sanger_learning_rule<F: Float>(patch_x: &[F], weights: &mut [F], lr: F){
+    let y: Vec<F> = weights.chunks_exact(patch_x.len()).map(|w| oja_y(patch_x, w)).collect();
+    let mut reconstruction = vec![F::ZERO; patch_x.len()];
+
+    for (w, &y) in weights.chunks_exact_mut(patch_x.len()).zip(y.iter()) {
+        for ((w, r), &x) in w.iter_mut().zip(reconstruction.iter_mut()).zip(patch_x.iter()) {
+            *r += y * *w;
+            *w += lr * y * (x - *r);
+        }
+    }
+}
+
+#[cfg(test)]
+mod test{
+    use super::*;
+    use crate::model::weights::Weights;
+    use rand::Rng;
+    use crate::utils::constants::PATCH_SIZE;
+
+    fn norm(w: &[f32]) -> f32 {
+        w.iter().map(|x| x * x).sum::<f32>().sqrt()
+    }
+
+    #[test]
+    fn sanger_extracts_distinct_components(){
+        let mut rng = rand::thread_rng();
+        let initial: Vec<f32> = (0..2 * PATCH_SIZE).map(|_| rng.gen_range(-0.1..0.1)).collect();
+        let mut weights = Weights::from_flat(initial, PATCH_SIZE);
+
+        // Variance is largest along the first axis and second largest along the second axis.
+        for _ in 0..20000 {
+            let mut patch = vec![0.0f32; PATCH_SIZE];
+            patch[0] = rng.gen_range(-2.0..2.0);
+            patch[1] = rng.gen_range(-1.0..1.0);
+            sanger_learning_rule(&patch, weights.as_mut_slice(), 0.01);
+        }
+
+        assert!((weights[0][0].abs() - 1.0).abs() < 0.1);
+        assert!((weights[1][1].abs() - 1.0).abs() < 0.1);
+        assert!((norm(&weights[0]) - 1.0).abs() < 0.1);
+        assert!((norm(&weights[1]) - 1.0).abs() < 0.1);
+    }
+}
@@ -0,0 +1,85 @@
+//! Learning rate schedules, letting the learning rate anneal over the course of training.
+//! An epoch here is a single update of a neuron, which is the unit the training loops count in.
+use std::f32::consts::PI;
+
+/// Computes the learning rate for a given epoch from the base learning rate of the network.
+pub trait LrSchedule: Send + Sync {
+    fn lr(&self, base_lr: f32, epoch: usize) -> f32;
+}
+
+/// Keeps the learning rate fixed for the whole run.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct Constant;
+
+impl LrSchedule for Constant {
+    fn lr(&self, base_lr: f32, _epoch: usize) -> f32 {
+        base_lr
+    }
+}
+
+/// Multiplies the learning rate by `gamma` every epoch.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ExponentialDecay {
+    pub gamma: f32,
+}
+
+impl LrSchedule for ExponentialDecay {
+    fn lr(&self, base_lr: f32, epoch: usize) -> f32 {
+        base_lr * self.gamma.powf(epoch as f32)
+    }
+}
+
+/// Multiplies the learning rate by `gamma` every `step_size` epochs.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct StepDecay {
+    pub step_size: usize,
+    pub gamma: f32,
+}
+
+impl LrSchedule for StepDecay {
+    fn lr(&self, base_lr: f32, epoch: usize) -> f32 {
+        base_lr * self.gamma.powi((epoch / self.step_size.max(1)) as i32)
+    }
+}
+
+/// Anneals the learning rate from the base rate down to `min_lr` along half a cosine over `total_epochs`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct CosineAnnealing {
+    pub total_epochs: usize,
+    pub min_lr: f32,
+}
+
+impl LrSchedule for CosineAnnealing {
+    fn lr(&self, base_lr: f32, epoch: usize) -> f32 {
+        let progress = (epoch as f32 / self.total_epochs.max(1) as f32).min(1.0);
+        self.min_lr + 0.5 * (base_lr - self.min_lr) * (1.0 + (PI * progress).cos())
+    }
+}
+
+#[cfg(test)]
+mod test{
+    use super::*;
+
+    fn assert_close(a: f32, b: f32) {
+        assert!((a - b).abs() < 1e-6, "{} != {}", a, b);
+    }
+
+    #[test]
+    fn schedules_anneal_learning_rate(){
+        assert_close(Constant.lr(0.1, 1000), 0.1);
+
+        assert_close(ExponentialDecay { gamma: 0.5 }.lr(0.1, 0), 0.1);
+        assert_close(ExponentialDecay { gamma: 0.5 }.lr(0.1, 2), 0.025);
+
+        let step = StepDecay { step_size: 10, gamma: 0.1 };
+        assert_close(step.lr(1.0, 9), 1.0);
+        assert_close(step.lr(1.0, 10), 0.1);
+        assert_close(step.lr(1.0, 25), 0.01);
+
+        let cosine = CosineAnnealing { total_epochs: 100, min_lr: 0.01 };
+        assert_close(cosine.lr(0.1, 0), 0.1);
+        assert_close(cosine.lr(0.1, 50), 0.055);
+        assert_close(cosine.lr(0.1, 100), 0.01);
+        assert_close(cosine.lr(0.1, 200), 0.01);
+    }
+}
@@ -0,0 +1,149 @@
+//! Vectorized kernels for the inner loops of the learning rules: a dot product and a scaled vector addition.
+//! On x86_64 processors supporting AVX2 and FMA the kernels use 256 bit intrinsics, detected once at runtime.
+//! Everywhere else they fall back to processing chunks of 8 floats with independent accumulators, which the compiler can vectorize.
+//! The scalar versions are kept for comparison, see the `simd_benchmark` binary.
+
+/// Number of floats processed at once by the chunked kernels, matching an f32x8 register.
+const LANES: usize = 8;
+
+/// Whether the kernels run on the AVX2/FMA implementation on this machine.
+pub fn simd_enabled() -> bool {
+    #[cfg(target_arch = "x86_64")]
+    {
+        is_x86_feature_detected!("avx2") && is_x86_feature_detected!("fma")
+    }
+    #[cfg(not(target_arch = "x86_64"))]
+    {
+        false
+    }
+}
+
+/// Dot product of two vectors, ignoring the trailing values of the longer one.
+pub fn dot(a: &[f32], b: &[f32]) -> f32 {
+    let len = a.len().min(b.len());
+    let (a, b) = (&a[..len], &b[..len]);
+
+    #[cfg(target_arch = "x86_64")]
+    {
+        if simd_enabled() {
+            // Safe since the required target features were detected above.
+            return unsafe { avx::dot(a, b) };
+        }
+    }
+    dot_chunked(a, b)
+}
+
+/// Computes `w = alpha * w + beta * x` in place.
+pub fn scale_add(w: &mut [f32], alpha: f32, x: &[f32], beta: f32) {
+    let len = w.len().min(x.len());
+    let (w, x) = (&mut w[..len], &x[..len]);
+
+    #[cfg(target_arch = "x86_64")]
+    {
+        if simd_enabled() {
+            // Safe since the required target features were detected above.
+            unsafe { avx::scale_add(w, alpha, x, beta) };
+            return;
+        }
+    }
+    scale_add_chunked(w, alpha, x, beta)
+}
+
+pub fn dot_scalar(a: &[f32], b: &[f32]) -> f32 {
+    a.iter().zip(b.iter()).map(|(a, b)| a * b).sum()
+}
+
+pub fn scale_add_scalar(w: &mut [f32], alpha: f32, x: &[f32], beta: f32) {
+    for (w, x) in w.iter_mut().zip(x.iter()) {
+        *w = alpha * *w + beta * x;
+    }
+}
+
+fn dot_chunked(a: &[f32], b: &[f32]) -> f32 {
+    let mut acc = [0.0f32; LANES];
+    let a_chunks = a.chunks_exact(LANES);
+    let b_chunks = b.chunks_exact(LANES);
+    let tail = dot_scalar(a_chunks.remainder(), b_chunks.remainder());
+
+    for (a, b) in a_chunks.zip(b_chunks) {
+        for ((acc, a), b) in acc.iter_mut().zip(a.iter()).zip(b.iter()) {
+            *acc += a * b;
+        }
+    }
+    acc.iter().sum::<f32>() + tail
+}
+
+fn scale_add_chunked(w: &mut [f32], alpha: f32, x: &[f32], beta: f32) {
+    let mut w_chunks = w.chunks_exact_mut(LANES);
+    let mut x_chunks = x.chunks_exact(LANES);
+
+    for (w, x) in (&mut w_chunks).zip(&mut x_chunks) {
+        scale_add_scalar(w, alpha, x, beta);
+    }
+    scale_add_scalar(w_chunks.into_remainder(), alpha, x_chunks.remainder(), beta);
+}
+
+#[cfg(target_arch = "x86_64")]
+mod avx {
+    use std::arch::x86_64::*;
+    use super::{dot_scalar, scale_add_scalar, LANES};
+
+    /// # Safety
+    /// The processor must support AVX2 and FMA, and both slices must have the same length.
+    #[target_feature(enable = "avx2,fma")]
+    pub unsafe fn dot(a: &[f32], b: &[f32]) -> f32 {
+        let chunks = a.len() / LANES;
+        let mut acc = _mm256_setzero_ps();
+        for chunk in 0..chunks {
+            let va = _mm256_loadu_ps(a.as_ptr().add(chunk * LANES));
+            let vb = _mm256_loadu_ps(b.as_ptr().add(chunk * LANES));
+            acc = _mm256_fmadd_ps(va, vb, acc);
+        }
+
+        let mut lanes = [0.0f32; LANES];
+        _mm256_storeu_ps(lanes.as_mut_ptr(), acc);
+        lanes.iter().sum::<f32>() + dot_scalar(&a[chunks * LANES..], &b[chunks * LANES..])
+    }
+
+    /// # Safety
+    /// The processor must support AVX2 and FMA, and both slices must have the same length.
+    #[target_feature(enable = "avx2,fma")]
+    pub unsafe fn scale_add(w: &mut [f32], alpha: f32, x: &[f32], beta: f32) {
+        let chunks = w.len() / LANES;
+        let valpha = _mm256_set1_ps(alpha);
+        let vbeta = _mm256_set1_ps(beta);
+        for chunk in 0..chunks {
+            let pw = w.as_mut_ptr().add(chunk * LANES);
+            let vx = _mm256_loadu_ps(x.as_ptr().add(chunk * LANES));
+            let scaled = _mm256_mul_ps(valpha, _mm256_loadu_ps(pw));
+            _mm256_storeu_ps(pw, _mm256_fmadd_ps(vbeta, vx, scaled));
+        }
+        scale_add_scalar(&mut w[chunks * LANES..], alpha, &x[chunks * LANES..], beta);
+    }
+}
+
+#[cfg(test)]
+mod test{
+    use super::*;
+
+    #[test]
+    fn kernels_match_the_scalar_versions(){
+        for len in [0, 3, 8, 25, 61] {
+            let a: Vec<f32> = (0..len).map(|i| (i as f32 * 0.37).sin()).collect();
+            let b: Vec<f32> = (0..len).map(|i| (i as f32 * 0.11).cos()).collect();
+            assert!((dot(&a, &b) - dot_scalar(&a, &b)).abs() < 1e-4);
+            assert!((dot_chunked(&a, &b) - dot_scalar(&a, &b)).abs() < 1e-4);
+
+            let mut expected = a.clone();
+            scale_add_scalar(&mut expected, 0.9, &b, 0.2);
+            let mut vectorized = a.clone();
+            scale_add(&mut vectorized, 0.9, &b, 0.2);
+            let mut chunked = a.clone();
+            scale_add_chunked(&mut chunked, 0.9, &b, 0.2);
+            for ((v, c), e) in vectorized.iter().zip(chunked.iter()).zip(expected.iter()) {
+                assert!((v - e).abs() < 1e-5);
+                assert!((c - e).abs() < 1e-5);
+            }
+        }
+    }
+}
@@ -0,0 +1,249 @@
+//! Kohonen self-organizing map trained on the same patches as the Hebbian networks, so topological maps can be compared
+//! against the components learned with Oja's rule.
+//! The map is trained in batches: the thread pool finds the best matching node of every patch in a batch in parallel, after which
+//! every node moves towards the patches matched by itself and its neighbours on the grid. The neighbourhood shrinks during training,
+//! so the map first orders itself globally and then fine-tunes locally.
+use std::path::Path;
+use std::sync::Arc;
+use std::time::Instant;
+use rand::SeedableRng;
+use rand::rngs::StdRng;
+use tracing::{debug, info, info_span};
+use crate::data::dataset::Dataset;
+use crate::data::mnist::MnistData;
+use crate::data::patch::{Patch, patch_width};
+use crate::error::{Error, Result};
+use crate::model::init::Init;
+use crate::model::schedule::{Constant, LrSchedule};
+use crate::threading::thread_pool::ThreadPool;
+use crate::utils::linalg::{nearest, squared_distance};
+use crate::visualization::receptive_fields::save_grid;
+
+/// Neighbourhood radius the map shrinks to by the end of training, at which a node barely affects its direct neighbours.
+const FINAL_SIGMA: f32 = 0.5;
+
+/// A rectangular grid of nodes, each holding a weight vector with the dimension of the patches.
+pub struct Som<D: Dataset = MnistData> {
+    rows: usize,
+    columns: usize,
+    lr: f32,
+    sigma: f32,
+    schedule: Arc<dyn LrSchedule>,
+    batch_size: usize,
+    thread_pool: ThreadPool,
+    dataset: D,
+    weights: Vec<Patch>,
+    rng: StdRng,
+}
+
+impl Som<MnistData> {
+    // Creates a map training on MNIST. Maps created with the same seed produce identical results, regardless of the number of threads.
+    pub fn new(rows: usize, columns: usize, threads: usize, lr: f32, patch_width: usize, seed: Option<u64>) -> Result<Som> {
+        Som::with_data(rows, columns, threads, lr, MnistData::new(1, patch_width)?, seed)
+    }
+}
+
+impl<D: Dataset> Som<D> {
+    pub fn with_data(rows: usize, columns: usize, threads: usize, lr: f32, dataset: D, seed: Option<u64>) -> Result<Som<D>> {
+        if rows == 0 || columns == 0 {
+            return Err(Error::InvalidConfig(format!("the map needs at least one node, got {}x{}", rows, columns)));
+        }
+        if threads == 0 {
+            return Err(Error::InvalidConfig("at least one thread is needed for training".to_string()));
+        }
+        if dataset.is_empty() {
+            return Err(Error::InvalidConfig("the dataset does not contain any samples".to_string()));
+        }
+        let thread_pool = ThreadPool::builder(threads).label("som").build()?;
+
+        let mut rng = match seed {
+            Some(seed) => StdRng::seed_from_u64(seed),
+            None => StdRng::from_entropy(),
+        };
+        let patch_size = dataset.patch_dim();
+        let weights = (0..rows * columns).map(|_| Init::Uniform.weights(patch_size, &mut rng)).collect();
+        let sigma = (rows.max(columns) as f32 / 2.0).max(FINAL_SIGMA);
+
+        Ok(Som { rows, columns, lr, sigma, schedule: Arc::new(Constant), batch_size: 100, thread_pool, dataset, weights, rng })
+    }
+
+    // Sets how the learning rate anneals over the epochs of a training run. Defaults to a constant learning rate.
+    pub fn set_schedule<S: LrSchedule + 'static>(&mut self, schedule: S) {
+        self.schedule = Arc::new(schedule);
+    }
+
+    // Sets the initial neighbourhood radius in nodes, which shrinks exponentially to half a node during training.
+    // Defaults to half the largest side of the map.
+    pub fn set_sigma(&mut self, sigma: f32) {
+        assert!(sigma > 0.0, "the neighbourhood radius must be positive");
+        self.sigma = sigma;
+    }
+
+    // Sets the number of patches whose best matching nodes are searched in parallel before the map is updated.
+    pub fn set_batch_size(&mut self, batch_size: usize) -> Result<()> {
+        if batch_size == 0 {
+            return Err(Error::InvalidConfig("the batch size must be at least one patch".to_string()));
+        }
+        self.batch_size = batch_size;
+        Ok(())
+    }
+
+    // Trains the map on one patch per epoch and returns the weights of every node, row by row.
+    pub fn train(&mut self, epochs: usize) -> Result<Vec<Patch>> {
+        let _span = info_span!("train_som", rows = self.rows, columns = self.columns, epochs = epochs).entered();
+        let now = Instant::now();
+        let training_data = self.dataset.sample_patches(0, epochs, &mut self.rng);
+
+        for (batch, patches) in training_data.chunks(self.batch_size).enumerate() {
+            let epoch = batch * self.batch_size;
+            let winners = self.best_matching_units(patches)?;
+            let sigma = self.sigma_at(epoch, epochs);
+            let lr = self.schedule.lr(self.lr, epoch);
+            self.update(patches, &winners, sigma, lr);
+            debug!(epoch = epoch + patches.len(), sigma = sigma, lr = lr, "batch");
+        }
+
+        info!(elapsed_ms = now.elapsed().as_millis() as u64, "som training completed");
+        Ok(self.weights.clone())
+    }
+
+    // Moves every node towards the mean of the patches in the batch, weighted by how close their best matching node is on the grid.
+    fn update(&mut self, patches: &[Patch], winners: &[usize], sigma: f32, lr: f32) {
+        let positions: Vec<(usize, usize)> = winners.iter().map(|winner| self.position(*winner)).collect();
+        for node in 0..self.weights.len() {
+            let position = self.position(node);
+            let mut target = vec![0.0; self.weights[node].len()];
+            let mut total = 0.0;
+            for (patch, winner) in patches.iter().zip(positions.iter()) {
+                let influence = neighbourhood(position, *winner, sigma);
+                target.iter_mut().zip(patch.iter()).for_each(|(t, x)| *t += influence * x);
+                total += influence;
+            }
+            if total > f32::EPSILON {
+                for (w, t) in self.weights[node].iter_mut().zip(target.iter()) {
+                    *w += lr * (t / total - *w);
+                }
+            }
+        }
+    }
+
+    // Finds the best matching node of every patch on the thread pool, with each worker taking chunks of patches from a shared queue.
+    fn best_matching_units(&self, patches: &[Patch]) -> Result<Vec<usize>> {
+        let weights = Arc::new(self.weights.clone());
+        let shared = Arc::new(patches.to_vec());
+        let chunk_size = patches.len().div_ceil(self.thread_pool.size() * 4).max(1);
+        let receiver = self.thread_pool.map_chunked(patches.len(), chunk_size, move |range| {
+            let winners: Vec<usize> = shared[range.clone()].iter().map(|patch| nearest(&weights, patch)).collect();
+            (range.start, winners)
+        });
+
+        let mut chunks: Vec<(usize, Vec<usize>)> = receiver.iter().collect();
+        self.thread_pool.join();
+        chunks.sort_by_key(|(start, _)| *start);
+        let winners: Vec<usize> = chunks.into_iter().flat_map(|(_, winners)| winners).collect();
+        if winners.len() != patches.len() {
+            return Err(Error::WorkerPanicked { trained: winners.len(), expected: patches.len() });
+        }
+        Ok(winners)
+    }
+
+    fn sigma_at(&self, epoch: usize, epochs: usize) -> f32 {
+        let progress = epoch as f32 / epochs.max(1) as f32;
+        self.sigma * (FINAL_SIGMA / self.sigma).powf(progress)
+    }
+
+    // Index of the node whose weights are closest to the patch.
+    pub fn best_matching_unit(&self, patch: &[f32]) -> usize {
+        nearest(&self.weights, patch)
+    }
+
+    // Row and column of the node on the grid.
+    pub fn position(&self, node: usize) -> (usize, usize) {
+        (node / self.columns, node % self.columns)
+    }
+
+    // Mean distance between the patches and the weights of their best matching node.
+    pub fn quantization_error(&self, patches: &[Patch]) -> f32 {
+        if patches.is_empty() {
+            return 0.0;
+        }
+        patches.iter()
+            .map(|patch| squared_distance(&self.weights[self.best_matching_unit(patch)], patch).sqrt())
+            .sum::<f32>() / patches.len() as f32
+    }
+
+    pub fn weights(&self) -> &[Patch] {
+        &self.weights
+    }
+
+    pub fn rows(&self) -> usize {
+        self.rows
+    }
+
+    pub fn columns(&self) -> usize {
+        self.columns
+    }
+
+    pub fn dataset(&self) -> &D {
+        &self.dataset
+    }
+
+    // Writes the weights of every node as an image, keeping the layout of the grid so neighbouring nodes are drawn next to each other.
+    pub fn save_map<P: AsRef<Path>>(&self, path: P, scale: usize) -> Result<()> {
+        let patch_width = patch_width(self.dataset.patch_dim()).ok_or_else(|| Error::Incompatible("maps can only be drawn for square patches".to_string()))?;
+        save_grid(path, &self.weights, patch_width, self.columns, scale).map_err(|error| Error::Image(error.to_string()))
+    }
+}
+
+/// Gaussian influence of the winning node on another node, falling off with their distance on the grid.
+fn neighbourhood(node: (usize, usize), winner: (usize, usize), sigma: f32) -> f32 {
+    let rows = node.0 as f32 - winner.0 as f32;
+    let columns = node.1 as f32 - winner.1 as f32;
+    (-(rows * rows + columns * columns) / (2.0 * sigma * sigma)).exp()
+}
+
+#[cfg(test)]
+mod test{
+    use super::*;
+    use rand::RngCore;
+
+    /// Points on a line, so a one dimensional map has to order its nodes along it.
+    struct Line;
+
+    impl Dataset for Line {
+        fn len(&self) -> usize {
+            1000
+        }
+
+        fn patch_dim(&self) -> usize {
+            2
+        }
+
+        fn sample_patch(&self, _index: usize, rng: &mut dyn RngCore) -> Patch {
+            let t = (rng.next_u32() % 1000) as f32 / 1000.0;
+            vec![t, t]
+        }
+    }
+
+    #[test]
+    fn map_orders_itself_along_the_data(){
+        let mut som = Som::with_data(1, 6, 3, 0.5, Line, Some(4)).unwrap();
+        som.set_batch_size(50).unwrap();
+        som.train(5000).unwrap();
+
+        let coordinates: Vec<f32> = som.weights().iter().map(|w| w[0]).collect();
+        let ascending = coordinates.windows(2).all(|pair| pair[0] < pair[1]);
+        let descending = coordinates.windows(2).all(|pair| pair[0] > pair[1]);
+        assert!(ascending || descending, "nodes are not ordered: {:?}", coordinates);
+        assert!(som.quantization_error(&[vec![0.5, 0.5], vec![0.1, 0.1]]) < 0.15);
+    }
+
+    #[test]
+    fn same_seed_gives_same_map_regardless_of_threads(){
+        let mut single = Som::with_data(3, 3, 1, 0.3, Line, Some(8)).unwrap();
+        let mut multi = Som::with_data(3, 3, 4, 0.3, Line, Some(8)).unwrap();
+        assert_eq!(single.train(500).unwrap(), multi.train(500).unwrap());
+        assert_eq!(single.position(5), (1, 2));
+        assert!(Som::with_data(0, 3, 1, 0.3, Line, None).is_err());
+    }
+}
@@ -0,0 +1,128 @@
+//! Single-threaded reference implementation of `MtNetwork`, used to check the multithreaded training for correctness.
+//! It draws from the random number generator in the same order as `MtNetwork`, so both produce identical weights for the same seed.
+use rand::{Rng, SeedableRng};
+use rand::rngs::StdRng;
+use crate::data::dataset::Dataset;
+use crate::data::mnist::MnistData;
+use crate::data::patch::Patch;
+use crate::error::Result;
+use crate::model::init::Init;
+use crate::model::learning_rule::{LearningRule, NeuronState};
+use crate::model::schedule::{Constant, LrSchedule};
+
+/// Trains every neuron independently, one after the other on the calling thread.
+pub struct StNetwork<D: Dataset = MnistData> {
+    lr: f32,
+    schedule: Box<dyn LrSchedule>,
+    rule: LearningRule,
+    init: Init,
+    dataset: D,
+    weights: Vec<Patch>,
+    rng: StdRng,
+}
+
+impl StNetwork<MnistData> {
+    pub fn new(neurons: usize, lr: f32, patch_width: usize, seed: Option<u64>) -> Result<StNetwork> {
+        Ok(StNetwork::with_data(neurons, lr, MnistData::new(1, patch_width)?, seed))
+    }
+}
+
+impl<D: Dataset> StNetwork<D> {
+    pub fn with_data(neurons: usize, lr: f32, dataset: D, seed: Option<u64>) -> StNetwork<D> {
+        let mut rng = match seed {
+            Some(seed) => StdRng::seed_from_u64(seed),
+            None => StdRng::from_entropy(),
+        };
+        let patch_size = dataset.patch_dim();
+        let init = Init::default();
+        let weights = (0..neurons).map(|_| init.weights(patch_size, &mut rng)).collect();
+
+        StNetwork { lr, schedule: Box::new(Constant), rule: LearningRule::default(), init, dataset, weights, rng }
+    }
+
+    pub fn set_rule(&mut self, rule: LearningRule) {
+        self.rule = rule;
+    }
+
+    pub fn set_schedule<S: LrSchedule + 'static>(&mut self, schedule: S) {
+        self.schedule = Box::new(schedule);
+    }
+
+    pub fn set_init(&mut self, init: Init) {
+        self.init = init;
+        let patch_size = self.dataset.patch_dim();
+        for weights in self.weights.iter_mut() {
+            *weights = init.weights(patch_size, &mut self.rng);
+        }
+    }
+
+    /// Trains every neuron on the same sequence of patches. Like `MtNetwork`, every neuron continues from the weights it holds and
+    /// is trained on all but the last sampled patch.
+    pub fn train(&mut self, epochs: usize) -> Vec<Patch> {
+        let training_data = self.dataset.sample_patches(0, epochs, &mut self.rng);
+        // Drawn like the seed of a run of `MtNetwork`, which only uses it to shuffle the patches.
+        let _seed: u64 = self.rng.gen();
+
+        for weights in self.weights.iter_mut() {
+            let mut state = NeuronState::default();
+            for (epoch, patch) in training_data[..epochs.saturating_sub(1)].iter().enumerate() {
+                self.rule.update(patch, weights, &mut state, self.schedule.lr(self.lr, epoch));
+            }
+        }
+        self.weights.clone()
+    }
+
+    pub fn weights(&self) -> &[Patch] {
+        &self.weights
+    }
+}
+
+#[cfg(test)]
+mod test{
+    use super::*;
+    use crate::model::network::MtNetwork;
+    use crate::model::schedule::ExponentialDecay;
+
+    #[test]
+    fn multithreaded_training_matches_reference(){
+        let mut reference = StNetwork::new(12, 0.01, 5, Some(5)).unwrap();
+        let expected = reference.train(400);
+
+        for threads in [1, 3, 8] {
+            let mut network = MtNetwork::new(4, threads, 12, 0.01, 5, Some(5)).unwrap();
+            assert_eq!(network.train_complete_iterations(400).unwrap(), expected, "weights differ with {} threads", threads);
+        }
+    }
+
+    #[test]
+    fn multithreaded_training_matches_reference_for_other_inits(){
+        for init in [Init::Gaussian { std: 0.1 }, Init::Xavier, Init::UnitNorm] {
+            let mut reference = StNetwork::new(6, 0.01, 5, Some(2)).unwrap();
+            reference.set_init(init);
+
+            let mut network = MtNetwork::new(2, 3, 6, 0.01, 5, Some(2)).unwrap();
+            network.set_init(init);
+            assert_eq!(network.weights(), reference.weights(), "initial weights differ for {:?}", init);
+            assert_eq!(network.train_complete_iterations(200).unwrap(), reference.train(200), "weights differ for {:?}", init);
+        }
+    }
+
+    #[test]
+    fn multithreaded_training_matches_reference_for_other_rules(){
+        let rules = [LearningRule::Bcm { tau: 50.0 }, LearningRule::Hebbian { normalize_every: 10 }];
+        for rule in rules {
+            let mut reference = StNetwork::new(6, 0.001, 5, Some(9)).unwrap();
+            reference.set_rule(rule);
+            reference.set_schedule(ExponentialDecay { gamma: 0.999 });
+
+            let mut network = MtNetwork::new(2, 4, 6, 0.001, 5, Some(9)).unwrap();
+            network.set_rule(rule);
+            network.set_schedule(ExponentialDecay { gamma: 0.999 });
+            network.set_chunk_size(Some(1)).unwrap();
+
+            let expected = reference.train(300);
+            assert!(expected.iter().flatten().all(|w| w.is_finite()), "reference diverged for {:?}", rule);
+            assert_eq!(network.train_complete_iterations(300).unwrap(), expected, "weights differ for {:?}", rule);
+        }
+    }
+}
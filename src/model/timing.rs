@@ -0,0 +1,94 @@
+//! Where the wall time of a training run went: sampling the training patches, sections waiting for a worker, the workers
+//! training their sections, and the calling thread collecting the results into the weights and the history.
+use std::fmt;
+use std::time::Duration;
+
+/// How long a section of neurons waited for a worker, and how long the worker spent training it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SectionTiming {
+    /// Index of the first neuron of the section.
+    pub start: usize,
+    pub neurons: usize,
+    /// Time from handing the section to the workers until one of them started it.
+    pub queued: Duration,
+    pub compute: Duration,
+}
+
+/// The phases of a training run. Sections are trained in parallel, so the summed queueing and compute time of the sections
+/// can exceed the wall time of the run.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Timing {
+    /// Time spent sampling the training patches from the dataset.
+    pub data_loading: Duration,
+    /// Every section trained during the run, sorted by their first neuron. Backends training in lockstep add up the time of a
+    /// section over all epochs.
+    pub sections: Vec<SectionTiming>,
+    /// Time spent on the calling thread after the sections were trained, assembling the weights and recording the history.
+    pub aggregation: Duration,
+    /// Wall time of the whole run.
+    pub total: Duration,
+}
+
+impl Timing {
+    /// Summed time the sections waited for a worker.
+    pub fn queueing(&self) -> Duration {
+        self.sections.iter().map(|section| section.queued).sum()
+    }
+
+    /// Summed time the workers spent training.
+    pub fn compute(&self) -> Duration {
+        self.sections.iter().map(|section| section.compute).sum()
+    }
+
+    /// The longest a single section took to train.
+    pub fn slowest_section(&self) -> Option<&SectionTiming> {
+        self.sections.iter().max_by_key(|section| section.compute)
+    }
+
+    // Adds the time of a section, merging it with an earlier section of the same neurons.
+    pub(crate) fn add_section(&mut self, section: SectionTiming) {
+        match self.sections.iter_mut().find(|known| known.start == section.start && known.neurons == section.neurons) {
+            Some(known) => {
+                known.queued += section.queued;
+                known.compute += section.compute;
+            }
+            None => {
+                let index = self.sections.partition_point(|known| known.start < section.start);
+                self.sections.insert(index, section);
+            }
+        }
+    }
+}
+
+impl fmt::Display for Timing {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let seconds = |duration: Duration| duration.as_secs_f64();
+        writeln!(f, "Total        {:>9.3}s", seconds(self.total))?;
+        writeln!(f, "Data loading {:>9.3}s", seconds(self.data_loading))?;
+        writeln!(f, "Queueing     {:>9.3}s over {} sections", seconds(self.queueing()), self.sections.len())?;
+        write!(f, "Compute      {:>9.3}s", seconds(self.compute()))?;
+        if let Some(slowest) = self.slowest_section() {
+            write!(f, ", slowest section {:.3}s (neurons {}..{})", seconds(slowest.compute), slowest.start, slowest.start + slowest.neurons)?;
+        }
+        write!(f, "\nAggregation  {:>9.3}s", seconds(self.aggregation))
+    }
+}
+
+#[cfg(test)]
+mod test{
+    use super::*;
+
+    #[test]
+    fn merges_sections_and_sums_phases(){
+        let millis = Duration::from_millis;
+        let mut timing = Timing::default();
+        timing.add_section(SectionTiming { start: 4, neurons: 4, queued: millis(1), compute: millis(10) });
+        timing.add_section(SectionTiming { start: 0, neurons: 4, queued: millis(2), compute: millis(5) });
+        timing.add_section(SectionTiming { start: 4, neurons: 4, queued: millis(3), compute: millis(20) });
+        assert_eq!(timing.sections.iter().map(|section| section.start).collect::<Vec<_>>(), vec![0, 4]);
+        assert_eq!(timing.queueing(), millis(6));
+        assert_eq!(timing.compute(), millis(35));
+        assert_eq!(timing.slowest_section().unwrap().compute, millis(30));
+        assert_eq!(timing.to_string().lines().count(), 5);
+    }
+}
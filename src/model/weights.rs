@@ -0,0 +1,183 @@
+//! Flat storage for the weights of a network: a single `neurons x patch_size` buffer in row major order instead of a separate
+//! vector per neuron. Neighbouring neurons lie next to each other in memory, so a section of neurons is one contiguous slice
+//! which can be copied, handed to a worker or viewed as a matrix without gathering the rows first.
+use std::ops::{Index, IndexMut, Range};
+use std::slice::{ChunksExact, ChunksExactMut};
+use ndarray::{Array2, ArrayView2, ArrayViewMut2};
+use crate::data::patch::Patch;
+
+/// The weight vectors of a network, stored one neuron after the other.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Weights {
+    values: Vec<f32>,
+    dim: usize,
+}
+
+impl Weights {
+    /// Weights without any neurons, which can be added with `push`.
+    pub fn new(dim: usize) -> Weights {
+        Weights::with_capacity(0, dim)
+    }
+
+    pub fn with_capacity(neurons: usize, dim: usize) -> Weights {
+        assert!(dim > 0, "the weight vectors need at least one value");
+        Weights { values: Vec::with_capacity(neurons * dim), dim }
+    }
+
+    pub fn zeros(neurons: usize, dim: usize) -> Weights {
+        Weights::from_flat(vec![0.0; neurons * dim], dim)
+    }
+
+    /// Takes the weights from a buffer holding the vectors of all neurons in order.
+    pub fn from_flat(values: Vec<f32>, dim: usize) -> Weights {
+        assert!(dim > 0, "the weight vectors need at least one value");
+        assert_eq!(values.len() % dim, 0, "the buffer must hold whole weight vectors");
+        Weights { values, dim }
+    }
+
+    /// Copies the weight vectors of every neuron, which must all have `dim` values.
+    pub fn from_rows<P: AsRef<[f32]>>(dim: usize, rows: &[P]) -> Weights {
+        let mut weights = Weights::with_capacity(rows.len(), dim);
+        for row in rows {
+            weights.push(row.as_ref());
+        }
+        weights
+    }
+
+    /// Stacks the rows of a matrix, one neuron per row.
+    pub fn from_matrix(matrix: Array2<f32>) -> Weights {
+        let dim = matrix.ncols();
+        let values = if matrix.is_standard_layout() { matrix.into_raw_vec() } else { matrix.iter().copied().collect() };
+        Weights::from_flat(values, dim)
+    }
+
+    pub fn push(&mut self, weights: &[f32]) {
+        assert_eq!(weights.len(), self.dim, "every weight vector must have the patch dimension");
+        self.values.extend_from_slice(weights);
+    }
+
+    /// Number of neurons.
+    pub fn len(&self) -> usize {
+        self.values.len() / self.dim.max(1)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.values.is_empty()
+    }
+
+    /// Number of values in the weight vector of every neuron.
+    pub fn dim(&self) -> usize {
+        self.dim
+    }
+
+    pub fn as_slice(&self) -> &[f32] {
+        &self.values
+    }
+
+    pub fn as_mut_slice(&mut self) -> &mut [f32] {
+        &mut self.values
+    }
+
+    /// The weight vectors of a range of neurons as one contiguous slice.
+    pub fn slice(&self, neurons: Range<usize>) -> &[f32] {
+        &self.values[neurons.start * self.dim..neurons.end * self.dim]
+    }
+
+    pub fn slice_mut(&mut self, neurons: Range<usize>) -> &mut [f32] {
+        &mut self.values[neurons.start * self.dim..neurons.end * self.dim]
+    }
+
+    /// Replaces the weights with the vectors in `values`, reusing the buffer when it is large enough.
+    pub fn copy_from(&mut self, values: &[f32]) {
+        assert_eq!(values.len() % self.dim.max(1), 0, "the buffer must hold whole weight vectors");
+        self.values.clear();
+        self.values.extend_from_slice(values);
+    }
+
+    pub fn iter(&self) -> ChunksExact<'_, f32> {
+        self.values.chunks_exact(self.dim.max(1))
+    }
+
+    pub fn iter_mut(&mut self) -> ChunksExactMut<'_, f32> {
+        self.values.chunks_exact_mut(self.dim.max(1))
+    }
+
+    /// A slice for every neuron, for functions taking the weight vectors one by one.
+    pub fn views(&self) -> Vec<&[f32]> {
+        self.iter().collect()
+    }
+
+    pub fn views_mut(&mut self) -> Vec<&mut [f32]> {
+        self.iter_mut().collect()
+    }
+
+    /// The weights as an `N x D` matrix, without copying them.
+    pub fn matrix(&self) -> ArrayView2<'_, f32> {
+        ArrayView2::from_shape((self.len(), self.dim), &self.values).expect("the buffer holds whole weight vectors")
+    }
+
+    pub fn matrix_mut(&mut self) -> ArrayViewMut2<'_, f32> {
+        ArrayViewMut2::from_shape((self.len(), self.dim), &mut self.values).expect("the buffer holds whole weight vectors")
+    }
+
+    /// Copies the weight vectors of every neuron into a separate vector.
+    pub fn to_patches(&self) -> Vec<Patch> {
+        self.iter().map(|weights| weights.to_vec()).collect()
+    }
+}
+
+impl Index<usize> for Weights {
+    type Output = [f32];
+
+    fn index(&self, neuron: usize) -> &[f32] {
+        &self.values[neuron * self.dim..(neuron + 1) * self.dim]
+    }
+}
+
+impl IndexMut<usize> for Weights {
+    fn index_mut(&mut self, neuron: usize) -> &mut [f32] {
+        &mut self.values[neuron * self.dim..(neuron + 1) * self.dim]
+    }
+}
+
+impl<'a> IntoIterator for &'a Weights {
+    type Item = &'a [f32];
+    type IntoIter = ChunksExact<'a, f32>;
+
+    fn into_iter(self) -> ChunksExact<'a, f32> {
+        self.iter()
+    }
+}
+
+impl PartialEq<[Patch]> for Weights {
+    fn eq(&self, other: &[Patch]) -> bool {
+        self.len() == other.len() && self.iter().zip(other.iter()).all(|(weights, other)| weights == other.as_slice())
+    }
+}
+
+impl PartialEq<Vec<Patch>> for Weights {
+    fn eq(&self, other: &Vec<Patch>) -> bool {
+        *self == other[..]
+    }
+}
+
+#[cfg(test)]
+mod test{
+    use super::*;
+
+    #[test]
+    fn neurons_are_stored_next_to_each_other(){
+        let rows = vec![vec![1.0, 2.0], vec![3.0, 4.0], vec![5.0, 6.0]];
+        let mut weights = Weights::from_rows(2, &rows);
+        assert_eq!(weights.len(), 3);
+        assert_eq!(weights.as_slice(), &[1.0, 2.0, 3.0, 4.0, 5.0, 6.0]);
+        assert_eq!(&weights[1], &[3.0, 4.0]);
+        assert_eq!(weights.slice(1..3), &[3.0, 4.0, 5.0, 6.0]);
+        assert_eq!(weights, rows);
+
+        weights[2][0] = 0.0;
+        assert_eq!(weights.matrix().nrows(), 3);
+        assert_eq!(Weights::from_matrix(weights.matrix().to_owned()), weights);
+        assert_eq!(weights.to_patches(), vec![vec![1.0, 2.0], vec![3.0, 4.0], vec![0.0, 6.0]]);
+    }
+}
@@ -0,0 +1,240 @@
+//! Python bindings, so the training loop can be driven from Python and the weights come back as NumPy arrays. Built with
+//! `--features python` into an extension module named `rust_ml`, e.g. with `maturin develop --release`:
+//!
+//! ```python
+//! import rust_ml
+//!
+//! dataset = rust_ml.Dataset.mnist("data/", patch_width=5)
+//! network = rust_ml.Network(dataset, neurons=64, lr=0.01, seed=1)
+//! weights = network.train(50000)                      # float32 array of shape (64, 25)
+//! codes = network.encode(dataset.sample(100, seed=2))  # shape (100, 64)
+//! ```
+use std::sync::Arc;
+use ndarray::Array2;
+use numpy::{IntoPyArray, PyArray2, PyReadonlyArray2, ToPyArray};
+use pyo3::exceptions::{PyIOError, PyRuntimeError, PyValueError};
+use pyo3::prelude::*;
+use rand::SeedableRng;
+use rand::rngs::StdRng;
+use crate::data::cifar::{CifarDataset, Color};
+use crate::data::csv::CsvData;
+use crate::data::dataset::Dataset;
+use crate::data::idx::IdxDataset;
+use crate::data::mnist::MnistData;
+use crate::data::natural_images::NaturalImages;
+use crate::data::patch::Patch;
+use crate::error::Error;
+use crate::experiment::config::TrainingMethod;
+use crate::experiment::runner::train;
+use crate::model::ica::Nonlinearity;
+use crate::model::learning_rule::LearningRule;
+use crate::model::network::{MtNetwork, NetworkBuilder};
+use crate::threading::thread_pool::Threads;
+use crate::utils::constants::PATCH_WIDTH;
+
+impl From<Error> for PyErr {
+    fn from(error: Error) -> PyErr {
+        match error {
+            Error::InvalidConfig(_) | Error::Incompatible(_) => PyValueError::new_err(error.to_string()),
+            Error::Io(_) => PyIOError::new_err(error.to_string()),
+            _ => PyRuntimeError::new_err(error.to_string()),
+        }
+    }
+}
+
+/// A dataset training patches are sampled from, which can be shared by several networks.
+#[pyclass(name = "Dataset", module = "rust_ml", frozen)]
+pub struct PyDataset {
+    dataset: Arc<dyn Dataset>,
+}
+
+impl PyDataset {
+    fn new<D: Dataset + 'static>(dataset: D) -> PyDataset {
+        PyDataset { dataset: Arc::new(dataset) }
+    }
+}
+
+#[pymethods]
+impl PyDataset {
+    /// The MNIST training images in a directory, extracted or gzipped.
+    #[staticmethod]
+    #[pyo3(signature = (path = "data/", patch_width = PATCH_WIDTH))]
+    fn mnist(path: &str, patch_width: usize) -> PyResult<PyDataset> {
+        Ok(PyDataset::new(MnistData::from_path(1, patch_width, path)?))
+    }
+
+    #[staticmethod]
+    #[pyo3(signature = (path, patch_width = PATCH_WIDTH))]
+    fn fashion_mnist(path: &str, patch_width: usize) -> PyResult<PyDataset> {
+        Ok(PyDataset::new(IdxDataset::fashion_mnist(path, patch_width)?))
+    }
+
+    /// `split` is one of byclass, bymerge, balanced, letters, digits or mnist.
+    #[staticmethod]
+    #[pyo3(signature = (path, split = "balanced", patch_width = PATCH_WIDTH))]
+    fn emnist(path: &str, split: &str, patch_width: usize) -> PyResult<PyDataset> {
+        let split = split.parse().map_err(PyValueError::new_err)?;
+        Ok(PyDataset::new(IdxDataset::emnist(path, split, patch_width)?))
+    }
+
+    /// The binary CIFAR-10 training batches, converted to grayscale unless `rgb` keeps the color channels.
+    #[staticmethod]
+    #[pyo3(signature = (path, rgb = false, patch_width = PATCH_WIDTH))]
+    fn cifar(path: &str, rgb: bool, patch_width: usize) -> PyResult<PyDataset> {
+        let color = if rgb { Color::Rgb } else { Color::Grayscale };
+        Ok(PyDataset::new(CifarDataset::training(path, color, patch_width)?))
+    }
+
+    /// The whitened natural images of sparsenet's `IMAGES.mat`.
+    #[staticmethod]
+    #[pyo3(signature = (path, patch_width = PATCH_WIDTH))]
+    fn natural_images(path: &str, patch_width: usize) -> PyResult<PyDataset> {
+        Ok(PyDataset::new(NaturalImages::olshausen(path, patch_width)?))
+    }
+
+    /// One sample per line of a CSV file, optionally below a header line.
+    #[staticmethod]
+    #[pyo3(signature = (path, header = false))]
+    fn csv(path: &str, header: bool) -> PyResult<PyDataset> {
+        Ok(PyDataset::new(CsvData::from_path(path, header)?))
+    }
+
+    #[getter]
+    fn patch_dim(&self) -> usize {
+        self.dataset.patch_dim()
+    }
+
+    fn __len__(&self) -> usize {
+        self.dataset.len()
+    }
+
+    /// Samples `count` patches from consecutive samples, as an array of shape `(count, patch_dim)`.
+    #[pyo3(signature = (count, seed = 0))]
+    fn sample<'py>(&self, py: Python<'py>, count: usize, seed: u64) -> Bound<'py, PyArray2<f32>> {
+        let patches = self.dataset.sample_patches(0, count, &mut StdRng::seed_from_u64(seed));
+        to_array(py, patches, self.dataset.patch_dim())
+    }
+}
+
+/// A network trained with a Hebbian learning rule, holding its weights between training runs.
+#[pyclass(name = "Network", module = "rust_ml")]
+pub struct PyNetwork {
+    network: MtNetwork<Arc<dyn Dataset>>,
+}
+
+#[pymethods]
+impl PyNetwork {
+    /// `rule` is one of oja, oja-momentum, bcm, hebbian or ica, with the defaults of the experiment files for their
+    /// parameters. Without `threads` one worker is started per core.
+    #[new]
+    #[pyo3(signature = (dataset, neurons = 256, lr = 0.01, rule = "oja", threads = None, batch_size = 1, seed = None))]
+    fn new(dataset: PyRef<'_, PyDataset>, neurons: usize, lr: f32, rule: &str, threads: Option<usize>, batch_size: usize, seed: Option<u64>) -> PyResult<PyNetwork> {
+        let mut builder = NetworkBuilder::new()
+            .neurons(neurons)
+            .threads(threads.map_or(Threads::Auto, Threads::Fixed))
+            .lr(lr)
+            .rule(learning_rule(rule)?)
+            .batch_size(batch_size)
+            .dataset(Arc::clone(&dataset.dataset));
+        if let Some(seed) = seed {
+            builder = builder.seed(seed);
+        }
+        Ok(PyNetwork { network: builder.build()? })
+    }
+
+    /// Trains every neuron on `epochs` patches and returns the weights. `method` is one of pool, rayon, scoped, matrix,
+    /// synchronous or hierarchical, which trains with Sanger's rule. The Python interpreter is released while training.
+    #[pyo3(signature = (epochs, method = "pool", patches_per_epoch = 100))]
+    fn train<'py>(&mut self, py: Python<'py>, epochs: usize, method: &str, patches_per_epoch: usize) -> PyResult<Bound<'py, PyArray2<f32>>> {
+        let method = training_method(method, patches_per_epoch)?;
+        let network = &mut self.network;
+        py.allow_threads(|| train(network, method, epochs))?;
+        Ok(self.weights(py))
+    }
+
+    /// The weights as an array of shape `(neurons, patch_dim)`.
+    #[getter]
+    fn weights<'py>(&self, py: Python<'py>) -> Bound<'py, PyArray2<f32>> {
+        self.network.weights().matrix().to_pyarray_bound(py)
+    }
+
+    #[getter]
+    fn neurons(&self) -> usize {
+        self.network.weights().len()
+    }
+
+    #[getter]
+    fn patch_dim(&self) -> usize {
+        self.network.dataset().patch_dim()
+    }
+
+    /// The outputs of every neuron for an array of patches of shape `(count, patch_dim)`, of shape `(count, neurons)`.
+    fn encode<'py>(&self, py: Python<'py>, patches: PyReadonlyArray2<'py, f32>) -> PyResult<Bound<'py, PyArray2<f32>>> {
+        let patches = from_array(&patches, self.patch_dim(), "patches")?;
+        Ok(to_array(py, self.network.encode_batch(&patches)?, self.neurons()))
+    }
+
+    /// Reconstructs patches from an array of activations of shape `(count, neurons)`, as returned by `encode`.
+    fn decode<'py>(&self, py: Python<'py>, activations: PyReadonlyArray2<'py, f32>) -> PyResult<Bound<'py, PyArray2<f32>>> {
+        let activations = from_array(&activations, self.neurons(), "activations")?;
+        Ok(to_array(py, self.network.decode_batch(&activations)?, self.patch_dim()))
+    }
+
+    fn save_checkpoint(&self, path: &str) -> PyResult<()> {
+        Ok(self.network.save_checkpoint(path)?)
+    }
+
+    fn load_checkpoint(&mut self, path: &str) -> PyResult<()> {
+        Ok(self.network.load_checkpoint(path)?)
+    }
+
+    /// Writes the weights as a `.npy` file for `numpy.load`.
+    fn save_npy(&self, path: &str) -> PyResult<()> {
+        Ok(self.network.save_npy(path)?)
+    }
+}
+
+fn learning_rule(name: &str) -> PyResult<LearningRule> {
+    Ok(match name {
+        "oja" => LearningRule::Oja,
+        "oja-momentum" => LearningRule::OjaMomentum { beta: 0.9 },
+        "bcm" => LearningRule::Bcm { tau: 100.0 },
+        "hebbian" => LearningRule::Hebbian { normalize_every: 1 },
+        "ica" => LearningRule::Ica { nonlinearity: Nonlinearity::Tanh },
+        other => return Err(PyValueError::new_err(format!("unknown rule `{}`, expected oja, oja-momentum, bcm, hebbian or ica", other))),
+    })
+}
+
+fn training_method(name: &str, patches_per_epoch: usize) -> PyResult<TrainingMethod> {
+    Ok(match name {
+        "pool" => TrainingMethod::Pool,
+        "rayon" => TrainingMethod::Rayon,
+        "scoped" => TrainingMethod::Scoped,
+        "matrix" => TrainingMethod::Matrix,
+        "synchronous" => TrainingMethod::Synchronous { patches_per_epoch: patches_per_epoch.max(1) },
+        "hierarchical" => TrainingMethod::Hierarchical,
+        other => return Err(PyValueError::new_err(format!("unknown method `{}`, expected pool, rayon, scoped, matrix, synchronous or hierarchical", other))),
+    })
+}
+
+// Copies the rows of an array, which must have `width` columns.
+fn from_array(array: &PyReadonlyArray2<'_, f32>, width: usize, name: &str) -> PyResult<Vec<Vec<f32>>> {
+    let array = array.as_array();
+    if array.ncols() != width {
+        return Err(PyValueError::new_err(format!("the {} must have {} columns, got {}", name, width, array.ncols())));
+    }
+    Ok(array.rows().into_iter().map(|row| row.to_vec()).collect())
+}
+
+// Stacks vectors of `width` values into an array with one row per vector.
+fn to_array(py: Python<'_>, rows: Vec<Patch>, width: usize) -> Bound<'_, PyArray2<f32>> {
+    let shape = (rows.len(), width);
+    Array2::from_shape_vec(shape, rows.concat()).expect("every row has the given width").into_pyarray_bound(py)
+}
+
+#[pymodule]
+fn rust_ml(module: &Bound<'_, PyModule>) -> PyResult<()> {
+    module.add_class::<PyDataset>()?;
+    module.add_class::<PyNetwork>()?;
+    Ok(())
+}
@@ -0,0 +1,38 @@
+//! Cooperative cancellation of work running on other threads.
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+/// A flag shared between the thread requesting the cancellation and the workers checking it. Clones share the same flag,
+/// and a cancelled token stays cancelled.
+#[derive(Debug, Clone, Default)]
+pub struct CancellationToken {
+    cancelled: Arc<AtomicBool>,
+}
+
+impl CancellationToken {
+    pub fn new() -> CancellationToken {
+        CancellationToken::default()
+    }
+
+    pub fn cancel(&self) {
+        self.cancelled.store(true, Ordering::Relaxed);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.cancelled.load(Ordering::Relaxed)
+    }
+}
+
+#[cfg(test)]
+mod test{
+    use super::*;
+
+    #[test]
+    fn clones_share_the_cancellation(){
+        let token = CancellationToken::new();
+        let worker = token.clone();
+        assert!(!worker.is_cancelled());
+        std::thread::spawn(move || token.cancel()).join().unwrap();
+        assert!(worker.is_cancelled());
+    }
+}
@@ -1,53 +1,546 @@
 //! Based on The Rust book's implementation of a Thread Pool and workers.  https://doc.rust-lang.org/book/ch20-02-multithreaded.html
 
-use std::{fmt, thread};
-use std::sync::{mpsc, Arc, Mutex};
+use std::{fmt, io, thread};
+use std::any::Any;
+use std::collections::VecDeque;
+use std::ops::Range;
+use std::str::FromStr;
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::{mpsc, Arc, Condvar, Mutex};
+use std::sync::mpsc::Receiver;
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::time::{Duration, Instant};
 use std::fmt::{Debug};
+use core_affinity::CoreId;
+use tracing::warn;
+use crate::error::Error;
+use crate::profile_scope;
 
 /// Thread pool struct for managing a pool of worker and distributing workloads.
 pub struct ThreadPool{
     workers: Vec<Worker>,
-    sender: mpsc::Sender<Message>
+    queue: Arc<JobQueue>,
+    pending: Arc<PendingJobs>,
+    failures: Arc<Mutex<Vec<JobFailure>>>,
+    busy: Arc<AtomicUsize>,
+    pinned: Arc<AtomicUsize>,
+    counters: Arc<Counters>,
+    label: String,
+    shut_down: bool,
+}
+
+/// Counters of the jobs a pool has run since it was created, see `ThreadPool::metrics`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PoolMetrics {
+    pub submitted: usize,
+    /// Jobs which have run to the end, including the ones which panicked.
+    pub completed: usize,
+    /// Jobs which panicked, besides the ranges of `map_ranges` jobs which panicked and were recorded as failures.
+    pub panicked: usize,
+    /// Jobs no worker has taken yet.
+    pub queued: usize,
+    pub busy_workers: usize,
+    /// One entry per worker, in the order of their ids.
+    pub workers: Vec<WorkerMetrics>,
+}
+
+impl PoolMetrics {
+    /// Jobs which have been submitted but not completed, whether queued or running.
+    pub fn in_flight(&self) -> usize {
+        // The counters are read one after another, so a job may complete after `submitted` was read.
+        self.submitted.saturating_sub(self.completed)
+    }
+
+    /// Busy time of the workers summed up.
+    pub fn busy_time(&self) -> Duration {
+        self.workers.iter().map(|worker| worker.busy).sum()
+    }
+
+    /// Busy time of the busiest worker divided by the average, 1 when the work was spread evenly. Values well above 1 mean
+    /// the jobs are too large or too few to keep every worker busy.
+    pub fn imbalance(&self) -> f64 {
+        let busiest = self.workers.iter().map(|worker| worker.busy).max().unwrap_or_default();
+        let average = self.busy_time().as_secs_f64() / self.workers.len().max(1) as f64;
+        if average > 0.0 { busiest.as_secs_f64() / average } else { 1.0 }
+    }
+}
+
+impl fmt::Display for PoolMetrics {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Jobs         {} submitted, {} completed, {} panicked, {} queued", self.submitted, self.completed, self.panicked, self.queued)?;
+        for (id, worker) in self.workers.iter().enumerate() {
+            write!(f, "\nWorker {:<5} {:>9.3}s busy over {} jobs", id, worker.busy.as_secs_f64(), worker.jobs)?;
+        }
+        Ok(())
+    }
+}
+
+/// What a single worker has done since the pool was created.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct WorkerMetrics {
+    pub jobs: usize,
+    /// Time spent running jobs, as opposed to waiting for them.
+    pub busy: Duration,
+}
+
+#[derive(Default)]
+struct Counters {
+    submitted: AtomicUsize,
+    completed: AtomicUsize,
+    panicked: AtomicUsize,
+    workers: Vec<WorkerCounters>,
+}
+
+#[derive(Default)]
+struct WorkerCounters {
+    jobs: AtomicUsize,
+    busy_nanos: AtomicU64,
+}
+
+/// A job which panicked. The worker running it catches the panic and keeps taking jobs from the queue.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct JobFailure {
+    /// The range of indexes the job was called with, for jobs submitted with `execute_chunked` or `execute_ranges`.
+    pub range: Option<Range<usize>>,
+    pub message: String,
+}
+
+/// The jobs no worker has taken yet, in the order they were submitted.
+struct JobQueue {
+    queued: Mutex<Queued>,
+    // Signalled when a job is queued or the pool shuts down.
+    available: Condvar,
+    // Signalled when a worker takes a job or starts waiting for one, so submitters blocked on a full queue try again.
+    space: Condvar,
+    capacity: Option<usize>,
+}
+
+struct Queued {
+    jobs: VecDeque<Job>,
+    // Workers waiting for a job, which take a job off a full queue right away.
+    waiting: usize,
+    terminated: bool,
+}
+
+impl JobQueue {
+    fn new(capacity: Option<usize>) -> JobQueue {
+        let queued = Queued { jobs: VecDeque::new(), waiting: 0, terminated: false };
+        JobQueue { queued: Mutex::new(queued), available: Condvar::new(), space: Condvar::new(), capacity }
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, Queued> {
+        self.queued.lock().unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+
+    // A bounded queue holds at most `capacity` jobs besides the ones waiting workers are about to take.
+    fn has_space(&self, queued: &Queued) -> bool {
+        match self.capacity {
+            Some(capacity) => queued.jobs.len() < capacity + queued.waiting,
+            None => true,
+        }
+    }
+
+    // Queues a job, blocking while a bounded queue is full.
+    fn push(&self, job: Job) {
+        let mut queued = self.lock();
+        while !self.has_space(&queued) {
+            queued = self.space.wait(queued).unwrap_or_else(|poisoned| poisoned.into_inner());
+        }
+        queued.jobs.push_back(job);
+        self.available.notify_one();
+    }
+
+    // Queues a job unless a bounded queue is full, in which case the job is handed back.
+    fn try_push(&self, job: Job) -> Result<(), Job> {
+        let mut queued = self.lock();
+        if !self.has_space(&queued) {
+            return Err(job);
+        }
+        queued.jobs.push_back(job);
+        self.available.notify_one();
+        Ok(())
+    }
+
+    // Blocks until a job is queued. Returns `None` once the pool shuts down.
+    fn pop(&self) -> Option<Job> {
+        let mut queued = self.lock();
+        loop {
+            if let Some(job) = queued.jobs.pop_front() {
+                self.space.notify_all();
+                return Some(job);
+            }
+            if queued.terminated {
+                return None;
+            }
+            queued.waiting += 1;
+            self.space.notify_all();
+            queued = self.available.wait(queued).unwrap_or_else(|poisoned| poisoned.into_inner());
+            queued.waiting -= 1;
+        }
+    }
+
+    // Stops the workers once they have taken every queued job.
+    fn terminate(&self) {
+        self.lock().terminated = true;
+        self.available.notify_all();
+    }
+
+    fn len(&self) -> usize {
+        self.lock().jobs.len()
+    }
+}
+
+/// Number of workers which keeps every core busy: one per core available to the process, or one if that can not be told.
+pub fn auto_threads() -> usize {
+    thread::available_parallelism().map_or(1, |cores| cores.get())
+}
+
+/// How many workers a pool has, either a fixed number or one per available core as counted by `auto_threads`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Threads {
+    Auto,
+    Fixed(usize),
+}
+
+impl Threads {
+    pub fn count(&self) -> usize {
+        match self {
+            Threads::Auto => auto_threads(),
+            Threads::Fixed(threads) => *threads,
+        }
+    }
+}
+
+impl From<usize> for Threads {
+    fn from(threads: usize) -> Threads {
+        Threads::Fixed(threads)
+    }
+}
+
+// Parses `auto` or a number of threads, e.g. from the command line.
+impl FromStr for Threads {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Threads, String> {
+        match value {
+            "auto" => Ok(Threads::Auto),
+            _ => value.parse().map(Threads::Fixed).map_err(|_| format!("`{}` is neither a number of threads nor auto", value)),
+        }
+    }
+}
+
+impl fmt::Display for Threads {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Threads::Auto => write!(f, "auto"),
+            Threads::Fixed(threads) => write!(f, "{}", threads),
+        }
+    }
+}
+
+/// Configures the workers and the queue of a thread pool before starting it.
+pub struct ThreadPoolBuilder {
+    size: usize,
+    label: String,
+    capacity: Option<usize>,
+    pin_cores: bool,
+}
+
+impl ThreadPoolBuilder {
+    /// Prefix of the worker thread names, which are `<label>-worker-<id>` so profilers and debuggers can attribute their time.
+    /// Defaults to `pool`.
+    pub fn label<T: Into<String>>(mut self, label: T) -> Self {
+        self.label = label.into();
+        self
+    }
+
+    /// Limits the queue to at most `capacity` jobs which no worker has taken yet. Once the queue is full `execute` blocks until
+    /// a worker takes the next job, and `try_execute` fails with `WouldBlock`, so submitting jobs is throttled to the rate the
+    /// workers consume them. With a capacity of zero every job is handed directly to an idle worker.
+    /// A job which submits further jobs to a full queue of its own pool blocks its worker until the queue drains.
+    pub fn queue_capacity(mut self, capacity: usize) -> Self {
+        self.capacity = Some(capacity);
+        self
+    }
+
+    /// Pins every worker to a core of its own, worker `i` to the `i`-th core the operating system reports, wrapping around
+    /// when there are more workers than cores. Without pinning the scheduler moves the workers between cores, e.g. from a
+    /// performance to an efficiency core or to another NUMA node, which shows up as noise in timing measurements.
+    /// The cores are logical CPUs, so with simultaneous multithreading two workers may share a physical core once there are
+    /// more workers than physical cores. Workers which can not be pinned run unpinned, see `ThreadPool::pinned_workers`.
+    pub fn pin_cores(mut self, pin_cores: bool) -> Self {
+        self.pin_cores = pin_cores;
+        self
+    }
+
+    pub fn build(self) -> Result<ThreadPool, PoolCreationError> {
+        if self.size < 1 {
+            return Err(PoolCreationError);
+        }
+        let queue = Arc::new(JobQueue::new(self.capacity));
+        let pending = Arc::new(PendingJobs { count: Mutex::new(0), done: Condvar::new() });
+        let failures = Arc::new(Mutex::new(Vec::new()));
+        let busy = Arc::new(AtomicUsize::new(0));
+        let pinned = Arc::new(AtomicUsize::new(0));
+        let counters = Arc::new(Counters { workers: (0..self.size).map(|_| WorkerCounters::default()).collect(), ..Counters::default() });
+        let cores = if self.pin_cores { core_affinity::get_core_ids().unwrap_or_default() } else { Vec::new() };
+        if self.pin_cores && cores.is_empty() {
+            warn!(label = %self.label, "unable to list the cores, the workers are not pinned");
+        }
+
+        let mut workers = Vec::with_capacity(self.size);
+
+        for id in 0..self.size {
+            let shared = WorkerShared { queue: Arc::clone(&queue), pending: Arc::clone(&pending), failures: Arc::clone(&failures), busy: Arc::clone(&busy), pinned: Arc::clone(&pinned), counters: Arc::clone(&counters) };
+            let core = (!cores.is_empty()).then(|| cores[id % cores.len()]);
+            match Worker::new(id, format!("{}-worker-{}", self.label, id), core, shared) {
+                Ok(worker) => workers.push(worker),
+                // The workers started so far would otherwise wait for jobs forever.
+                Err(_) => {
+                    queue.terminate();
+                    return Err(PoolCreationError);
+                }
+            }
+        }
+
+        Ok(ThreadPool { workers, queue, pending, failures, busy, pinned, counters, label: self.label, shut_down: false })
+    }
 }
 
 impl ThreadPool {
     pub fn new(size: usize) -> Result<ThreadPool, PoolCreationError>{
-        if size < 1 {
-            return Err(PoolCreationError);
+        ThreadPool::builder(size).build()
+    }
+
+    /// Creates a pool whose queue holds at most `capacity` jobs, see `ThreadPoolBuilder::queue_capacity`.
+    pub fn bounded(size: usize, capacity: usize) -> Result<ThreadPool, PoolCreationError> {
+        ThreadPool::builder(size).queue_capacity(capacity).build()
+    }
+
+    /// Creates a pool with one worker per available core, see `auto_threads`.
+    pub fn with_auto_threads() -> Result<ThreadPool, PoolCreationError> {
+        ThreadPool::new(auto_threads())
+    }
+
+    /// Creates a pool whose workers are pinned to cores of their own, see `ThreadPoolBuilder::pin_cores`.
+    pub fn pinned(size: usize) -> Result<ThreadPool, PoolCreationError> {
+        ThreadPool::builder(size).pin_cores(true).build()
+    }
+
+    pub fn builder(size: usize) -> ThreadPoolBuilder {
+        ThreadPoolBuilder { size, label: "pool".to_string(), capacity: None, pin_cores: false }
+    }
+
+    /// Queues a job for the next free worker. Fails with `Error::InvalidConfig` once the pool has been shut down.
+    pub fn execute<F>(&self, f: F) -> Result<(), Error> where F: FnOnce() + Send + 'static,  {
+        self.check_running()?;
+        self.pending.add();
+        self.counters.submitted.fetch_add(1, Ordering::SeqCst);
+        self.queue.push(Box::new(f));
+        Ok(())
+    }
+
+    /// Same as `execute`, but fails with an `io::ErrorKind::WouldBlock` error instead of blocking when the queue of a bounded
+    /// pool is full. The job is dropped in that case, so the caller has to submit it again.
+    pub fn try_execute<F>(&self, f: F) -> Result<(), Error> where F: FnOnce() + Send + 'static {
+        self.check_running()?;
+        self.pending.add();
+        // Counted before the push, so a worker can never complete the job before it was counted as submitted.
+        self.counters.submitted.fetch_add(1, Ordering::SeqCst);
+        if self.queue.try_push(Box::new(f)).is_err() {
+            self.counters.submitted.fetch_sub(1, Ordering::SeqCst);
+            drop(JobGuard(&self.pending));
+            return Err(io::Error::new(io::ErrorKind::WouldBlock, "the job queue is full").into());
         }
+        Ok(())
+    }
+
+    fn check_running(&self) -> Result<(), Error> {
+        if self.shut_down {
+            return Err(Error::InvalidConfig(format!("cannot execute jobs on the {} pool, which has been shut down", self.label)));
+        }
+        Ok(())
+    }
+
+    /// Runs `job` for every index in `0..count`, handing out ranges of at most `chunk_size` consecutive indexes at a time.
+    /// The ranges are taken from a shared counter by one job per worker, so workers that finish early keep picking up the
+    /// remaining ranges instead of idling while a slow worker finishes a large fixed share. Use `join` to wait for completion.
+    pub fn execute_chunked<F>(&self, count: usize, chunk_size: usize, job: F) where F: Fn(Range<usize>) + Send + Sync + 'static {
+        assert!(chunk_size > 0, "the chunk size must be at least one");
+        self.execute_ranges(chunks(0..count, chunk_size), job);
+    }
+
+    /// Runs `job` once for every range, handing the ranges out to the workers in order like `execute_chunked`.
+    /// A range whose job panics is recorded as a failure, see `take_failures`, and the worker continues with the next range.
+    pub fn execute_ranges<F>(&self, ranges: Vec<Range<usize>>, job: F) where F: Fn(Range<usize>) + Send + Sync + 'static {
+        drop(self.map_ranges(ranges, job));
+    }
 
+    /// Same as `execute_chunked`, but collects what `job` returns for every range, see `map_ranges`.
+    pub fn map_chunked<T, F>(&self, count: usize, chunk_size: usize, job: F) -> Receiver<T>
+        where T: Send + 'static, F: Fn(Range<usize>) -> T + Send + Sync + 'static {
+        assert!(chunk_size > 0, "the chunk size must be at least one");
+        self.map_ranges(chunks(0..count, chunk_size), job)
+    }
+
+    /// Runs `job` once for every range like `execute_ranges`, and sends each result to the returned receiver as soon as it is
+    /// computed. This is the recommended way to collect results: every worker owns a clone of the lock-free sender, so delivering
+    /// a result never waits on the other workers, and iterating the receiver ends once every range has been processed.
+    /// Results arrive in completion order, and a range whose job panicked sends nothing.
+    pub fn map_ranges<T, F>(&self, ranges: Vec<Range<usize>>, job: F) -> Receiver<T>
+        where T: Send + 'static, F: Fn(Range<usize>) -> T + Send + Sync + 'static {
         let (sender, receiver) = mpsc::channel();
+        let next = Arc::new(AtomicUsize::new(0));
+        let ranges = Arc::new(ranges);
+        let job = Arc::new(job);
+
+        for _ in 0..self.workers.len().min(ranges.len()) {
+            let sender = sender.clone();
+            let next = Arc::clone(&next);
+            let ranges = Arc::clone(&ranges);
+            let job = Arc::clone(&job);
+            let failures = Arc::clone(&self.failures);
+            // A pool which has been shut down runs nothing, so the receiver ends without any results.
+            let submitted = self.execute(move || {
+                while let Some(range) = ranges.get(next.fetch_add(1, Ordering::Relaxed)).cloned() {
+                    match panic::catch_unwind(AssertUnwindSafe(|| job(range.clone()))) {
+                        // Nobody may be listening, e.g. for jobs submitted with `execute_ranges`.
+                        Ok(result) => drop(sender.send(result)),
+                        Err(payload) => failures.lock().unwrap_or_else(|poisoned| poisoned.into_inner()).push(JobFailure { range: Some(range), message: panic_message(payload) }),
+                    }
+                }
+            });
+            if submitted.is_err() {
+                break;
+            }
+        }
+        receiver
+    }
+
+    /// Returns the jobs which panicked since the last call, so the submitter can report or retry them.
+    pub fn take_failures(&self) -> Vec<JobFailure> {
+        std::mem::take(&mut *self.failures.lock().unwrap_or_else(|poisoned| poisoned.into_inner()))
+    }
+
+    pub fn size(&self) -> usize {
+        self.workers.len()
+    }
+
+    pub fn label(&self) -> &str {
+        &self.label
+    }
 
-        let receiver = Arc::new(Mutex::new(receiver));
+    /// Number of workers currently running a job.
+    pub fn busy_workers(&self) -> usize {
+        self.busy.load(Ordering::SeqCst)
+    }
 
-        let mut workers = Vec::with_capacity(size);
+    /// Number of workers which have been pinned to a core so far. Every worker pins itself once its thread has started, so
+    /// right after building the pool not all of them may have done so yet.
+    pub fn pinned_workers(&self) -> usize {
+        self.pinned.load(Ordering::SeqCst)
+    }
 
-        for id in 0..size {
-            workers.push(Worker::new(id, Arc::clone(&receiver)))
+    /// Snapshot of the counters of the pool, e.g. to see whether the workers are kept evenly busy by a scheduling strategy.
+    /// The counters are read one after another while the workers keep running, so they are only consistent with each other
+    /// once the pool is idle, e.g. after `join`.
+    pub fn metrics(&self) -> PoolMetrics {
+        let workers = self.counters.workers.iter()
+            .map(|worker| WorkerMetrics { jobs: worker.jobs.load(Ordering::SeqCst), busy: Duration::from_nanos(worker.busy_nanos.load(Ordering::SeqCst)) })
+            .collect();
+        PoolMetrics {
+            submitted: self.counters.submitted.load(Ordering::SeqCst),
+            completed: self.counters.completed.load(Ordering::SeqCst),
+            panicked: self.counters.panicked.load(Ordering::SeqCst),
+            queued: self.queued_jobs(),
+            busy_workers: self.busy_workers(),
+            workers,
         }
+    }
 
-        return Ok(ThreadPool { workers, sender })
+    /// Number of jobs which no worker has taken yet.
+    pub fn queued_jobs(&self) -> usize {
+        self.queue.len()
     }
 
-    pub fn execute<F>(&self, f: F) where F: FnOnce() + Send + 'static,  {
-        let job = Box::new(f);
-        self.sender.send(Message::NewJob(job)).unwrap()
+    /// Number of workers waiting for a job.
+    pub fn idle_workers(&self) -> usize {
+        self.size() - self.busy_workers()
     }
-}
 
-impl Drop for ThreadPool {
-    fn drop(&mut self) {
-        for _ in &self.workers{
-            self.sender.send(Message::Terminate).unwrap();
+    /// Blocks until every job submitted so far has completed. The pool can still be used afterwards.
+    pub fn join(&self) {
+        let mut count = self.pending.lock();
+        while *count > 0 {
+            count = self.pending.done.wait(count).unwrap_or_else(|poisoned| poisoned.into_inner());
+        }
+    }
+
+    /// Waits for all queued jobs to complete, then stops every worker. Calling this more than once has no effect.
+    pub fn shutdown(&mut self) {
+        if self.shut_down {
+            return;
         }
+        self.join();
+
+        self.queue.terminate();
 
         for worker in &mut self.workers {
 
             if let Some(thread) =  worker.thread.take(){
-                  thread.join().unwrap();
+                  thread.join().unwrap_or_else(|_| panic!("worker {} panicked while shutting down", worker.id));
             };
             //println!("worker {}, has successfully shut down", worker.id)
         }
+        self.shut_down = true;
+    }
+}
+
+impl Drop for ThreadPool {
+    fn drop(&mut self) {
+        self.shutdown();
+    }
+}
+
+/// Splits a range into consecutive ranges of at most `chunk_size` indexes.
+pub fn chunks(range: Range<usize>, chunk_size: usize) -> Vec<Range<usize>> {
+    range.clone().step_by(chunk_size.max(1)).map(|start| start..(start + chunk_size).min(range.end)).collect()
+}
+
+fn panic_message(payload: Box<dyn Any + Send>) -> String {
+    match payload.downcast::<String>() {
+        Ok(message) => *message,
+        Err(payload) => payload.downcast_ref::<&str>().map_or_else(|| "unknown panic".to_string(), |message| message.to_string()),
+    }
+}
+
+/// Number of jobs which have been submitted to the pool but not yet completed.
+struct PendingJobs {
+    count: Mutex<usize>,
+    done: Condvar,
+}
+
+impl PendingJobs {
+    // A job which panicked while holding the lock can not have left the count half updated, so a poisoned lock is used as is.
+    fn lock(&self) -> std::sync::MutexGuard<'_, usize> {
+        self.count.lock().unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+
+    fn add(&self) {
+        *self.lock() += 1;
+    }
+}
+
+/// Marks a job as completed when dropped, so the count stays correct even if the job panics.
+struct JobGuard<'a>(&'a PendingJobs);
+
+impl Drop for JobGuard<'_> {
+    fn drop(&mut self) {
+        let mut count = self.0.lock();
+        *count -= 1;
+        if *count == 0 {
+            self.0.done.notify_all();
+        }
     }
 }
 
@@ -60,41 +553,62 @@ impl fmt::Display for PoolCreationError {
     }
 }
 
+impl std::error::Error for PoolCreationError {}
+
 
 
+/// The state of the pool every worker holds on to.
+struct WorkerShared {
+    queue: Arc<JobQueue>,
+    pending: Arc<PendingJobs>,
+    failures: Arc<Mutex<Vec<JobFailure>>>,
+    busy: Arc<AtomicUsize>,
+    pinned: Arc<AtomicUsize>,
+    counters: Arc<Counters>,
+}
+
 struct Worker{
     id: usize,
     thread: Option<thread::JoinHandle<()>>,
 }
 
 impl Worker{
-    pub fn new(id: usize, receiver: Arc<Mutex<mpsc::Receiver<Message>>>) -> Worker {
-        let thread = thread::spawn( move ||  {
+    pub fn new(id: usize, name: String, core: Option<CoreId>, shared: WorkerShared) -> io::Result<Worker> {
+        let WorkerShared { queue, pending, failures, busy, pinned, counters } = shared;
+        let thread = thread::Builder::new().name(name).spawn( move ||  {
+            if let Some(core) = core {
+                if core_affinity::set_for_current(core) {
+                    pinned.fetch_add(1, Ordering::SeqCst);
+                } else {
+                    warn!(worker = id, core = core.id, "unable to pin the worker to its core");
+                }
+            }
             //println!("Thread {} is alive and ready to receive work", id);
-            loop {
-                let message = receiver.lock().expect("Worker Cannot obtain lock, the mutex might be poisoned").recv().expect("The sender is no longer available");
-                match message {
-                    Message::NewJob(job) => {
-                        //println!("worker {} performing new task", id);
-                        job()
-                    }
-                    Message::Terminate => {
-                        break;
-                    }
+            // The queue only runs dry once the pool shuts down, or creating one of the other workers failed.
+            while let Some(job) = queue.pop() {
+                //println!("worker {} performing new task", id);
+                profile_scope!("job");
+                let _guard = JobGuard(&pending);
+                busy.fetch_add(1, Ordering::SeqCst);
+                let started = Instant::now();
+                // A panicking job must not take the worker down with it, or the jobs still queued would never run.
+                if let Err(payload) = panic::catch_unwind(AssertUnwindSafe(job)) {
+                    failures.lock().unwrap_or_else(|poisoned| poisoned.into_inner()).push(JobFailure { range: None, message: panic_message(payload) });
+                    counters.panicked.fetch_add(1, Ordering::SeqCst);
                 }
+                let worker = &counters.workers[id];
+                worker.busy_nanos.fetch_add(started.elapsed().as_nanos() as u64, Ordering::SeqCst);
+                worker.jobs.fetch_add(1, Ordering::SeqCst);
+                counters.completed.fetch_add(1, Ordering::SeqCst);
+                busy.fetch_sub(1, Ordering::SeqCst);
             }
-        });
-        Worker{id, thread: Some(thread)}
+        })?;
+        Ok(Worker{id, thread: Some(thread)})
     }
 }
 
 type Job = Box<dyn FnOnce() + Send + 'static>;
 
-enum Message{
-    NewJob(Job),
-    Terminate,
-}
-
 #[cfg(test)]
 mod test{
     use super::*;
@@ -106,8 +620,195 @@ mod test{
         for i in 0..100 {
             pool.execute(move || {
                 let  _o = i + i;
-            })
+            }).unwrap();
+        }
+    }
+
+    #[test]
+    fn join_waits_for_all_jobs(){
+        let pool = ThreadPool::new(4).unwrap();
+        let counter = Arc::new(Mutex::new(0));
+
+        for _ in 0..100 {
+            let counter = Arc::clone(&counter);
+            pool.execute(move || {
+                thread::sleep(std::time::Duration::from_millis(1));
+                *counter.lock().unwrap() += 1;
+            }).unwrap();
         }
+
+        pool.join();
+        assert_eq!(*counter.lock().unwrap(), 100);
+    }
+
+    #[test]
+    fn execute_chunked_visits_every_index_once(){
+        let pool = ThreadPool::new(3).unwrap();
+        let visits = Arc::new(Mutex::new(vec![0; 50]));
+
+        let counted = Arc::clone(&visits);
+        pool.execute_chunked(50, 7, move |range| {
+            let mut visits = counted.lock().unwrap();
+            for index in range {
+                visits[index] += 1;
+            }
+        });
+
+        pool.join();
+        assert!(visits.lock().unwrap().iter().all(|visits| *visits == 1));
+    }
+
+    #[test]
+    fn panicking_jobs_are_reported_and_the_workers_survive(){
+        let pool = ThreadPool::new(2).unwrap();
+        let visits = Arc::new(Mutex::new(vec![0; 20]));
+
+        pool.execute(|| panic!("plain job")).unwrap();
+        let counted = Arc::clone(&visits);
+        pool.execute_chunked(20, 3, move |range| {
+            if range.start == 6 {
+                panic!("chunk {:?}", range);
+            }
+            let mut visits = counted.lock().unwrap();
+            for index in range {
+                visits[index] += 1;
+            }
+        });
+        pool.join();
+
+        let mut failures = pool.take_failures();
+        failures.sort_by_key(|failure| failure.range.clone().map(|range| range.start));
+        assert_eq!(failures, vec![
+            JobFailure { range: None, message: "plain job".to_string() },
+            JobFailure { range: Some(6..9), message: "chunk 6..9".to_string() },
+        ]);
+        assert!(pool.take_failures().is_empty());
+        assert_eq!(visits.lock().unwrap().iter().filter(|visits| **visits == 0).count(), 3);
+        assert_eq!(chunks(5..12, 3), vec![5..8, 8..11, 11..12]);
+    }
+
+    #[test]
+    fn bounded_queue_rejects_jobs_when_full(){
+        let pool = ThreadPool::bounded(1, 1).unwrap();
+        let counter = Arc::new(Mutex::new(0));
+        let (started, running) = mpsc::channel();
+        let (release, gate) = mpsc::channel::<()>();
+
+        let count = Arc::clone(&counter);
+        pool.execute(move || {
+            started.send(()).unwrap();
+            gate.recv().unwrap();
+            *count.lock().unwrap() += 1;
+        }).unwrap();
+        running.recv().unwrap();
+
+        let count = Arc::clone(&counter);
+        pool.try_execute(move || *count.lock().unwrap() += 1).unwrap();
+        let error = pool.try_execute(|| panic!("the queue should have been full")).unwrap_err();
+        assert!(matches!(error, Error::Io(ref error) if error.kind() == io::ErrorKind::WouldBlock));
+        assert_eq!(pool.metrics().submitted, 2);
+
+        release.send(()).unwrap();
+        pool.join();
+        assert_eq!(*counter.lock().unwrap(), 2);
+        assert!(pool.take_failures().is_empty());
+
+        // Without any capacity every job waits for an idle worker to take it.
+        let rendezvous = ThreadPool::bounded(2, 0).unwrap();
+        for _ in 0..20 {
+            let count = Arc::clone(&counter);
+            rendezvous.execute(move || *count.lock().unwrap() += 1).unwrap();
+        }
+        rendezvous.join();
+        assert_eq!(*counter.lock().unwrap(), 22);
+    }
+
+    #[test]
+    fn metrics_count_jobs_and_busy_time(){
+        let pool = ThreadPool::new(2).unwrap();
+        for _ in 0..6 {
+            pool.execute(|| thread::sleep(Duration::from_millis(5))).unwrap();
+        }
+        pool.execute(|| panic!("job failed")).unwrap();
+        pool.join();
+        let metrics = pool.metrics();
+        assert_eq!((metrics.submitted, metrics.completed, metrics.panicked, metrics.queued, metrics.in_flight()), (7, 7, 1, 0, 0));
+        assert_eq!(metrics.workers.len(), 2);
+        assert_eq!(metrics.workers.iter().map(|worker| worker.jobs).sum::<usize>(), 7);
+        assert!(metrics.busy_time() >= Duration::from_millis(30));
+        assert!(metrics.imbalance() >= 1.0);
+        assert_eq!(metrics.to_string().lines().count(), 3);
+    }
+
+    #[test]
+    fn workers_are_named_and_report_when_busy(){
+        let pool = ThreadPool::builder(2).label("oja").build().unwrap();
+        assert_eq!(pool.label(), "oja");
+        assert_eq!(pool.idle_workers(), 2);
+
+        let (started, running) = mpsc::channel();
+        let (release, gate) = mpsc::channel::<()>();
+        pool.execute(move || {
+            started.send(thread::current().name().map(str::to_string)).unwrap();
+            gate.recv().unwrap();
+        }).unwrap();
+        let name = running.recv().unwrap().unwrap();
+        assert!(name == "oja-worker-0" || name == "oja-worker-1", "{}", name);
+        assert_eq!((pool.busy_workers(), pool.idle_workers()), (1, 1));
+
+        release.send(()).unwrap();
+        pool.join();
+        assert_eq!(pool.busy_workers(), 0);
+    }
+
+    #[test]
+    fn thread_counts_are_detected(){
+        let cores = thread::available_parallelism().unwrap().get();
+        assert_eq!(auto_threads(), cores);
+        assert_eq!(ThreadPool::with_auto_threads().unwrap().size(), auto_threads());
+        assert_eq!("auto".parse(), Ok(Threads::Auto));
+        assert_eq!("4".parse::<Threads>().map(|threads| threads.count()), Ok(4));
+        assert!("four".parse::<Threads>().is_err());
+        assert_eq!(Threads::Auto.to_string().parse(), Ok(Threads::Auto));
+    }
+
+    #[test]
+    fn workers_are_pinned_to_cores(){
+        let pool = ThreadPool::pinned(3).unwrap();
+        // Every worker holds on to a job until all of them have started, so every worker has pinned itself.
+        let barrier = Arc::new(std::sync::Barrier::new(3));
+        for _ in 0..3 {
+            let barrier = Arc::clone(&barrier);
+            pool.execute(move || {
+                barrier.wait();
+            }).unwrap();
+        }
+        pool.join();
+        #[cfg(target_os = "linux")]
+        assert_eq!(pool.pinned_workers(), 3);
+        assert_eq!(ThreadPool::new(2).unwrap().pinned_workers(), 0);
+    }
+
+    #[test]
+    fn map_chunked_sends_every_result(){
+        let pool = ThreadPool::new(3).unwrap();
+        let mut results: Vec<(usize, usize)> = pool.map_chunked(50, 7, |range| (range.start, range.sum())).iter().collect();
+        results.sort_unstable();
+        assert_eq!(results.len(), 8);
+        assert_eq!(results.iter().map(|(_, sum)| sum).sum::<usize>(), (0..50).sum());
+        assert_eq!(results[1], (7, (7..14).sum()));
+    }
+
+    #[test]
+    fn shutdown_is_idempotent(){
+        let mut pool = ThreadPool::new(2).unwrap();
+        pool.execute(|| {}).unwrap();
+        pool.shutdown();
+        pool.shutdown();
+        assert!(matches!(pool.execute(|| {}), Err(Error::InvalidConfig(_))));
+        assert!(matches!(pool.try_execute(|| {}), Err(Error::InvalidConfig(_))));
+        assert_eq!(pool.map_chunked(10, 2, |range| range.start).iter().count(), 0);
+        assert_eq!(pool.metrics().submitted, 1);
     }
 
 }
\ No newline at end of file
@@ -1 +1,3 @@
-pub const PATCH_SIZE: usize = 25;
\ No newline at end of file
+pub const IMAGE_WIDTH: usize = 28;
+pub const PATCH_WIDTH: usize = 5;
+pub const PATCH_SIZE: usize = PATCH_WIDTH * PATCH_WIDTH;
@@ -0,0 +1,147 @@
+//! Small dense linear algebra helpers for the patch statistics, sized for covariance matrices of a few hundred dimensions at most.
+use crate::data::patch::Patch;
+
+/// Mean of the given patches.
+pub fn mean_patch(patches: &[Patch]) -> Patch {
+    assert!(!patches.is_empty(), "at least one patch is needed to compute the mean");
+    let mut mean = vec![0.0f32; patches[0].len()];
+    for patch in patches {
+        for (m, x) in mean.iter_mut().zip(patch.iter()) {
+            *m += x;
+        }
+    }
+    mean.iter_mut().for_each(|m| *m /= patches.len() as f32);
+    mean
+}
+
+/// Covariance matrix of the patches around the given mean, accumulated in double precision.
+pub fn covariance(patches: &[Patch], mean: &[f32]) -> Vec<Vec<f64>> {
+    let dim = mean.len();
+    let mut covariance = vec![vec![0.0f64; dim]; dim];
+    for patch in patches {
+        let centered: Vec<f64> = patch.iter().zip(mean.iter()).map(|(x, m)| (x - m) as f64).collect();
+        for (row, xi) in covariance.iter_mut().zip(centered.iter()) {
+            for (value, xj) in row.iter_mut().zip(centered.iter()) {
+                *value += xi * xj;
+            }
+        }
+    }
+    covariance.iter_mut().flatten().for_each(|value| *value /= patches.len() as f64);
+    covariance
+}
+
+/// Lower triangular factor `L` of a symmetric positive definite matrix with `L L^T = matrix`, none if the matrix is not
+/// positive definite.
+pub fn cholesky(matrix: &[Vec<f64>]) -> Option<Vec<Vec<f64>>> {
+    let dim = matrix.len();
+    let mut factor = vec![vec![0.0f64; dim]; dim];
+    for row in 0..dim {
+        for column in 0..=row {
+            let sum: f64 = (0..column).map(|k| factor[row][k] * factor[column][k]).sum();
+            if row == column {
+                let diagonal = matrix[row][row] - sum;
+                if diagonal <= 0.0 {
+                    return None;
+                }
+                factor[row][row] = diagonal.sqrt();
+            } else {
+                factor[row][column] = (matrix[row][column] - sum) / factor[column][column];
+            }
+        }
+    }
+    Some(factor)
+}
+
+/// Eigenvalues and eigenvectors (as columns) of a symmetric matrix, using cyclic Jacobi rotations.
+/// The eigenvalues are not sorted.
+pub fn symmetric_eigen(mut matrix: Vec<Vec<f64>>) -> (Vec<f64>, Vec<Vec<f64>>) {
+    let dim = matrix.len();
+    let mut vectors = vec![vec![0.0f64; dim]; dim];
+    for (i, row) in vectors.iter_mut().enumerate() {
+        row[i] = 1.0;
+    }
+
+    for _ in 0..100 {
+        let off_diagonal: f64 = (0..dim).flat_map(|i| (0..dim).filter(move |&j| j != i).map(move |j| (i, j)))
+            .map(|(i, j)| matrix[i][j] * matrix[i][j])
+            .sum();
+        if off_diagonal < 1e-18 {
+            break;
+        }
+
+        for p in 0..dim {
+            for q in p + 1..dim {
+                if matrix[p][q].abs() < 1e-30 {
+                    continue;
+                }
+                let theta = (matrix[q][q] - matrix[p][p]) / (2.0 * matrix[p][q]);
+                let t = theta.signum() / (theta.abs() + (theta * theta + 1.0).sqrt());
+                let c = 1.0 / (t * t + 1.0).sqrt();
+                let s = t * c;
+
+                for row in matrix.iter_mut().chain(vectors.iter_mut()) {
+                    let (kp, kq) = (row[p], row[q]);
+                    row[p] = c * kp - s * kq;
+                    row[q] = s * kp + c * kq;
+                }
+                let mut row_p = std::mem::take(&mut matrix[p]);
+                let mut row_q = std::mem::take(&mut matrix[q]);
+                for (pk, qk) in row_p.iter_mut().zip(row_q.iter_mut()) {
+                    let (old_p, old_q) = (*pk, *qk);
+                    *pk = c * old_p - s * old_q;
+                    *qk = s * old_p + c * old_q;
+                }
+                matrix[p] = row_p;
+                matrix[q] = row_q;
+            }
+        }
+    }
+
+    ((0..dim).map(|i| matrix[i][i]).collect(), vectors)
+}
+
+/// Eigenvalues in descending order, with the matching eigenvectors as rows.
+pub fn sorted_eigen(matrix: Vec<Vec<f64>>) -> Vec<(f64, Vec<f64>)> {
+    let (values, vectors) = symmetric_eigen(matrix);
+    let mut pairs: Vec<(f64, Vec<f64>)> = values.iter().enumerate()
+        .map(|(k, value)| (*value, vectors.iter().map(|row| row[k]).collect()))
+        .collect();
+    pairs.sort_by(|a, b| b.0.partial_cmp(&a.0).unwrap_or(std::cmp::Ordering::Equal));
+    pairs
+}
+
+pub fn squared_distance(a: &[f32], b: &[f32]) -> f32 {
+    a.iter().zip(b.iter()).map(|(a, b)| (a - b) * (a - b)).sum()
+}
+
+/// Index of the vector closest to the patch by euclidean distance, the first one on ties.
+pub fn nearest(vectors: &[Patch], patch: &[f32]) -> usize {
+    vectors.iter()
+        .map(|vector| squared_distance(vector, patch))
+        .enumerate()
+        .fold((0, f32::INFINITY), |best, (index, distance)| if distance < best.1 { (index, distance) } else { best })
+        .0
+}
+
+#[cfg(test)]
+mod test{
+    use super::*;
+
+    #[test]
+    fn eigen_decomposition_of_symmetric_matrix(){
+        let matrix = vec![vec![2.0, 1.0], vec![1.0, 2.0]];
+        let pairs = sorted_eigen(matrix);
+
+        assert!((pairs[0].0 - 3.0).abs() < 1e-9);
+        assert!((pairs[1].0 - 1.0).abs() < 1e-9);
+        assert!((pairs[0].1[0].abs() - 0.5f64.sqrt()).abs() < 1e-9);
+        assert!((pairs[0].1[0] - pairs[0].1[1]).abs() < 1e-9);
+    }
+
+    #[test]
+    fn cholesky_factor_reproduces_the_matrix(){
+        let factor = cholesky(&[vec![4.0, 2.0], vec![2.0, 5.0]]).unwrap();
+        assert_eq!(factor, vec![vec![2.0, 0.0], vec![1.0, 2.0]]);
+        assert!(cholesky(&[vec![1.0, 2.0], vec![2.0, 1.0]]).is_none());
+    }
+}
@@ -0,0 +1,73 @@
+//! Profiling scopes around the workers, the data loading and the aggregation of the training, recorded with puffin when the
+//! `profiling` feature is enabled. A recording saved with `Recording::save` opens in `puffin_viewer` as a flamegraph per
+//! thread, which shows where the time of a run goes in more detail than the totals of `model::timing`.
+//! Without the feature `profile_scope!` expands to nothing, so the scopes cost nothing in normal builds.
+#[cfg(feature = "profiling")]
+use std::fs::File;
+#[cfg(feature = "profiling")]
+use std::io::{self, BufWriter};
+#[cfg(feature = "profiling")]
+use std::path::Path;
+
+#[cfg(feature = "profiling")]
+pub use puffin;
+
+/// Opens a profiling scope with the given name which lasts until the end of the enclosing block.
+#[macro_export]
+macro_rules! profile_scope {
+    ($name:expr) => {
+        #[cfg(feature = "profiling")]
+        $crate::utils::profiling::puffin::profile_scope!($name);
+    };
+}
+
+/// Collects the profiling scopes of every thread from its creation until it is saved.
+#[cfg(feature = "profiling")]
+pub struct Recording {
+    frames: puffin::GlobalFrameView,
+}
+
+#[cfg(feature = "profiling")]
+impl Recording {
+    /// Turns the scopes on, which are off by default as recording them has a small cost.
+    pub fn start() -> Recording {
+        puffin::set_scopes_on(true);
+        Recording { frames: puffin::GlobalFrameView::default() }
+    }
+
+    /// Ends the current frame, e.g. after every training run, so the runs show up as separate frames.
+    pub fn new_frame(&self) {
+        puffin::GlobalProfiler::lock().new_frame();
+    }
+
+    /// Ends the current frame and writes every recorded frame to a `.puffin` file.
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> io::Result<()> {
+        self.new_frame();
+        let mut file = BufWriter::new(File::create(path)?);
+        self.frames.lock().write(&mut file).map_err(|error| io::Error::other(error.to_string()))
+    }
+}
+
+#[cfg(feature = "profiling")]
+impl Drop for Recording {
+    fn drop(&mut self) {
+        puffin::set_scopes_on(false);
+    }
+}
+
+#[cfg(all(test, feature = "profiling"))]
+mod test{
+    use super::*;
+
+    #[test]
+    fn recordings_are_saved(){
+        let recording = Recording::start();
+        {
+            profile_scope!("test");
+        }
+        let path = std::env::temp_dir().join(format!("rust_ml_profile_test_{}.puffin", std::process::id()));
+        recording.save(&path).unwrap();
+        assert!(path.exists());
+        std::fs::remove_file(&path).unwrap();
+    }
+}
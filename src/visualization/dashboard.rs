@@ -0,0 +1,187 @@
+//! Terminal dashboard for monitoring long training runs, driven by the progress events of a network.
+//! Shows how much of the run is done, the current learning rate, how busy every worker thread is and a sparkline of the
+//! reconstruction error. Requires the `tui` feature.
+use std::io;
+use std::sync::mpsc::{Receiver, TryRecvError};
+use std::thread::ThreadId;
+use std::time::Duration;
+use crossterm::event::{self, Event, KeyCode, KeyEventKind, KeyModifiers};
+use crossterm::execute;
+use crossterm::terminal::{disable_raw_mode, enable_raw_mode, EnterAlternateScreen, LeaveAlternateScreen};
+use ratatui::backend::{Backend, CrosstermBackend};
+use ratatui::layout::{Constraint, Direction, Layout};
+use ratatui::text::Line;
+use ratatui::widgets::{Block, Borders, Gauge, Paragraph, Sparkline};
+use ratatui::{Frame, Terminal};
+use crate::model::progress::Progress;
+
+const REFRESH: Duration = Duration::from_millis(100);
+const BAR_WIDTH: usize = 30;
+
+/// Everything the dashboard knows about the run, accumulated from the progress events.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct DashboardState {
+    pub completed: usize,
+    pub total: usize,
+    pub elapsed: Duration,
+    pub lr: Option<f32>,
+    /// Time every worker thread spent training, in the order the threads first completed some work.
+    pub busy: Vec<(ThreadId, Duration)>,
+    /// Reconstruction errors in the order they were recorded.
+    pub errors: Vec<f32>,
+    /// Whether the dashboard was closed with Ctrl-C, which raw mode keeps from interrupting the process.
+    pub interrupted: bool,
+}
+
+impl DashboardState {
+    pub fn new() -> DashboardState {
+        DashboardState::default()
+    }
+
+    pub fn update(&mut self, progress: Progress) {
+        self.completed = progress.completed;
+        self.total = progress.total;
+        self.elapsed = self.elapsed.max(progress.elapsed);
+        self.lr = progress.lr.or(self.lr);
+        if let Some(worker) = progress.worker {
+            match self.busy.iter_mut().find(|(thread, _)| *thread == worker.thread) {
+                Some((_, busy)) => *busy += worker.busy,
+                None => self.busy.push((worker.thread, worker.busy)),
+            }
+        }
+        // Every event repeats the latest error until the next record.
+        if let Some(error) = progress.reconstruction_error {
+            if self.errors.last() != Some(&error) {
+                self.errors.push(error);
+            }
+        }
+    }
+
+    pub fn fraction(&self) -> f32 {
+        if self.total == 0 { 0.0 } else { (self.completed as f32 / self.total as f32).min(1.0) }
+    }
+
+    /// Share of the elapsed time every worker thread spent training.
+    pub fn utilization(&self) -> Vec<f32> {
+        let elapsed = self.elapsed.as_secs_f32();
+        self.busy.iter().map(|(_, busy)| if elapsed > 0.0 { (busy.as_secs_f32() / elapsed).min(1.0) } else { 0.0 }).collect()
+    }
+}
+
+/// Renders the progress events received on a channel, e.g. one created with `progress_channel`, until every sender is dropped.
+pub struct Dashboard {
+    title: String,
+    receiver: Receiver<Progress>,
+    state: DashboardState,
+}
+
+impl Dashboard {
+    pub fn new<T: Into<String>>(title: T, receiver: Receiver<Progress>) -> Dashboard {
+        Dashboard { title: title.into(), receiver, state: DashboardState::new() }
+    }
+
+    // Takes over the terminal until the channel is closed, or until the user presses `q`, Esc or Ctrl-C. Training continues
+    // when the dashboard is closed early. Returns the final state of the run.
+    pub fn run(mut self) -> io::Result<DashboardState> {
+        enable_raw_mode()?;
+        let mut stdout = io::stdout();
+        execute!(stdout, EnterAlternateScreen)?;
+        let mut terminal = Terminal::new(CrosstermBackend::new(stdout))?;
+
+        let result = self.event_loop(&mut terminal);
+
+        disable_raw_mode()?;
+        execute!(terminal.backend_mut(), LeaveAlternateScreen)?;
+        terminal.show_cursor()?;
+        result.map(|()| self.state)
+    }
+
+    fn event_loop<B: Backend>(&mut self, terminal: &mut Terminal<B>) -> io::Result<()> {
+        loop {
+            let finished = loop {
+                match self.receiver.try_recv() {
+                    Ok(progress) => self.state.update(progress),
+                    Err(TryRecvError::Empty) => break false,
+                    Err(TryRecvError::Disconnected) => break true,
+                }
+            };
+            terminal.draw(|frame| draw(frame, &self.title, &self.state))?;
+            if finished {
+                return Ok(());
+            }
+
+            if event::poll(REFRESH)? {
+                if let Event::Key(key) = event::read()? {
+                    if key.kind != KeyEventKind::Press {
+                        continue;
+                    }
+                    match key.code {
+                        KeyCode::Char('c') if key.modifiers.contains(KeyModifiers::CONTROL) => {
+                            self.state.interrupted = true;
+                            return Ok(());
+                        }
+                        KeyCode::Char('q') | KeyCode::Esc => return Ok(()),
+                        _ => {}
+                    }
+                }
+            }
+        }
+    }
+}
+
+fn draw(frame: &mut Frame, title: &str, state: &DashboardState) {
+    let areas = Layout::default()
+        .direction(Direction::Vertical)
+        .constraints([Constraint::Length(3), Constraint::Length(3), Constraint::Min(3), Constraint::Length(8)])
+        .split(frame.size());
+
+    let gauge = Gauge::default()
+        .block(Block::default().borders(Borders::ALL).title(format!("{} (q to close)", title)))
+        .ratio(state.fraction() as f64)
+        .label(format!("{}/{} done ({:.1}%)", state.completed, state.total, 100.0 * state.fraction()));
+    frame.render_widget(gauge, areas[0]);
+
+    let lr = state.lr.map_or("-".to_string(), |lr| format!("{:.6}", lr));
+    let error = state.errors.last().map_or("-".to_string(), |error| format!("{:.6}", error));
+    let stats = format!("elapsed {:.1}s   learning rate {}   reconstruction error {}", state.elapsed.as_secs_f32(), lr, error);
+    frame.render_widget(Paragraph::new(stats).block(Block::default().borders(Borders::ALL).title("Run")), areas[1]);
+
+    let threads: Vec<Line> = state.utilization().iter().enumerate().map(|(index, utilization)| {
+        let filled = (utilization * BAR_WIDTH as f32).round() as usize;
+        Line::from(format!("thread {:>3} {}{} {:>5.1}%", index + 1, "█".repeat(filled), "░".repeat(BAR_WIDTH - filled), 100.0 * utilization))
+    }).collect();
+    frame.render_widget(Paragraph::new(threads).block(Block::default().borders(Borders::ALL).title("Thread utilization")), areas[2]);
+
+    // The sparkline takes integers, so the errors are scaled relative to the largest one.
+    let max = state.errors.iter().cloned().fold(0.0f32, f32::max);
+    let errors: Vec<u64> = state.errors.iter().map(|error| if max > 0.0 { (1000.0 * error / max) as u64 } else { 0 }).collect();
+    let sparkline = Sparkline::default().block(Block::default().borders(Borders::ALL).title("Reconstruction error")).data(&errors);
+    frame.render_widget(sparkline, areas[3]);
+}
+
+#[cfg(test)]
+mod test{
+    use super::*;
+    use std::thread;
+    use crate::model::progress::WorkerTime;
+
+    #[test]
+    fn state_accumulates_progress_events(){
+        let other = thread::spawn(|| thread::current().id()).join().unwrap();
+        let mut state = DashboardState::new();
+        let worker = |thread, millis| Some(WorkerTime { thread, busy: Duration::from_millis(millis) });
+        state.update(Progress { worker: worker(thread::current().id(), 300), ..Progress::new(1, 4, Duration::from_millis(400)) });
+        state.update(Progress { worker: worker(other, 200), ..Progress::new(2, 4, Duration::from_millis(500)) });
+        state.update(Progress { worker: worker(thread::current().id(), 200), ..Progress::new(3, 4, Duration::from_millis(1000)) });
+
+        assert_eq!(state.busy.len(), 2);
+        assert_eq!(state.utilization(), vec![0.5, 0.2]);
+        assert_eq!(state.fraction(), 0.75);
+
+        for error in [0.5, 0.5, 0.25] {
+            state.update(Progress { lr: Some(0.01), reconstruction_error: Some(error), ..Progress::new(4, 4, Duration::from_secs(2)) });
+        }
+        assert_eq!(state.errors, vec![0.5, 0.25]);
+        assert_eq!(state.lr, Some(0.01));
+    }
+}
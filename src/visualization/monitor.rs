@@ -0,0 +1,237 @@
+//! Tiny HTTP server for watching long training runs from a browser, fed by the progress events and the weights of a network.
+//! `GET /status` returns the progress of the run as JSON, `GET /weights.png` the current receptive fields and `GET /` a page
+//! showing both. It only speaks as much HTTP/1.1 as a browser or `curl` needs, answering one request at a time on a thread of
+//! its own. Connections which stay idle, like the ones browsers open in advance, are dropped after a timeout so they can not
+//! hold up the others or the shutdown of the monitor. Requires the `monitor` feature.
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::net::{SocketAddr, TcpListener, TcpStream, ToSocketAddrs};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+use tracing::{debug, info};
+use crate::data::patch::{patch_width, Patch};
+use crate::model::history::json_number;
+use crate::model::progress::{Progress, ProgressCallback};
+use crate::visualization::receptive_fields::render_montage;
+
+const SCALE: usize = 4;
+const TIMEOUT: Duration = Duration::from_secs(1);
+// Longest request or header line read, and the most header lines skipped, before a request is given up on.
+const MAX_LINE: u64 = 8192;
+const MAX_HEADERS: usize = 100;
+
+const INDEX: &str = "<!DOCTYPE html><html><head><title>Training</title></head><body>\
+<pre id=\"status\"></pre><img id=\"weights\" src=\"/weights.png\"><script>\
+setInterval(function () {\
+fetch('/status').then(function (response) { return response.text(); }).then(function (text) { document.getElementById('status').textContent = text; });\
+document.getElementById('weights').src = '/weights.png?' + Date.now();\
+}, 2000);</script></body></html>";
+
+/// Everything the monitor knows about the run.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct MonitorState {
+    /// Number of epochs the run trains for.
+    pub epochs: usize,
+    /// The latest progress event, none before the first unit of work completed.
+    pub progress: Option<Progress>,
+    /// The latest weights handed to the monitor.
+    pub weights: Vec<Patch>,
+}
+
+impl MonitorState {
+    /// The state as a JSON object, using null for values which are not known yet.
+    pub fn to_json(&self) -> String {
+        let progress = self.progress.unwrap_or_else(|| Progress::new(0, 0, Default::default()));
+        format!("{{\"epochs\":{},\"completed\":{},\"total\":{},\"fraction\":{},\"elapsed_seconds\":{},\"lr\":{},\"reconstruction_error\":{},\"neurons\":{}}}",
+                self.epochs, progress.completed, progress.total, json_number(self.progress.map(|progress| progress.fraction())),
+                progress.elapsed.as_secs_f64(), json_number(progress.lr), json_number(progress.reconstruction_error), self.weights.len())
+    }
+}
+
+/// A running monitor. The server stops once the monitor is dropped.
+///
+/// ```no_run
+/// use rust_ml::model::network::NetworkBuilder;
+/// use rust_ml::visualization::monitor::Monitor;
+///
+/// let mut network = NetworkBuilder::new().build()?;
+/// let monitor = Monitor::start("127.0.0.1:8080", 50000)?;
+/// network.set_progress_callback(Some(monitor.progress_callback()));
+/// for section in network.train_streaming(50000) {
+///     monitor.update_section(section.start, &section.weights);
+/// }
+/// # Ok::<(), Box<dyn std::error::Error>>(())
+/// ```
+pub struct Monitor {
+    state: Arc<Mutex<MonitorState>>,
+    address: SocketAddr,
+    stopped: Arc<AtomicBool>,
+    server: Option<JoinHandle<()>>,
+}
+
+impl Monitor {
+    /// Starts serving on the given address, e.g. `0.0.0.0:8080` to be reachable from other machines. Port 0 picks a free port,
+    /// see `address`.
+    pub fn start<A: ToSocketAddrs>(address: A, epochs: usize) -> io::Result<Monitor> {
+        let listener = TcpListener::bind(address)?;
+        let address = listener.local_addr()?;
+        let state = Arc::new(Mutex::new(MonitorState { epochs, ..MonitorState::default() }));
+        let stopped = Arc::new(AtomicBool::new(false));
+        let server = {
+            let state = Arc::clone(&state);
+            let stopped = Arc::clone(&stopped);
+            thread::Builder::new().name("monitor".to_string()).spawn(move || {
+                for stream in listener.incoming() {
+                    if stopped.load(Ordering::Relaxed) {
+                        break;
+                    }
+                    // A client which went away is no reason to stop serving the others.
+                    if let Err(error) = stream.and_then(|stream| respond(stream, &state)) {
+                        debug!(error = %error, "monitor request failed");
+                    }
+                }
+            })?
+        };
+        info!(address = %address, "monitor listening");
+        Ok(Monitor { state, address, stopped, server: Some(server) })
+    }
+
+    /// The address the server listens on.
+    pub fn address(&self) -> SocketAddr {
+        self.address
+    }
+
+    /// A progress callback storing every event in the monitor, for `MtNetwork::set_progress_callback`.
+    pub fn progress_callback(&self) -> ProgressCallback {
+        let state = Arc::clone(&self.state);
+        Arc::new(move |progress| lock(&state).progress = Some(progress))
+    }
+
+    /// Replaces the weights shown by the monitor.
+    pub fn update_weights(&self, weights: &[Patch]) {
+        lock(&self.state).weights = weights.to_vec();
+    }
+
+    /// Replaces the weights of the neurons starting at `start`, e.g. with a section handed out by `MtNetwork::train_streaming`.
+    /// Neurons the monitor did not know about yet are added.
+    pub fn update_section(&self, start: usize, weights: &[Patch]) {
+        let mut state = lock(&self.state);
+        if state.weights.len() < start + weights.len() {
+            state.weights.resize(start + weights.len(), Vec::new());
+        }
+        state.weights[start..start + weights.len()].clone_from_slice(weights);
+    }
+
+    pub fn state(&self) -> MonitorState {
+        lock(&self.state).clone()
+    }
+}
+
+impl Drop for Monitor {
+    // Wakes the server up with a last connection, so it sees that it was stopped.
+    fn drop(&mut self) {
+        self.stopped.store(true, Ordering::Relaxed);
+        let _ = TcpStream::connect(self.address);
+        if let Some(server) = self.server.take() {
+            let _ = server.join();
+        }
+    }
+}
+
+// The training thread only holds the lock while copying, so a panic while holding it leaves a consistent state.
+fn lock(state: &Mutex<MonitorState>) -> std::sync::MutexGuard<'_, MonitorState> {
+    state.lock().unwrap_or_else(|poisoned| poisoned.into_inner())
+}
+
+// Reads the request line, skips the headers and writes the response. The connection is closed after every response.
+fn respond(stream: TcpStream, state: &Mutex<MonitorState>) -> io::Result<()> {
+    stream.set_read_timeout(Some(TIMEOUT))?;
+    stream.set_write_timeout(Some(TIMEOUT))?;
+    let mut reader = BufReader::new(stream);
+    let mut request = String::new();
+    read_line(&mut reader, &mut request)?;
+    let mut header = String::new();
+    let mut headers = 0;
+    while read_line(&mut reader, &mut header)? > 0 && !header.trim().is_empty() {
+        headers += 1;
+        if headers > MAX_HEADERS {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, "too many header lines"));
+        }
+        header.clear();
+    }
+    let mut parts = request.split_whitespace();
+    let (method, target) = (parts.next().unwrap_or(""), parts.next().unwrap_or(""));
+    // Query strings only keep browsers from caching the image.
+    let path = target.split('?').next().unwrap_or("");
+
+    let (status, content_type, body) = match (method, path) {
+        ("GET", "/") => ("200 OK", "text/html; charset=utf-8", INDEX.as_bytes().to_vec()),
+        ("GET", "/status") => ("200 OK", "application/json", lock(state).to_json().into_bytes()),
+        ("GET", "/weights.png") => {
+            let weights = lock(state).weights.clone();
+            match weights.first().and_then(|weights| patch_width(weights.len())) {
+                Some(width) if weights.iter().all(|weights| weights.len() == width * width) => ("200 OK", "image/png", render_montage(&weights, width, SCALE).to_png()),
+                _ => ("404 Not Found", "text/plain", b"no square weights to show yet".to_vec()),
+            }
+        }
+        ("GET", _) => ("404 Not Found", "text/plain", b"not found".to_vec()),
+        _ => ("405 Method Not Allowed", "text/plain", b"only GET is supported".to_vec()),
+    };
+    let mut stream = reader.into_inner();
+    write!(stream, "HTTP/1.1 {}\r\nContent-Type: {}\r\nContent-Length: {}\r\nCache-Control: no-store\r\nConnection: close\r\n\r\n", status, content_type, body.len())?;
+    stream.write_all(&body)?;
+    stream.flush()
+}
+
+// Reads a line of at most `MAX_LINE` bytes.
+fn read_line(reader: &mut BufReader<TcpStream>, line: &mut String) -> io::Result<usize> {
+    let read = reader.by_ref().take(MAX_LINE).read_line(line)?;
+    if read as u64 == MAX_LINE && !line.ends_with('\n') {
+        return Err(io::Error::new(io::ErrorKind::InvalidData, "request line too long"));
+    }
+    Ok(read)
+}
+
+#[cfg(test)]
+mod test{
+    use super::*;
+
+    fn get(monitor: &Monitor, path: &str) -> (String, Vec<u8>) {
+        let mut stream = TcpStream::connect(monitor.address()).unwrap();
+        write!(stream, "GET {} HTTP/1.1\r\nHost: localhost\r\n\r\n", path).unwrap();
+        let mut response = Vec::new();
+        stream.read_to_end(&mut response).unwrap();
+        let split = response.windows(4).position(|window| window == b"\r\n\r\n").unwrap();
+        (String::from_utf8_lossy(&response[..split]).into_owned(), response[split + 4..].to_vec())
+    }
+
+    #[test]
+    fn serves_the_status_and_the_weights(){
+        let monitor = Monitor::start("127.0.0.1:0", 100).unwrap();
+        let (head, _) = get(&monitor, "/weights.png");
+        assert!(head.starts_with("HTTP/1.1 404"));
+
+        monitor.progress_callback()(Progress { lr: Some(0.5), ..Progress::new(1, 4, Duration::from_secs(2)) });
+        monitor.update_section(1, &[vec![0.0, 1.0, 2.0, 3.0]]);
+        monitor.update_section(0, &[vec![3.0, 2.0, 1.0, 0.0]]);
+        let (head, body) = get(&monitor, "/status");
+        assert!(head.starts_with("HTTP/1.1 200") && head.contains("application/json"));
+        assert_eq!(String::from_utf8(body).unwrap(), "{\"epochs\":100,\"completed\":1,\"total\":4,\"fraction\":0.25,\"elapsed_seconds\":2,\"lr\":0.5,\"reconstruction_error\":null,\"neurons\":2}");
+
+        let (head, body) = get(&monitor, "/weights.png?1");
+        assert!(head.contains("image/png"));
+        assert_eq!(&body[1..4], b"PNG");
+        assert!(get(&monitor, "/missing").0.starts_with("HTTP/1.1 404"));
+    }
+
+    #[test]
+    fn idle_connections_do_not_block_the_server(){
+        let monitor = Monitor::start("127.0.0.1:0", 100).unwrap();
+        let _idle = TcpStream::connect(monitor.address()).unwrap();
+        let mut endless = TcpStream::connect(monitor.address()).unwrap();
+        let _ = endless.write_all(&[b'a'; 2 * MAX_LINE as usize]);
+        assert!(get(&monitor, "/status").0.starts_with("HTTP/1.1 200"));
+        drop(monitor);
+    }
+}
@@ -0,0 +1,152 @@
+//! Renders learned weight vectors as grayscale images, so the filters learned by a network can be inspected visually.
+use std::error::Error;
+use std::io::Write;
+use std::path::Path;
+use flate2::Compression;
+use flate2::write::ZlibEncoder;
+use plotters::prelude::*;
+use crate::data::patch::Patch;
+
+/// Grayscale image with one tile per neuron, stored row by row.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Montage {
+    pub width: usize,
+    pub height: usize,
+    pub pixels: Vec<u8>,
+}
+
+impl Montage {
+    /// Encodes the montage as an 8 bit grayscale PNG in memory, e.g. to serve it over HTTP.
+    pub fn to_png(&self) -> Vec<u8> {
+        // Every row of the image data starts with the filter type, none.
+        let mut encoder = ZlibEncoder::new(Vec::new(), Compression::fast());
+        for row in self.pixels.chunks(self.width.max(1)) {
+            encoder.write_all(&[0]).and_then(|_| encoder.write_all(row)).expect("writing to memory can not fail");
+        }
+        let data = encoder.finish().expect("writing to memory can not fail");
+
+        let mut header = Vec::with_capacity(13);
+        header.extend_from_slice(&(self.width as u32).to_be_bytes());
+        header.extend_from_slice(&(self.height as u32).to_be_bytes());
+        // Bit depth 8, grayscale, deflate, adaptive filtering, no interlacing.
+        header.extend_from_slice(&[8, 0, 0, 0, 0]);
+
+        let mut png = vec![0x89, b'P', b'N', b'G', b'\r', b'\n', 0x1a, b'\n'];
+        for (kind, chunk) in [(b"IHDR", header.as_slice()), (b"IDAT", data.as_slice()), (b"IEND", &[][..])] {
+            png.extend_from_slice(&(chunk.len() as u32).to_be_bytes());
+            let start = png.len();
+            png.extend_from_slice(kind);
+            png.extend_from_slice(chunk);
+            let crc = crc32(&png[start..]);
+            png.extend_from_slice(&crc.to_be_bytes());
+        }
+        png
+    }
+
+    /// The pixels as opaque RGBA, four bytes per pixel, the layout of `ImageData` for drawing the montage on a canvas.
+    pub fn to_rgba(&self) -> Vec<u8> {
+        self.pixels.iter().flat_map(|&value| [value, value, value, 255]).collect()
+    }
+}
+
+/// CRC-32 as used by PNG and zlib, computed bit by bit as the images are small.
+fn crc32(bytes: &[u8]) -> u32 {
+    let mut crc = 0xFFFF_FFFFu32;
+    for &byte in bytes {
+        crc ^= byte as u32;
+        for _ in 0..8 {
+            crc = if crc & 1 == 1 { (crc >> 1) ^ 0xEDB8_8320 } else { crc >> 1 };
+        }
+    }
+    !crc
+}
+
+/// Lays out every weight vector as a `patch_width` x `patch_width` tile in a square grid, scaled up by `scale` and separated by a one pixel border.
+/// Each tile is normalized to its own minimum and maximum so that weak filters are as visible as strong ones.
+pub fn render_montage(weights: &[Patch], patch_width: usize, scale: usize) -> Montage {
+    render_grid(weights, patch_width, ((weights.len() as f32).sqrt().ceil() as usize).max(1), scale)
+}
+
+/// Same as `render_montage`, but with a fixed number of tiles per row, e.g. to keep the layout of a self-organizing map.
+pub fn render_grid(weights: &[Patch], patch_width: usize, columns: usize, scale: usize) -> Montage {
+    let columns = columns.max(1);
+    let rows = weights.len().div_ceil(columns).max(1);
+    let tile = patch_width * scale;
+    let width = columns * (tile + 1) + 1;
+    let height = rows * (tile + 1) + 1;
+    let mut pixels = vec![0u8; width * height];
+
+    for (n, weight) in weights.iter().enumerate() {
+        let min = weight.iter().cloned().fold(f32::INFINITY, f32::min);
+        let max = weight.iter().cloned().fold(f32::NEG_INFINITY, f32::max);
+        let range = (max - min).max(f32::EPSILON);
+        let x0 = (n % columns) * (tile + 1) + 1;
+        let y0 = (n / columns) * (tile + 1) + 1;
+
+        for row in 0..tile {
+            for column in 0..tile {
+                let value = weight[(row / scale) * patch_width + column / scale];
+                pixels[(y0 + row) * width + x0 + column] = ((value - min) / range * 255.0).round() as u8;
+            }
+        }
+    }
+
+    Montage { width, height, pixels }
+}
+
+/// Writes the montage of the given weights to an image file. The format is picked from the file extension, e.g. `.png`.
+pub fn save_montage<P: AsRef<Path>>(path: P, weights: &[Patch], patch_width: usize, scale: usize) -> Result<(), Box<dyn Error>> {
+    save_image(path, &render_montage(weights, patch_width, scale))
+}
+
+/// Writes the weights as a grid with a fixed number of tiles per row, see `render_grid`.
+pub fn save_grid<P: AsRef<Path>>(path: P, weights: &[Patch], patch_width: usize, columns: usize, scale: usize) -> Result<(), Box<dyn Error>> {
+    save_image(path, &render_grid(weights, patch_width, columns, scale))
+}
+
+fn save_image<P: AsRef<Path>>(path: P, montage: &Montage) -> Result<(), Box<dyn Error>> {
+    let root = BitMapBackend::new(path.as_ref(), (montage.width as u32, montage.height as u32)).into_drawing_area();
+
+    for y in 0..montage.height {
+        for x in 0..montage.width {
+            let value = montage.pixels[y * montage.width + x];
+            root.draw_pixel((x as i32, y as i32), &RGBColor(value, value, value))?;
+        }
+    }
+    root.present()?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod test{
+    use super::*;
+
+    #[test]
+    fn montage_layout_and_normalization(){
+        let weights: Vec<Patch> = (0..5).map(|n| (0..4).map(|i| (i * n) as f32).collect()).collect();
+        let montage = render_montage(&weights, 2, 3);
+
+        // 5 tiles are laid out in a 3x2 grid of 6x6 pixel tiles with a 1 pixel border.
+        assert_eq!(montage.width, 3 * 7 + 1);
+        assert_eq!(montage.height, 2 * 7 + 1);
+        assert_eq!(montage.pixels.len(), montage.width * montage.height);
+
+        // The second tile starts at (8, 1); its smallest weight is drawn black and its largest white.
+        assert_eq!(montage.pixels[montage.width + 8], 0);
+        assert_eq!(montage.pixels[6 * montage.width + 13], 255);
+        let rgba = montage.to_rgba();
+        assert_eq!(rgba.len(), 4 * montage.pixels.len());
+        assert_eq!(&rgba[4 * (6 * montage.width + 13)..][..4], &[255, 255, 255, 255]);
+    }
+
+    #[test]
+    fn montage_encodes_as_png(){
+        let montage = render_montage(&[vec![0.0, 1.0, 2.0, 3.0]], 2, 1);
+        let png = montage.to_png();
+        assert_eq!(&png[..8], b"\x89PNG\r\n\x1a\n");
+        assert_eq!(&png[12..16], b"IHDR");
+        assert_eq!(&png[16..24], &[0, 0, 0, 4, 0, 0, 0, 4]);
+        assert_eq!(&png[png.len() - 8..png.len() - 4], b"IEND");
+        assert_eq!(crc32(b"123456789"), 0xCBF4_3926);
+    }
+}
@@ -0,0 +1,83 @@
+//! Browser bindings, built with `--features wasm` for `wasm32-unknown-unknown`, e.g. with
+//! `wasm-pack build --target web -- --features wasm`, and used by the demo in `demo/`.
+//! Browsers can not start the workers of the thread pool, so the neurons are trained one after the other with `StNetwork`,
+//! on images JavaScript hands over instead of files. Training a few hundred patches per animation frame keeps the page
+//! responsive while the receptive fields form, and running it in a Web Worker takes it off the main thread entirely.
+use wasm_bindgen::prelude::*;
+use crate::data::natural_images::NaturalImages;
+use crate::model::learning_rule::LearningRule;
+use crate::model::st_network::StNetwork;
+use crate::visualization::receptive_fields::render_montage;
+
+/// A network trained live on images in the browser.
+#[wasm_bindgen]
+pub struct Demo {
+    network: StNetwork<NaturalImages>,
+    patch_width: usize,
+    trained: usize,
+}
+
+#[wasm_bindgen]
+impl Demo {
+    /// Trains `neurons` neurons with Oja's rule on grayscale images of `rows` x `columns` pixels, stored row by row and image
+    /// after image, e.g. centered to zero mean. Without a seed the weights are initialized from the browser's random source.
+    #[wasm_bindgen(constructor)]
+    pub fn new(pixels: Vec<f32>, rows: usize, columns: usize, neurons: usize, patch_width: usize, lr: f32, seed: Option<u32>) -> Result<Demo, JsError> {
+        let images = NaturalImages::from_pixels(pixels, rows, columns, patch_width)?;
+        let network = StNetwork::with_data(neurons, lr, images, seed.map(u64::from));
+        Ok(Demo { network, patch_width, trained: 0 })
+    }
+
+    /// Switches to the BCM rule with a sliding threshold of time constant `tau`, or back to Oja's rule without one.
+    pub fn set_bcm(&mut self, tau: Option<f32>) {
+        self.network.set_rule(tau.map_or(LearningRule::Oja, |tau| LearningRule::Bcm { tau }));
+    }
+
+    /// Trains every neuron on `patches` more patches, continuing from its current weights.
+    pub fn step(&mut self, patches: usize) {
+        self.network.train(patches);
+        self.trained += patches;
+    }
+
+    /// Number of patches every neuron has been trained on so far.
+    #[wasm_bindgen(getter)]
+    pub fn trained(&self) -> usize {
+        self.trained
+    }
+
+    /// The weights of every neuron, one after the other.
+    pub fn weights(&self) -> Vec<f32> {
+        self.network.weights().concat()
+    }
+
+    /// Renders the receptive fields as a montage scaled up by `scale`, see `render_montage`.
+    pub fn render(&self, scale: usize) -> Frame {
+        let montage = render_montage(self.network.weights(), self.patch_width, scale.max(1));
+        Frame { width: montage.width, height: montage.height, pixels: montage.to_rgba() }
+    }
+}
+
+/// RGBA pixels for a canvas, e.g. `new ImageData(new Uint8ClampedArray(frame.pixels()), frame.width)`.
+#[wasm_bindgen]
+pub struct Frame {
+    width: usize,
+    height: usize,
+    pixels: Vec<u8>,
+}
+
+#[wasm_bindgen]
+impl Frame {
+    #[wasm_bindgen(getter)]
+    pub fn width(&self) -> usize {
+        self.width
+    }
+
+    #[wasm_bindgen(getter)]
+    pub fn height(&self) -> usize {
+        self.height
+    }
+
+    pub fn pixels(&self) -> Vec<u8> {
+        self.pixels.clone()
+    }
+}
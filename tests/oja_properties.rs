@@ -0,0 +1,90 @@
+//! Invariants of Oja's rule that every implementation of it has to keep, checked on random patches and weights so a
+//! regression in the vectorized or batched kernels shows up as a failing case instead of subtly different receptive fields.
+use proptest::prelude::*;
+use rust_ml::model::oja::{oja_batch_learning_rule, oja_learning_rule, oja_learning_rule_scalar};
+
+/// Patches and weights of the same length, as pairs of values.
+fn pairs() -> impl Strategy<Value = Vec<(f32, f32)>> {
+    prop::collection::vec((-1.0f32..1.0, -1.0f32..1.0), 1..64)
+}
+
+fn norm(values: &[f32]) -> f32 {
+    values.iter().map(|value| value * value).sum::<f32>().sqrt()
+}
+
+// The update `w + lr * y * (x - y * w)` with `y = w . x`, computed in double precision.
+fn analytical(patch: &[f32], weights: &[f32], lr: f32) -> Vec<f64> {
+    let y: f64 = patch.iter().zip(weights.iter()).map(|(x, w)| *x as f64 * *w as f64).sum();
+    patch.iter().zip(weights.iter()).map(|(x, w)| *w as f64 + lr as f64 * y * (*x as f64 - y * *w as f64)).collect()
+}
+
+fn close(actual: &[f32], expected: &[f64]) -> bool {
+    actual.iter().zip(expected.iter()).all(|(a, e)| (*a as f64 - e).abs() <= 1e-4 * (1.0 + e.abs()))
+}
+
+proptest! {
+    #[test]
+    fn zero_input_leaves_the_weights_unchanged(weights in prop::collection::vec(-1.0f32..1.0, 1..64), lr in 0.0f32..1.0) {
+        let zeros = vec![0.0f32; weights.len()];
+        let mut single = weights.clone();
+        oja_learning_rule(&zeros, &mut single, lr);
+        prop_assert_eq!(&single, &weights);
+
+        let mut scalar = weights.clone();
+        oja_learning_rule_scalar(&zeros, &mut scalar, lr);
+        prop_assert_eq!(&scalar, &weights);
+
+        let mut batched = weights.clone();
+        oja_batch_learning_rule(&[zeros.clone(), zeros], &mut batched, lr);
+        prop_assert_eq!(&batched, &weights);
+    }
+
+    #[test]
+    fn update_matches_the_analytical_formula(values in pairs(), lr in 0.0f32..0.5) {
+        let (patch, weights): (Vec<f32>, Vec<f32>) = values.into_iter().unzip();
+        let expected = analytical(&patch, &weights, lr);
+
+        let mut single = weights.clone();
+        oja_learning_rule(&patch, &mut single, lr);
+        prop_assert!(close(&single, &expected), "vectorized {:?} != {:?}", single, expected);
+
+        let mut scalar = weights.clone();
+        oja_learning_rule_scalar(&patch, &mut scalar, lr);
+        prop_assert!(close(&scalar, &expected), "scalar {:?} != {:?}", scalar, expected);
+
+        let mut batched = weights;
+        oja_batch_learning_rule(&[patch], &mut batched, lr);
+        prop_assert!(close(&batched, &expected), "batch of one {:?} != {:?}", batched, expected);
+    }
+
+    #[test]
+    fn batch_update_is_the_mean_of_the_single_updates(values in prop::collection::vec((-1.0f32..1.0, -1.0f32..1.0, -1.0f32..1.0), 1..64), lr in 0.0f32..0.5) {
+        let first: Vec<f32> = values.iter().map(|value| value.0).collect();
+        let second: Vec<f32> = values.iter().map(|value| value.1).collect();
+        let weights: Vec<f32> = values.iter().map(|value| value.2).collect();
+        let (a, b) = (analytical(&first, &weights, lr), analytical(&second, &weights, lr));
+        let expected: Vec<f64> = a.iter().zip(b.iter()).map(|(a, b)| (a + b) / 2.0).collect();
+
+        let mut batched = weights;
+        oja_batch_learning_rule(&[first, second], &mut batched, lr);
+        prop_assert!(close(&batched, &expected), "batch {:?} != {:?}", batched, expected);
+    }
+
+    #[test]
+    fn weight_norm_converges_to_one(values in pairs(), initial_norm in 0.2f32..2.0, step in 0.01f32..0.1) {
+        let (patch, mut weights): (Vec<f32>, Vec<f32>) = values.into_iter().unzip();
+        let (patch_norm, weights_norm) = (norm(&patch), norm(&weights));
+        prop_assume!(patch_norm > 0.1 && weights_norm > 0.1);
+        // Starting almost orthogonal to the patch, the neuron barely responds and takes too long to align for the test.
+        let cosine = patch.iter().zip(weights.iter()).map(|(x, w)| x * w).sum::<f32>() / (patch_norm * weights_norm);
+        prop_assume!(cosine.abs() > 0.1);
+        weights.iter_mut().for_each(|w| *w *= initial_norm / weights_norm);
+
+        // Scaling the learning rate by the patch energy keeps every step small enough to be stable.
+        let lr = step / (patch_norm * patch_norm);
+        for _ in 0..2000 {
+            oja_learning_rule(&patch, &mut weights, lr);
+        }
+        prop_assert!((norm(&weights) - 1.0).abs() < 1e-2, "norm {} after training", norm(&weights));
+    }
+}